@@ -0,0 +1,51 @@
+// times ETC1 and ETC1A4 decoding of a 1024x1024 texture. compare the results with and
+// without the simd feature:
+//
+//     cargo bench --bench etc1_decode
+//     cargo bench --bench etc1_decode --features simd
+
+use std::{hint::black_box, time::Instant};
+
+use ctr_bcres::{image_codec::decode_swizzled_buffer, texture::PicaTextureFormat};
+
+const SIZE: u32 = 1024;
+const ITERATIONS: u32 = 20;
+
+// random blocks cover every mode, table and modifier
+fn random_bytes(length: usize) -> Vec<u8> {
+    let mut state: u64 = 0x2545F4914F6CDD1D;
+    
+    (0..length)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect()
+}
+
+fn bench(format: PicaTextureFormat, bytes_per_block: usize) {
+    let blocks = (SIZE / 4) as usize * (SIZE / 4) as usize;
+    let bytes = random_bytes(blocks * bytes_per_block);
+    
+    // warm up
+    decode_swizzled_buffer(&bytes, format, SIZE, SIZE).unwrap();
+    
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        black_box(decode_swizzled_buffer(black_box(&bytes), format, SIZE, SIZE).unwrap());
+    }
+    let per_iteration = start.elapsed() / ITERATIONS;
+    
+    let megapixels = (SIZE * SIZE) as f64 / 1_000_000.0;
+    println!("{format:?}: {per_iteration:?} per {SIZE}x{SIZE} texture, {:.1} MPixel/s",
+        megapixels / per_iteration.as_secs_f64());
+}
+
+fn main() {
+    println!("simd feature: {}", cfg!(feature = "simd"));
+    
+    bench(PicaTextureFormat::ETC1, 8);
+    bench(PicaTextureFormat::ETC1A4, 16);
+}
@@ -0,0 +1,52 @@
+// times swizzled decoding and encoding of a 1024x1024 texture in a few formats:
+//
+//     cargo bench --bench swizzle
+
+use std::{hint::black_box, time::{Duration, Instant}};
+
+use ctr_bcres::{
+    image_codec::{decode_swizzled_buffer, encode_swizzled_buffer, RgbaColor},
+    texture::PicaTextureFormat,
+};
+
+const SIZE: u32 = 1024;
+const ITERATIONS: u32 = 20;
+
+fn random_pixels() -> Vec<RgbaColor> {
+    let mut state: u64 = 0x2545F4914F6CDD1D;
+    
+    (0..SIZE * SIZE)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            
+            let [r, g, b, a, ..] = state.to_le_bytes();
+            RgbaColor::new(r, g, b, a)
+        })
+        .collect()
+}
+
+fn time<T>(f: impl Fn() -> T) -> Duration {
+    // warm up
+    black_box(f());
+    
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        black_box(f());
+    }
+    start.elapsed() / ITERATIONS
+}
+
+fn main() {
+    let pixels = random_pixels();
+    
+    for format in [PicaTextureFormat::RGBA8, PicaTextureFormat::RGB565, PicaTextureFormat::LA4, PicaTextureFormat::L4] {
+        let bytes = encode_swizzled_buffer(&pixels, format, SIZE, SIZE).unwrap();
+        
+        let decode = time(|| decode_swizzled_buffer(black_box(&bytes), format, SIZE, SIZE).unwrap());
+        let encode = time(|| encode_swizzled_buffer(black_box(&pixels), format, SIZE, SIZE).unwrap());
+        
+        println!("{format:?}: decoding takes {decode:?}, encoding {encode:?} per {SIZE}x{SIZE} texture");
+    }
+}
@@ -0,0 +1,168 @@
+use std::{
+    fs,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::{mpsc, Mutex},
+    thread,
+};
+
+use anyhow::{Error, Result};
+
+use crate::cgfx_container::CgfxContainer;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BatchOperation {
+    /// Only parse every file
+    Validate,
+    /// Decode every texture and write it as `<output_dir>/<file stem>/<texture name>.png`
+    #[cfg(feature = "png")]
+    ExtractTextures { output_dir: PathBuf },
+}
+
+/// What was found in a single successfully processed file
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FileSummary {
+    pub model_count: usize,
+    pub texture_count: usize,
+    /// Files created by the operation
+    pub written_files: Vec<PathBuf>,
+}
+
+#[derive(Debug)]
+pub struct FileReport<T> {
+    pub path: PathBuf,
+    pub result: Result<T>,
+}
+
+/// Results of a batch run, in the same order as the input paths
+#[derive(Debug)]
+pub struct BatchReport<T = FileSummary> {
+    pub files: Vec<FileReport<T>>,
+}
+
+impl<T> BatchReport<T> {
+    pub fn succeeded(&self) -> impl Iterator<Item = (&Path, &T)> {
+        self.files.iter().filter_map(|file| Some((file.path.as_path(), file.result.as_ref().ok()?)))
+    }
+    
+    pub fn failed(&self) -> impl Iterator<Item = (&Path, &Error)> {
+        self.files.iter().filter_map(|file| Some((file.path.as_path(), file.result.as_ref().err()?)))
+    }
+    
+    pub fn is_success(&self) -> bool {
+        self.files.iter().all(|file| file.result.is_ok())
+    }
+}
+
+/// Amount of workers used when none is specified, one per available core
+pub fn default_worker_count() -> usize {
+    thread::available_parallelism().map_or(1, NonZeroUsize::get)
+}
+
+/// Runs `operation` on every file in `paths` using `workers` threads
+pub fn run(paths: impl IntoIterator<Item = PathBuf>, operation: &BatchOperation, workers: usize) -> BatchReport {
+    run_with(paths, workers, |path| process_file(path, operation))
+}
+
+/// Runs an arbitrary function on every file in `paths` using `workers` threads,
+/// for operations that aren't covered by [`BatchOperation`]
+pub fn run_with<T, F>(paths: impl IntoIterator<Item = PathBuf>, workers: usize, f: F) -> BatchReport<T>
+where
+    T: Send,
+    F: Fn(&Path) -> Result<T> + Sync,
+{
+    let paths: Vec<PathBuf> = paths.into_iter().collect();
+    let workers = workers.clamp(1, paths.len().max(1));
+    
+    let queue = Mutex::new(paths.iter().enumerate());
+    let (sender, receiver) = mpsc::channel();
+    
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            let sender = sender.clone();
+            let queue = &queue;
+            let f = &f;
+            
+            scope.spawn(move || loop {
+                // release the lock before processing so other workers can continue
+                let next = queue.lock().unwrap_or_else(|err| err.into_inner()).next();
+                let Some((index, path)) = next else {
+                    break;
+                };
+                
+                // the receiver outlives all workers
+                sender.send((index, f(path))).unwrap();
+            });
+        }
+    });
+    
+    drop(sender);
+    
+    let mut results: Vec<Option<Result<T>>> = paths.iter().map(|_| None).collect();
+    
+    for (index, result) in receiver {
+        results[index] = Some(result);
+    }
+    
+    let files = paths.into_iter()
+        .zip(results)
+        .map(|(path, result)| FileReport {
+            path,
+            result: result.expect("every path is processed exactly once"),
+        })
+        .collect();
+    
+    BatchReport { files }
+}
+
+fn process_file(path: &Path, operation: &BatchOperation) -> Result<FileSummary> {
+    let buffer = fs::read(path)?;
+    let container = CgfxContainer::new(&buffer)?;
+    
+    let written_files = match operation {
+        BatchOperation::Validate => Vec::new(),
+        #[cfg(feature = "png")]
+        BatchOperation::ExtractTextures { output_dir } => extract_textures(path, &container, output_dir)?,
+    };
+    
+    Ok(FileSummary {
+        model_count: container.models.as_ref().map_or(0, |models| models.values_count as usize),
+        texture_count: container.textures.as_ref().map_or(0, |textures| textures.values_count as usize),
+        written_files,
+    })
+}
+
+#[cfg(feature = "png")]
+fn extract_textures(path: &Path, container: &CgfxContainer, output_dir: &Path) -> Result<Vec<PathBuf>> {
+    use crate::image_codec::to_png;
+    
+    let Some(textures) = &container.textures else {
+        return Ok(Vec::new());
+    };
+    
+    let file_stem = path.file_stem().unwrap_or(path.as_os_str());
+    let directory = output_dir.join(file_stem);
+    fs::create_dir_all(&directory)?;
+    
+    let mut written_files = Vec::new();
+    
+    // render targets have nothing to extract
+    for texture in textures.values().filter(|texture| !texture.is_render_target()) {
+        let decoded = texture.decode()?;
+        let name = decoded.name.as_deref().unwrap_or("unnamed").replace(['/', '\\'], "_");
+        
+        for (i, image) in decoded.images.iter().enumerate() {
+            let file_name = if decoded.images.len() == 1 {
+                format!("{name}.png")
+            } else {
+                format!("{name}_{i}.png")
+            };
+            
+            let out_path = directory.join(file_name);
+            fs::write(&out_path, to_png(image, decoded.width, decoded.height)?)?;
+            written_files.push(out_path);
+        }
+    }
+    
+    Ok(written_files)
+}
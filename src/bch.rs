@@ -0,0 +1,404 @@
+// minimal in-memory representation of BCH (H3D) scenes. H3D stores textures and
+// vertex data the same way CGFX does and its materials, meshes and bones map onto
+// CGFX's one to one, so converting between the two is mostly renaming fields.
+// reading and writing BCH files themselves isn't part of this
+
+use anyhow::{anyhow, bail, ensure, Result};
+
+use crate::{
+    cgfx_container::CgfxContainer,
+    model::{
+        material::{CgfxMaterial, FragmentOp, MaterialColors, Rasterization, TextureCoord, TextureMapper, TextureReference, TextureTransformType},
+        mesh::{Mesh, Shape},
+        skeleton::{BoneDict, CgfxBone, CgfxSkeleton},
+        CgfxModel, CgfxModelCommon,
+    },
+    texture::{CgfxTexture, CgfxTextureCommon, ImageData, PicaTextureFormat},
+    util::{
+        math::{Mat3x4, Vec2, Vec3},
+        util::{CgfxNodeHeader, CgfxObjectHeader, CgfxTransform, RuntimeHandle, SharedBytes},
+    },
+    CgfxDict, CollisionPolicy,
+};
+
+fn object_header(magic: &str, name: Option<String>) -> CgfxObjectHeader {
+    CgfxObjectHeader {
+        magic: magic.to_string(),
+        revision: 0,
+        name,
+        metadata_count: 0,
+        metadata_pointer: None,
+        metadata: None,
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BchTexture {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub format: PicaTextureFormat,
+    pub mipmap_count: u32,
+    /// Swizzled image data of all mipmaps, laid out like in CGFX
+    pub data: SharedBytes,
+}
+
+impl BchTexture {
+    pub fn from_cgfx(texture: &CgfxTexture) -> Result<Self> {
+        let CgfxTexture::Image(common, image) = texture else {
+            bail!("Converting cube textures to BCH is not supported yet");
+        };
+        
+        let name = common.cgfx_object_header.name.clone()
+            .ok_or_else(|| anyhow!("Texture has no name"))?;
+        let image = image.as_ref()
+            .ok_or_else(|| anyhow!("Texture {name:?} has no image"))?;
+        
+        ensure!(!image.is_render_target(), "Texture {name:?} is a render target");
+        
+        Ok(Self {
+            width: common.width,
+            height: common.height,
+            format: common.texture_format,
+            mipmap_count: common.mipmap_size,
+            data: image.image_bytes.clone(),
+            name,
+        })
+    }
+    
+    pub fn to_cgfx(&self) -> Result<CgfxTexture> {
+        let (gl_format, gl_type) = self.format.gl_format_and_type()
+            .ok_or_else(|| anyhow!("Format {:?} of texture {:?} has no GL equivalent", self.format, self.name))?;
+        
+        let common = CgfxTextureCommon {
+            cgfx_object_header: object_header("TXOB", Some(self.name.clone())),
+            height: self.height,
+            width: self.width,
+            gl_format,
+            gl_type,
+            mipmap_size: self.mipmap_count,
+            texture_obj: RuntimeHandle::default(),
+            location_flag: 0,
+            texture_format: self.format,
+        };
+        
+        let image = ImageData::new(self.width, self.height, self.format, self.data.to_vec())?;
+        
+        Ok(CgfxTexture::Image(common, Some(image)))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BchTextureUnit {
+    /// Name of the sampled texture, None for units without a texture mapper
+    pub texture_name: Option<String>,
+    pub coord: TextureCoord,
+    /// PICA commands setting up the sampler of the unit
+    pub sampler_commands: Vec<u32>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BchMaterial {
+    pub name: String,
+    pub flags: u32,
+    pub render_layer: u32,
+    pub colors: MaterialColors,
+    pub rasterization: Rasterization,
+    pub fragment_operation: FragmentOp,
+    /// The used texture units, at most three
+    pub texture_units: Vec<BchTextureUnit>,
+}
+
+impl BchMaterial {
+    pub fn from_cgfx(material: &CgfxMaterial) -> Result<Self> {
+        let name = material.cgfx_object_header.name.clone()
+            .ok_or_else(|| anyhow!("Material has no name"))?;
+        
+        let used_count = material.used_texture_coords_count as usize;
+        ensure!(used_count <= 3, "Material {name:?} uses {used_count} texture coordinates, expected at most 3");
+        
+        let texture_units = material.texture_coords.iter()
+            .zip(&material.texture_mappers)
+            .take(used_count)
+            .map(|(coord, mapper)| {
+                let texture_name = mapper.as_ref()
+                    .and_then(|mapper| mapper.texture.as_ref()?.path.clone());
+                
+                let sampler_commands = match mapper {
+                    Some(mapper) => mapper.commands.get(..mapper.commands_len as usize)
+                        .ok_or_else(|| anyhow!("Texture mapper of material {name:?} has {} commands", mapper.commands_len))?
+                        .to_vec(),
+                    None => Vec::new(),
+                };
+                
+                Ok(BchTextureUnit {
+                    texture_name,
+                    coord: coord.clone(),
+                    sampler_commands,
+                })
+            })
+            .collect::<Result<Vec<BchTextureUnit>>>()?;
+        
+        Ok(Self {
+            flags: material.flags,
+            render_layer: material.render_layer,
+            colors: material.colors.clone(),
+            rasterization: material.rasterization.clone(),
+            fragment_operation: material.fragment_operation.clone(),
+            texture_units,
+            name,
+        })
+    }
+    
+    pub fn to_cgfx(&self) -> Result<CgfxMaterial> {
+        ensure!(self.texture_units.len() <= 3, "Material {:?} has {} texture units, expected at most 3",
+            self.name, self.texture_units.len());
+        
+        let unused_coord = TextureCoord {
+            source_coord_index: 0,
+            mapping_type: 0,
+            reference_camera_index: 0,
+            transform_type: TextureTransformType::DccMaya,
+            scale: Vec2::default(),
+            rotation: 0.0,
+            translation: Vec2::default(),
+            flags: 0,
+            transform: Mat3x4::IDENTITY,
+        };
+        
+        let mut texture_coords = [unused_coord.clone(), unused_coord.clone(), unused_coord];
+        let mut texture_mappers: [Option<TextureMapper>; 3] = Default::default();
+        
+        for (i, unit) in self.texture_units.iter().enumerate() {
+            texture_coords[i] = unit.coord.clone();
+            
+            let Some(texture_name) = &unit.texture_name else {
+                continue;
+            };
+            
+            ensure!(unit.sampler_commands.len() <= 14, "Texture unit {i} of material {:?} has {} sampler commands, expected at most 14",
+                self.name, unit.sampler_commands.len());
+            
+            let mut commands = [0; 14];
+            commands[..unit.sampler_commands.len()].copy_from_slice(&unit.sampler_commands);
+            
+            texture_mappers[i] = Some(TextureMapper {
+                dynamic_alloc: 0,
+                texture: Some(TextureReference {
+                    cgfx_object_header: object_header("TXOB", Some(texture_name.clone())),
+                    path: Some(texture_name.clone()),
+                    texture_ptr: 0,
+                    texture_index: None,
+                }),
+                sampler: None,
+                commands,
+                commands_len: unit.sampler_commands.len() as u32,
+            });
+        }
+        
+        Ok(CgfxMaterial {
+            cgfx_object_header: object_header("MTOB", Some(self.name.clone())),
+            flags: self.flags,
+            tex_coord_config: 0,
+            render_layer: self.render_layer,
+            colors: self.colors.clone(),
+            rasterization: self.rasterization.clone(),
+            fragment_operation: self.fragment_operation.clone(),
+            used_texture_coords_count: self.texture_units.len() as u32,
+            texture_coords,
+            texture_mappers,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BchMesh {
+    pub shape_index: u32,
+    pub material_index: u32,
+    pub visible: bool,
+    pub render_priority: u8,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BchBone {
+    pub name: String,
+    /// Index of the parent bone, None for root bones
+    pub parent_index: Option<u32>,
+    
+    pub scale: Vec3,
+    pub rotation: Vec3,
+    pub translation: Vec3,
+    
+    pub local_transform: Mat3x4,
+    pub world_transform: Mat3x4,
+    pub inv_world_transform: Mat3x4,
+}
+
+impl BchBone {
+    pub fn from_cgfx(bone: &CgfxBone) -> Result<Self> {
+        let name = bone.name.clone()
+            .ok_or_else(|| anyhow!("Bone {} has no name", bone.index))?;
+        
+        Ok(Self {
+            name,
+            parent_index: bone.parent_bone_index(),
+            scale: bone.scale,
+            rotation: bone.rotation,
+            translation: bone.translation,
+            local_transform: bone.local_transform,
+            world_transform: bone.world_transform,
+            inv_world_transform: bone.inv_world_transform,
+        })
+    }
+    
+    pub fn to_cgfx(&self, index: u32) -> CgfxBone {
+        let mut bone = CgfxBone::new(&self.name, index, self.parent_index.unwrap_or(u32::MAX));
+        
+        bone.scale = self.scale;
+        bone.rotation = self.rotation;
+        bone.translation = self.translation;
+        bone.local_transform = self.local_transform;
+        bone.world_transform = self.world_transform;
+        bone.inv_world_transform = self.inv_world_transform;
+        bone
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BchModel {
+    pub name: String,
+    pub transform: CgfxTransform,
+    pub materials: Vec<BchMaterial>,
+    pub meshes: Vec<BchMesh>,
+    /// Vertex and index data, which H3D stores in the same PICA formats as CGFX
+    pub shapes: Vec<Shape>,
+    /// Empty for models without a skeleton
+    pub bones: Vec<BchBone>,
+}
+
+impl BchModel {
+    pub fn from_cgfx(model: &CgfxModel) -> Result<Self> {
+        let common = model.common();
+        
+        let name = common.cgfx_object_header.name.clone()
+            .ok_or_else(|| anyhow!("Model has no name"))?;
+        
+        let materials = common.materials.iter()
+            .flat_map(|materials| materials.values())
+            .map(BchMaterial::from_cgfx)
+            .collect::<Result<Vec<BchMaterial>>>()?;
+        
+        let meshes = common.meshes.iter()
+            .map(|mesh| {
+                let mesh = mesh.as_ref().ok_or_else(|| anyhow!("Model {name:?} has a null mesh"))?;
+                
+                Ok(BchMesh {
+                    shape_index: mesh.shape_index,
+                    material_index: mesh.material_index,
+                    visible: mesh.visible,
+                    render_priority: mesh.render_priority,
+                })
+            })
+            .collect::<Result<Vec<BchMesh>>>()?;
+        
+        let shapes = common.shapes.iter()
+            .map(|shape| shape.clone().ok_or_else(|| anyhow!("Model {name:?} has a null shape")))
+            .collect::<Result<Vec<Shape>>>()?;
+        
+        let bones = match model {
+            CgfxModel::Standard(_) => Vec::new(),
+            CgfxModel::Skeletal(_, skeleton) => skeleton.bones.values()
+                .map(BchBone::from_cgfx)
+                .collect::<Result<Vec<BchBone>>>()?,
+        };
+        
+        Ok(Self {
+            transform: common.transform_node_header.clone(),
+            materials,
+            meshes,
+            shapes,
+            bones,
+            name,
+        })
+    }
+    
+    /// Models with bones become skeletal models, the others standard ones
+    pub fn to_cgfx(&self) -> Result<CgfxModel> {
+        let mut materials = CgfxDict::new();
+        
+        for material in &self.materials {
+            materials.insert(&material.name, material.to_cgfx()?, CollisionPolicy::Error)?;
+        }
+        
+        let meshes = self.meshes.iter()
+            .map(|bch_mesh| {
+                let mut mesh = Mesh::new(bch_mesh.shape_index, bch_mesh.material_index);
+                mesh.visible = bch_mesh.visible;
+                mesh.render_priority = bch_mesh.render_priority;
+                Some(mesh)
+            })
+            .collect();
+        
+        let common = CgfxModelCommon {
+            cgfx_object_header: object_header("CMDL", Some(self.name.clone())),
+            cgfx_node_header: CgfxNodeHeader::default(),
+            transform_node_header: self.transform.clone(),
+            meshes,
+            materials: (!self.materials.is_empty()).then_some(materials),
+            shapes: self.shapes.iter().cloned().map(Some).collect(),
+            mesh_node_visibilities: None,
+            flags: 0,
+            face_culling: 0,
+            layer_id: 0,
+        };
+        
+        if self.bones.is_empty() {
+            return Ok(CgfxModel::Standard(common));
+        }
+        
+        let mut bones = BoneDict::new();
+        
+        for (i, bone) in self.bones.iter().enumerate() {
+            bones.insert(&bone.name, bone.to_cgfx(i.try_into()?), CollisionPolicy::Error)?;
+        }
+        
+        Ok(CgfxModel::Skeletal(common, CgfxSkeleton::new(bones)))
+    }
+}
+
+/// Contents of a BCH file that have a CGFX equivalent
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BchScene {
+    pub models: Vec<BchModel>,
+    pub textures: Vec<BchTexture>,
+}
+
+impl BchScene {
+    pub fn from_container(container: &CgfxContainer) -> Result<Self> {
+        let models = container.models.iter()
+            .flat_map(|models| models.values())
+            .map(BchModel::from_cgfx)
+            .collect::<Result<Vec<BchModel>>>()?;
+        
+        let textures = container.textures.iter()
+            .flat_map(|textures| textures.values())
+            .map(BchTexture::from_cgfx)
+            .collect::<Result<Vec<BchTexture>>>()?;
+        
+        Ok(Self { models, textures })
+    }
+    
+    pub fn to_container(&self) -> Result<CgfxContainer> {
+        let mut container = CgfxContainer::empty();
+        
+        for model in &self.models {
+            container.add_model(model.to_cgfx()?, CollisionPolicy::Error)?;
+        }
+        
+        for texture in &self.textures {
+            container.add_texture(texture.to_cgfx()?, CollisionPolicy::Error)?;
+        }
+        
+        Ok(container)
+    }
+}
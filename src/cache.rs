@@ -0,0 +1,318 @@
+// memoizing expensive results like decoded textures and vertex attributes, so that
+// tools reloading the same file (or files sharing the same textures) only decode
+// everything once. entries are keyed by a hash of everything the result depends
+// on, so they never have to be invalidated, a changed input just misses the cache
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::ErrorKind,
+    path::PathBuf,
+    process,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use anyhow::{anyhow, Result};
+use byteorder::{ByteOrder, LittleEndian};
+
+use crate::{
+    image_codec::{bytes_to_colors, colors_to_bytes},
+    model::mesh::{AttributeName, AttributeStorage, Shape},
+    texture::{CgfxTexture, DecodedTexture},
+};
+
+// part of every key, bump it whenever the encoding of cached values changes
+const CACHE_VERSION: u32 = 1;
+
+/// md5 hash of the inputs of a cached operation
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CacheKey(pub [u8; 16]);
+
+impl CacheKey {
+    fn new(operation: &str, parts: &[&[u8]]) -> Self {
+        let mut context = md5::Context::new();
+        context.consume(CACHE_VERSION.to_le_bytes());
+        context.consume(operation);
+        
+        for part in parts {
+            // length prefix, so that different splits of the same bytes don't collide
+            context.consume((part.len() as u64).to_le_bytes());
+            context.consume(part);
+        }
+        
+        Self(context.finalize().0)
+    }
+    
+    pub fn to_hex(self) -> String {
+        self.0.iter().map(|byte| format!("{byte:02x}")).collect()
+    }
+}
+
+/// Storage for cached values. Failing to read an entry is treated like a miss
+pub trait CacheBackend {
+    fn get(&mut self, key: CacheKey) -> Option<Vec<u8>>;
+    fn insert(&mut self, key: CacheKey, value: Vec<u8>) -> Result<()>;
+}
+
+/// Keeps all entries in memory, for the lifetime of a single session
+#[derive(Clone, Debug, Default)]
+pub struct MemoryCache {
+    entries: HashMap<CacheKey, Vec<u8>>,
+}
+
+impl MemoryCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Total size of all cached values in bytes
+    pub fn byte_size(&self) -> usize {
+        self.entries.values().map(Vec::len).sum()
+    }
+    
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+impl CacheBackend for MemoryCache {
+    fn get(&mut self, key: CacheKey) -> Option<Vec<u8>> {
+        self.entries.get(&key).cloned()
+    }
+    
+    fn insert(&mut self, key: CacheKey, value: Vec<u8>) -> Result<()> {
+        self.entries.insert(key, value);
+        Ok(())
+    }
+}
+
+/// Stores every entry as a file named after its key in a directory, so that the
+/// cache survives restarts. The directory gets created when needed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiskCache {
+    directory: PathBuf,
+}
+
+impl DiskCache {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self { directory: directory.into() }
+    }
+    
+    fn path(&self, key: CacheKey) -> PathBuf {
+        self.directory.join(format!("{}.bin", key.to_hex()))
+    }
+    
+    /// Removes all entries, leaving other files in the directory alone
+    pub fn clear(&self) -> Result<()> {
+        let entries = match fs::read_dir(&self.directory) {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(error) => return Err(error.into()),
+        };
+        
+        for entry in entries {
+            let path = entry?.path();
+            let is_entry = path.extension().is_some_and(|extension| extension == "bin")
+                && path.file_stem().is_some_and(|stem| stem.len() == 32);
+            
+            if is_entry {
+                fs::remove_file(path)?;
+            }
+        }
+        
+        Ok(())
+    }
+}
+
+impl CacheBackend for DiskCache {
+    fn get(&mut self, key: CacheKey) -> Option<Vec<u8>> {
+        fs::read(self.path(key)).ok()
+    }
+    
+    fn insert(&mut self, key: CacheKey, value: Vec<u8>) -> Result<()> {
+        fs::create_dir_all(&self.directory)?;
+        
+        // write to a temporary file first, so that readers never see half an entry.
+        // its name is unique to this write, so that writers of the same key don't
+        // write into each other's files
+        static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+        
+        let path = self.path(key);
+        let temp_name = format!("{}.{}-{}.tmp", key.to_hex(), process::id(), TEMP_COUNTER.fetch_add(1, Ordering::Relaxed));
+        let temp_path = self.directory.join(temp_name);
+        fs::write(&temp_path, value)?;
+        fs::rename(temp_path, path)?;
+        
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: usize,
+    pub misses: usize,
+}
+
+/// Memoizes texture decoding and attribute extraction through a [`CacheBackend`]
+///
+/// ```
+/// use ctr_bcres::{cache::{ExtractionCache, MemoryCache}, cgfx_container::CgfxContainer, fixtures};
+///
+/// let container = CgfxContainer::new(&fixtures::minimal_bcres()).unwrap();
+/// let texture = container.textures.as_ref().unwrap().values().next().unwrap();
+///
+/// let mut cache = ExtractionCache::new(MemoryCache::new());
+/// let decoded = cache.decode_texture(texture).unwrap();
+///
+/// assert_eq!(cache.decode_texture(texture).unwrap(), decoded);
+/// assert_eq!(cache.stats().hits, 1);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ExtractionCache<B: CacheBackend> {
+    backend: B,
+    stats: CacheStats,
+}
+
+impl<B: CacheBackend> ExtractionCache<B> {
+    pub fn new(backend: B) -> Self {
+        Self { backend, stats: CacheStats::default() }
+    }
+    
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+    
+    pub fn into_backend(self) -> B {
+        self.backend
+    }
+    
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+    
+    fn memoize<T>(&mut self, key: CacheKey, decode: impl FnOnce(&[u8]) -> Option<T>, encode: impl FnOnce(&T) -> Vec<u8>,
+        compute: impl FnOnce() -> Result<T>) -> Result<T>
+    {
+        // entries that don't decode, like ones written by another version, count as misses
+        if let Some(value) = self.backend.get(key).and_then(|bytes| decode(&bytes)) {
+            self.stats.hits += 1;
+            return Ok(value);
+        }
+        
+        self.stats.misses += 1;
+        
+        let value = compute()?;
+        self.backend.insert(key, encode(&value))?;
+        
+        Ok(value)
+    }
+    
+    /// Same as [`CgfxTexture::decode`]. The key only depends on the format, size and
+    /// image data, so textures with the same content share an entry
+    pub fn decode_texture(&mut self, texture: &CgfxTexture) -> Result<DecodedTexture> {
+        let common = texture.metadata();
+        let header = [
+            u32::from(matches!(texture, CgfxTexture::Cube(..))),
+            common.texture_format as u32,
+            common.width,
+            common.height,
+        ].map(u32::to_le_bytes).concat();
+        
+        let images = texture.images();
+        let parts: Vec<&[u8]> = [header.as_slice()].into_iter()
+            .chain(images.iter().map(|image| &*image.image_bytes))
+            .collect();
+        
+        let key = CacheKey::new("decode_texture", &parts);
+        let name = common.cgfx_object_header.name.clone();
+        
+        let mut decoded = self.memoize(key, decode_texture_entry, encode_texture_entry, || texture.decode())?;
+        decoded.name = name;
+        
+        Ok(decoded)
+    }
+    
+    /// Same as [`Shape::attribute`]. Fixed attributes only hold a single value and
+    /// don't go through the cache
+    pub fn attribute(&mut self, shape: &Shape, name: AttributeName) -> Result<Option<Vec<f32>>> {
+        let layout = shape.layout();
+        
+        let Some(attribute) = layout.attribute(name).filter(|attribute| attribute.storage != AttributeStorage::Fixed) else {
+            return shape.attribute(name);
+        };
+        
+        let Some(raw_bytes) = shape.vertex_buffers[attribute.buffer_index].as_ref().and_then(|buffer| buffer.raw_bytes()) else {
+            return shape.attribute(name);
+        };
+        
+        let descriptor = [
+            name as u32,
+            attribute.format as u32,
+            attribute.elements,
+            attribute.scale.to_bits(),
+            attribute.offset,
+            attribute.stride,
+        ].map(u32::to_le_bytes).concat();
+        
+        let key = CacheKey::new("attribute", &[&descriptor, raw_bytes]);
+        
+        let compute = || shape.attribute(name)?.ok_or_else(|| anyhow!("Shape has no attribute {name:?}"));
+        self.memoize(key, decode_f32_entry, |values| encode_f32_entry(values), compute).map(Some)
+    }
+}
+
+fn encode_texture_entry(texture: &DecodedTexture) -> Vec<u8> {
+    let mut bytes = [texture.width, texture.height, texture.images.len() as u32].map(u32::to_le_bytes).concat();
+    
+    for image in &texture.images {
+        bytes.extend((image.len() as u32).to_le_bytes());
+        bytes.extend(colors_to_bytes(image));
+    }
+    
+    bytes
+}
+
+fn decode_texture_entry(bytes: &[u8]) -> Option<DecodedTexture> {
+    let mut reader = EntryReader { bytes };
+    let width = reader.u32()?;
+    let height = reader.u32()?;
+    let image_count = reader.u32()?;
+    
+    let images = (0..image_count)
+        .map(|_| {
+            let pixel_count = reader.u32()? as usize;
+            bytes_to_colors(reader.take(pixel_count.checked_mul(4)?)?).ok()
+        })
+        .collect::<Option<Vec<_>>>()?;
+    
+    reader.bytes.is_empty().then_some(DecodedTexture {
+        name: None,
+        width,
+        height,
+        images,
+    })
+}
+
+fn encode_f32_entry(values: &[f32]) -> Vec<u8> {
+    values.iter().flat_map(|value| value.to_le_bytes()).collect()
+}
+
+fn decode_f32_entry(bytes: &[u8]) -> Option<Vec<f32>> {
+    bytes.len().is_multiple_of(4).then(|| bytes.chunks_exact(4).map(LittleEndian::read_f32).collect())
+}
+
+struct EntryReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> EntryReader<'a> {
+    fn take(&mut self, length: usize) -> Option<&'a [u8]> {
+        let (taken, rest) = self.bytes.split_at_checked(length)?;
+        self.bytes = rest;
+        Some(taken)
+    }
+    
+    fn u32(&mut self) -> Option<u32> {
+        self.take(4).map(LittleEndian::read_u32)
+    }
+}
@@ -1,115 +1,793 @@
 use std::{
-    fs, io::{Cursor, Write}, path::Path, str::from_utf8
+    collections::HashMap, fmt::Display, fs, io::{Cursor, ErrorKind, Read, Seek, SeekFrom, Write}, path::Path, str::from_utf8
 };
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, ensure, Context, Result};
 use binrw::{BinRead, BinWrite};
-use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use byteorder::{ByteOrder, LittleEndian, ReadBytesExt, WriteBytesExt};
 
 use crate::{
-    assert_matching, util::{blz::blz_decode, pointer::Pointer}, write_at_pointer, CgfxDict, CgfxNode, WriteContext,
+    error::with_path,
+    image_codec::colors_to_bytes,
+    limits,
+    magic::{DATA_MAGIC, IMAG_MAGIC},
+    plugin::{RawObject, SectionObject},
+    progress::{report_progress, NoProgress, Progress, ProgressStage},
+    texture::{DecodedTexture, MipmapPolicy, PicaTextureFormat, TextureSize},
+    util::{blz::blz_decode, pointer::Pointer, util::{point_here, track_content_end, track_strings_start, RuntimeHandles, SharedBytes}},
+    write_at_pointer, CgfxCollectionValue, CgfxDict, CgfxNode, CollisionPolicy, DictIssue, ReadOptions, SourceSpan,
+    SizeLimitPolicy, WriteContext, WriteOptions, WrittenValue,
 };
 
-use super::{model::CgfxModel, texture::CgfxTexture};
+use super::{
+    model::{material::TextureReference, CgfxModel, ModelDict},
+    texture::{CgfxTexture, FormatMismatch, RecompressConfig, TextureDict, TextureRecompression},
+};
+
+#[cfg(feature = "arena")]
+use crate::util::util::with_arena;
+
+/// Byte order of a file, given by the byte order mark in its header
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ByteOrderMark {
+    LittleEndian,
+    BigEndian,
+}
+
+impl ByteOrderMark {
+    /// Byte order mark of little endian files, which is what the 3ds uses
+    pub const LITTLE_ENDIAN: u16 = 0xfeff;
+    /// Byte order mark of big endian files, when read as little endian
+    pub const BIG_ENDIAN: u16 = 0xfffe;
+    
+    pub fn parse(value: u16) -> Result<Self> {
+        match value {
+            Self::LITTLE_ENDIAN => Ok(ByteOrderMark::LittleEndian),
+            Self::BIG_ENDIAN => Ok(ByteOrderMark::BigEndian),
+            _ => bail!("Invalid byte order mark 0x{value:04x}, the header is probably corrupted"),
+        }
+    }
+    
+    pub fn value(self) -> u16 {
+        match self {
+            ByteOrderMark::LittleEndian => Self::LITTLE_ENDIAN,
+            ByteOrderMark::BigEndian => Self::BIG_ENDIAN,
+        }
+    }
+}
 
 #[derive(Clone, Debug, PartialEq, Eq, Default, BinRead, BinWrite)]
 #[brw(little, magic = b"CGFX")]
 pub struct CgfxHeader {
+    #[br(assert(byte_order_mark == CgfxHeader::BYTE_ORDER_MARK,
+        "Invalid byte order mark 0x{byte_order_mark:04x}, big endian files are not supported"))]
     pub byte_order_mark: u16,
+    #[br(assert(header_length == CgfxHeader::LENGTH,
+        "Invalid header length 0x{header_length:x}, expected 0x{:x}", CgfxHeader::LENGTH))]
     pub header_length: u16,
     pub revision: u32,
     pub file_length: u32,
     pub sections_count: u32,
     
-    #[br(assert(content_magic_number == 0x41544144u32,
+    #[br(assert(content_magic_number == DATA_MAGIC,
         "Invalid magic number for data, expected 'DATA' but got '{}'",
         from_utf8(&content_magic_number.to_le_bytes()).unwrap()))]
     pub content_magic_number: u32,
     pub content_length: u32,
 }
 
+impl CgfxHeader {
+    pub const BYTE_ORDER_MARK: u16 = ByteOrderMark::LITTLE_ENDIAN;
+    /// Length of the header without the DATA section header, the same in every known revision
+    pub const LENGTH: u16 = 20;
+    /// Length of the header including the DATA section header
+    pub const LENGTH_WITH_DATA_HEADER: usize = Self::LENGTH as usize + 8;
+    
+    /// Header of a file that only consists of an empty DATA section.
+    /// The lengths get filled in when writing
+    pub fn new(revision: u32) -> Self {
+        Self {
+            byte_order_mark: Self::BYTE_ORDER_MARK,
+            header_length: Self::LENGTH,
+            revision,
+            file_length: 0,
+            sections_count: 1,
+            content_magic_number: DATA_MAGIC,
+            content_length: 0,
+        }
+    }
+    
+    /// Reads the header at the start of `buffer`. Corrupted byte order marks and header
+    /// lengths and files shorter than their file_length are reported before parsing anything
+    ///
+    /// ```
+    /// use ctr_bcres::{cgfx_container::CgfxHeader, fixtures};
+    ///
+    /// let file = fixtures::minimal_bcres();
+    /// assert_eq!(CgfxHeader::from_buffer(&file).unwrap().revision, 0x5000000);
+    ///
+    /// let error = CgfxHeader::from_buffer(&file[..100]).unwrap_err();
+    /// assert!(error.to_string().starts_with("File is truncated"));
+    /// ```
+    pub fn from_buffer(buffer: &[u8]) -> Result<Self> {
+        read_header(&mut Cursor::new(buffer))
+    }
+    
+    pub fn recompute_header_length(&mut self) {
+        self.header_length = Self::LENGTH;
+    }
+    
+    /// Counts the DATA section, the IMAG section if there is one and all other sections
+    pub fn recompute_sections_count(&mut self, has_image_section: bool, extra_sections_count: usize) -> Result<()> {
+        self.sections_count = (1 + usize::from(has_image_section) + extra_sections_count).try_into()?;
+        Ok(())
+    }
+}
+
+fn write_dict_reference<W: Write + Seek>(writer: &mut W, dict_pointers_location: Pointer, kind: SectionKind, count: usize) -> Result<()> {
+    let reference_offset: Pointer = dict_pointers_location + u32::try_from(kind.index() * 8)?;
+    
+    let current_offset = Pointer::current(writer)?;
+    let relative_offset: Pointer = current_offset - (reference_offset + 4);
+    
+    write_at_pointer(writer, reference_offset, count.try_into()?)?;
+    write_at_pointer(writer, reference_offset + 4, relative_offset.into())?;
+    Ok(())
+}
+
+// format with fewer bits per pixel that keeps the same channels, for SizeLimitPolicy::CompressTextures
+fn compressed_format(format: PicaTextureFormat) -> Option<PicaTextureFormat> {
+    match format {
+        PicaTextureFormat::RGBA8 => Some(PicaTextureFormat::RGBA4),
+        PicaTextureFormat::RGB8 => Some(PicaTextureFormat::RGB565),
+        PicaTextureFormat::LA8 => Some(PicaTextureFormat::LA4),
+        PicaTextureFormat::L8 => Some(PicaTextureFormat::L4),
+        PicaTextureFormat::A8 => Some(PicaTextureFormat::A4),
+        _ => None,
+    }
+}
+
+// where the writer puts a file, either into memory or nowhere when only its size is needed
+trait FileOutput: Write + Seek {
+    // everything written so far, None if it isn't kept
+    fn written(&self) -> Option<&[u8]>;
+}
+
+impl FileOutput for Cursor<&mut Vec<u8>> {
+    fn written(&self) -> Option<&[u8]> {
+        Some(self.get_ref())
+    }
+}
+
+// discards all bytes and only keeps track of the position and length
+#[derive(Clone, Copy, Debug, Default)]
+struct SizeCounter {
+    position: u64,
+    length: u64,
+}
+
+impl Write for SizeCounter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.position += buf.len() as u64;
+        self.length = self.length.max(self.position);
+        Ok(buf.len())
+    }
+    
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for SizeCounter {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.length.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        
+        self.position = position.ok_or_else(|| std::io::Error::new(ErrorKind::InvalidInput, "Invalid seek to a negative position"))?;
+        Ok(self.position)
+    }
+}
+
+impl FileOutput for SizeCounter {
+    fn written(&self) -> Option<&[u8]> {
+        None
+    }
+}
+
+// same as assert_matching, for any output that keeps its bytes
+fn check_matching(writer: &impl FileOutput, original: Option<&[u8]>) {
+    if let (Some(base), Some(written)) = (original, writer.written()) {
+        assert!(written == &base[..written.len()], "Not matching");
+    }
+}
+
+// amount of bytes needed from the start of a file to know its file_length
+const HEADER_PREFIX_LENGTH: usize = 20;
+
+// checks everything in the first HEADER_PREFIX_LENGTH bytes of a file and returns its file_length
+fn file_length_from_prefix(prefix: &[u8]) -> Result<usize> {
+    check_truncation(prefix.len(), HEADER_PREFIX_LENGTH, "its header needs")?;
+    
+    ensure!(&prefix[0..4] == b"CGFX", "Invalid magic number, expected 'CGFX' but got {:?}",
+        String::from_utf8_lossy(&prefix[0..4]));
+    
+    let byte_order_mark = ByteOrderMark::parse(LittleEndian::read_u16(&prefix[4..6]))?;
+    ensure!(byte_order_mark == ByteOrderMark::LittleEndian, "File is big endian, which is not supported");
+    
+    let header_length = LittleEndian::read_u16(&prefix[6..8]);
+    let revision = LittleEndian::read_u32(&prefix[8..12]);
+    ensure!(header_length == CgfxHeader::LENGTH,
+        "Invalid header length 0x{header_length:x} for revision 0x{revision:x}, all known revisions have 0x{:x}",
+        CgfxHeader::LENGTH);
+    
+    let file_length: usize = LittleEndian::read_u32(&prefix[12..16]).try_into()?;
+    ensure!(file_length >= CgfxHeader::LENGTH_WITH_DATA_HEADER,
+        "Invalid file length 0x{file_length:x}, the header alone is 0x{:x} bytes long", CgfxHeader::LENGTH_WITH_DATA_HEADER);
+    
+    Ok(file_length)
+}
+
+fn check_truncation(available: usize, expected: usize, what: &str) -> Result<()> {
+    ensure!(available >= expected,
+        "File is truncated, {what} 0x{expected:x} bytes but only 0x{available:x} bytes are available");
+    Ok(())
+}
+
+// reads the header of the file that starts at the beginning of the cursor
+fn read_header(cursor: &mut Cursor<&[u8]>) -> Result<CgfxHeader> {
+    let buffer: &[u8] = cursor.get_ref();
+    let file_length = file_length_from_prefix(buffer)?;
+    check_truncation(buffer.len(), file_length, "its header says it is")?;
+    
+    Ok(CgfxHeader::read(cursor)?)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SectionKind {
+    Models,
+    Textures,
+    Luts,
+    Materials,
+    Shaders,
+    Cameras,
+    Lights,
+    Fogs,
+    Scenes,
+    SkeletalAnimations,
+    MaterialAnimations,
+    VisibilityAnimations,
+    CameraAnimations,
+    LightAnimations,
+    FogAnimations,
+    Emitters,
+}
+
+impl SectionKind {
+    /// All sections in the order of the dict reference table
+    pub const ALL: [SectionKind; 16] = [
+        SectionKind::Models,
+        SectionKind::Textures,
+        SectionKind::Luts,
+        SectionKind::Materials,
+        SectionKind::Shaders,
+        SectionKind::Cameras,
+        SectionKind::Lights,
+        SectionKind::Fogs,
+        SectionKind::Scenes,
+        SectionKind::SkeletalAnimations,
+        SectionKind::MaterialAnimations,
+        SectionKind::VisibilityAnimations,
+        SectionKind::CameraAnimations,
+        SectionKind::LightAnimations,
+        SectionKind::FogAnimations,
+        SectionKind::Emitters,
+    ];
+    
+    /// Index into the dict reference table
+    pub fn index(self) -> usize {
+        self as usize
+    }
+    
+    pub fn name(self) -> &'static str {
+        match self {
+            SectionKind::Models => "models",
+            SectionKind::Textures => "textures",
+            SectionKind::Luts => "luts",
+            SectionKind::Materials => "materials",
+            SectionKind::Shaders => "shaders",
+            SectionKind::Cameras => "cameras",
+            SectionKind::Lights => "lights",
+            SectionKind::Fogs => "fogs",
+            SectionKind::Scenes => "scenes",
+            SectionKind::SkeletalAnimations => "skeletal_animations",
+            SectionKind::MaterialAnimations => "material_animations",
+            SectionKind::VisibilityAnimations => "visibility_animations",
+            SectionKind::CameraAnimations => "camera_animations",
+            SectionKind::LightAnimations => "light_animations",
+            SectionKind::FogAnimations => "fog_animations",
+            SectionKind::Emitters => "emitters",
+        }
+    }
+}
+
+type DictReferences = [(u32, Option<Pointer>); 16];
+
+// reads the table of (count, absolute dict offset) right after the header
+fn read_dict_references(cursor: &mut Cursor<&[u8]>) -> Result<DictReferences> {
+    let mut dict_references: DictReferences = [Default::default(); 16];
+    
+    for dict_ref in &mut dict_references {
+        let position = Pointer::try_from(&*cursor)?;
+        
+        *dict_ref = (
+            cursor.read_u32::<LittleEndian>()?,
+            Pointer::read(cursor)?.map(|pointer| pointer + position + 4),
+        );
+    }
+    
+    Ok(dict_references)
+}
+
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(section = kind.name())))]
+fn read_dict<T: CgfxCollectionValue>(buffer: &[u8], kind: SectionKind, offset: Option<Pointer>,
+    on_node: impl FnMut() -> Result<()>) -> Result<Option<CgfxDict<T>>>
+{
+    offset
+        .map(|offset| {
+            let mut cursor = Cursor::new(buffer);
+            cursor.set_position(offset.into());
+            
+            let dict = CgfxDict::from_reader_with_progress(&mut cursor, on_node);
+            with_path(dict, || kind.name().to_string(), offset.into())
+        })
+        .transpose()
+}
+
+/// Inconsistency between the dict reference table and the dicts of a file,
+/// see [`CgfxContainer::validate_counts`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CountIssue {
+    /// The reference table and the dict disagree on the amount of entries
+    ReferenceCount { kind: SectionKind, reference_count: u32, dict_count: u32 },
+    /// The reference table has entries for a section, but no dict
+    MissingDict { kind: SectionKind, reference_count: u32 },
+    Dict { kind: SectionKind, issue: DictIssue },
+}
+
+impl Display for CountIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CountIssue::ReferenceCount { kind, reference_count, dict_count } =>
+                write!(f, "{} has {reference_count} entries in the reference table but {dict_count} in its dict", kind.name()),
+            CountIssue::MissingDict { kind, reference_count } =>
+                write!(f, "{} has {reference_count} entries in the reference table but no dict", kind.name()),
+            CountIssue::Dict { kind, issue } => write!(f, "Dict for {}: {issue}", kind.name()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SectionIndex {
+    pub kind: SectionKind,
+    pub count: u32,
+    pub names: Vec<String>,
+}
+
+/// Overview over the contents of a file, see [`CgfxContainer::read_index`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CgfxIndex {
+    pub header: CgfxHeader,
+    /// Only contains sections that are present in the file
+    pub sections: Vec<SectionIndex>,
+}
+
+impl CgfxIndex {
+    pub fn section(&self, kind: SectionKind) -> Option<&SectionIndex> {
+        self.sections.iter().find(|section| section.kind == kind)
+    }
+}
+
+/// Cheap overview over a file, see [`CgfxContainer::summary`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContainerSummary {
+    pub revision: u32,
+    pub file_length: u32,
+    /// Amount of entries of every section that is present in the file
+    pub counts: Vec<(SectionKind, u32)>,
+    pub model_names: Vec<String>,
+    /// Size of the image data of all textures, including mipmaps
+    pub texture_bytes: u64,
+}
+
+impl ContainerSummary {
+    pub fn count(&self, kind: SectionKind) -> u32 {
+        self.counts.iter()
+            .find(|(section, _)| *section == kind)
+            .map_or(0, |&(_, count)| count)
+    }
+}
+
+// whether the texture is a cube, whether its content was decoded, its width, height and
+// mipmap count and the hash of its content. raw bytes are keyed separately from decoded
+// pixels, so they can't be mistaken for them
+type TextureContentKey = (bool, bool, u32, u32, u32, [u8; 16]);
+
+/// Textures removed by [`CgfxContainer::dedupe_textures`]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct TextureDedupe {
+    /// Name of every removed texture and the texture it was merged into
+    pub merged: Vec<(String, String)>,
+    /// Size of the image data of all removed textures
+    pub saved_bytes: u64,
+}
+
+/// Where an entry of a section ended up in a written file, see [`WriteReport`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WrittenObject {
+    pub section: SectionKind,
+    pub name: Option<String>,
+    /// Bytes of the object in the DATA section, including the objects it owns like
+    /// the materials of a model, but not its strings
+    pub span: SourceSpan,
+    /// Image data of the object in the IMAG section, None if it has none
+    pub image_span: Option<SourceSpan>,
+}
+
+/// Byte ranges that the objects of a container occupy in a written file, to find out
+/// which assets take up how much space
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct WriteReport {
+    /// Every entry of every section, in the order they were written
+    pub objects: Vec<WrittenObject>,
+    /// Size of the string table, which all objects share
+    pub string_bytes: u64,
+}
+
+impl WriteReport {
+    /// Bytes an object takes up in both the DATA and the IMAG section
+    pub fn object_bytes(&self, section: SectionKind, name: &str) -> Option<u64> {
+        let object = self.objects.iter()
+            .find(|object| object.section == section && object.name.as_deref() == Some(name))?;
+        
+        Some(object.span.length + object.image_span.map_or(0, |span| span.length))
+    }
+}
+
+/// Size of a file before writing it, see [`CgfxContainer::calculate_size`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WriteSize {
+    pub file_length: u64,
+    /// Magic and span of the DATA section, the IMAG section and all other sections
+    /// in file order, including their headers and the padding between them
+    pub file_sections: Vec<([u8; 4], SourceSpan)>,
+    /// Bytes the entries of every written section take up in the DATA and IMAG
+    /// sections, not counting strings
+    pub section_bytes: Vec<(SectionKind, u64)>,
+}
+
+impl WriteSize {
+    pub fn file_section(&self, magic: &[u8; 4]) -> Option<SourceSpan> {
+        self.file_sections.iter()
+            .find(|(section_magic, _)| section_magic == magic)
+            .map(|&(_, span)| span)
+    }
+    
+    pub fn section_bytes(&self, kind: SectionKind) -> u64 {
+        self.section_bytes.iter()
+            .find(|(section, _)| *section == kind)
+            .map_or(0, |&(_, bytes)| bytes)
+    }
+}
+
+/// Section following the DATA section that this library doesn't know about,
+/// preserved as-is
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawSection {
+    pub magic: [u8; 4],
+    /// Section content, excluding its magic number and length
+    pub data: Vec<u8>,
+}
+
+fn read_extra_sections(buffer: &[u8], header: &CgfxHeader) -> Result<Vec<RawSection>> {
+    let mut offset = usize::from(header.header_length) + usize::try_from(header.content_length)?;
+    let mut sections = Vec::new();
+    
+    // the DATA section is already accounted for
+    for _ in 1..header.sections_count {
+        ensure!(offset + 8 <= buffer.len(),
+            "Expected {} sections but section header at 0x{offset:x} is out of bounds", header.sections_count);
+        
+        let magic: [u8; 4] = buffer[offset..offset + 4].try_into()?;
+        let length: usize = LittleEndian::read_u32(&buffer[offset + 4..offset + 8]).try_into()?;
+        
+        ensure!(length >= 8 && offset + length <= buffer.len(),
+            "Section {:?} at 0x{offset:x} has invalid length 0x{length:x}", String::from_utf8_lossy(&magic));
+        
+        // image data is referenced by pointers and read from there
+        if &magic != IMAG_MAGIC {
+            sections.push(RawSection {
+                magic,
+                data: buffer[offset + 8..offset + length].to_vec(),
+            });
+        }
+        
+        offset += length;
+    }
+    
+    Ok(sections)
+}
+
+// keeps the bytes of every object no plug-in parsed, up to the first of `boundaries` after it.
+// objects past the end of the DATA section (files with a wrong content length) stay unknown
+fn keep_raw_objects(buffer: &[u8], unit_dicts: &mut [Option<CgfxDict<SectionObject>>], boundaries: &[usize],
+    strings_start: usize) -> Result<()>
+{
+    for node in unit_dicts.iter_mut().flatten().flat_map(|dict| dict.nodes.iter_mut()) {
+        let (Some(SectionObject::Unknown { .. }), Some(start)) = (&node.value, node.value_pointer) else {
+            continue;
+        };
+        
+        let start = usize::from(start);
+        let Some(end) = boundaries.iter().find(|&&boundary| boundary > start).map(|&end| end.min(buffer.len())) else {
+            continue;
+        };
+        
+        node.value = Some(SectionObject::Raw(RawObject::from_buffer(buffer, start, end, strings_start)?));
+    }
+    
+    Ok(())
+}
+
+// bytes between the end of everything parsed, usually the strings, which come last,
+// and the end of the DATA section, without the zeroes that pad the IMAG section that follows
+fn read_data_trailing_bytes(buffer: &[u8], header: &CgfxHeader, content_end: usize) -> Result<Vec<u8>> {
+    let data_end = usize::from(header.header_length) + usize::try_from(header.content_length)?;
+    
+    // files with a wrong content length have parsed fine before, so only warn about them
+    let Some(trailing) = buffer.get(content_end..data_end) else {
+        #[cfg(feature = "tracing")]
+        tracing::warn!(content_end, data_end, file_length = buffer.len(),
+            "DATA section length disagrees with where its content ends");
+        
+        return Ok(Vec::new());
+    };
+    
+    let length = trailing.iter().rposition(|&byte| byte != 0).map_or(0, |i| i + 1);
+    
+    Ok(trailing[..length].to_vec())
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct CgfxContainer {
     pub header: CgfxHeader,
     
-    pub models: Option<CgfxDict<CgfxModel>>,
-    pub textures: Option<CgfxDict<CgfxTexture>>,
-    pub luts: Option<CgfxDict<()>>,
-    pub materials: Option<CgfxDict<()>>,
-    pub shaders: Option<CgfxDict<()>>,
-    pub cameras: Option<CgfxDict<()>>,
-    pub lights: Option<CgfxDict<()>>,
-    pub fogs: Option<CgfxDict<()>>,
-    pub scenes: Option<CgfxDict<()>>,
-    pub skeletal_animations: Option<CgfxDict<()>>,
-    pub material_animations: Option<CgfxDict<()>>,
-    pub visibility_animations: Option<CgfxDict<()>>,
-    pub camera_animations: Option<CgfxDict<()>>,
-    pub light_animations: Option<CgfxDict<()>>,
-    pub fog_animations: Option<CgfxDict<()>>,
-    pub emitters: Option<CgfxDict<()>>,
+    pub models: Option<ModelDict>,
+    pub textures: Option<TextureDict>,
+    pub luts: Option<CgfxDict<SectionObject>>,
+    pub materials: Option<CgfxDict<SectionObject>>,
+    pub shaders: Option<CgfxDict<SectionObject>>,
+    pub cameras: Option<CgfxDict<SectionObject>>,
+    pub lights: Option<CgfxDict<SectionObject>>,
+    pub fogs: Option<CgfxDict<SectionObject>>,
+    pub scenes: Option<CgfxDict<SectionObject>>,
+    pub skeletal_animations: Option<CgfxDict<SectionObject>>,
+    pub material_animations: Option<CgfxDict<SectionObject>>,
+    pub visibility_animations: Option<CgfxDict<SectionObject>>,
+    pub camera_animations: Option<CgfxDict<SectionObject>>,
+    pub light_animations: Option<CgfxDict<SectionObject>>,
+    pub fog_animations: Option<CgfxDict<SectionObject>>,
+    pub emitters: Option<CgfxDict<SectionObject>>,
+    
+    /// Sections other than DATA and IMAG, written after the IMAG section
+    pub extra_sections: Vec<RawSection>,
+    /// Unknown bytes some files have between the strings and the end of the DATA section,
+    /// written again after the strings. Zeroes at the end are counted as padding instead
+    pub data_trailing_bytes: Vec<u8>,
+    
+    /// The file this was parsed from, only kept with [`ReadOptions::retain_source`]
+    pub source: Option<SharedBytes>,
+}
+
+impl RuntimeHandles for CgfxContainer {
+    fn clear_runtime_handles(&mut self) {
+        self.models.clear_runtime_handles();
+        self.textures.clear_runtime_handles();
+    }
 }
 
 impl CgfxContainer {
     pub fn load_bcrez(path: &Path) -> Result<Self> {
         let input_file = fs::read(path)
             .map_err(|err| anyhow!("Failed reading bcres file {}: {err}", path.display()))?;
+        
+        Self::from_bcrez_bytes(input_file)
+    }
+    
+    #[cfg(feature = "tokio")]
+    pub async fn load_bcrez_async(path: &Path) -> Result<Self> {
+        let input_file = tokio::fs::read(path).await
+            .map_err(|err| anyhow!("Failed reading bcres file {}: {err}", path.display()))?;
+        
+        Self::from_bcrez_bytes(input_file)
+    }
+    
+    fn from_bcrez_bytes(input_file: Vec<u8>) -> Result<Self> {
         let decoded = match blz_decode(&input_file) {
             Ok(value) => value,
             Err(_) => input_file,
         };
         
-        Ok(CgfxContainer::new(&decoded)?)
+        CgfxContainer::new(&decoded)
+    }
+    
+    /// Reads a file starting at the reader's current position. Only file_length
+    /// bytes are consumed, so this also works on files embedded in archives.
+    pub fn from_reader<R: Read + Seek>(reader: &mut R) -> Result<Self> {
+        let start = reader.stream_position()?;
+        
+        // reading with take instead of read_exact, so that truncated files can report how much is missing
+        let read_buffer = |reader: &mut R| {
+            let mut buffer = Vec::new();
+            reader.take(HEADER_PREFIX_LENGTH as u64).read_to_end(&mut buffer)?;
+            
+            let file_length = file_length_from_prefix(&buffer)?;
+            reader.take((file_length - HEADER_PREFIX_LENGTH) as u64).read_to_end(&mut buffer)?;
+            check_truncation(buffer.len(), file_length, "its header says it is")?;
+            
+            Ok::<Vec<u8>, anyhow::Error>(buffer)
+        };
+        
+        let buffer = read_buffer(reader)
+            .with_context(|| format!("Failed reading CGFX file at offset 0x{start:x}"))?;
+        
+        Self::new(&buffer)
+    }
+    
+    /// Async version of [`CgfxContainer::from_reader`]. Only the reading is done
+    /// asynchronously, parsing happens on the buffer like in [`CgfxContainer::new`].
+    #[cfg(feature = "tokio")]
+    pub async fn from_async_reader<R>(reader: &mut R) -> Result<Self>
+    where
+        R: tokio::io::AsyncRead + tokio::io::AsyncSeek + Unpin,
+    {
+        use tokio::io::{AsyncReadExt, AsyncSeekExt};
+        
+        let start = reader.stream_position().await?;
+        
+        let buffer = async {
+            let mut buffer = Vec::new();
+            (&mut *reader).take(HEADER_PREFIX_LENGTH as u64).read_to_end(&mut buffer).await?;
+            
+            let file_length = file_length_from_prefix(&buffer)?;
+            (&mut *reader).take((file_length - HEADER_PREFIX_LENGTH) as u64).read_to_end(&mut buffer).await?;
+            check_truncation(buffer.len(), file_length, "its header says it is")?;
+            
+            Ok::<Vec<u8>, anyhow::Error>(buffer)
+        }.await.with_context(|| format!("Failed reading CGFX file at offset 0x{start:x}"))?;
+        
+        Self::new(&buffer)
     }
     
     pub fn new(buffer: &[u8]) -> Result<Self> {
-        let mut cursor = Cursor::new(buffer);
+        Self::new_with_progress(buffer, &mut NoProgress)
+    }
+    
+    pub fn new_with_options(buffer: &[u8], options: &ReadOptions) -> Result<Self> {
+        let parse = || limits::with_limits(options.limits, || Self::new(buffer));
+        
+        #[cfg(feature = "arena")]
+        let container = if options.arena { with_arena(buffer.to_vec().into(), parse) } else { parse() };
+        #[cfg(not(feature = "arena"))]
+        let container = parse();
         
-        let header = CgfxHeader::read(&mut cursor)?;
-        let mut dict_references: [(u32, Option<Pointer>); 16] = [Default::default(); 16];
+        let mut container = container?;
+        
+        if options.retain_source {
+            container.source = Some(buffer.to_vec().into());
+        }
         
-        for dict_ref in &mut dict_references {
-            let position = Pointer::try_from(&cursor)?;
+        for texture in container.textures.iter_mut().flat_map(|textures| textures.values_mut()) {
+            #[cfg(feature = "tracing")]
+            if let Some(mismatch) = texture.format_mismatch() {
+                tracing::warn!(texture = texture.metadata().cgfx_object_header.name.as_deref(), ?mismatch,
+                    "Texture format disagrees with its OpenGL format and type");
+            }
             
-            *dict_ref = (
-                cursor.read_u32::<LittleEndian>()?,
-                Pointer::read(&mut cursor)?.map(|pointer| pointer + position + 4),
-            );
+            texture.resolve_format_mismatch(options.format_policy);
         }
         
-        let mut unit_dicts: [Option<CgfxDict<()>>; 16] = Default::default();
+        Ok(container)
+    }
+    
+    /// Names and values of all models, skipping nodes without either
+    pub fn models_iter(&self) -> impl Iterator<Item = (&str, &CgfxModel)> {
+        self.models.iter().flat_map(CgfxDict::entries)
+    }
+    
+    /// Names and values of all textures, skipping nodes without either
+    pub fn textures_iter(&self) -> impl Iterator<Item = (&str, &CgfxTexture)> {
+        self.textures.iter().flat_map(CgfxDict::entries)
+    }
+    
+    /// All textures whose format disagrees with their OpenGL format and type, by name
+    pub fn texture_format_mismatches(&self) -> Vec<(String, FormatMismatch)> {
+        self.textures_iter()
+            .filter_map(|(name, texture)| Some((name.to_string(), texture.format_mismatch()?)))
+            .collect()
+    }
+    
+    /// Original bytes of a parsed dict value, requires [`ReadOptions::retain_source`]
+    pub fn raw<T: CgfxCollectionValue>(&self, node: &CgfxNode<T>) -> Option<&[u8]> {
+        node.raw(self.source.as_deref()?)
+    }
+    
+    /// Reports [`ProgressStage::Parsing`] after every dict node
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(length = buffer.len())))]
+    pub fn new_with_progress(buffer: &[u8], progress: &mut impl Progress) -> Result<Self> {
+        let mut cursor = Cursor::new(buffer);
         
-        for (i, (count, offset)) in dict_references.into_iter().enumerate() {
-            // textures
-            if i == 1 {
-                continue;
-            }
+        let header = read_header(&mut cursor)?;
+        let dict_references = read_dict_references(&mut cursor)?;
+        
+        let total_nodes: usize = dict_references.iter()
+            .filter(|(_, offset)| offset.is_some())
+            .map(|(count, _)| *count as usize + 1)
+            .sum();
+        let mut parsed_nodes = 0;
+        
+        let mut on_node = || {
+            parsed_nodes += 1;
+            report_progress(progress, ProgressStage::Parsing, parsed_nodes, total_nodes)
+        };
+        
+        let references_end: usize = cursor.position().try_into()?;
+        
+        let ((dicts, strings_start), content_end) = track_content_end(|| track_strings_start(|| {
+            let models = read_dict::<CgfxModel>(buffer, SectionKind::Models,
+                dict_references[SectionKind::Models.index()].1, &mut on_node)?;
+            let textures = read_dict::<CgfxTexture>(buffer, SectionKind::Textures,
+                dict_references[SectionKind::Textures.index()].1, &mut on_node)?;
             
-            let dict = match offset {
-                Some(value) => Some(CgfxDict::from_buffer(buffer, value)?),
-                None => None,
-            };
+            let mut unit_dicts: [Option<CgfxDict<SectionObject>>; 16] = Default::default();
             
-            if let Some(dict) = &dict {
-                assert_eq!(dict.nodes.len(), (count + 1).try_into().unwrap());
-            } else {
-                assert_eq!(count, 0);
+            // counts in the reference table aren't needed for parsing, validate_counts checks them
+            for (i, (_, offset)) in dict_references.into_iter().enumerate() {
+                // models and textures
+                if i < 2 {
+                    continue;
+                }
+                
+                unit_dicts[i] = read_dict(buffer, SectionKind::ALL[i], offset, &mut on_node)?;
             }
             
-            unit_dicts[i] = dict;
-        }
+            Ok::<_, anyhow::Error>((models, textures, unit_dicts))
+        }));
+        let (models, textures, mut unit_dicts) = dicts?;
         
-        let mut unit_dicts_iter = unit_dicts.into_iter();
+        let data_end = usize::from(header.header_length) + usize::try_from(header.content_length)?;
+        let strings_start = strings_start.map_or(Ok(data_end), usize::try_from)?;
         
-        let models = match dict_references[0].1 {
-            Some(pointer) => Some(CgfxDict::<CgfxModel>::from_buffer(buffer, pointer)?),
-            None => None,
-        };
+        // objects and dicts start where the object before them ends at the latest
+        let mut boundaries: Vec<usize> = dict_references.iter()
+            .filter_map(|(_, offset)| *offset)
+            .chain(models.iter().flat_map(|dict| dict.nodes.iter().filter_map(|node| node.value_pointer)))
+            .chain(textures.iter().flat_map(|dict| dict.nodes.iter().filter_map(|node| node.value_pointer)))
+            .chain(unit_dicts.iter().flatten().flat_map(|dict| dict.nodes.iter().filter_map(|node| node.value_pointer)))
+            .map(usize::from)
+            .chain([strings_start, data_end])
+            .collect();
+        boundaries.sort_unstable();
         
-        let textures = match dict_references[1].1 {
-            Some(pointer) => Some(CgfxDict::<CgfxTexture>::from_buffer(buffer, pointer)?),
-            None => None,
-        };
+        keep_raw_objects(buffer, &mut unit_dicts, &boundaries, strings_start)?;
+        
+        let content_end = references_end.max(content_end.try_into()?);
+        let data_trailing_bytes = read_data_trailing_bytes(buffer, &header, content_end)?;
+        let extra_sections = read_extra_sections(buffer, &header)?;
+        
+        let mut unit_dicts_iter = unit_dicts.into_iter();
         
         Ok(CgfxContainer {
             header,
@@ -130,143 +808,895 @@ impl CgfxContainer {
             light_animations: unit_dicts_iter.next().unwrap(),
             fog_animations: unit_dicts_iter.next().unwrap(),
             emitters: unit_dicts_iter.next().unwrap(),
+            
+            extra_sections,
+            data_trailing_bytes,
+            source: None,
+        })
+    }
+    
+    /// Quickly lists the names of all objects in all sections without parsing them
+    pub fn read_index(buffer: &[u8]) -> Result<CgfxIndex> {
+        let mut cursor = Cursor::new(buffer);
+        
+        let header = read_header(&mut cursor)?;
+        let dict_references = read_dict_references(&mut cursor)?;
+        
+        let mut sections = Vec::new();
+        
+        for (kind, (count, offset)) in SectionKind::ALL.into_iter().zip(dict_references) {
+            // reading values as () only reads the dict nodes themselves
+            let Some(dict) = read_dict::<()>(buffer, kind, offset, || Ok(()))? else {
+                continue;
+            };
+            
+            sections.push(SectionIndex {
+                kind,
+                count,
+                names: dict.nodes.into_iter().filter_map(|node| node.name).collect(),
+            });
+        }
+        
+        Ok(CgfxIndex {
+            header,
+            sections,
         })
     }
     
+    /// Quick overview of a file for listing many of them, only reads the dicts
+    /// and the headers of the textures' images
+    pub fn summary(buffer: &[u8]) -> Result<ContainerSummary> {
+        let index = Self::read_index(buffer)?;
+        
+        let mut cursor = Cursor::new(buffer);
+        read_header(&mut cursor)?;
+        let dict_references = read_dict_references(&mut cursor)?;
+        
+        let texture_offset = dict_references[SectionKind::Textures.index()].1;
+        let texture_bytes = read_dict::<TextureSize>(buffer, SectionKind::Textures, texture_offset, || Ok(()))?
+            .map_or(0, |textures| textures.values().map(|size| u64::from(size.0)).sum());
+        
+        Ok(ContainerSummary {
+            revision: index.header.revision,
+            file_length: index.header.file_length,
+            counts: index.sections.iter().map(|section| (section.kind, section.count)).collect(),
+            model_names: index.section(SectionKind::Models)
+                .map(|section| section.names.clone())
+                .unwrap_or_default(),
+            texture_bytes,
+        })
+    }
+    
+    /// Compares the counts in the dict reference table with the dicts and checks every
+    /// dict for inconsistencies, without parsing any values. Only fails if the file can't
+    /// be read at all, an empty list means everything is consistent.
+    pub fn validate_counts(buffer: &[u8]) -> Result<Vec<CountIssue>> {
+        let mut cursor = Cursor::new(buffer);
+        
+        read_header(&mut cursor)?;
+        let dict_references = read_dict_references(&mut cursor)?;
+        
+        let mut issues = Vec::new();
+        
+        for (kind, (reference_count, offset)) in SectionKind::ALL.into_iter().zip(dict_references) {
+            let Some(dict) = read_dict::<()>(buffer, kind, offset, || Ok(()))? else {
+                if reference_count != 0 {
+                    issues.push(CountIssue::MissingDict { kind, reference_count });
+                }
+                
+                continue;
+            };
+            
+            if dict.values_count != reference_count {
+                issues.push(CountIssue::ReferenceCount { kind, reference_count, dict_count: dict.values_count });
+            }
+            
+            issues.extend(dict.validate().into_iter().map(|issue| CountIssue::Dict { kind, issue }));
+        }
+        
+        Ok(issues)
+    }
+    
+    /// Parses only the models of a file, skipping textures and everything else
+    pub fn read_models(buffer: &[u8]) -> Result<Option<ModelDict>> {
+        let mut cursor = Cursor::new(buffer);
+        
+        read_header(&mut cursor)?;
+        let dict_references = read_dict_references(&mut cursor)?;
+        
+        read_dict(buffer, SectionKind::Models, dict_references[SectionKind::Models.index()].1, || Ok(()))
+    }
+    
+    /// Only parses the model `name`, see [`CgfxDict::read_value_on_demand`]
+    pub fn read_model(buffer: &[u8], name: &str) -> Result<Option<CgfxModel>> {
+        let mut cursor = Cursor::new(buffer);
+        
+        read_header(&mut cursor)?;
+        let dict_references = read_dict_references(&mut cursor)?;
+        
+        let Some(offset) = dict_references[SectionKind::Models.index()].1 else {
+            return Ok(None);
+        };
+        
+        cursor.set_position(offset.into());
+        let model = ModelDict::read_value_on_demand(&mut cursor, name);
+        with_path(model, || SectionKind::Models.name().to_string(), offset.into())
+    }
+    
+    /// Decodes every texture in the container, reporting [`ProgressStage::DecodingTextures`]
+    /// after each one
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub fn decode_textures(&self, progress: &mut impl Progress) -> Result<Vec<DecodedTexture>> {
+        let Some(textures) = &self.textures else {
+            return Ok(Vec::new());
+        };
+        
+        let total = textures.values_count as usize;
+        let mut decoded = Vec::with_capacity(total);
+        
+        for texture in textures.nodes.iter().filter_map(|node| node.value.as_ref()) {
+            decoded.push(texture.decode()?);
+            report_progress(progress, ProgressStage::DecodingTextures, decoded.len(), total)?;
+        }
+        
+        Ok(decoded)
+    }
+    
+    /// Adds a texture under its own name, returning the name it ended up under
+    pub fn add_texture(&mut self, texture: CgfxTexture, policy: CollisionPolicy) -> Result<String> {
+        let name = texture.metadata().cgfx_object_header.name.clone()
+            .ok_or_else(|| anyhow!("Texture has no name"))?;
+        
+        let textures = self.textures.get_or_insert_with(CgfxDict::new);
+        let new_name = textures.insert(&name, texture, policy)?;
+        
+        if new_name != name {
+            let texture = textures.get_mut(&new_name).unwrap();
+            texture.metadata_mut().cgfx_object_header.name = Some(new_name.clone());
+        }
+        
+        Ok(new_name)
+    }
+    
+    /// Adds a model under its own name, returning the name it ended up under
+    pub fn add_model(&mut self, model: CgfxModel, policy: CollisionPolicy) -> Result<String> {
+        let name = model.common().cgfx_object_header.name.clone()
+            .ok_or_else(|| anyhow!("Model has no name"))?;
+        
+        let models = self.models.get_or_insert_with(CgfxDict::new);
+        let new_name = models.insert(&name, model, policy)?;
+        
+        if new_name != name {
+            let model = models.get_mut(&new_name).unwrap();
+            model.common_mut().cgfx_object_header.name = Some(new_name.clone());
+        }
+        
+        Ok(new_name)
+    }
+    
+    /// Models attached to the model `name` as its children, skipping children
+    /// that aren't models of this container
+    pub fn child_models(&self, name: &str) -> Result<Vec<&CgfxModel>> {
+        let models = self.models.as_ref()
+            .ok_or_else(|| anyhow!("Container has no models"))?;
+        let model = models.get(name)
+            .ok_or_else(|| anyhow!("Container has no model named {name:?}"))?;
+        
+        Ok(model.common().cgfx_node_header.children.iter()
+            .flatten()
+            .filter_map(|child| models.get(child.name.as_deref()?))
+            .collect())
+    }
+    
+    /// Moves all textures and models of `other` into this container. Textures that
+    /// get renamed because of `policy` are also renamed in the materials of `other`
+    pub fn merge(&mut self, other: CgfxContainer, policy: CollisionPolicy) -> Result<()> {
+        for (kind, dict) in other.unit_dicts() {
+            if let Some(dict) = dict {
+                ensure!(dict.is_empty(), "Merging {} is not supported yet", kind.name());
+            }
+        }
+        
+        let mut texture_renames: HashMap<String, String> = HashMap::new();
+        
+        for node in other.textures.into_iter().flat_map(|textures| textures.nodes) {
+            let Some(texture) = node.value else {
+                continue;
+            };
+            
+            let name = texture.metadata().cgfx_object_header.name.clone();
+            let new_name = self.add_texture(texture, policy)?;
+            
+            if let Some(name) = name.filter(|name| *name != new_name) {
+                texture_renames.insert(name, new_name);
+            }
+        }
+        
+        for node in other.models.into_iter().flat_map(|models| models.nodes) {
+            let Some(mut model) = node.value else {
+                continue;
+            };
+            
+            let mappers = model.common_mut().materials.iter_mut()
+                .flat_map(|materials| materials.values_mut())
+                .flat_map(|material| material.texture_mappers.iter_mut().flatten());
+            
+            for reference in mappers.filter_map(|mapper| mapper.texture.as_mut()) {
+                // indices into the texture dict of `other` don't mean anything here
+                reference.texture_index = None;
+                
+                let Some(path) = reference.path.as_mut() else {
+                    continue;
+                };
+                
+                if let Some(new_path) = texture_renames.get(path) {
+                    path.clone_from(new_path);
+                }
+            }
+            
+            self.add_model(model, policy)?;
+        }
+        
+        Ok(())
+    }
+    
+    /// Re-encodes the textures of the container, for example into ETC1 to save space.
+    /// See [`TextureDict::recompress`]
+    pub fn recompress_textures(&mut self, config: &RecompressConfig) -> Result<TextureRecompression> {
+        match &mut self.textures {
+            Some(textures) => textures.recompress(config),
+            None => Ok(TextureRecompression::default()),
+        }
+    }
+    
+    /// Merges textures with the same dimensions, mipmap count and decoded pixels into
+    /// the first of them, even if they are encoded in different formats. Textures that
+    /// can't be decoded only get merged with ones that have the same format and bytes,
+    /// and render targets are never merged. The materials of all models get pointed to
+    /// the texture that was kept, and references that were already resolved are
+    /// resolved again, since removing textures shifts the index of the ones after them
+    pub fn dedupe_textures(&mut self) -> Result<TextureDedupe> {
+        let Some(textures) = &mut self.textures else {
+            return Ok(TextureDedupe::default());
+        };
+        
+        let mut kept: HashMap<TextureContentKey, String> = HashMap::new();
+        let mut dedupe = TextureDedupe::default();
+        
+        for node in &textures.nodes {
+            let (Some(name), Some(texture)) = (&node.name, &node.value) else {
+                continue;
+            };
+            
+            if texture.is_render_target() {
+                continue;
+            }
+            
+            let common = texture.metadata();
+            let (decoded, content): (bool, Vec<u8>) = match texture.decode() {
+                Ok(decoded) => (true, decoded.images.iter().flat_map(|image| colors_to_bytes(image)).collect()),
+                Err(_) => {
+                    let mut content = (common.texture_format as u32).to_le_bytes().to_vec();
+                    content.extend(texture.images().iter().flat_map(|image| image.image_bytes.iter()));
+                    (false, content)
+                },
+            };
+            
+            let is_cube = matches!(texture, CgfxTexture::Cube(..));
+            let key = (is_cube, decoded, common.width, common.height, common.mipmap_size, md5::compute(&content).0);
+            
+            match kept.get(&key) {
+                Some(kept_name) => {
+                    dedupe.merged.push((name.clone(), kept_name.clone()));
+                    dedupe.saved_bytes += u64::from(texture.size());
+                },
+                None => {
+                    kept.insert(key, name.clone());
+                },
+            }
+        }
+        
+        if dedupe.merged.is_empty() {
+            return Ok(dedupe);
+        }
+        
+        for (name, _) in &dedupe.merged {
+            textures.remove(name)?;
+        }
+        
+        let renames: HashMap<&str, &str> = dedupe.merged.iter()
+            .map(|(name, kept_name)| (name.as_str(), kept_name.as_str()))
+            .collect();
+        
+        let mappers = self.models.iter_mut()
+            .flat_map(|models| models.values_mut())
+            .flat_map(|model| model.common_mut().materials.iter_mut())
+            .flat_map(|materials| materials.values_mut())
+            .flat_map(|material| material.texture_mappers.iter_mut().flatten());
+        
+        for reference in mappers.filter_map(|mapper| mapper.texture.as_mut()) {
+            let Some(path) = reference.path.as_mut() else {
+                continue;
+            };
+            
+            if let Some(kept_name) = renames.get(path.as_str()) {
+                *path = kept_name.to_string();
+            }
+            
+            if reference.texture_index.is_some() {
+                reference.texture_index = textures.nodes.iter().position(|node| node.name.as_ref() == Some(path));
+            }
+        }
+        
+        Ok(dedupe)
+    }
+    
+    /// Links every texture reference in the materials of all models to its entry
+    /// in the texture dict by setting [`TextureReference::texture_index`].
+    ///
+    /// References without a path are left unresolved. If any other reference
+    /// points to a texture that doesn't exist, all of them are listed in the error,
+    /// but the ones that could be resolved are still linked.
+    pub fn resolve_texture_references(&mut self) -> Result<()> {
+        let mut missing: Vec<String> = Vec::new();
+        
+        let textures = &self.textures;
+        let models = self.models.iter_mut().flat_map(|models| models.nodes.iter_mut());
+        
+        for model_node in models {
+            let Some(model) = model_node.value.as_mut() else {
+                continue;
+            };
+            
+            let model_name = model_node.name.as_deref().unwrap_or_default();
+            let materials = model.common_mut().materials.iter_mut().flat_map(|materials| materials.nodes.iter_mut());
+            
+            for material_node in materials {
+                let Some(material) = material_node.value.as_mut() else {
+                    continue;
+                };
+                
+                let material_name = material_node.name.as_deref().unwrap_or_default();
+                let references = material.texture_mappers.iter_mut()
+                    .flatten()
+                    .filter_map(|mapper| mapper.texture.as_mut());
+                
+                for reference in references {
+                    let Some(path) = reference.path.as_deref() else {
+                        reference.texture_index = None;
+                        continue;
+                    };
+                    
+                    reference.texture_index = textures.as_ref()
+                        .and_then(|textures| textures.nodes.iter().position(|node| node.name.as_deref() == Some(path)));
+                    
+                    if reference.texture_index.is_none() {
+                        missing.push(format!("{path:?} (material {material_name:?} of model {model_name:?})"));
+                    }
+                }
+            }
+        }
+        
+        ensure!(missing.is_empty(), "Missing referenced textures: {}", missing.join(", "));
+        Ok(())
+    }
+    
+    /// Restores the consistency of all fields that are derived from other fields,
+    /// like the counts of dicts or the image size of textures, after editing the
+    /// structs directly. File and section lengths are always recomputed when writing
+    pub fn normalize(&mut self) -> Result<()> {
+        self.header.recompute_header_length();
+        
+        if let Some(models) = &mut self.models {
+            models.normalize()?;
+            
+            for model in models.values_mut() {
+                model.normalize()?;
+            }
+        }
+        
+        if let Some(textures) = &mut self.textures {
+            textures.normalize()?;
+            
+            for texture in textures.values_mut() {
+                texture.normalize()?;
+            }
+        }
+        
+        for dict in self.unit_dicts_mut().into_iter().flatten() {
+            dict.normalize()?;
+        }
+        
+        Ok(())
+    }
+    
+    /// The texture a resolved texture reference points to
+    pub fn referenced_texture(&self, reference: &TextureReference) -> Option<&CgfxTexture> {
+        self.textures.as_ref()?.nodes.get(reference.texture_index?)?.value.as_ref()
+    }
+    
+    // every section that can only be read as names for now
+    pub(crate) fn unit_dicts(&self) -> [(SectionKind, &Option<CgfxDict<SectionObject>>); 14] {
+        [
+            (SectionKind::Luts, &self.luts),
+            (SectionKind::Materials, &self.materials),
+            (SectionKind::Shaders, &self.shaders),
+            (SectionKind::Cameras, &self.cameras),
+            (SectionKind::Lights, &self.lights),
+            (SectionKind::Fogs, &self.fogs),
+            (SectionKind::Scenes, &self.scenes),
+            (SectionKind::SkeletalAnimations, &self.skeletal_animations),
+            (SectionKind::MaterialAnimations, &self.material_animations),
+            (SectionKind::VisibilityAnimations, &self.visibility_animations),
+            (SectionKind::CameraAnimations, &self.camera_animations),
+            (SectionKind::LightAnimations, &self.light_animations),
+            (SectionKind::FogAnimations, &self.fog_animations),
+            (SectionKind::Emitters, &self.emitters),
+        ]
+    }
+    
+    pub(crate) fn unit_dicts_mut(&mut self) -> [&mut Option<CgfxDict<SectionObject>>; 14] {
+        [
+            &mut self.luts,
+            &mut self.materials,
+            &mut self.shaders,
+            &mut self.cameras,
+            &mut self.lights,
+            &mut self.fogs,
+            &mut self.scenes,
+            &mut self.skeletal_animations,
+            &mut self.material_animations,
+            &mut self.visibility_animations,
+            &mut self.camera_animations,
+            &mut self.light_animations,
+            &mut self.fog_animations,
+            &mut self.emitters,
+        ]
+    }
+    
+    /// Writes the container using the options it was most likely written with originally.
+    ///
+    /// Any combination of sections can be written. Objects of the other sections are
+    /// written by their [plug-in](crate::plugin) or copied from the bytes kept of them
+    /// when reading, see [`RawObject`]:
+    ///
+    /// ```
+    /// use ctr_bcres::{cgfx_container::{CgfxContainer, SectionKind}, fixtures, CgfxDict};
+    ///
+    /// let mut container = CgfxContainer::new(&fixtures::minimal_bcres()).unwrap();
+    /// container.models = None;
+    /// container.textures = None;
+    /// container.luts = Some(CgfxDict::new());
+    /// container.skeletal_animations = Some(CgfxDict::new());
+    ///
+    /// let buffer = container.to_buffer().unwrap();
+    /// let index = CgfxContainer::read_index(&buffer).unwrap();
+    ///
+    /// let kinds: Vec<SectionKind> = index.sections.iter().map(|section| section.kind).collect();
+    /// assert_eq!(kinds, [SectionKind::Luts, SectionKind::SkeletalAnimations]);
+    ///
+    /// let reread = CgfxContainer::new(&buffer).unwrap();
+    /// assert_eq!(reread.luts, container.luts);
+    /// assert_eq!(reread.to_buffer().unwrap(), buffer);
+    /// ```
     pub fn to_buffer(&self) -> Result<Vec<u8>> {
-        self.to_buffer_debug(None)
+        self.to_buffer_with_options(&WriteOptions::from_container(self))
+    }
+    
+    pub fn to_buffer_with_options(&self, options: &WriteOptions) -> Result<Vec<u8>> {
+        Ok(self.write(options, None)?.0)
+    }
+    
+    /// Writes the container like [`to_buffer_with_options`](Self::to_buffer_with_options)
+    /// and also returns where every object ended up.
+    ///
+    /// ```
+    /// use ctr_bcres::{cgfx_container::{CgfxContainer, SectionKind}, fixtures, WriteOptions};
+    ///
+    /// let container = CgfxContainer::new(&fixtures::minimal_bcres()).unwrap();
+    ///
+    /// let options = WriteOptions::from_container(&container);
+    /// let (buffer, report) = container.to_buffer_with_report(&options).unwrap();
+    ///
+    /// let [model, texture] = &report.objects[..] else { panic!() };
+    /// assert_eq!(model.section, SectionKind::Models);
+    /// assert_eq!(texture.section, SectionKind::Textures);
+    ///
+    /// let image = texture.image_span.unwrap().slice(&buffer).unwrap();
+    /// assert_eq!(image.len(), 256);
+    /// ```
+    pub fn to_buffer_with_report(&self, options: &WriteOptions) -> Result<(Vec<u8>, WriteReport)> {
+        self.write(options, None)
+    }
+    
+    /// Lays out the file [`to_buffer_with_options`](Self::to_buffer_with_options) would
+    /// write without keeping any of its bytes, to check size constraints like fitting
+    /// into the space of the original file before writing it. Mipmaps still get
+    /// encoded, since their size depends on the options. `max_file_length` is applied
+    /// like when writing
+    ///
+    /// ```
+    /// use ctr_bcres::{cgfx_container::CgfxContainer, fixtures, WriteOptions};
+    ///
+    /// let container = CgfxContainer::new(&fixtures::minimal_bcres()).unwrap();
+    ///
+    /// let options = WriteOptions::from_container(&container);
+    /// let size = container.calculate_size(&options).unwrap();
+    ///
+    /// assert_eq!(size.file_length, container.to_buffer_with_options(&options).unwrap().len() as u64);
+    /// assert_eq!(size.file_section(b"IMAG").unwrap().length, 256 + 8);
+    /// ```
+    pub fn calculate_size(&self, options: &WriteOptions) -> Result<WriteSize> {
+        Ok(self.write_to(&mut SizeCounter::default(), options, None)?.1)
     }
     
+    /// Writes the container like [`to_buffer`](Self::to_buffer) and panics as soon as the
+    /// output stops matching `original`. Every object is written right behind the dict or
+    /// object pointing to it, followed by everything it points to, like the files of
+    /// [`fixtures`](crate::fixtures). Files laid out differently by other tools keep
+    /// their content but not their offsets
+    ///
+    /// ```
+    /// use ctr_bcres::{cgfx_container::CgfxContainer, fixtures};
+    ///
+    /// let original = fixtures::minimal_bcres();
+    /// let container = CgfxContainer::new(&original).unwrap();
+    /// assert_eq!(container.to_buffer_debug(Some(&original)).unwrap(), original);
+    /// ```
     pub fn to_buffer_debug(&self, original: Option<&[u8]>) -> Result<Vec<u8>> {
+        Ok(self.write(&WriteOptions::from_container(self), original)?.0)
+    }
+    
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    fn write(&self, options: &WriteOptions, original: Option<&[u8]>) -> Result<(Vec<u8>, WriteReport)> {
         let mut out = Vec::new();
-        let mut writer = Cursor::new(&mut out);
+        let (report, _) = self.write_to(&mut Cursor::new(&mut out), options, original)?;
+        
+        Ok((out, report))
+    }
+    
+    fn write_to<W: FileOutput>(&self, writer: &mut W, options: &WriteOptions, original: Option<&[u8]>)
+        -> Result<(WriteReport, WriteSize)>
+    {
+        let Some(max_file_length) = options.max_file_length else {
+            return self.write_unlimited(writer, options, original);
+        };
+        
+        // the dry run doesn't keep any bytes, so it's cheap compared to writing twice
+        let file_length = self.write_unlimited(&mut SizeCounter::default(), options, None)?.1.file_length;
+        
+        if file_length <= max_file_length {
+            return self.write_unlimited(writer, options, original);
+        }
+        
+        match options.size_limit_policy {
+            SizeLimitPolicy::Error => bail!("File would be 0x{file_length:x} bytes long, \
+                which is more than the limit of 0x{max_file_length:x} bytes"),
+            SizeLimitPolicy::CompressTextures => {
+                let (container, options) = self.compress_textures(options, file_length, max_file_length)?;
+                container.write_unlimited(writer, &options, original)
+            },
+        }
+    }
+    
+    // shrinks textures until the file fits into max_file_length, see SizeLimitPolicy::CompressTextures.
+    // savings are only estimated from the image sizes, the file gets measured again once they add up
+    fn compress_textures(&self, options: &WriteOptions, file_length: u64, max_file_length: u64)
+        -> Result<(CgfxContainer, WriteOptions)>
+    {
+        let mut container = self.clone();
+        let mut options = options.clone();
+        let mut excess = file_length - max_file_length;
+        let mut saved: u64 = 0;
+        
+        let sizes: Vec<u32> = container.textures.iter()
+            .flat_map(|textures| &textures.nodes)
+            .map(|node| node.value.as_ref().map_or(0, CgfxTexture::size))
+            .collect();
+        
+        let mut order: Vec<usize> = (0..sizes.len()).collect();
+        order.sort_by_key(|&i| std::cmp::Reverse(sizes[i]));
+        
+        for strip_mipmaps in [true, false] {
+            for &i in &order {
+                let Some(texture) = container.textures.as_mut().unwrap().nodes[i].value.as_mut() else {
+                    continue;
+                };
+                
+                let name = texture.metadata().cgfx_object_header.name.clone();
+                let common = texture.metadata();
+                let size_before = texture.size();
+                
+                if texture.is_render_target() {
+                    continue;
+                }
+                
+                if strip_mipmaps {
+                    if common.mipmap_size <= 1 || options.mipmap_policy(name.as_deref()) == MipmapPolicy::Strip {
+                        continue;
+                    }
+                    
+                    texture.strip_mipmaps()?;
+                } else {
+                    let Some(format) = compressed_format(common.texture_format) else {
+                        continue;
+                    };
+                    
+                    if !matches!(texture, CgfxTexture::Image(..)) {
+                        continue;
+                    }
+                    
+                    let (width, height) = (common.width, common.height);
+                    let pixels = texture.decode()?.images.swap_remove(0);
+                    texture.set_rgba(width, height, format, &pixels)?;
+                }
+                
+                // regenerated mipmaps would undo the savings
+                if let Some(name) = name {
+                    options.texture_mipmaps.insert(name, MipmapPolicy::Keep);
+                }
+                
+                saved += u64::from(size_before.saturating_sub(texture.size()));
+                
+                if saved >= excess {
+                    let file_length = container.write_unlimited(&mut SizeCounter::default(), &options, None)?.1.file_length;
+                    
+                    if file_length <= max_file_length {
+                        return Ok((container, options));
+                    }
+                    
+                    excess = file_length - max_file_length;
+                    saved = 0;
+                }
+            }
+        }
         
-        self.header.write(&mut writer)?;
-        assert_matching!(writer, original);
+        let file_length = container.write_unlimited(&mut SizeCounter::default(), &options, None)?.1.file_length;
+        bail!("File would be 0x{file_length:x} bytes long even with all textures compressed, \
+            which is more than the limit of 0x{max_file_length:x} bytes")
+    }
+    
+    fn write_unlimited<W: FileOutput>(&self, writer: &mut W, options: &WriteOptions, original: Option<&[u8]>)
+        -> Result<(WriteReport, WriteSize)>
+    {
+        ensure!(options.section_alignment != 0 && options.image_alignment != 0, "Alignments can not be zero");
+        
+        // counts and lengths get patched in once everything else is written
+        let mut header = self.header.clone();
+        header.recompute_header_length();
+        header.write(writer)?;
+        check_matching(writer, original);
         
         // write zeroes for all dicts for now and patch them later
-        let dict_pointers_location = Pointer::try_from(&writer)?;
+        let dict_pointers_location = Pointer::current(writer)?;
         
         for _ in 0..16 {
             writer.write_u32::<LittleEndian>(0)?;
             writer.write_u32::<LittleEndian>(0)?;
         }
         
+        // objects whose bytes weren't kept can't be written, bail instead of silently dropping them
+        for (kind, dict) in self.unit_dicts() {
+            if let Some(dict) = dict {
+                ensure!(dict.values().all(|value| !matches!(value, SectionObject::Unknown { .. })),
+                    "Writing {} is not supported without a plug-in or their raw bytes", kind.name());
+            }
+        }
+        
+        // re-encoding mipmaps is the slowest part of writing, so it's done for all
+        // textures up front instead of while writing them one after another
+        let textures = self.textures.as_ref()
+            .map(|textures| textures.with_mipmap_policies(options))
+            .transpose()?;
+        
         // write main content
-        let mut ctx = WriteContext::new();
+        let mut ctx = WriteContext::with_options(WriteOptions {
+            mipmaps: MipmapPolicy::Keep,
+            texture_mipmaps: HashMap::new(),
+            ..options.clone()
+        });
+        let mut written: Vec<(SectionKind, WrittenValue)> = Vec::new();
         
-        if let Some(textures) = &self.textures {
-            // write reference in dict pointer array above
-            let reference_offset: Pointer = dict_pointers_location + 8;
+        if let Some(models) = &self.models {
+            write_dict_reference(writer, dict_pointers_location, SectionKind::Models, models.len())?;
             
-            let current_offset: Pointer = Pointer::try_from(&writer)?;
-            let relative_offset: Pointer = current_offset - (reference_offset + 4);
-            let count = textures.nodes.len() - 1;
+            #[cfg(feature = "parallel")]
+            if models.shares_vertex_bytes_between_models() {
+                models.to_writer(writer, &mut ctx)?;
+            } else {
+                models.to_writer_parallel(writer, &mut ctx)?;
+            }
             
-            write_at_pointer(&mut writer, reference_offset, count.try_into()?)?;
-            write_at_pointer(&mut writer, reference_offset + 4, relative_offset.into())?;
+            #[cfg(not(feature = "parallel"))]
+            models.to_writer(writer, &mut ctx)?;
             
-            // write dict
-            textures.to_writer(&mut writer, &mut ctx)?;
+            written.extend(ctx.take_written_objects().into_iter().map(|value| (SectionKind::Models, value)));
         }
         
+        if let Some(textures) = &textures {
+            write_dict_reference(writer, dict_pointers_location, SectionKind::Textures, textures.len())?;
+            
+            #[cfg(feature = "parallel")]
+            textures.to_writer_parallel(writer, &mut ctx)?;
+            #[cfg(not(feature = "parallel"))]
+            textures.to_writer(writer, &mut ctx)?;
+            
+            written.extend(ctx.take_written_objects().into_iter().map(|value| (SectionKind::Textures, value)));
+        }
+        
+        for (kind, dict) in self.unit_dicts() {
+            if let Some(dict) = dict {
+                write_dict_reference(writer, dict_pointers_location, kind, dict.len())?;
+                dict.to_writer(writer, &mut ctx)?;
+                written.extend(ctx.take_written_objects().into_iter().map(|value| (kind, value)));
+            }
+        }
+        
+        // metadata of all objects, which isn't part of any object in the report
+        for (location, metadata) in ctx.take_metadata_references() {
+            point_here(writer, location)?;
+            metadata.to_writer(writer, &mut ctx)?;
+        }
+        
+        ctx.take_written_objects();
+        
         // apply string references
-        let string_section_start = Pointer::try_from(&writer)?;
+        let string_section_start = Pointer::current(writer)?;
+        let mut image_section_offset: Option<u64> = None;
         
         for (location, target_string) in ctx.string_references {
-            if let Some(string_offset_usize) = ctx.string_section.find(&target_string) {
+            if let Some(string_offset_usize) = ctx.string_section.find(&format!("{target_string}\0")) {
                 let string_offset = Pointer::from(string_offset_usize) + string_section_start;
                 let relative_offset = string_offset - location;
                 
-                write_at_pointer(&mut writer, location, relative_offset.into())?;
+                write_at_pointer(writer, location, relative_offset.into())?;
             }
         }
         
         // write strings
         writer.write_all(ctx.string_section.as_bytes())?;
+        writer.write_all(&self.data_trailing_bytes)?;
         
-        // apply padding
-        let alignment: i32 = 128;
-        let buffer_size: i32 = writer.position().try_into()?;
-        let padding_size = ((-buffer_size - 8) % alignment + alignment) % alignment; // weird padding calculation
-        
-        writer.write_all(&vec![0u8; padding_size.try_into()?])?;
+        // only write an IMAG section if there is something to put into it
+        // or if the original file had one (even if it was empty)
+        let had_image_section = self.header.sections_count as usize > 1 + self.extra_sections.len();
+        let write_image_section = !ctx.image_references.is_empty() || had_image_section;
+        let data_section_end = writer.stream_position()?;
+        let mut file_sections: Vec<([u8; 4], SourceSpan)> = vec![
+            (DATA_MAGIC.to_le_bytes(), SourceSpan { offset: 20, length: data_section_end - 20 }),
+        ];
         
-        // apply image section references
-        let image_section_offset: Pointer = Pointer::try_from(&writer)? + 8;
+        if write_image_section {
+            // apply padding so that the image data after the IMAG header is aligned
+            let alignment: i64 = options.section_alignment.into();
+            let buffer_size: i64 = writer.stream_position()?.try_into()?;
+            let padding_size = ((-buffer_size - 8) % alignment + alignment) % alignment; // weird padding calculation
+            
+            writer.write_all(&vec![options.pad_byte; padding_size.try_into()?])?;
+            
+            // the DATA section ends where the IMAG header begins
+            let image_section_start = writer.stream_position()?;
+            write_at_pointer(writer, Pointer(24), (image_section_start - 20).try_into()?)?;
+            file_sections[0].1.length = image_section_start - 20;
+            
+            // apply image section references
+            let section_offset: Pointer = Pointer::current(writer)? + 8;
+            image_section_offset = Some(section_offset.into());
+            
+            for (location, image_offset) in ctx.image_references {
+                let absolute_offset = section_offset + image_offset;
+                let relative_offset = absolute_offset - location;
+                
+                write_at_pointer(writer, location, relative_offset.into())?;
+            }
+            
+            check_matching(writer, original);
+            
+            // write image data section
+            let image_section_length: u32 = ctx.image_section.len().try_into()?;
+            
+            writer.write_all(IMAG_MAGIC)?;
+            writer.write_u32::<LittleEndian>(image_section_length + 8)?;
+            
+            writer.write_all(&ctx.image_section)?;
+            file_sections.push((*IMAG_MAGIC, SourceSpan { offset: image_section_start, length: u64::from(image_section_length) + 8 }));
+            
+            check_matching(writer, original);
+        }
         
-        for (location, image_offset) in ctx.image_references {
-            let absolute_offset = image_section_offset + image_offset;
-            let relative_offset = absolute_offset - location;
+        // write unknown sections
+        for section in &self.extra_sections {
+            let section_length: u32 = section.data.len().try_into()?;
+            let offset = writer.stream_position()?;
+            file_sections.push((section.magic, SourceSpan { offset, length: u64::from(section_length) + 8 }));
             
-            write_at_pointer(&mut writer, location, relative_offset.into())?;
+            writer.write_all(&section.magic)?;
+            writer.write_u32::<LittleEndian>(section_length + 8)?;
+            writer.write_all(&section.data)?;
         }
         
-        assert_matching!(writer, original);
+        header.recompute_sections_count(write_image_section, self.extra_sections.len())?;
+        write_at_pointer(writer, Pointer(16), header.sections_count)?;
         
-        // write image data section
-        let image_section_length: u32 = ctx.image_section.len().try_into()?;
+        if !write_image_section {
+            write_at_pointer(writer, Pointer(24), (data_section_end - 20).try_into()?)?;
+        }
         
-        writer.write_all(b"IMAG")?;
-        writer.write_u32::<LittleEndian>(image_section_length + 8)?;
+        let file_length = writer.stream_position()?;
+        write_at_pointer(writer, Pointer(12), file_length.try_into()?)?;
         
-        writer.write_all(&ctx.image_section)?;
+        check_matching(writer, original);
         
-        assert_matching!(writer, original);
-        assert!(writer.get_ref().len() == self.header.file_length as usize,
-            "Written file size does not match expected file size, expected 0x{:x} bytes but got 0x{:x} bytes",
-            self.header.file_length,
-            writer.get_ref().len());
+        // objects inside of other objects are already counted as part of them
+        let objects = written.into_iter()
+            .filter(|(_, value)| value.depth == 0)
+            .map(|(section, value)| WrittenObject {
+                section,
+                name: value.name,
+                span: SourceSpan { offset: value.start, length: value.end - value.start },
+                image_span: value.image_range.zip(image_section_offset)
+                    .map(|((start, end), offset)| SourceSpan { offset: offset + start, length: end - start }),
+            })
+            .collect();
         
-        Ok(out)
-    }
-    
-    #[allow(unused_variables)] // temporary until I figure out how this works
-    pub fn from_single_texture(name: String, orig_reference_bit: u32, texture: CgfxTexture) -> CgfxContainer {
-        let header = CgfxHeader {
-            byte_order_mark: 0xfeff,
-            header_length: 20,
-            revision: 0x5000000,
-            file_length: 0x180 + texture.size(),
-            sections_count: 2,
-            content_magic_number: 0x41544144,
-            content_length: 356,
+        let report = WriteReport {
+            objects,
+            string_bytes: ctx.string_section.len() as u64,
         };
         
-        let name_len =  texture.metadata().cgfx_object_header.name.as_ref()
-            .map_or(0, |name| name.len());
-        
-        // println!("{}: {} {}", texture.metadata().name.as_ref().unwrap_or(&"None".to_string()), (name_len << 3) - 2, orig_reference_bit);
-        
-        let textures = CgfxDict::<CgfxTexture> {
-            magic_number: "DICT".to_string(),
-            tree_length: 44,
-            values_count: 1,
-            nodes: vec![
-                CgfxNode::<CgfxTexture> {
-                    reference_bit: 0xFFFFFFFF,
-                    left_node_index: 1,
-                    right_node_index: 0,
-                    name: None,
-                    value_pointer: None,
-                    value: None,
-                },
-                CgfxNode::<CgfxTexture> {
-                    reference_bit: ((name_len << 3) - 2).try_into().unwrap(),
-                    left_node_index: 0,
-                    right_node_index: 1,
-                    name: Some(name),
-                    value_pointer: None,
-                    value: Some(texture),
-                },
-            ],
+        let mut section_bytes: Vec<(SectionKind, u64)> = Vec::new();
+        
+        for object in &report.objects {
+            let length = object.span.length + object.image_span.map_or(0, |span| span.length);
+            
+            match section_bytes.iter_mut().find(|(kind, _)| *kind == object.section) {
+                Some((_, bytes)) => *bytes += length,
+                None => section_bytes.push((object.section, length)),
+            }
+        }
+        
+        let size = WriteSize {
+            file_length,
+            file_sections,
+            section_bytes,
         };
         
+        Ok((report, size))
+    }
+    
+    /// A container with only `texture` in its texture dict, under the name `name`.
+    /// It has no IMAG section if the texture has no image data, like render targets
+    pub fn from_texture(name: &str, texture: CgfxTexture) -> Result<CgfxContainer> {
+        let has_image_data = !texture.images().is_empty();
+        
+        let mut textures = TextureDict::new();
+        textures.insert(name, texture, CollisionPolicy::Error)?;
+        
+        let mut container = Self::empty();
+        container.header.recompute_sections_count(has_image_data, 0)?;
+        container.textures = Some(textures);
+        Ok(container)
+    }
+    
+    /// Like [`from_texture`](Self::from_texture), but an empty `name` falls back to the
+    /// name of the texture itself, or `"texture"` if that is empty too
+    #[deprecated(note = "the reference bit is computed from the name now, use `CgfxContainer::from_texture`")]
+    pub fn from_single_texture(name: String, _orig_reference_bit: u32, texture: CgfxTexture) -> CgfxContainer {
+        let name = Some(name)
+            .filter(|name| !name.is_empty())
+            .or_else(|| texture.metadata().cgfx_object_header.name.clone().filter(|name| !name.is_empty()))
+            .unwrap_or_else(|| "texture".to_string());
+        
+        Self::from_texture(&name, texture).expect("Dict keys that aren't empty can always be inserted")
+    }
+    
+    /// A container without any sections
+    pub fn empty() -> CgfxContainer {
         CgfxContainer {
-            header,
+            header: CgfxHeader::new(0x5000000),
             
             models: None,
-            textures: Some(textures),
+            textures: None,
             luts: None,
             materials: None,
             shaders: None,
@@ -281,6 +1711,10 @@ impl CgfxContainer {
             light_animations: None,
             fog_animations: None,
             emitters: None,
+            
+            extra_sections: Vec::new(),
+            data_trailing_bytes: Vec::new(),
+            source: None,
         }
     }
 }
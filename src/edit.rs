@@ -0,0 +1,424 @@
+use std::{
+    collections::HashSet,
+    mem,
+    ops::{Deref, DerefMut},
+};
+
+use anyhow::{anyhow, ensure, Result};
+
+use crate::{
+    cgfx_container::{CgfxContainer, SectionKind},
+    model::{material::CgfxMaterial, CgfxModel, CgfxModelCommon},
+    texture::CgfxTexture,
+    CgfxCollectionValue, CgfxDict,
+};
+
+/// A single reversible change to a container.
+///
+/// Applying an edit returns its inverse, so undo and redo only have to keep edits
+/// around instead of copies of the whole container.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ContainerEdit {
+    /// Replaces the texture `name` with `texture`
+    ReplaceTexture { name: String, texture: Box<CgfxTexture> },
+    /// Renames a texture, including all texture references to it
+    RenameTexture { from: String, to: String },
+    RenameModel { from: String, to: String },
+    RenameMaterial { model: String, from: String, to: String },
+    /// Replaces the material `name` of `model` with `material`
+    ReplaceMaterial { model: String, name: String, material: Box<CgfxMaterial> },
+    SetMeshVisibility { model: String, mesh_index: usize, visible: bool },
+    /// Applies all edits in order. If one fails, the ones before are reverted again
+    Batch(Vec<ContainerEdit>),
+}
+
+impl ContainerEdit {
+    /// Applies the edit and returns the edit that reverts it
+    pub fn apply(self, container: &mut CgfxContainer) -> Result<ContainerEdit> {
+        let inverse = match self {
+            ContainerEdit::ReplaceTexture { name, mut texture } => {
+                let current = container.textures.as_mut()
+                    .and_then(|textures| textures.get_mut(&name))
+                    .ok_or_else(|| anyhow!("Texture {name:?} does not exist"))?;
+                
+                mem::swap(current, &mut texture);
+                ContainerEdit::ReplaceTexture { name, texture }
+            },
+            ContainerEdit::RenameTexture { from, to } => {
+                let textures = container.textures.as_mut()
+                    .ok_or_else(|| anyhow!("Texture {from:?} does not exist"))?;
+                
+                textures.rename(&from, &to)?;
+                textures.get_mut(&to).unwrap().metadata_mut().cgfx_object_header.name = Some(to.clone());
+                
+                let mappers = container.models.iter_mut()
+                    .flat_map(|models| models.values_mut())
+                    .flat_map(|model| model.common_mut().materials.iter_mut())
+                    .flat_map(|materials| materials.values_mut())
+                    .flat_map(|material| material.texture_mappers.iter_mut().flatten());
+                
+                for mapper in mappers {
+                    let Some(path) = mapper.texture.as_mut().and_then(|texture| texture.path.as_mut()) else {
+                        continue;
+                    };
+                    
+                    if *path == from {
+                        path.clone_from(&to);
+                    }
+                }
+                
+                ContainerEdit::RenameTexture { from: to, to: from }
+            },
+            ContainerEdit::RenameModel { from, to } => {
+                let models = container.models.as_mut()
+                    .ok_or_else(|| anyhow!("Model {from:?} does not exist"))?;
+                
+                models.rename(&from, &to)?;
+                models.get_mut(&to).unwrap().common_mut().cgfx_object_header.name = Some(to.clone());
+                
+                ContainerEdit::RenameModel { from: to, to: from }
+            },
+            ContainerEdit::RenameMaterial { model, from, to } => {
+                let materials = model_common(container, &model)?.materials.as_mut()
+                    .ok_or_else(|| anyhow!("Material {from:?} does not exist in model {model:?}"))?;
+                
+                materials.rename(&from, &to)?;
+                materials.get_mut(&to).unwrap().cgfx_object_header.name = Some(to.clone());
+                
+                ContainerEdit::RenameMaterial { model, from: to, to: from }
+            },
+            ContainerEdit::ReplaceMaterial { model, name, mut material } => {
+                let current = model_common(container, &model)?.materials.as_mut()
+                    .and_then(|materials| materials.get_mut(&name))
+                    .ok_or_else(|| anyhow!("Material {name:?} does not exist in model {model:?}"))?;
+                
+                mem::swap(current, &mut material);
+                ContainerEdit::ReplaceMaterial { model, name, material }
+            },
+            ContainerEdit::SetMeshVisibility { model, mesh_index, visible } => {
+                let mesh = model_common(container, &model)?.meshes.get_mut(mesh_index)
+                    .and_then(Option::as_mut)
+                    .ok_or_else(|| anyhow!("Mesh {mesh_index} does not exist in model {model:?}"))?;
+                
+                let previous = mem::replace(&mut mesh.visible, visible);
+                ContainerEdit::SetMeshVisibility { model, mesh_index, visible: previous }
+            },
+            ContainerEdit::Batch(edits) => {
+                let mut inverses = Vec::with_capacity(edits.len());
+                
+                for edit in edits {
+                    match edit.apply(container) {
+                        Ok(inverse) => inverses.push(inverse),
+                        Err(err) => {
+                            // inverses of successfully applied edits can't fail
+                            for inverse in inverses.into_iter().rev() {
+                                inverse.apply(container)?;
+                            }
+                            
+                            return Err(err);
+                        },
+                    }
+                }
+                
+                inverses.reverse();
+                ContainerEdit::Batch(inverses)
+            },
+        };
+        
+        Ok(inverse)
+    }
+}
+
+fn model_common<'a>(container: &'a mut CgfxContainer, name: &str) -> Result<&'a mut CgfxModelCommon> {
+    container.models.as_mut()
+        .and_then(|models| models.get_mut(name))
+        .map(|model| model.common_mut())
+        .ok_or_else(|| anyhow!("Model {name:?} does not exist"))
+}
+
+/// What a string passed to [`CgfxContainer::rewrite_strings`] names
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum StringKind {
+    TextureName,
+    /// Name of a model, including the names of child models
+    ModelName,
+    MaterialName,
+    MeshName,
+    ShapeName,
+    MeshNodeName,
+    SkeletonName,
+    BoneName,
+    /// Path of a texture reference in a material, which is the name of the texture it
+    /// points to
+    TexturePath,
+    /// Name of an entry of a section that is only read as names
+    SectionEntry(SectionKind),
+}
+
+struct StringRewriter<'a> {
+    rewrite: &'a mut dyn FnMut(StringKind, &str) -> Option<String>,
+    count: usize,
+}
+
+impl StringRewriter<'_> {
+    fn name(&mut self, kind: StringKind, name: &mut Option<String>) -> Result<()> {
+        let Some(current) = name.as_deref() else {
+            return Ok(());
+        };
+        
+        if let Some(new_name) = (self.rewrite)(kind, current) {
+            ensure!(!new_name.is_empty(), "{kind:?} {current:?} can not be renamed to an empty string");
+            
+            *name = Some(new_name);
+            self.count += 1;
+        }
+        
+        Ok(())
+    }
+    
+    // rewrites the keys of `dict`, but not the names inside its values
+    fn dict_keys<T: CgfxCollectionValue>(&mut self, kind: StringKind, dict: &mut CgfxDict<T>) -> Result<()> {
+        let count = self.count;
+        
+        for node in &mut dict.nodes {
+            self.name(kind, &mut node.name)?;
+        }
+        
+        if self.count == count {
+            return Ok(());
+        }
+        
+        let mut names: HashSet<&str> = HashSet::new();
+        
+        for name in dict.nodes.iter().filter_map(|node| node.name.as_deref()) {
+            ensure!(names.insert(name), "Rewriting {kind:?} strings results in {name:?} twice in the same dict");
+        }
+        
+        dict.rebuild_tree()
+    }
+    
+    fn model(&mut self, model: &mut CgfxModel) -> Result<()> {
+        if let CgfxModel::Skeletal(_, skeleton) = model {
+            self.name(StringKind::SkeletonName, &mut skeleton.cgfx_object_header.name)?;
+            self.dict_keys(StringKind::BoneName, &mut skeleton.bones)?;
+            
+            for bone in skeleton.bones.values_mut() {
+                self.name(StringKind::BoneName, &mut bone.name)?;
+            }
+        }
+        
+        let common = model.common_mut();
+        self.name(StringKind::ModelName, &mut common.cgfx_object_header.name)?;
+        
+        for child in common.cgfx_node_header.children.iter_mut().flatten() {
+            self.name(StringKind::ModelName, &mut child.name)?;
+        }
+        
+        for mesh in common.meshes.iter_mut().flatten() {
+            self.name(StringKind::MeshName, &mut mesh.cgfx_object_header.name)?;
+        }
+        
+        for shape in common.shapes.iter_mut().flatten() {
+            self.name(StringKind::ShapeName, &mut shape.cgfx_object_header.name)?;
+        }
+        
+        if let Some(visibilities) = &mut common.mesh_node_visibilities {
+            self.dict_keys(StringKind::MeshNodeName, visibilities)?;
+            
+            for visibility in visibilities.values_mut() {
+                self.name(StringKind::MeshNodeName, &mut visibility.name)?;
+            }
+        }
+        
+        if let Some(materials) = &mut common.materials {
+            self.dict_keys(StringKind::MaterialName, materials)?;
+            
+            for material in materials.values_mut() {
+                self.name(StringKind::MaterialName, &mut material.cgfx_object_header.name)?;
+                
+                let references = material.texture_mappers.iter_mut()
+                    .flatten()
+                    .filter_map(|mapper| mapper.texture.as_mut());
+                
+                for reference in references {
+                    self.name(StringKind::TexturePath, &mut reference.path)?;
+                    self.name(StringKind::TexturePath, &mut reference.cgfx_object_header.name)?;
+                }
+            }
+        }
+        
+        Ok(())
+    }
+}
+
+impl CgfxContainer {
+    /// Calls `rewrite` with every string in the container that names something, like
+    /// object names, dict keys and texture paths, and replaces the ones it returns a new
+    /// string for. Returns how many strings were replaced. Dict trees are rebuilt, and
+    /// keys and the names of their objects are passed separately, so `rewrite` should
+    /// only depend on the kind and string it gets for references to stay intact.
+    /// Strings in metadata are left alone.
+    ///
+    /// If two keys of a dict end up the same, an error is returned and the strings
+    /// visited until then stay replaced.
+    pub fn rewrite_strings(&mut self, mut rewrite: impl FnMut(StringKind, &str) -> Option<String>) -> Result<usize> {
+        let mut rewriter = StringRewriter { rewrite: &mut rewrite, count: 0 };
+        
+        if let Some(textures) = &mut self.textures {
+            rewriter.dict_keys(StringKind::TextureName, textures)?;
+            
+            for texture in textures.values_mut() {
+                rewriter.name(StringKind::TextureName, &mut texture.metadata_mut().cgfx_object_header.name)?;
+            }
+        }
+        
+        if let Some(models) = &mut self.models {
+            rewriter.dict_keys(StringKind::ModelName, models)?;
+            
+            for model in models.values_mut() {
+                rewriter.model(model)?;
+            }
+        }
+        
+        let kinds = self.unit_dicts().map(|(kind, _)| kind);
+        
+        for (kind, dict) in kinds.into_iter().zip(self.unit_dicts_mut()) {
+            if let Some(dict) = dict {
+                rewriter.dict_keys(StringKind::SectionEntry(kind), dict)?;
+            }
+        }
+        
+        Ok(rewriter.count)
+    }
+    
+    /// Mutable access to the texture `name`, which fixes up the fields derived from its
+    /// size, format and image bytes once it's dropped. See [`TextureMut`]
+    ///
+    /// ```
+    /// use ctr_bcres::{cgfx_container::CgfxContainer, fixtures};
+    ///
+    /// let mut container = CgfxContainer::new(&fixtures::minimal_bcres()).unwrap();
+    ///
+    /// let mut texture = container.texture_mut("fixture_texture").unwrap();
+    /// texture.images_mut()[0].image_bytes = vec![0; 512].into();
+    /// texture.finish().unwrap();
+    ///
+    /// // the image bytes now have a second level
+    /// let texture = container.textures.as_ref().unwrap().get("fixture_texture").unwrap();
+    /// assert_eq!(texture.metadata().mipmap_size, 2);
+    /// assert_eq!(texture.images()[0].buffer_length(), 512);
+    /// ```
+    pub fn texture_mut(&mut self, name: &str) -> Option<TextureMut<'_>> {
+        let texture = self.textures.as_mut()?.get_mut(name)?;
+        Some(TextureMut { texture })
+    }
+}
+
+/// Texture borrowed from a container by [`CgfxContainer::texture_mut`]. When it's
+/// dropped, the amount of mipmaps gets inferred from the length of the image bytes if
+/// it doesn't match anymore, and [`CgfxTexture::normalize`] recomputes everything else,
+/// so that editing the image bytes of a texture can't leave its lengths out of sync
+pub struct TextureMut<'a> {
+    texture: &'a mut CgfxTexture,
+}
+
+impl TextureMut<'_> {
+    /// Fixes up the texture right away, returning the error that dropping would ignore
+    pub fn finish(self) -> Result<()> {
+        sync_texture(self.texture)
+    }
+}
+
+impl Deref for TextureMut<'_> {
+    type Target = CgfxTexture;
+    
+    fn deref(&self) -> &CgfxTexture {
+        self.texture
+    }
+}
+
+impl DerefMut for TextureMut<'_> {
+    fn deref_mut(&mut self) -> &mut CgfxTexture {
+        self.texture
+    }
+}
+
+impl Drop for TextureMut<'_> {
+    fn drop(&mut self) {
+        // only fails for image bytes longer than 4 GiB, which can't be written anyway
+        let _ = sync_texture(self.texture);
+    }
+}
+
+fn sync_texture(texture: &mut CgfxTexture) -> Result<()> {
+    let common = texture.metadata();
+    let (format, width, height) = (common.texture_format, common.width, common.height);
+    
+    let length = texture.images().into_iter()
+        .find(|image| !image.is_render_target())
+        .map(|image| image.image_bytes.len());
+    
+    if let Some(length) = length.filter(|&length| length != format.buffer_size(width, height, common.mipmap_size.max(1))) {
+        // every level makes the image bytes longer, so at most one amount of levels fits
+        if let Some(levels) = (1..=16).find(|&levels| format.buffer_size(width, height, levels) == length) {
+            texture.metadata_mut().mipmap_size = levels;
+        }
+    }
+    
+    texture.normalize()
+}
+
+/// Undo and redo stacks of applied edits
+#[derive(Debug, Clone, Default)]
+pub struct EditHistory {
+    undo_stack: Vec<ContainerEdit>,
+    redo_stack: Vec<ContainerEdit>,
+}
+
+impl EditHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Applies `edit` and records it, clearing everything that could be redone
+    pub fn apply(&mut self, container: &mut CgfxContainer, edit: ContainerEdit) -> Result<()> {
+        let inverse = edit.apply(container)?;
+        
+        self.undo_stack.push(inverse);
+        self.redo_stack.clear();
+        Ok(())
+    }
+    
+    /// Reverts the last edit, returns false if there was nothing to undo
+    pub fn undo(&mut self, container: &mut CgfxContainer) -> Result<bool> {
+        let Some(edit) = self.undo_stack.pop() else {
+            return Ok(false);
+        };
+        
+        self.redo_stack.push(edit.apply(container)?);
+        Ok(true)
+    }
+    
+    /// Applies the last undone edit again, returns false if there was nothing to redo
+    pub fn redo(&mut self, container: &mut CgfxContainer) -> Result<bool> {
+        let Some(edit) = self.redo_stack.pop() else {
+            return Ok(false);
+        };
+        
+        self.undo_stack.push(edit.apply(container)?);
+        Ok(true)
+    }
+    
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+    
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+    
+    pub fn clear(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+    }
+}
@@ -0,0 +1,84 @@
+use std::{
+    error::Error,
+    fmt::Display,
+    io::{Read, Seek},
+};
+
+use anyhow::Result;
+
+use crate::progress::Cancelled;
+
+/// Error while parsing an object, including where in the file it happened
+#[derive(Debug)]
+pub struct ParseError {
+    path: Vec<String>,
+    offset: u64,
+    error: anyhow::Error,
+}
+
+impl ParseError {
+    /// Path of the object that failed to parse, for example
+    /// `models/chr_mario/shapes[2]/vertex_buffers[0]`
+    pub fn path(&self) -> String {
+        let mut path = String::new();
+        
+        for segment in &self.path {
+            if !path.is_empty() && !segment.starts_with('[') {
+                path.push('/');
+            }
+            
+            path.push_str(segment);
+        }
+        
+        path
+    }
+    
+    /// Absolute file offset of the innermost object that failed to parse
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+    
+    /// The original error
+    pub fn inner(&self) -> &anyhow::Error {
+        &self.error
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Failed parsing {} at offset 0x{:x}: {:#}", self.path(), self.offset, self.error)
+    }
+}
+
+impl Error for ParseError {}
+
+/// Adds a path segment to errors coming out of `result`. The offset is only
+/// used if the error doesn't already know where it came from.
+pub(crate) fn with_path<T>(result: Result<T>, segment: impl FnOnce() -> String, offset: u64) -> Result<T> {
+    result.map_err(|error| {
+        if error.is::<Cancelled>() {
+            return error;
+        }
+        
+        match error.downcast::<ParseError>() {
+            Ok(mut parse_error) => {
+                parse_error.path.insert(0, segment());
+                parse_error.into()
+            },
+            Err(error) => ParseError {
+                path: vec![segment()],
+                offset,
+                error,
+            }.into(),
+        }
+    })
+}
+
+/// Reads a field of an object, adding its name to the error path on failure
+pub(crate) fn read_field<R, T>(reader: &mut R, name: &str, read: impl FnOnce(&mut R) -> Result<T>) -> Result<T>
+where
+    R: Read + Seek,
+{
+    let offset = reader.stream_position()?;
+    with_path(read(reader), || name.to_string(), offset)
+}
@@ -0,0 +1,252 @@
+// ETC1 encoding of single 4x4 blocks. every block is split into two halves of 8 pixels,
+// each with a base color and one of eight intensity tables, and every pixel picks the
+// modifier of its half's table that gets closest to its color. the encoder searches
+// base colors and tables by brute force, EncodeQuality decides how many it tries
+
+use crate::image_codec::{EncodeQuality, RgbaColor, ETC1_LUT};
+
+// a half of a block with everything needed to write it
+#[derive(Clone, Copy, Debug)]
+struct HalfEncoding {
+    // quantized to 4 bits in individual mode and 5 bits in differential mode
+    base: [u8; 3],
+    table: u8,
+    modifiers: [u8; 8],
+    error: u32,
+}
+
+// row major indices of the pixels of both halves, in the order ETC1 numbers them.
+// without flip the block is split into a left and right half, with flip into a top
+// and bottom half
+fn half_indices(flip: bool, half: usize) -> [usize; 8] {
+    std::array::from_fn(|i| {
+        let (x, y) = if flip { (i % 4, i / 4 + half * 2) } else { (i / 4 + half * 2, i % 4) };
+        y * 4 + x
+    })
+}
+
+fn expand(value: u8, bits: u32) -> i32 {
+    if bits == 4 {
+        i32::from(value) * 17
+    } else {
+        i32::from(value << 3 | value >> 2)
+    }
+}
+
+fn color_error(pixel: RgbaColor, base: [i32; 3], modifier: i32) -> u32 {
+    [pixel.r, pixel.g, pixel.b].into_iter()
+        .zip(base)
+        .map(|(value, base)| (i32::from(value) - (base + modifier).clamp(0, 255)).pow(2) as u32)
+        .sum()
+}
+
+// best table and modifiers for a half with a fixed base color
+fn encode_half(pixels: &[RgbaColor; 8], base: [u8; 3], bits: u32) -> HalfEncoding {
+    let base_color = base.map(|value| expand(value, bits));
+    let mut best: Option<HalfEncoding> = None;
+    
+    for (table, modifiers) in ETC1_LUT.iter().enumerate() {
+        let mut encoding = HalfEncoding { base, table: table as u8, modifiers: [0; 8], error: 0 };
+        
+        for (i, &pixel) in pixels.iter().enumerate() {
+            let (modifier, error) = modifiers.iter()
+                .map(|&modifier| color_error(pixel, base_color, modifier))
+                .enumerate()
+                .min_by_key(|&(_, error)| error)
+                .unwrap();
+            
+            encoding.modifiers[i] = modifier as u8;
+            encoding.error += error;
+        }
+        
+        if best.is_none_or(|best| encoding.error < best.error) {
+            best = Some(encoding);
+        }
+    }
+    
+    best.unwrap()
+}
+
+// encodings of a half for every base color worth trying, which is the average color
+// and with EncodeQuality::Best also every color next to it
+fn half_candidates(pixels: &[RgbaColor; 8], bits: u32, quality: EncodeQuality) -> Vec<HalfEncoding> {
+    let max = (1u32 << bits) - 1;
+    let sums = pixels.iter().fold([0u32; 3], |[r, g, b], pixel| {
+        [r + u32::from(pixel.r), g + u32::from(pixel.g), b + u32::from(pixel.b)]
+    });
+    let average = sums.map(|sum| ((sum * max + 255 * 4) / (255 * 8)) as i32);
+    
+    let radius = if quality == EncodeQuality::Best { 1 } else { 0 };
+    let mut candidates = Vec::new();
+    
+    for dr in -radius..=radius {
+        for dg in -radius..=radius {
+            for db in -radius..=radius {
+                let base = [average[0] + dr, average[1] + dg, average[2] + db];
+                
+                if base.iter().all(|&value| (0..=max as i32).contains(&value)) {
+                    candidates.push(encode_half(pixels, base.map(|value| value as u8), bits));
+                }
+            }
+        }
+    }
+    
+    candidates
+}
+
+fn best_of(candidates: &[HalfEncoding]) -> HalfEncoding {
+    *candidates.iter().min_by_key(|candidate| candidate.error).unwrap()
+}
+
+// in differential mode, the second base color is stored as a 3 bit signed offset from the first
+fn fits_differential(base0: [u8; 3], base1: [u8; 3]) -> bool {
+    base0.into_iter().zip(base1).all(|(base0, base1)| (-4..=3).contains(&(i32::from(base1) - i32::from(base0))))
+}
+
+fn block_bits(flip: bool, differential: bool, halves: [HalfEncoding; 2]) -> u64 {
+    let [half0, half1] = halves;
+    let mut high = u32::from(flip) | u32::from(differential) << 1
+        | u32::from(half1.table) << 2 | u32::from(half0.table) << 5;
+    
+    // red, green and blue are in the top three bytes
+    for channel in 0..3 {
+        let shift = 24 - channel * 8;
+        let (base0, base1) = (u32::from(half0.base[channel]), u32::from(half1.base[channel]));
+        
+        if differential {
+            high |= base0 << (shift + 3) | (base1.wrapping_sub(base0) & 0x7) << shift;
+        } else {
+            high |= base0 << (shift + 4) | base1 << shift;
+        }
+    }
+    
+    // pixels are numbered column by column, the high bit of every modifier
+    // index is in the upper half of the word and the low bit in the lower half
+    let mut low: u32 = 0;
+    
+    for (half, encoding) in halves.iter().enumerate() {
+        for (i, index) in half_indices(flip, half).into_iter().enumerate() {
+            let pixel = (index % 4) * 4 + index / 4;
+            let modifier = u32::from(encoding.modifiers[i]);
+            
+            low |= (modifier >> 1) << (16 + pixel) | (modifier & 1) << pixel;
+        }
+    }
+    
+    u64::from(high) << 32 | u64::from(low)
+}
+
+pub(crate) fn encode_etc1_color_block(pixels: &[RgbaColor; 16], quality: EncodeQuality) -> u64 {
+    let flips: &[bool] = if quality == EncodeQuality::Fast { &[false] } else { &[false, true] };
+    let mut best: Option<(u32, u64)> = None;
+    
+    for &flip in flips {
+        let halves = [0, 1].map(|half| half_indices(flip, half).map(|index| pixels[index]));
+        let mut encodings: Vec<(bool, [HalfEncoding; 2])> = Vec::new();
+        
+        let differential = halves.map(|half| half_candidates(&half, 5, quality));
+        let best_differential = differential[0].iter()
+            .flat_map(|half0| differential[1].iter().map(move |half1| [*half0, *half1]))
+            .filter(|[half0, half1]| fits_differential(half0.base, half1.base))
+            .min_by_key(|[half0, half1]| half0.error + half1.error);
+        
+        if let Some(halves) = best_differential {
+            encodings.push((true, halves));
+        }
+        
+        // the fast encoder only falls back to individual mode when it has to
+        if encodings.is_empty() || quality != EncodeQuality::Fast {
+            let individual = halves.map(|half| best_of(&half_candidates(&half, 4, quality)));
+            encodings.push((false, individual));
+        }
+        
+        for (differential, halves) in encodings {
+            let error = halves[0].error + halves[1].error;
+            
+            if best.is_none_or(|(best_error, _)| error < best_error) {
+                best = Some((error, block_bits(flip, differential, halves)));
+            }
+        }
+    }
+    
+    best.unwrap().1
+}
+
+pub(crate) fn encode_etc1_alpha_block(pixels: &[RgbaColor; 16]) -> u64 {
+    pixels.iter().enumerate().fold(0, |block, (i, pixel)| {
+        let (x, y) = (i % 4, i / 4);
+        block | u64::from(pixel.a >> 4) << ((x * 4 + y) * 4)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{image_codec::decode_etc1_block, image_metrics::psnr};
+    
+    // 16x16 pixels of slowly changing colors with brightness gradients and a few hard
+    // edges on top, which is the kind of detail ETC1 is made for. split into blocks
+    fn test_blocks() -> Vec<[RgbaColor; 16]> {
+        let pixel = |x: usize, y: usize| {
+            let brightness = (x + y) * 3 + if (x / 5 + y / 7).is_multiple_of(2) { 0 } else { 60 };
+            let channel = |base: usize| (base + brightness).min(255) as u8;
+            
+            RgbaColor::new(channel(20 + x * 4), channel(60 + y * 3), channel(100), (x * 16) as u8)
+        };
+        
+        (0..16).map(|block| {
+            let (block_x, block_y) = (block % 4 * 4, block / 4 * 4);
+            std::array::from_fn(|i| pixel(block_x + i % 4, block_y + i / 4))
+        }).collect()
+    }
+    
+    fn encoded_psnr(blocks: &[[RgbaColor; 16]], quality: EncodeQuality) -> f64 {
+        let decoded: Vec<RgbaColor> = blocks.iter()
+            .flat_map(|block| decode_etc1_block(encode_etc1_color_block(block, quality), None))
+            .collect();
+        let original: Vec<RgbaColor> = blocks.iter()
+            .flatten()
+            .map(|color| RgbaColor::new(color.r, color.g, color.b, 0xFF))
+            .collect();
+        
+        psnr(&original, &decoded).unwrap()
+    }
+    
+    // base colors only have 4 or 5 bits, so solid colors can be a few steps off
+    #[test]
+    fn solid_blocks_are_close_to_exact() {
+        for color in [RgbaColor::new(0, 0, 0, 0xFF), RgbaColor::new(0xFF, 0xFF, 0xFF, 0xFF), RgbaColor::new(0x40, 0x80, 0xC0, 0xFF)] {
+            for quality in [EncodeQuality::Fast, EncodeQuality::Balanced, EncodeQuality::Best] {
+                let decoded = decode_etc1_block(encode_etc1_color_block(&[color; 16], quality), None);
+                
+                for pixel in decoded {
+                    let difference = [pixel.r.abs_diff(color.r), pixel.g.abs_diff(color.g), pixel.b.abs_diff(color.b)];
+                    assert!(difference.iter().all(|&difference| difference <= 6), "{color:?} became {pixel:?} with {quality:?}");
+                }
+            }
+        }
+    }
+    
+    #[test]
+    fn minimum_quality() {
+        let blocks = test_blocks();
+        let [fast, balanced, best] = [EncodeQuality::Fast, EncodeQuality::Balanced, EncodeQuality::Best]
+            .map(|quality| encoded_psnr(&blocks, quality));
+        
+        assert!(fast >= 34.0, "Fast: {fast} dB");
+        assert!(balanced >= 34.5, "Balanced: {balanced} dB");
+        assert!(best >= 35.0, "Best: {best} dB");
+        assert!(fast <= balanced && balanced <= best, "{fast} {balanced} {best} dB");
+    }
+    
+    #[test]
+    fn alpha_round_trip() {
+        for block in test_blocks() {
+            let decoded = decode_etc1_block(encode_etc1_color_block(&block, EncodeQuality::Fast), Some(encode_etc1_alpha_block(&block)));
+            
+            for (original, decoded) in block.iter().zip(decoded) {
+                assert_eq!(decoded.a, original.a >> 4 | original.a & 0xF0);
+            }
+        }
+    }
+}
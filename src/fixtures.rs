@@ -0,0 +1,492 @@
+// small synthetic bcres files for testing, so nobody has to ship game files
+use std::io::Cursor;
+
+use binrw::BinWrite;
+
+use crate::{
+    magic::{self, AnimGroupMemberKind, ModelType, TextureType, VertexBufferKind, CGFX_MAGIC, DATA_MAGIC, DICT_MAGIC, IMAG_MAGIC},
+    model::mesh::{AttributeName, GlDataType, SubMeshSkinning},
+    texture::PicaTextureFormat,
+    util::math::{Mat3x4, Vec3},
+};
+
+pub const TEXTURE_NAME: &str = "fixture_texture";
+pub const MODEL_NAME: &str = "fixture_model";
+pub const MATERIAL_NAME: &str = "fixture_material";
+pub const BONE_NAME: &str = "fixture_root";
+pub const ANIM_GROUP_NAME: &str = "MaterialAnimation";
+pub const ANIM_GROUP_MEMBER_PATH: &str = "Materials[\"fixture_material\"].MaterialColor.Diffuse";
+
+pub const TEXTURE_SIZE: u32 = 8;
+pub const TEXTURE_COLOR: [u8; 4] = [0x40, 0x80, 0xc0, 0xff];
+
+pub const TRIANGLE_POSITIONS: [[f32; 3]; 3] = [
+    [0.0, 0.0, 0.0],
+    [1.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0],
+];
+
+// sizes of structs that are written as all zeroes
+const MATERIAL_COLORS_SIZE: usize = 11 * 16 + 11 * 4 + 4;
+const RASTERIZATION_SIZE: usize = 20;
+const FRAGMENT_OP_SIZE: usize = 80;
+const TEXTURE_COORD_SIZE: usize = 40;
+
+/// Builds a small but complete bcres file in memory. It contains one 8x8 RGBA8
+/// texture ([`TEXTURE_NAME`]) and one skeletal model ([`MODEL_NAME`]) made of
+/// a single triangle, with one material referencing the texture, one bone and
+/// an anim group ([`ANIM_GROUP_NAME`]) animating the color of the material.
+///
+/// ```
+/// use ctr_bcres::{cgfx_container::CgfxContainer, fixtures};
+///
+/// let container = CgfxContainer::new(&fixtures::minimal_bcres()).unwrap();
+/// assert!(container.textures.unwrap().get(fixtures::TEXTURE_NAME).is_some());
+/// ```
+///
+/// The parsed file is compared against `snapshots/minimal_bcres.txt`, so any change
+/// in how a field gets read shows up there. Update the snapshot when that's intended:
+///
+/// ```
+/// use ctr_bcres::{cgfx_container::CgfxContainer, fixtures};
+///
+/// let container = CgfxContainer::new(&fixtures::minimal_bcres()).unwrap();
+/// assert_eq!(format!("{container:#?}\n"), include_str!("../snapshots/minimal_bcres.txt"));
+/// ```
+pub fn minimal_bcres() -> Vec<u8> {
+    let mut b = Builder::default();
+    
+    // header, lengths get patched at the end
+    b.bytes(CGFX_MAGIC);
+    b.u16(0xfeff);
+    b.u16(20);
+    b.u32(0x5000000);
+    let file_length = b.u32(0);
+    b.u32(2);
+    
+    b.u32(DATA_MAGIC);
+    let content_length = b.u32(0);
+    
+    // dict references, only models and textures are present
+    b.u32(1);
+    let models_dict = b.pointer();
+    b.u32(1);
+    let textures_dict = b.pointer();
+    b.zeros(14 * 8);
+    
+    // every dict is followed by its values, which are followed by everything they
+    // point to, in the same order ctr_bcres writes them in
+    b.point_here(models_dict);
+    let model = b.single_entry_dict(MODEL_NAME);
+    b.point_here(model);
+    write_model(&mut b);
+    
+    b.point_here(textures_dict);
+    let texture = b.single_entry_dict(TEXTURE_NAME);
+    b.point_here(texture);
+    let image_bytes = write_texture(&mut b);
+    
+    b.write_strings();
+    
+    // pad so that the image data after the IMAG header is 128 byte aligned
+    while (b.data.len() + 8) % 128 != 0 {
+        b.u8(0);
+    }
+    
+    let image_section = b.data.len();
+    b.patch(content_length, (image_section - 20) as u32);
+    
+    b.bytes(IMAG_MAGIC);
+    b.u32(image_bytes.len() as u32 + 8);
+    b.patch_relative(b.image_pointer.unwrap(), b.data.len());
+    b.bytes(&image_bytes);
+    
+    b.patch(file_length, b.data.len() as u32);
+    b.data
+}
+
+fn write_model(b: &mut Builder) {
+    let model = b.data.len();
+    b.u32(ModelType::Skeletal.into());
+    b.object_header(b"CMDL", MODEL_NAME);
+    
+    // node header
+    b.u32(1);
+    b.u32(1);
+    b.u32(0);
+    b.u32(0);
+    b.u32(1);
+    let anim_groups = b.pointer();
+    
+    // transform
+    b.binrw(&Vec3::new(1.0, 1.0, 1.0));
+    b.binrw(&Vec3::default());
+    b.binrw(&Vec3::default());
+    b.binrw(&Mat3x4::IDENTITY);
+    b.binrw(&Mat3x4::IDENTITY);
+    
+    b.u32(1);
+    let meshes = b.pointer();
+    b.u32(1);
+    let materials = b.pointer();
+    b.u32(1);
+    let shapes = b.pointer();
+    
+    // mesh node visibilities
+    b.u32(0);
+    b.u32(0);
+    
+    b.u32(0);
+    b.u32(0);
+    b.u32(0);
+    
+    let skeleton = b.pointer();
+    
+    // meshes
+    b.point_here(meshes);
+    let mesh = b.pointer();
+    b.point_here(mesh);
+    
+    b.u32(magic::MESH);
+    b.object_header(b"SOBJ", "");
+    b.u32(0);
+    b.u32(0);
+    let parent = b.pointer();
+    b.patch(parent, (model as i32 - parent as i32) as u32);
+    b.u8(1);
+    b.u8(0);
+    b.u16(0);
+    b.u32(0);
+    
+    // materials
+    b.point_here(materials);
+    let material = b.single_entry_dict(MATERIAL_NAME);
+    b.point_here(material);
+    write_material(b);
+    
+    // shapes
+    b.point_here(shapes);
+    let shape = b.pointer();
+    b.point_here(shape);
+    write_shape(b);
+    
+    // skeleton
+    b.point_here(skeleton);
+    b.u32(magic::SKELETON);
+    b.object_header(b"SOBJ", "");
+    b.u32(1);
+    let bones = b.pointer();
+    let root_bone = b.pointer();
+    b.u32(0);
+    b.u32(0);
+    
+    b.point_here(bones);
+    let bone = b.single_entry_dict(BONE_NAME);
+    b.point_here(bone);
+    b.point_here(root_bone);
+    
+    b.string(BONE_NAME);
+    b.u32(0);
+    b.u32(0);
+    b.u32(u32::MAX);
+    b.zeros(4 * 4);
+    b.binrw(&Vec3::new(1.0, 1.0, 1.0));
+    b.binrw(&Vec3::default());
+    b.binrw(&Vec3::default());
+    b.binrw(&Mat3x4::IDENTITY);
+    b.binrw(&Mat3x4::IDENTITY);
+    b.binrw(&Mat3x4::IDENTITY);
+    b.u32(0);
+    b.u32(0);
+    
+    // anim groups
+    b.point_here(anim_groups);
+    let anim_group = b.single_entry_dict(ANIM_GROUP_NAME);
+    b.point_here(anim_group);
+    
+    b.u32(0x80000000);
+    b.string(ANIM_GROUP_NAME);
+    b.u32(0);
+    b.u32(1);
+    let members = b.pointer();
+    b.u32(1);
+    let blend_operations = b.pointer();
+    b.u32(0);
+    
+    b.point_here(members);
+    let member = b.single_entry_dict(ANIM_GROUP_MEMBER_PATH);
+    b.point_here(member);
+    
+    b.u32(AnimGroupMemberKind::MaterialColor.into());
+    b.string(ANIM_GROUP_MEMBER_PATH);
+    b.u32(0);
+    b.u32(0);
+    b.u32(0);
+    b.u32(0);
+    
+    b.point_here(blend_operations);
+    b.u32(0);
+}
+
+fn write_material(b: &mut Builder) {
+    b.u32(magic::MATERIAL);
+    b.object_header(b"MTOB", MATERIAL_NAME);
+    b.u32(0);
+    b.u32(0);
+    b.u32(0);
+    b.zeros(MATERIAL_COLORS_SIZE + RASTERIZATION_SIZE + FRAGMENT_OP_SIZE);
+    
+    b.u32(1);
+    
+    for _ in 0..3 {
+        b.zeros(TEXTURE_COORD_SIZE);
+        b.binrw(&Mat3x4::IDENTITY);
+    }
+    
+    let texture_mapper = b.pointer();
+    b.u32(0);
+    b.u32(0);
+    
+    b.point_here(texture_mapper);
+    b.u32(magic::TEXTURE_MAPPER);
+    b.u32(0);
+    let texture_reference = b.pointer();
+    b.u32(0);
+    b.zeros(14 * 4);
+    b.u32(0);
+    
+    b.point_here(texture_reference);
+    b.u32(magic::TEXTURE_REFERENCE);
+    b.object_header(b"TXOB", TEXTURE_NAME);
+    b.string(TEXTURE_NAME);
+    b.u32(0);
+}
+
+fn write_shape(b: &mut Builder) {
+    b.u32(magic::SHAPE);
+    b.object_header(b"SOBJ", "");
+    b.u32(0);
+    b.u32(0);
+    b.binrw(&Vec3::default());
+    
+    b.u32(1);
+    let sub_meshes = b.pointer();
+    b.u32(0);
+    b.u32(1);
+    let vertex_buffers = b.pointer();
+    b.u32(0);
+    
+    // sub mesh
+    b.point_here(sub_meshes);
+    let sub_mesh = b.pointer();
+    b.point_here(sub_mesh);
+    
+    b.u32(1);
+    let bone_indices = b.pointer();
+    b.u32(SubMeshSkinning::Rigid as u32);
+    b.u32(1);
+    let faces = b.pointer();
+    
+    b.point_here(bone_indices);
+    b.u32(0);
+    
+    // face
+    b.point_here(faces);
+    let face = b.pointer();
+    b.point_here(face);
+    
+    b.u32(1);
+    let face_descriptors = b.pointer();
+    b.u32(0);
+    b.u32(0);
+    b.u32(0);
+    b.u32(0);
+    
+    b.point_here(face_descriptors);
+    let face_descriptor = b.pointer();
+    b.point_here(face_descriptor);
+    
+    b.u32(GlDataType::UByte as u32);
+    b.u8(0);
+    b.u8(1);
+    b.u16(0);
+    b.u32(3);
+    let indices = b.pointer();
+    b.zeros(6 * 4);
+    b.u32(0);
+    
+    b.point_here(indices);
+    b.bytes(&[0, 1, 2, 0]);
+    
+    // vertex buffer
+    b.point_here(vertex_buffers);
+    let vertex_buffer = b.pointer();
+    b.point_here(vertex_buffer);
+    
+    b.u32(VertexBufferKind::Attribute.into());
+    b.u32(AttributeName::Position as u32);
+    b.u32(0);
+    b.u32(0);
+    b.u32(0);
+    b.u32(TRIANGLE_POSITIONS.len() as u32 * 12);
+    let raw_bytes = b.pointer();
+    b.u32(0);
+    b.u32(0);
+    b.u32(GlDataType::Float as u32);
+    b.u32(3);
+    b.f32(1.0);
+    b.u32(0);
+    
+    b.point_here(raw_bytes);
+    
+    for value in TRIANGLE_POSITIONS.iter().flatten() {
+        b.f32(*value);
+    }
+}
+
+fn write_texture(b: &mut Builder) -> Vec<u8> {
+    b.u32(TextureType::Image.into());
+    b.object_header(b"TXOB", TEXTURE_NAME);
+    b.u32(TEXTURE_SIZE);
+    b.u32(TEXTURE_SIZE);
+    b.u32(0);
+    b.u32(0);
+    b.u32(1);
+    b.u32(0);
+    b.u32(0);
+    b.u32(PicaTextureFormat::RGBA8 as u32);
+    
+    let image = b.pointer();
+    b.point_here(image);
+    
+    let image_bytes: Vec<u8> = (0..TEXTURE_SIZE * TEXTURE_SIZE)
+        .flat_map(|_| TEXTURE_COLOR.into_iter().rev())
+        .collect();
+    
+    b.u32(TEXTURE_SIZE);
+    b.u32(TEXTURE_SIZE);
+    b.u32(image_bytes.len() as u32);
+    b.image_pointer = Some(b.pointer());
+    b.u32(0);
+    b.u32(PicaTextureFormat::RGBA8.get_bpp());
+    b.u32(0);
+    b.u32(0);
+    
+    image_bytes
+}
+
+#[derive(Default)]
+struct Builder {
+    data: Vec<u8>,
+    
+    // (pointer location, string)
+    strings: Vec<(usize, &'static str)>,
+    image_pointer: Option<usize>,
+}
+
+impl Builder {
+    fn bytes(&mut self, bytes: &[u8]) -> usize {
+        let location = self.data.len();
+        self.data.extend_from_slice(bytes);
+        location
+    }
+    
+    fn zeros(&mut self, count: usize) {
+        self.data.resize(self.data.len() + count, 0);
+    }
+    
+    fn u8(&mut self, value: u8) -> usize {
+        self.bytes(&[value])
+    }
+    
+    fn u16(&mut self, value: u16) -> usize {
+        self.bytes(&value.to_le_bytes())
+    }
+    
+    fn u32(&mut self, value: u32) -> usize {
+        self.bytes(&value.to_le_bytes())
+    }
+    
+    fn f32(&mut self, value: f32) -> usize {
+        self.bytes(&value.to_le_bytes())
+    }
+    
+    fn binrw<T: for<'a> BinWrite<Args<'a> = ()>>(&mut self, value: &T) {
+        let mut cursor = Cursor::new(Vec::new());
+        value.write_le(&mut cursor).expect("writing to a Vec can not fail");
+        self.data.extend(cursor.into_inner());
+    }
+    
+    // relative pointer that gets patched later
+    fn pointer(&mut self) -> usize {
+        self.u32(0)
+    }
+    
+    fn patch(&mut self, location: usize, value: u32) {
+        self.data[location..location + 4].copy_from_slice(&value.to_le_bytes());
+    }
+    
+    fn patch_relative(&mut self, location: usize, target: usize) {
+        self.patch(location, (target - location) as u32);
+    }
+    
+    fn point_here(&mut self, location: usize) {
+        self.patch_relative(location, self.data.len());
+    }
+    
+    // empty strings are written as null pointers
+    fn string(&mut self, string: &'static str) {
+        let location = self.pointer();
+        
+        if !string.is_empty() {
+            self.strings.push((location, string));
+        }
+    }
+    
+    fn object_header(&mut self, magic: &[u8; 4], name: &'static str) {
+        self.bytes(magic);
+        self.u32(0);
+        self.string(name);
+        self.u32(0);
+        self.u32(0);
+    }
+    
+    // returns the location of the value pointer
+    fn single_entry_dict(&mut self, name: &'static str) -> usize {
+        self.bytes(DICT_MAGIC);
+        self.u32(12 + 2 * 16);
+        self.u32(1);
+        
+        // root node
+        self.u32(0xFFFFFFFF);
+        self.u16(1);
+        self.u16(0);
+        self.u32(0);
+        self.u32(0);
+        
+        self.u32(crate::reference_bit(name, "").unwrap());
+        self.u16(0);
+        self.u16(1);
+        self.string(name);
+        self.pointer()
+    }
+    
+    // every string is only written once, in the order they are first referred to
+    fn write_strings(&mut self) {
+        let mut written: Vec<(&'static str, usize)> = Vec::new();
+        
+        for (location, string) in std::mem::take(&mut self.strings) {
+            let offset = match written.iter().find(|(other, _)| *other == string) {
+                Some(&(_, offset)) => offset,
+                None => {
+                    let offset = self.bytes(string.as_bytes());
+                    self.u8(0);
+                    written.push((string, offset));
+                    offset
+                },
+            };
+            
+            self.patch_relative(location, offset);
+        }
+    }
+}
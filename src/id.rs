@@ -0,0 +1,82 @@
+use std::fmt::Display;
+
+use crate::{
+    cgfx_container::{CgfxContainer, SectionKind},
+    CgfxCollectionValue, CgfxDict,
+};
+
+/// Identifies an entry of one of the sections of a container. Names are what the game
+/// uses to look objects up, so they are preferred over the index when resolving an ID,
+/// which keeps IDs valid when entries get added or reordered.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ObjectId {
+    pub section: SectionKind,
+    /// Index of the entry in its dict, not counting the root node
+    pub index: usize,
+    pub name: Option<String>,
+}
+
+impl ObjectId {
+    pub fn new(section: SectionKind, index: usize, name: Option<String>) -> Self {
+        Self { section, index, name }
+    }
+}
+
+impl Display for ObjectId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "{}/{name}", self.section.name()),
+            None => write!(f, "{}[{}]", self.section.name(), self.index),
+        }
+    }
+}
+
+fn dict_ids<T: CgfxCollectionValue>(section: SectionKind, dict: &Option<CgfxDict<T>>) -> Vec<ObjectId> {
+    let nodes = dict.iter().flat_map(|dict| dict.nodes.iter().skip(1));
+    
+    nodes.enumerate()
+        .map(|(index, node)| ObjectId::new(section, index, node.name.clone()))
+        .collect()
+}
+
+impl CgfxContainer {
+    /// IDs of all entries in the given section, in dict order
+    pub fn section_ids(&self, section: SectionKind) -> Vec<ObjectId> {
+        match section {
+            SectionKind::Models => dict_ids(section, &self.models),
+            SectionKind::Textures => dict_ids(section, &self.textures),
+            SectionKind::Luts => dict_ids(section, &self.luts),
+            SectionKind::Materials => dict_ids(section, &self.materials),
+            SectionKind::Shaders => dict_ids(section, &self.shaders),
+            SectionKind::Cameras => dict_ids(section, &self.cameras),
+            SectionKind::Lights => dict_ids(section, &self.lights),
+            SectionKind::Fogs => dict_ids(section, &self.fogs),
+            SectionKind::Scenes => dict_ids(section, &self.scenes),
+            SectionKind::SkeletalAnimations => dict_ids(section, &self.skeletal_animations),
+            SectionKind::MaterialAnimations => dict_ids(section, &self.material_animations),
+            SectionKind::VisibilityAnimations => dict_ids(section, &self.visibility_animations),
+            SectionKind::CameraAnimations => dict_ids(section, &self.camera_animations),
+            SectionKind::LightAnimations => dict_ids(section, &self.light_animations),
+            SectionKind::FogAnimations => dict_ids(section, &self.fog_animations),
+            SectionKind::Emitters => dict_ids(section, &self.emitters),
+        }
+    }
+    
+    /// IDs of all entries of all sections
+    pub fn object_ids(&self) -> Vec<ObjectId> {
+        SectionKind::ALL.into_iter()
+            .flat_map(|section| self.section_ids(section))
+            .collect()
+    }
+    
+    /// Finds the current ID of the object `id` refers to. Named IDs are looked up
+    /// by name, unnamed ones by index
+    pub fn resolve_id(&self, id: &ObjectId) -> Option<ObjectId> {
+        let ids = self.section_ids(id.section);
+        
+        match &id.name {
+            Some(_) => ids.into_iter().find(|current| current.name == id.name),
+            None => ids.into_iter().nth(id.index).filter(|current| current.name.is_none()),
+        }
+    }
+}
@@ -0,0 +1,126 @@
+// moving channels between decoded images, for converting between PICA textures,
+// which often pack unrelated data into one texture, and pipelines that expect
+// every map in its own texture (or packed differently)
+
+use anyhow::{ensure, Result};
+
+use crate::image_codec::RgbaColor;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Channel {
+    Red,
+    Green,
+    Blue,
+    Alpha,
+}
+
+impl Channel {
+    pub const ALL: [Channel; 4] = [Channel::Red, Channel::Green, Channel::Blue, Channel::Alpha];
+    
+    pub fn get(self, color: RgbaColor) -> u8 {
+        match self {
+            Channel::Red => color.r,
+            Channel::Green => color.g,
+            Channel::Blue => color.b,
+            Channel::Alpha => color.a,
+        }
+    }
+    
+    pub fn set(self, color: &mut RgbaColor, value: u8) {
+        match self {
+            Channel::Red => color.r = value,
+            Channel::Green => color.g = value,
+            Channel::Blue => color.b = value,
+            Channel::Alpha => color.a = value,
+        }
+    }
+}
+
+/// Where a channel of a swizzled image takes its value from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChannelSource {
+    Channel(Channel),
+    Constant(u8),
+}
+
+impl ChannelSource {
+    fn get(self, color: RgbaColor) -> u8 {
+        match self {
+            ChannelSource::Channel(channel) => channel.get(color),
+            ChannelSource::Constant(value) => value,
+        }
+    }
+}
+
+/// Rearranges the channels of every pixel, `sources` is in RGBA order
+pub fn swizzle(pixels: &[RgbaColor], sources: [ChannelSource; 4]) -> Vec<RgbaColor> {
+    pixels.iter()
+        .map(|&color| {
+            let [r, g, b, a] = sources.map(|source| source.get(color));
+            RgbaColor::new(r, g, b, a)
+        })
+        .collect()
+}
+
+/// Copies one channel into an opaque grayscale image, which encodes losslessly
+/// to L8, for example to get the alpha of a texture as a separate mask
+pub fn extract_channel(pixels: &[RgbaColor], channel: Channel) -> Vec<RgbaColor> {
+    pixels.iter().map(|&color| RgbaColor::grayscale(channel.get(color))).collect()
+}
+
+/// Splits an image into four grayscale images, one per channel in RGBA order
+pub fn split_channels(pixels: &[RgbaColor]) -> [Vec<RgbaColor>; 4] {
+    Channel::ALL.map(|channel| extract_channel(pixels, channel))
+}
+
+/// Overwrites channel `into` of every pixel with channel `from` of the same pixel
+/// in `source`, for example to pack a roughness map into the alpha of a texture.
+///
+/// ```
+/// use ctr_bcres::{image_codec::RgbaColor, image_channels::{extract_channel, merge_channel, Channel}};
+///
+/// let mut albedo = vec![RgbaColor::new(200, 100, 50, 255); 4];
+/// let roughness = vec![RgbaColor::grayscale(64); 4];
+///
+/// merge_channel(&mut albedo, &roughness, Channel::Red, Channel::Alpha).unwrap();
+/// assert_eq!(albedo[0], RgbaColor::new(200, 100, 50, 64));
+///
+/// assert_eq!(extract_channel(&albedo, Channel::Alpha), roughness);
+/// ```
+pub fn merge_channel(pixels: &mut [RgbaColor], source: &[RgbaColor], from: Channel, into: Channel) -> Result<()> {
+    ensure!(pixels.len() == source.len(),
+        "Images have different sizes ({} and {} pixels)", pixels.len(), source.len());
+    
+    for (color, &source_color) in pixels.iter_mut().zip(source) {
+        into.set(color, from.get(source_color));
+    }
+    
+    Ok(())
+}
+
+/// Builds an image out of four images, taking channel `from` of image `i` as channel `i`
+/// of the result. Inverse of [`split_channels`] when `from` is [`Channel::Red`]
+pub fn merge_channels(images: [&[RgbaColor]; 4], from: Channel) -> Result<Vec<RgbaColor>> {
+    let len = images[0].len();
+    ensure!(images.iter().all(|image| image.len() == len), "Images have different sizes");
+    
+    let mut pixels = vec![RgbaColor::default(); len];
+    
+    for (channel, image) in Channel::ALL.into_iter().zip(images) {
+        merge_channel(&mut pixels, image, from, channel)?;
+    }
+    
+    Ok(pixels)
+}
+
+/// Recalculates the blue channel of a tangent space normal map from red and green,
+/// which is needed for normal maps stored in two channel formats like HiLo8
+pub fn reconstruct_normal_z(pixels: &mut [RgbaColor]) {
+    for color in pixels {
+        let x = f32::from(color.r) / 127.5 - 1.0;
+        let y = f32::from(color.g) / 127.5 - 1.0;
+        let z = (1.0 - x * x - y * y).max(0.0).sqrt();
+        
+        color.b = ((z + 1.0) * 127.5).round() as u8;
+    }
+}
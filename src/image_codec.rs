@@ -1,12 +1,12 @@
-use std::{cmp::max, io::Cursor, slice::from_raw_parts};
+use std::{cmp::max, io::Cursor};
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, ensure, Result};
 use binrw::{BinRead, BinWrite};
 #[cfg(feature = "bytemuck")]
 use bytemuck::{Pod, Zeroable};
-use byteorder::{LittleEndian, ReadBytesExt};
+use byteorder::{ByteOrder, LittleEndian, ReadBytesExt};
 
-use super::texture::PicaTextureFormat;
+use super::{etc1_encode, image_channels::Channel, texture::PicaTextureFormat};
 
 #[derive(Clone, Copy, Default, Debug, PartialEq, Eq, BinRead, BinWrite)]
 #[cfg_attr(feature = "bytemuck", derive(Pod, Zeroable))]
@@ -52,24 +52,32 @@ impl RgbaColor {
             a: alpha,
         }
     }
+    
+    // rec. 601 luma
+    pub const fn luminance(self) -> u8 {
+        ((self.r as u32 * 299 + self.g as u32 * 587 + self.b as u32 * 114 + 500) / 1000) as u8
+    }
+    
+    /// Channels normalized to 0.0 - 1.0, in RGBA order
+    pub fn to_f32(self) -> [f32; 4] {
+        [self.r, self.g, self.b, self.a].map(|channel| f32::from(channel) / 255.0)
+    }
 }
 
-pub fn colors_to_bytes(image_buffer: &[RgbaColor]) -> &[u8] {
-    unsafe {
-        let bytes_pointer = (&image_buffer[0] as *const RgbaColor) as *const u8;
-        
-        from_raw_parts(bytes_pointer, image_buffer.len() * 4)
-    }
+pub fn colors_to_bytes(image_buffer: &[RgbaColor]) -> Vec<u8> {
+    image_buffer.iter()
+        .flat_map(|color| [color.r, color.g, color.b, color.a])
+        .collect()
 }
 
-pub fn bytes_to_colors(bytes: &[u8]) -> &[RgbaColor] {
-    assert!(bytes.len() % 4 == 0, "Length of color buffer has to be divisible by 4");
+pub fn bytes_to_colors(bytes: &[u8]) -> Result<Vec<RgbaColor>> {
+    ensure!(bytes.len().is_multiple_of(4), "Length of color buffer has to be divisible by 4, got {}", bytes.len());
     
-    unsafe {
-        let colors_pointer = (&bytes[0] as *const u8) as *const RgbaColor;
-        
-        from_raw_parts(colors_pointer, bytes.len() / 4)
-    }
+    let colors = bytes.chunks_exact(4)
+        .map(|chunk| RgbaColor::new(chunk[0], chunk[1], chunk[2], chunk[3]))
+        .collect();
+    
+    Ok(colors)
 }
 
 #[cfg(feature = "png")]
@@ -96,16 +104,64 @@ pub fn to_png(image_buffer: &[RgbaColor], width: u32, height: u32) -> Result<Vec
         let mut writer = encoder.write_header().unwrap();
         
         // write png
-        writer.write_image_data(bytes)?;
+        writer.write_image_data(&bytes)?;
     }
     
     Ok(out)
 }
 
-pub const ENCODABLE_FORMATS: [PicaTextureFormat; 0] = [
-    // PicaTextureFormat::RGBA5551,
+pub const DECODABLE_FORMATS: [PicaTextureFormat; 13] = [
+    PicaTextureFormat::RGBA8,
+    PicaTextureFormat::RGB8,
+    PicaTextureFormat::RGBA5551,
+    PicaTextureFormat::RGB565,
+    PicaTextureFormat::RGBA4,
+    PicaTextureFormat::LA8,
+    PicaTextureFormat::L8,
+    PicaTextureFormat::A8,
+    PicaTextureFormat::LA4,
+    PicaTextureFormat::L4,
+    PicaTextureFormat::A4,
+    PicaTextureFormat::ETC1,
+    PicaTextureFormat::ETC1A4,
+];
+
+pub const ENCODABLE_FORMATS: [PicaTextureFormat; 13] = [
+    PicaTextureFormat::RGBA8,
+    PicaTextureFormat::RGB8,
+    PicaTextureFormat::RGBA5551,
+    PicaTextureFormat::RGB565,
+    PicaTextureFormat::RGBA4,
+    PicaTextureFormat::LA8,
+    PicaTextureFormat::L8,
+    PicaTextureFormat::A8,
+    PicaTextureFormat::LA4,
+    PicaTextureFormat::L4,
+    PicaTextureFormat::A4,
+    PicaTextureFormat::ETC1,
+    PicaTextureFormat::ETC1A4,
 ];
 
+// mipmaps get generated from the decoded image, so the format has to be decodable as well
+pub const MIPMAP_GENERATION_FORMATS: [PicaTextureFormat; 13] = ENCODABLE_FORMATS;
+
+/// What the codec can do with a texture format in this build, so that
+/// tools can disable unsupported operations up front
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FormatSupport {
+    pub decode: bool,
+    pub encode: bool,
+    pub generate_mipmaps: bool,
+}
+
+pub fn format_support(format: PicaTextureFormat) -> FormatSupport {
+    FormatSupport {
+        decode: DECODABLE_FORMATS.contains(&format),
+        encode: ENCODABLE_FORMATS.contains(&format),
+        generate_mipmaps: MIPMAP_GENERATION_FORMATS.contains(&format),
+    }
+}
+
 // look-up table for 3ds swizzling
 // all of this is confusing so this
 // is from SPICA/CTR Studio
@@ -120,140 +176,519 @@ const SWIZZLE_LUT: [u32; 64] = [
     52, 53, 60, 61, 54, 55, 62, 63
 ];
 
+// offset of every pixel of a tile from the tile's top left corner in storage order,
+// for images `width` pixels wide. computed once per image so that the pixel loops
+// only have to add it to the offset of the tile
+fn tile_offsets(width: u32) -> [usize; 64] {
+    SWIZZLE_LUT.map(|p| ((p & 7) + (p >> 3) * width) as usize)
+}
+
+// calls `f` with every tile's offset into an image `width` by `height` pixels large,
+// in the order the tiles are stored in
+fn for_each_tile(width: u32, height: u32, mut f: impl FnMut(usize, usize)) {
+    let tiles_per_row = (width / 8) as usize;
+    
+    for tile_index in 0..tiles_per_row * (height / 8) as usize {
+        let (tile_x, tile_y) = (tile_index % tiles_per_row, tile_index / tiles_per_row);
+        f(tile_index, tile_x * 8 + tile_y * 8 * width as usize);
+    }
+}
+
+// decoders for single pixels in every format, in the byte order they are stored in
+
+pub fn decode_rgba8(bytes: [u8; 4]) -> RgbaColor {
+    RgbaColor::new(bytes[3], bytes[2], bytes[1], bytes[0])
+}
+
+pub fn decode_rgb8(bytes: [u8; 3]) -> RgbaColor {
+    RgbaColor::new(bytes[2], bytes[1], bytes[0], 0xFF)
+}
+
+pub fn decode_rgba5551(raw: u16) -> RgbaColor {
+    let r = (((raw >> 11) & 0x1f) << 3) as u8;
+    let g = (((raw >> 6) & 0x1f) << 3) as u8;
+    let b = (((raw >> 1) & 0x1f) << 3) as u8;
+    let a = ((raw & 1) * 0xFF) as u8;
+    
+    RgbaColor::new(r | (r >> 5), g | (g >> 5), b | (b >> 5), a)
+}
+
+pub fn decode_rgb565(raw: u16) -> RgbaColor {
+    let r = (((raw >> 11) & 0x1f) << 3) as u8;
+    let g = (((raw >> 5) & 0x3f) << 2) as u8;
+    let b = ((raw & 0x1f) << 3) as u8;
+    
+    RgbaColor::new(r | (r >> 5), g | (g >> 6), b | (b >> 5), 0xFF)
+}
+
+pub fn decode_rgba4(raw: u16) -> RgbaColor {
+    let r = ((raw >> 12) & 0xf) as u8;
+    let g = ((raw >> 8) & 0xf) as u8;
+    let b = ((raw >> 4) & 0xf) as u8;
+    let a = (raw & 0xf) as u8;
+    
+    RgbaColor::new(r | (r << 4), g | (g << 4), b | (b << 4), a | (a << 4))
+}
+
+pub fn decode_la8(bytes: [u8; 2]) -> RgbaColor {
+    RgbaColor::grayscale_alpha(bytes[1], bytes[0])
+}
+
+pub fn decode_l8(value: u8) -> RgbaColor {
+    RgbaColor::grayscale(value)
+}
+
+pub fn decode_a8(value: u8) -> RgbaColor {
+    RgbaColor::from_alpha(value)
+}
+
+pub fn decode_la4(value: u8) -> RgbaColor {
+    let high = value & 0xF0;
+    let low = value & 0x0F;
+    
+    RgbaColor::grayscale_alpha(high | (high >> 4), low | (low << 4))
+}
+
+/// `nibble` is the lower four bits
+pub fn decode_l4(nibble: u8) -> RgbaColor {
+    RgbaColor::grayscale((nibble & 0x0F) * 0x11)
+}
+
+/// `nibble` is the lower four bits
+pub fn decode_a4(nibble: u8) -> RgbaColor {
+    RgbaColor::from_alpha((nibble & 0x0F) * 0x11)
+}
+
+/// Decodes the pixel at `index` of a buffer in storage order. The pixels of
+/// 4-bit formats share a byte, with the first pixel in the lower four bits.
+/// ETC1 and ETC1A4 are stored in blocks, see [`decode_etc1_block`]
+pub fn decode_pixel(image_buffer: &[u8], format: PicaTextureFormat, index: usize) -> Result<RgbaColor> {
+    let bytes_per_pixel = max(format.get_bpp() / 8, 1) as usize;
+    let offset = index * bytes_per_pixel;
+    
+    let bytes = |count: usize| image_buffer.get(offset..offset + count)
+        .ok_or_else(|| anyhow!("Pixel {index} is outside of the image data"));
+    
+    let nibble = || -> Result<u8> {
+        let raw = *image_buffer.get(index / 2)
+            .ok_or_else(|| anyhow!("Pixel {index} is outside of the image data"))?;
+        
+        Ok(if index.is_multiple_of(2) { raw & 0x0F } else { raw >> 4 })
+    };
+    
+    let color = match format {
+        PicaTextureFormat::RGBA8 => decode_rgba8(bytes(4)?.try_into()?),
+        PicaTextureFormat::RGB8 => decode_rgb8(bytes(3)?.try_into()?),
+        PicaTextureFormat::RGBA5551 => decode_rgba5551(LittleEndian::read_u16(bytes(2)?)),
+        PicaTextureFormat::RGB565 => decode_rgb565(LittleEndian::read_u16(bytes(2)?)),
+        PicaTextureFormat::RGBA4 => decode_rgba4(LittleEndian::read_u16(bytes(2)?)),
+        PicaTextureFormat::LA8 => decode_la8(bytes(2)?.try_into()?),
+        PicaTextureFormat::L8 => decode_l8(bytes(1)?[0]),
+        PicaTextureFormat::A8 => decode_a8(bytes(1)?[0]),
+        PicaTextureFormat::LA4 => decode_la4(bytes(1)?[0]),
+        PicaTextureFormat::L4 => decode_l4(nibble()?),
+        PicaTextureFormat::A4 => decode_a4(nibble()?),
+        _ => bail!("Format {:?} can not be decoded pixel by pixel", format),
+    };
+    
+    Ok(color)
+}
+
+/// Decodes pixels (or 4x4 blocks for ETC1 and ETC1A4) in the order they are stored in,
+/// without undoing the swizzling. Decoding the result of
+/// [`encode_swizzled_buffer`] needs [`decode_swizzled_buffer`] instead
+pub fn decode_linear(image_buffer: &[u8], format: PicaTextureFormat) -> Result<Vec<RgbaColor>> {
+    if !format_support(format).decode {
+        return Err(anyhow!("Format {:?} not implemented yet", format));
+    }
+    
+    if format == PicaTextureFormat::ETC1A4 || format == PicaTextureFormat::ETC1 {
+        let mut reader = Cursor::new(image_buffer);
+        let mut output = Vec::new();
+        
+        while reader.position() < image_buffer.len() as u64 {
+            output.extend(read_etc1_block(&mut reader, format == PicaTextureFormat::ETC1A4)?);
+        }
+        
+        return Ok(output);
+    }
+    
+    let pixel_count = image_buffer.len() * 8 / format.get_bpp() as usize;
+    
+    (0..pixel_count)
+        .map(|index| decode_pixel(image_buffer, format, index))
+        .collect()
+}
+
 pub fn decode_swizzled_buffer(image_buffer: &[u8], input_format: PicaTextureFormat, width: u32, height: u32) -> Result<Vec<RgbaColor>> {
+    decode_swizzled(image_buffer, input_format, width, height, |color| color)
+}
+
+/// Byte layout of every pixel for [`decode_swizzled_buffer_to`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PixelLayout {
+    /// Red, green, blue and alpha, like [`RgbaColor`]
+    #[default]
+    Rgba8,
+    /// Blue, green, red and alpha, which most UI frameworks on Windows expect
+    Bgra8,
+    /// Little endian RGB565 without alpha. RGB565 textures are passed through
+    /// as they are stored instead of getting decoded and encoded again
+    Rgb565,
+}
+
+impl PixelLayout {
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            PixelLayout::Rgba8 | PixelLayout::Bgra8 => 4,
+            PixelLayout::Rgb565 => 2,
+        }
+    }
+}
+
+/// Same as [`decode_swizzled_buffer`], but with pixels written as bytes in `layout`
+/// directly, which saves downstream UIs a conversion pass
+///
+/// ```
+/// use ctr_bcres::{image_codec::{decode_swizzled_buffer_to, encode_swizzled_buffer, PixelLayout, RgbaColor},
+///     texture::PicaTextureFormat};
+///
+/// let pixels = vec![RgbaColor::new(0x10, 0x20, 0x30, 0xFF); 64];
+/// let encoded = encode_swizzled_buffer(&pixels, PicaTextureFormat::RGBA8, 8, 8).unwrap();
+///
+/// let bgra = decode_swizzled_buffer_to(&encoded, PicaTextureFormat::RGBA8, 8, 8, PixelLayout::Bgra8).unwrap();
+/// assert_eq!(bgra[..4], [0x30, 0x20, 0x10, 0xFF]);
+/// ```
+pub fn decode_swizzled_buffer_to(image_buffer: &[u8], input_format: PicaTextureFormat, width: u32, height: u32,
+    layout: PixelLayout) -> Result<Vec<u8>>
+{
+    let pixels = match layout {
+        PixelLayout::Rgba8 => decode_swizzled(image_buffer, input_format, width, height,
+            |color| [color.r, color.g, color.b, color.a])?.into_flattened(),
+        PixelLayout::Bgra8 => decode_swizzled(image_buffer, input_format, width, height,
+            |color| [color.b, color.g, color.r, color.a])?.into_flattened(),
+        PixelLayout::Rgb565 if input_format == PicaTextureFormat::RGB565 => {
+            let image_buffer = check_swizzled_buffer(image_buffer, input_format, width, height)?;
+            let (padded_width, padded_height) = (width.next_multiple_of(8), height.next_multiple_of(8));
+            
+            let pixels = decode_tiles(image_buffer, 16, padded_width, padded_height, |bytes| [bytes[0], bytes[1]]);
+            crop(pixels, padded_width, width, height).into_flattened()
+        },
+        PixelLayout::Rgb565 => decode_swizzled(image_buffer, input_format, width, height,
+            |color| encode_rgb565(color).to_le_bytes())?.into_flattened(),
+    };
+    
+    Ok(pixels)
+}
+
+// the part of image_buffer that holds the image, after checking that it can be decoded
+fn check_swizzled_buffer(image_buffer: &[u8], input_format: PicaTextureFormat, width: u32, height: u32) -> Result<&[u8]> {
+    if !format_support(input_format).decode {
+        return Err(anyhow!("Format {:?} not implemented yet", input_format));
+    }
+    
+    let size = input_format.buffer_size(width, height, 1);
+    if image_buffer.len() < size {
+        return Err(anyhow!("Expected {} bytes for a {}x{} {:?} image, got {}",
+            size, width, height, input_format, image_buffer.len()));
+    }
+    
+    Ok(&image_buffer[..size])
+}
+
+// decodes and unswizzles every pixel, `convert` turns it into the output type
+fn decode_swizzled<T: Copy + Default>(image_buffer: &[u8], input_format: PicaTextureFormat, width: u32, height: u32,
+    convert: impl Fn(RgbaColor) -> T + Copy) -> Result<Vec<T>>
+{
+    let image_buffer = check_swizzled_buffer(image_buffer, input_format, width, height)?;
+    
     if input_format == PicaTextureFormat::ETC1A4 || input_format == PicaTextureFormat::ETC1 {
-        return decode_etc1(image_buffer, width, height, input_format == PicaTextureFormat::ETC1A4);
+        let pixels = decode_etc1(image_buffer, width, height, input_format == PicaTextureFormat::ETC1A4)?;
+        return Ok(pixels.into_iter().map(convert).collect());
     }
     
-    let bytes_per_pixel = max(input_format.get_bpp() / 8, 1);
-    let mut input_offset: usize = 0;
-    let mut output: Vec<RgbaColor> = vec![RgbaColor::default(); (width * height).try_into()?];
+    // images smaller than a tile still take up a whole one, they get cropped afterwards
+    let (padded_width, padded_height) = (width.next_multiple_of(8), height.next_multiple_of(8));
     
-    // iterate over every 8x8px chunk
+    // one loop per format, so that the format only gets matched once
+    let output = match input_format {
+        PicaTextureFormat::RGBA8 => decode_tiles(image_buffer, 32, padded_width, padded_height,
+            |bytes| convert(decode_rgba8(bytes.try_into().unwrap()))),
+        PicaTextureFormat::RGB8 => decode_tiles(image_buffer, 24, padded_width, padded_height,
+            |bytes| convert(decode_rgb8(bytes.try_into().unwrap()))),
+        PicaTextureFormat::RGBA5551 => decode_tiles(image_buffer, 16, padded_width, padded_height,
+            |bytes| convert(decode_rgba5551(LittleEndian::read_u16(bytes)))),
+        PicaTextureFormat::RGB565 => decode_tiles(image_buffer, 16, padded_width, padded_height,
+            |bytes| convert(decode_rgb565(LittleEndian::read_u16(bytes)))),
+        PicaTextureFormat::RGBA4 => decode_tiles(image_buffer, 16, padded_width, padded_height,
+            |bytes| convert(decode_rgba4(LittleEndian::read_u16(bytes)))),
+        PicaTextureFormat::LA8 => decode_tiles(image_buffer, 16, padded_width, padded_height,
+            |bytes| convert(decode_la8(bytes.try_into().unwrap()))),
+        PicaTextureFormat::L8 => decode_tiles(image_buffer, 8, padded_width, padded_height, |bytes| convert(decode_l8(bytes[0]))),
+        PicaTextureFormat::A8 => decode_tiles(image_buffer, 8, padded_width, padded_height, |bytes| convert(decode_a8(bytes[0]))),
+        PicaTextureFormat::LA4 => decode_tiles(image_buffer, 8, padded_width, padded_height, |bytes| convert(decode_la4(bytes[0]))),
+        PicaTextureFormat::L4 => decode_nibble_tiles(image_buffer, padded_width, padded_height, |nibble| convert(decode_l4(nibble))),
+        PicaTextureFormat::A4 => decode_nibble_tiles(image_buffer, padded_width, padded_height, |nibble| convert(decode_a4(nibble))),
+        _ => bail!("Format {:?} can not be decoded pixel by pixel", input_format),
+    };
+    
+    Ok(crop(output, padded_width, width, height))
+}
+
+// cuts the padding of images smaller than a tile off again
+fn crop<T: Copy>(pixels: Vec<T>, padded_width: u32, width: u32, height: u32) -> Vec<T> {
+    if padded_width == width && pixels.len() == (width * height) as usize {
+        return pixels;
+    }
+    
+    pixels.chunks_exact(padded_width as usize)
+        .take(height as usize)
+        .flat_map(|row| &row[..width as usize])
+        .copied()
+        .collect()
+}
+
+// decodes every tile of formats with at least 8 bits per pixel, `decode` gets the bytes of a single pixel
+fn decode_tiles<T: Copy + Default>(image_buffer: &[u8], bits_per_pixel: usize, width: u32, height: u32,
+    decode: impl Fn(&[u8]) -> T) -> Vec<T>
+{
+    let bytes_per_pixel = bits_per_pixel / 8;
+    let offsets = tile_offsets(width);
+    let mut output: Vec<T> = vec![T::default(); (width * height) as usize];
+    
+    for_each_tile(width, height, |tile_index, tile_offset| {
+        let tile = &image_buffer[tile_index * 64 * bytes_per_pixel..(tile_index + 1) * 64 * bytes_per_pixel];
+        
+        for (bytes, offset) in tile.chunks_exact(bytes_per_pixel).zip(offsets) {
+            output[tile_offset + offset] = decode(bytes);
+        }
+    });
+    
+    output
+}
+
+// same as decode_tiles for 4-bit formats, the first of two pixels sharing a byte is in its lower four bits
+fn decode_nibble_tiles<T: Copy + Default>(image_buffer: &[u8], width: u32, height: u32, decode: impl Fn(u8) -> T) -> Vec<T> {
+    let offsets = tile_offsets(width);
+    let mut output: Vec<T> = vec![T::default(); (width * height) as usize];
+    
+    for_each_tile(width, height, |tile_index, tile_offset| {
+        let tile = &image_buffer[tile_index * 32..(tile_index + 1) * 32];
+        
+        for (&byte, offsets) in tile.iter().zip(offsets.chunks_exact(2)) {
+            output[tile_offset + offsets[0]] = decode(byte & 0x0F);
+            output[tile_offset + offsets[1]] = decode(byte >> 4);
+        }
+    });
+    
+    output
+}
+
+// encodes every tile of formats with at least 8 bits per pixel, `encode` writes the
+// bytes of a single pixel
+#[inline(always)]
+fn encode_tiles(image_buffer: &[RgbaColor], output: &mut [u8], width: u32, height: u32, encode: impl Fn(RgbaColor, &mut [u8])) {
+    let bytes_per_pixel = output.len() / image_buffer.len();
+    let offsets = tile_offsets(width);
+    
+    for_each_tile(width, height, |tile_index, tile_offset| {
+        let tile = &mut output[tile_index * 64 * bytes_per_pixel..(tile_index + 1) * 64 * bytes_per_pixel];
+        
+        for (bytes, offset) in tile.chunks_exact_mut(bytes_per_pixel).zip(offsets) {
+            encode(image_buffer[tile_offset + offset], bytes);
+        }
+    });
+}
+
+/// Which value of a pixel gets stored in the single nibble of L4 and A4 textures
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MaskSource {
+    Channel(Channel),
+    Luminance,
+}
+
+/// How hard lossy formats like ETC1 search for the encoding closest to the original
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum EncodeQuality {
+    /// Only tries the average colors of the left and right half of every block
+    Fast,
+    /// Also tries splitting blocks into a top and bottom half and both ways of
+    /// storing the base colors
+    #[default]
+    Balanced,
+    /// Also tries every base color next to the average colors, which is a lot slower
+    Best,
+}
+
+/// Settings for [`encode_swizzled_buffer_with_options`], mostly for packing masks
+/// into 4 bit formats. The default encodes like [`encode_swizzled_buffer`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EncodeOptions {
+    /// Value L4 and A4 store, None for the luminance for L4 and alpha for A4
+    pub mask_source: Option<MaskSource>,
+    /// Makes L4 and A4 values at or above this fully set and all others zero,
+    /// so that masks keep hard edges instead of getting banded by rounding
+    pub threshold: Option<u8>,
+    /// Effort for ETC1 and ETC1A4, the other formats are lossless apart from rounding
+    pub quality: EncodeQuality,
+}
+
+impl EncodeOptions {
+    /// Nibble that L4 or A4 stores for `color`, in the high four bits
+    pub fn mask_value(&self, color: RgbaColor, output_format: PicaTextureFormat) -> u8 {
+        let source = self.mask_source.unwrap_or(match output_format {
+            PicaTextureFormat::A4 => MaskSource::Channel(Channel::Alpha),
+            _ => MaskSource::Luminance,
+        });
+        
+        let value = match source {
+            MaskSource::Channel(channel) => channel.get(color),
+            MaskSource::Luminance => color.luminance(),
+        };
+        
+        match self.threshold {
+            Some(threshold) if value >= threshold => 0xF0,
+            Some(_) => 0,
+            None => value & 0xF0,
+        }
+    }
+}
+
+pub fn encode_swizzled_buffer(image_buffer: &[RgbaColor], output_format: PicaTextureFormat, width: u32, height: u32) -> Result<Vec<u8>> {
+    encode_swizzled_buffer_with_options(image_buffer, output_format, width, height, &EncodeOptions::default())
+}
+
+pub fn encode_swizzled_buffer_with_options(image_buffer: &[RgbaColor], output_format: PicaTextureFormat,
+    width: u32, height: u32, options: &EncodeOptions) -> Result<Vec<u8>>
+{
+    if !format_support(output_format).encode {
+        return Err(anyhow!("Encoding format {:?} is not implemented yet", output_format));
+    }
+    
+    if !width.is_multiple_of(8) || !height.is_multiple_of(8) {
+        return Err(anyhow!("Texture size {}x{} has to be a multiple of 8", width, height));
+    }
+    
+    if image_buffer.len() != (width * height) as usize {
+        return Err(anyhow!("Expected {} pixels for a {}x{} texture, got {}",
+            width * height, width, height, image_buffer.len()));
+    }
+    
+    let mut output: Vec<u8> = vec![0; output_format.buffer_size(width, height, 1)];
+    
+    // same traversal as decode_swizzled_buffer but other way around, with one loop per
+    // format so that the format only gets matched once
+    let output_bytes = output.as_mut_slice();
+    
+    match output_format {
+        PicaTextureFormat::RGBA8 => encode_tiles(image_buffer, output_bytes, width, height, |color, bytes| {
+            bytes.copy_from_slice(&[color.a, color.b, color.g, color.r]);
+        }),
+        PicaTextureFormat::RGB8 => encode_tiles(image_buffer, output_bytes, width, height, |color, bytes| {
+            bytes.copy_from_slice(&[color.b, color.g, color.r]);
+        }),
+        PicaTextureFormat::RGBA4 => encode_tiles(image_buffer, output_bytes, width, height, |color, bytes| {
+            let raw = (color.r as u16 >> 4) << 12 | (color.g as u16 >> 4) << 8
+                | (color.b as u16 >> 4) << 4 | color.a as u16 >> 4;
+            bytes.copy_from_slice(&raw.to_le_bytes());
+        }),
+        PicaTextureFormat::RGB565 => encode_tiles(image_buffer, output_bytes, width, height, |color, bytes| {
+            bytes.copy_from_slice(&encode_rgb565(color).to_le_bytes());
+        }),
+        PicaTextureFormat::RGBA5551 => encode_tiles(image_buffer, output_bytes, width, height, |color, bytes| {
+            let raw = (color.r as u16 >> 3) << 11 | (color.g as u16 >> 3) << 6
+                | (color.b as u16 >> 3) << 1 | (color.a >= 0x80) as u16;
+            bytes.copy_from_slice(&raw.to_le_bytes());
+        }),
+        PicaTextureFormat::L8 => encode_tiles(image_buffer, output_bytes, width, height, |color, bytes| {
+            bytes[0] = color.luminance();
+        }),
+        PicaTextureFormat::A8 => encode_tiles(image_buffer, output_bytes, width, height, |color, bytes| {
+            bytes[0] = color.a;
+        }),
+        PicaTextureFormat::LA8 => encode_tiles(image_buffer, output_bytes, width, height, |color, bytes| {
+            bytes.copy_from_slice(&[color.a, color.luminance()]);
+        }),
+        PicaTextureFormat::LA4 => encode_tiles(image_buffer, output_bytes, width, height, |color, bytes| {
+            bytes[0] = (color.luminance() & 0xF0) | (color.a >> 4);
+        }),
+        PicaTextureFormat::ETC1 | PicaTextureFormat::ETC1A4 => encode_etc1(image_buffer, output_bytes, width, height,
+            output_format == PicaTextureFormat::ETC1A4, options.quality),
+        PicaTextureFormat::L4 | PicaTextureFormat::A4 => {
+            let offsets = tile_offsets(width);
+            
+            for_each_tile(width, height, |tile_index, tile_offset| {
+                let tile = &mut output_bytes[tile_index * 32..(tile_index + 1) * 32];
+                
+                // even pixels go into the low nibble
+                for (byte, offsets) in tile.iter_mut().zip(offsets.chunks_exact(2)) {
+                    let even = options.mask_value(image_buffer[tile_offset + offsets[0]], output_format);
+                    let odd = options.mask_value(image_buffer[tile_offset + offsets[1]], output_format);
+                    *byte = even >> 4 | odd & 0xF0;
+                }
+            });
+        },
+        _ => unreachable!(),
+    }
+    
+    Ok(output)
+}
+
+/// Packs a color into RGB565, dropping its alpha
+pub fn encode_rgb565(color: RgbaColor) -> u16 {
+    (color.r as u16 >> 3) << 11 | (color.g as u16 >> 2) << 5 | color.b as u16 >> 3
+}
+
+const ETC1_X: [u32; 4] = [ 0, 4, 0, 4 ];
+const ETC1_Y: [u32; 4] = [ 0, 0, 4, 4 ];
+
+fn encode_etc1(image_buffer: &[RgbaColor], output: &mut [u8], width: u32, height: u32, use_alpha: bool,
+    quality: EncodeQuality)
+{
+    let block_size = if use_alpha { 16 } else { 8 };
+    let mut blocks = output.chunks_exact_mut(block_size);
+    
+    // same order as decode_etc1, 8x8px chunks made of four 4x4px blocks
     for y in (0..height).step_by(8) {
         for x in (0..width).step_by(8) {
-            
-            // iterate over every pixel in the current chunk
-            for p in SWIZZLE_LUT {
-                let local_x = p & 7;
-                let local_y = (p - local_x) >> 3;
+            for (sub_x, sub_y) in ETC1_X.into_iter().zip(ETC1_Y) {
+                let pixels: [RgbaColor; 16] = std::array::from_fn(|i| {
+                    let (local_x, local_y) = (i as u32 % 4, i as u32 / 4);
+                    image_buffer[(x + sub_x + local_x + (y + sub_y + local_y) * width) as usize]
+                });
                 
-                let output_offset: usize = (x + local_x + (y + local_y) * width).try_into()?;
+                let (color_block, alpha_block) = encode_etc1_block(&pixels, use_alpha, quality);
+                let block = blocks.next().unwrap();
                 
-                match input_format {
-                    PicaTextureFormat::RGBA8 => {
-                        output[output_offset] = RgbaColor {
-                            r: image_buffer[input_offset + 3],
-                            g: image_buffer[input_offset + 2],
-                            b: image_buffer[input_offset + 1],
-                            a: image_buffer[input_offset],
-                        }
-                    },
-                    PicaTextureFormat::RGBA4 => {
-                        let raw = u16::from_le_bytes(image_buffer[input_offset..input_offset + 2].try_into().unwrap());
-                        
-                        let r: u8 = ((raw >> 12) & 0xf).try_into()?;
-                        let g: u8 = ((raw >> 8) & 0xf).try_into()?;
-                        let b: u8 = ((raw >> 4) & 0xf).try_into()?;
-                        let a: u8 = (raw & 0xf).try_into()?;
-                        
-                        output[output_offset] = RgbaColor {
-                            r: r | (r << 4),
-                            g: g | (g << 4),
-                            b: b | (b << 4),
-                            a: a | (a << 4),
-                        }
-                    },
-                    PicaTextureFormat::RGB565 => {
-                        let raw = u16::from_le_bytes(image_buffer[input_offset..input_offset + 2].try_into().unwrap());
-                        
-                        let r: u8 = (((raw >> 11) & 0x1f) << 3).try_into()?;
-                        let g: u8 = (((raw >> 5) & 0x3f) << 2).try_into()?;
-                        let b: u8 = ((raw & 0x1f) << 3).try_into()?;
-                        
-                        output[output_offset] = RgbaColor {
-                            r: r | (r >> 5),
-                            g: g | (g >> 6),
-                            b: b | (b >> 5),
-                            a: 0xFF,
-                        }
-                    },
-                    PicaTextureFormat::RGBA5551 => {
-                        let raw = u16::from_le_bytes(image_buffer[input_offset..input_offset + 2].try_into().unwrap());
-                        
-                        let r: u8 = (((raw >> 11) & 0x1f) << 3).try_into()?;
-                        let g: u8 = (((raw >> 6) & 0x1f) << 3).try_into()?;
-                        let b: u8 = (((raw >> 1) & 0x1f) << 3).try_into()?;
-                        let a: u8 = ((raw & 1) * 0xFF).try_into()?;
-                        
-                        output[output_offset] = RgbaColor {
-                            r: r | (r >> 5),
-                            g: g | (g >> 5),
-                            b: b | (b >> 5),
-                            a,
-                        }
-                    },
-                    PicaTextureFormat::L8 => {
-                        output[output_offset] = RgbaColor::grayscale(image_buffer[input_offset])
-                    },
-                    PicaTextureFormat::L4 => {
-                        let raw = image_buffer[input_offset / 2];
-                        
-                        let color = if input_offset % 2 == 0 {
-                            (raw & 0x0F) | (raw << 4)
-                        } else {
-                            (raw & 0xF0) | (raw >> 4)
-                        };
-                        
-                        output[output_offset] = RgbaColor::grayscale(color)
-                    },
-                    PicaTextureFormat::A8 => {
-                        output[output_offset] = RgbaColor::from_alpha(image_buffer[input_offset])
-                    },
-                    PicaTextureFormat::A4 => {
-                        let raw = image_buffer[input_offset / 2];
-                        
-                        let alpha = if input_offset % 2 == 0 {
-                            (raw & 0x0F) | (raw << 4)
-                        } else {
-                            (raw & 0xF0) | (raw >> 4)
-                        };
-                        
-                        output[output_offset] = RgbaColor::from_alpha(alpha)
-                    },
-                    PicaTextureFormat::LA8 => {
-                        let alpha: u8 = image_buffer[input_offset];
-                        let color: u8 = image_buffer[input_offset + 1];
-                        
-                        output[output_offset] = RgbaColor::grayscale_alpha(color, alpha)
-                    },
-                    PicaTextureFormat::LA4 => {
-                        let high: u8 = image_buffer[input_offset] & 0xF0;
-                        let low: u8 = image_buffer[input_offset] & 0x0F;
-                        
-                        output[output_offset] = RgbaColor {
-                            r: high | (high >> 4),
-                            g: high | (high >> 4),
-                            b: high | (high >> 4),
-                            a: low | (low << 4),
-                        }
-                    },
-                    _ => {
-                        return Err(anyhow!("Format {:?} not implemented yet", input_format));
-                    }
+                if let Some(alpha_block) = alpha_block {
+                    block[..8].copy_from_slice(&alpha_block.to_le_bytes());
                 }
                 
-                input_offset += bytes_per_pixel as usize;
+                block[block_size - 8..].copy_from_slice(&color_block.to_le_bytes());
             }
-            
         }
     }
+}
+
+/// Encodes a 4x4 block of pixels, row by row, into an ETC1 color block and with
+/// `use_alpha` an ETC1A4 alpha block, the reverse of [`decode_etc1_block`]
+pub fn encode_etc1_block(pixels: &[RgbaColor; 16], use_alpha: bool, quality: EncodeQuality) -> (u64, Option<u64>) {
+    let color_block = etc1_encode::encode_etc1_color_block(pixels, quality);
+    let alpha_block = use_alpha.then(|| etc1_encode::encode_etc1_alpha_block(pixels));
     
-    Ok(output)
+    (color_block, alpha_block)
 }
 
-const ETC1_X: [u32; 4] = [ 0, 4, 0, 4 ];
-const ETC1_Y: [u32; 4] = [ 0, 0, 4, 4 ];
+fn read_etc1_block(reader: &mut Cursor<&[u8]>, use_alpha: bool) -> Result<[RgbaColor; 16]> {
+    let alpha_block = if use_alpha {
+        Some(reader.read_u64::<LittleEndian>()?)
+    } else {
+        None
+    };
+    
+    let color_block = reader.read_u64::<LittleEndian>()?;
+    Ok(decode_etc1_block(color_block, alpha_block))
+}
 
 fn decode_etc1(image_buffer: &[u8], width: u32, height: u32, use_alpha: bool) -> Result<Vec<RgbaColor>> {
     let mut input_reader = Cursor::new(image_buffer);
@@ -265,90 +700,7 @@ fn decode_etc1(image_buffer: &[u8], width: u32, height: u32, use_alpha: bool) ->
             
             // iterate over every 4x4px block in this chunk
             for (sub_x, sub_y) in ETC1_X.into_iter().zip(ETC1_Y) {
-                let alpha_block = if use_alpha {
-                    input_reader.read_u64::<LittleEndian>()?
-                } else {
-                    u64::MAX
-                };
-                
-                let color_block_low = input_reader.read_u32::<LittleEndian>()?;
-                let color_block_high = input_reader.read_u32::<LittleEndian>()?;
-                
-                // decode color block
-                let mut base0: RgbaColor;
-                let mut base1: RgbaColor;
-                
-                // determines whether the current 4x4px chunk is
-                // subdivided horizontally (true) or vertically (false)
-                let flip = color_block_high & 0x1 != 0;
-                // if true, base0 will be RGBA5 and base1 will only
-                // encode the difference to base0 in RGBA3
-                let diff = color_block_high & 0x2 != 0;
-                
-                if diff {
-                    base0 = RgbaColor {
-                        r: ((color_block_high & 0xf8000000) >> 24) as u8,
-                        g: ((color_block_high & 0x00f80000) >> 16) as u8,
-                        b: ((color_block_high & 0x0000f800) >> 8) as u8,
-                        a: 0xFF,
-                    };
-                    base1 = RgbaColor { // confusing calculation I don't really understand but I hope this checks out
-                        r: ((base0.r >> 3) as i32 + (((color_block_high & 0x07000000) >> 19) as i8 >> 5) as i32) as u8,
-                        g: ((base0.g >> 3) as i32 + (((color_block_high & 0x00070000) >> 11) as i8 >> 5) as i32) as u8,
-                        b: ((base0.b >> 3) as i32 + (((color_block_high & 0x00000700) >> 3) as i8 >> 5) as i32) as u8,
-                        a: 0xFF,
-                    };
-                    base0.r |= base0.r >> 5;
-                    base0.g |= base0.g >> 5;
-                    base0.b |= base0.b >> 5;
-                    
-                    base1.r = (base1.r << 3) | (base1.r >> 2);
-                    base1.g = (base1.g << 3) | (base1.g >> 2);
-                    base1.b = (base1.b << 3) | (base1.b >> 2);
-                } else {
-                    base0 = RgbaColor {
-                        r: ((color_block_high & 0xf0000000) >> 24) as u8,
-                        g: ((color_block_high & 0x00f00000) >> 16) as u8,
-                        b: ((color_block_high & 0x0000f000) >> 8) as u8,
-                        a: 0xFF,
-                    };
-                    base1 = RgbaColor {
-                        r: ((color_block_high & 0x0f000000) >> 20) as u8,
-                        g: ((color_block_high & 0x000f0000) >> 12) as u8,
-                        b: ((color_block_high & 0x00000f00) >> 4) as u8,
-                        a: 0xFF,
-                    };
-                    base0.r |= base0.r >> 4;
-                    base0.g |= base0.g >> 4;
-                    base0.b |= base0.b >> 4;
-                    
-                    base1.r |= base1.r >> 4;
-                    base1.g |= base1.g >> 4;
-                    base1.b |= base1.b >> 4;
-                }
-                
-                let table0 = (color_block_high >> 5) & 0b111;
-                let table1 = (color_block_high >> 2) & 0b111;
-                
-                let mut current_chunk: [RgbaColor; 16] = [RgbaColor::default(); 16];
-                
-                for local_y in if flip { 0u32..2u32 } else { 0u32..4u32 } {
-                    for local_x in if flip { 0u32..4u32 } else { 0u32..2u32 } {
-                        let offset0 = local_y * 4 + local_x;
-                        let offset1 = if flip {
-                            (local_y + 2) * 4 + local_x
-                        } else {
-                            local_y * 4 + local_x + 2
-                        };
-                        let x1: u32 = if flip { local_x } else { local_x + 2 };
-                        let y1: u32 = if flip { local_y + 2 } else { local_y };
-                        
-                        current_chunk[offset0 as usize] =
-                            decode_etc1_pixel(base0, local_x, local_y, color_block_low.to_be(), table0)?;
-                        current_chunk[offset1 as usize] =
-                            decode_etc1_pixel(base1, x1, y1, color_block_low.to_be(), table1)?;
-                    }
-                }
+                let block = read_etc1_block(&mut input_reader, use_alpha)?;
                 
                 // write colors into output
                 let mut tile_offset: u32 = 0;
@@ -357,12 +709,7 @@ fn decode_etc1(image_buffer: &[u8], width: u32, height: u32, use_alpha: bool) ->
                     for local_x in sub_x..sub_x + 4 {
                         let output_offset = x + local_x + (y + local_y) * width;
                         
-                        output[output_offset as usize] = current_chunk[tile_offset as usize];
-                        
-                        let alpha_shift = ((local_x & 3) * 4 + (local_y & 3)) << 2;
-                        let alpha = (alpha_block >> alpha_shift) as u8 & 0xF;
-                        
-                        output[output_offset as usize].a = alpha | alpha << 4;
+                        output[output_offset as usize] = block[tile_offset as usize];
                         tile_offset += 1;
                     }
                 }
@@ -374,7 +721,117 @@ fn decode_etc1(image_buffer: &[u8], width: u32, height: u32, use_alpha: bool) ->
     Ok(output)
 }
 
-const ETC1_LUT: [[i32; 4]; 8] = [
+/// Decodes a 4x4 block of ETC1 (or ETC1A4 if there is an `alpha_block`) into
+/// its pixels, row by row. Both blocks are read as little endian. With the
+/// `simd` feature, all pixels of the block are decoded at once
+pub fn decode_etc1_block(color_block: u64, alpha_block: Option<u64>) -> [RgbaColor; 16] {
+    #[cfg(feature = "simd")]
+    return crate::etc1_simd::decode_etc1_block(color_block, alpha_block);
+    
+    #[cfg(not(feature = "simd"))]
+    decode_etc1_block_scalar(color_block, alpha_block)
+}
+
+#[cfg_attr(feature = "simd", allow(dead_code))]
+pub(crate) fn decode_etc1_block_scalar(color_block: u64, alpha_block: Option<u64>) -> [RgbaColor; 16] {
+    let color_block_low = color_block as u32;
+    let color_block_high = (color_block >> 32) as u32;
+    let alpha_block = alpha_block.unwrap_or(u64::MAX);
+    
+    let [base0, base1] = etc1_base_colors(color_block_high);
+    
+    // determines whether the current 4x4px chunk is
+    // subdivided horizontally (true) or vertically (false)
+    let flip = color_block_high & 0x1 != 0;
+    
+    let table0 = (color_block_high >> 5) & 0b111;
+    let table1 = (color_block_high >> 2) & 0b111;
+    
+    let mut block: [RgbaColor; 16] = [RgbaColor::default(); 16];
+    
+    for local_y in if flip { 0u32..2u32 } else { 0u32..4u32 } {
+        for local_x in if flip { 0u32..4u32 } else { 0u32..2u32 } {
+            let offset0 = local_y * 4 + local_x;
+            let offset1 = if flip {
+                (local_y + 2) * 4 + local_x
+            } else {
+                local_y * 4 + local_x + 2
+            };
+            let x1: u32 = if flip { local_x } else { local_x + 2 };
+            let y1: u32 = if flip { local_y + 2 } else { local_y };
+            
+            block[offset0 as usize] = decode_etc1_pixel(base0, local_x, local_y, color_block_low.to_be(), table0);
+            block[offset1 as usize] = decode_etc1_pixel(base1, x1, y1, color_block_low.to_be(), table1);
+        }
+    }
+    
+    for (i, color) in block.iter_mut().enumerate() {
+        let (x, y) = (i as u32 % 4, i as u32 / 4);
+        
+        let alpha_shift = (x * 4 + y) << 2;
+        let alpha = (alpha_block >> alpha_shift) as u8 & 0xF;
+        
+        color.a = alpha | alpha << 4;
+    }
+    
+    block
+}
+
+// base colors of both halves of an ETC1 block, from the upper half of its color block
+pub(crate) fn etc1_base_colors(color_block_high: u32) -> [RgbaColor; 2] {
+    let mut base0: RgbaColor;
+    let mut base1: RgbaColor;
+    
+    // if true, base0 will be RGBA5 and base1 will only
+    // encode the difference to base0 in RGBA3
+    let diff = color_block_high & 0x2 != 0;
+    
+    if diff {
+        base0 = RgbaColor {
+            r: ((color_block_high & 0xf8000000) >> 24) as u8,
+            g: ((color_block_high & 0x00f80000) >> 16) as u8,
+            b: ((color_block_high & 0x0000f800) >> 8) as u8,
+            a: 0xFF,
+        };
+        base1 = RgbaColor { // confusing calculation I don't really understand but I hope this checks out
+            r: ((base0.r >> 3) as i32 + (((color_block_high & 0x07000000) >> 19) as i8 >> 5) as i32) as u8,
+            g: ((base0.g >> 3) as i32 + (((color_block_high & 0x00070000) >> 11) as i8 >> 5) as i32) as u8,
+            b: ((base0.b >> 3) as i32 + (((color_block_high & 0x00000700) >> 3) as i8 >> 5) as i32) as u8,
+            a: 0xFF,
+        };
+        base0.r |= base0.r >> 5;
+        base0.g |= base0.g >> 5;
+        base0.b |= base0.b >> 5;
+        
+        base1.r = (base1.r << 3) | (base1.r >> 2);
+        base1.g = (base1.g << 3) | (base1.g >> 2);
+        base1.b = (base1.b << 3) | (base1.b >> 2);
+    } else {
+        base0 = RgbaColor {
+            r: ((color_block_high & 0xf0000000) >> 24) as u8,
+            g: ((color_block_high & 0x00f00000) >> 16) as u8,
+            b: ((color_block_high & 0x0000f000) >> 8) as u8,
+            a: 0xFF,
+        };
+        base1 = RgbaColor {
+            r: ((color_block_high & 0x0f000000) >> 20) as u8,
+            g: ((color_block_high & 0x000f0000) >> 12) as u8,
+            b: ((color_block_high & 0x00000f00) >> 4) as u8,
+            a: 0xFF,
+        };
+        base0.r |= base0.r >> 4;
+        base0.g |= base0.g >> 4;
+        base0.b |= base0.b >> 4;
+        
+        base1.r |= base1.r >> 4;
+        base1.g |= base1.g >> 4;
+        base1.b |= base1.b >> 4;
+    }
+    
+    [base0, base1]
+}
+
+pub(crate) const ETC1_LUT: [[i32; 4]; 8] = [
     [   2,   8,    -2,   -8  ],
     [   5,   17,   -5,  -17  ],
     [   9,   29,   -9,  -29  ],
@@ -395,7 +852,7 @@ fn saturate(value: i32) -> u8 {
     }
 }
 
-fn decode_etc1_pixel(base_color: RgbaColor, x: u32, y: u32, block_big_endian: u32, table: u32) -> Result<RgbaColor> {
+fn decode_etc1_pixel(base_color: RgbaColor, x: u32, y: u32, block_big_endian: u32, table: u32) -> RgbaColor {
     let index = x * 4 + y;
     let msb = block_big_endian << 1; // why?
     
@@ -405,10 +862,10 @@ fn decode_etc1_pixel(base_color: RgbaColor, x: u32, y: u32, block_big_endian: u3
         ETC1_LUT[table as usize][((block_big_endian >> (index +  8)) & 1) as usize + ((msb >> (index - 8)) & 2) as usize]
     };
     
-    Ok(RgbaColor {
+    RgbaColor {
         r: saturate(base_color.r as i32 + pixel),
         g: saturate(base_color.g as i32 + pixel),
         b: saturate(base_color.b as i32 + pixel),
         a: 0xFF,
-    })
+    }
 }
@@ -0,0 +1,196 @@
+// hand-made test vectors with the colors they have to decode to for every decodable
+// texture format, for checking other decoders and changes to this one bit by bit
+
+use anyhow::{ensure, Result};
+
+use crate::{image_codec::RgbaColor, texture::PicaTextureFormat};
+
+pub struct DecodeVector {
+    pub format: PicaTextureFormat,
+    /// Pixels in the order they are stored in, without swizzling,
+    /// or whole 4x4 blocks for ETC1 and ETC1A4
+    pub bytes: &'static [u8],
+    /// One color per pixel in the same order, row by row within ETC1 blocks
+    pub expected: &'static [RgbaColor],
+}
+
+const fn gray(value: u8) -> RgbaColor {
+    RgbaColor::grayscale(value)
+}
+
+const fn alpha(value: u8) -> RgbaColor {
+    RgbaColor::from_alpha(value)
+}
+
+// ETC1 blocks with a base color of 0x88 in individual mode and 0x84 in differential mode
+const ETC_A: RgbaColor = RgbaColor::new(0x8A, 0x8A, 0x8A, 0xFF);
+const ETC_B: RgbaColor = RgbaColor::new(0x8A, 0x8A, 0x8A, 0x00);
+const ETC_C: RgbaColor = RgbaColor::new(0x86, 0x86, 0x86, 0xFF);
+const ETC_D: RgbaColor = RgbaColor::new(0x7D, 0x86, 0x86, 0xFF);
+
+pub const DECODE_VECTORS: &[DecodeVector] = &[
+    DecodeVector {
+        format: PicaTextureFormat::RGBA8,
+        bytes: &[0xFF, 0x30, 0x20, 0x10, 0x00, 0x00, 0x00, 0xFF],
+        expected: &[RgbaColor::new(0x10, 0x20, 0x30, 0xFF), RgbaColor::new(0xFF, 0x00, 0x00, 0x00)],
+    },
+    DecodeVector {
+        format: PicaTextureFormat::RGB8,
+        bytes: &[0x30, 0x20, 0x10, 0xFF, 0x00, 0x00],
+        expected: &[RgbaColor::new(0x10, 0x20, 0x30, 0xFF), RgbaColor::new(0x00, 0x00, 0xFF, 0xFF)],
+    },
+    DecodeVector {
+        format: PicaTextureFormat::RGBA5551,
+        bytes: &[0x01, 0xF8, 0xC0, 0x07, 0x3E, 0x00, 0x21, 0x84],
+        expected: &[
+            RgbaColor::new(0xFF, 0x00, 0x00, 0xFF),
+            RgbaColor::new(0x00, 0xFF, 0x00, 0x00),
+            RgbaColor::new(0x00, 0x00, 0xFF, 0x00),
+            RgbaColor::new(0x84, 0x84, 0x84, 0xFF),
+        ],
+    },
+    DecodeVector {
+        format: PicaTextureFormat::RGB565,
+        bytes: &[0x00, 0xF8, 0xE0, 0x07, 0x1F, 0x00, 0x10, 0x84],
+        expected: &[
+            RgbaColor::new(0xFF, 0x00, 0x00, 0xFF),
+            RgbaColor::new(0x00, 0xFF, 0x00, 0xFF),
+            RgbaColor::new(0x00, 0x00, 0xFF, 0xFF),
+            RgbaColor::new(0x84, 0x82, 0x84, 0xFF),
+        ],
+    },
+    DecodeVector {
+        format: PicaTextureFormat::RGBA4,
+        bytes: &[0x34, 0x12, 0x0F, 0xF0],
+        expected: &[RgbaColor::new(0x11, 0x22, 0x33, 0x44), RgbaColor::new(0xFF, 0x00, 0x00, 0xFF)],
+    },
+    DecodeVector {
+        format: PicaTextureFormat::LA8,
+        bytes: &[0x80, 0x40, 0xFF, 0x00],
+        expected: &[RgbaColor::grayscale_alpha(0x40, 0x80), RgbaColor::grayscale_alpha(0x00, 0xFF)],
+    },
+    DecodeVector {
+        format: PicaTextureFormat::L8,
+        bytes: &[0x00, 0x7F, 0xFF],
+        expected: &[gray(0x00), gray(0x7F), gray(0xFF)],
+    },
+    DecodeVector {
+        format: PicaTextureFormat::A8,
+        bytes: &[0x00, 0x7F, 0xFF],
+        expected: &[alpha(0x00), alpha(0x7F), alpha(0xFF)],
+    },
+    DecodeVector {
+        format: PicaTextureFormat::LA4,
+        bytes: &[0x3C, 0xF0],
+        expected: &[RgbaColor::grayscale_alpha(0x33, 0xCC), RgbaColor::grayscale_alpha(0xFF, 0x00)],
+    },
+    // the first of two pixels sharing a byte is in the lower four bits
+    DecodeVector {
+        format: PicaTextureFormat::L4,
+        bytes: &[0x5A, 0xF0],
+        expected: &[gray(0xAA), gray(0x55), gray(0x00), gray(0xFF)],
+    },
+    DecodeVector {
+        format: PicaTextureFormat::A4,
+        bytes: &[0x5A, 0xF0],
+        expected: &[alpha(0xAA), alpha(0x55), alpha(0x00), alpha(0xFF)],
+    },
+    // individual mode with all pixels at the first modifier (+2) of table 0
+    DecodeVector {
+        format: PicaTextureFormat::ETC1,
+        bytes: &[0x00, 0x00, 0x00, 0x00, 0x00, 0x88, 0x88, 0x88],
+        expected: &[ETC_A; 16],
+    },
+    // all pixel index LSBs set, which selects the second modifier (+8)
+    DecodeVector {
+        format: PicaTextureFormat::ETC1,
+        bytes: &[0xFF, 0xFF, 0x00, 0x00, 0x00, 0x88, 0x88, 0x88],
+        expected: &[RgbaColor::new(0x90, 0x90, 0x90, 0xFF); 16],
+    },
+    // differential mode, the right half has a red delta of -1
+    DecodeVector {
+        format: PicaTextureFormat::ETC1,
+        bytes: &[0x00, 0x00, 0x00, 0x00, 0x02, 0x80, 0x80, 0x87],
+        expected: &[
+            ETC_C, ETC_C, ETC_D, ETC_D,
+            ETC_C, ETC_C, ETC_D, ETC_D,
+            ETC_C, ETC_C, ETC_D, ETC_D,
+            ETC_C, ETC_C, ETC_D, ETC_D,
+        ],
+    },
+    // the alpha block is stored column by column, only the left column is opaque
+    DecodeVector {
+        format: PicaTextureFormat::ETC1A4,
+        bytes: &[
+            0xFF, 0xFF, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x88, 0x88, 0x88,
+        ],
+        expected: &[
+            ETC_A, ETC_B, ETC_B, ETC_B,
+            ETC_A, ETC_B, ETC_B, ETC_B,
+            ETC_A, ETC_B, ETC_B, ETC_B,
+            ETC_A, ETC_B, ETC_B, ETC_B,
+        ],
+    },
+];
+
+/// Where the pixels of an 8x8 tile end up after unswizzling, row by row. Decoding an
+/// L8 tile containing the bytes 0 to 63 results in these values
+pub const SWIZZLE_ORDER: [u8; 64] = [
+     0,  1,  4,  5, 16, 17, 20, 21,
+     2,  3,  6,  7, 18, 19, 22, 23,
+     8,  9, 12, 13, 24, 25, 28, 29,
+    10, 11, 14, 15, 26, 27, 30, 31,
+    32, 33, 36, 37, 48, 49, 52, 53,
+    34, 35, 38, 39, 50, 51, 54, 55,
+    40, 41, 44, 45, 56, 57, 60, 61,
+    42, 43, 46, 47, 58, 59, 62, 63,
+];
+
+/// Runs `decode`, which works like [`decode_linear`], on all [`DECODE_VECTORS`]
+/// and fails on the first color that doesn't match.
+///
+/// ```
+/// use ctr_bcres::{image_codec::{decode_linear, decode_swizzled_buffer, DECODABLE_FORMATS},
+///     image_conformance::{verify_decoder, verify_swizzling, DECODE_VECTORS}};
+///
+/// assert!(DECODABLE_FORMATS.iter().all(|&format| DECODE_VECTORS.iter().any(|vector| vector.format == format)));
+///
+/// verify_decoder(decode_linear).unwrap();
+/// verify_swizzling(decode_swizzled_buffer).unwrap();
+/// ```
+///
+/// [`decode_linear`]: crate::image_codec::decode_linear
+pub fn verify_decoder(decode: impl Fn(&[u8], PicaTextureFormat) -> Result<Vec<RgbaColor>>) -> Result<()> {
+    for (i, vector) in DECODE_VECTORS.iter().enumerate() {
+        let decoded = decode(vector.bytes, vector.format)?;
+        
+        ensure!(decoded.len() == vector.expected.len(),
+            "Vector {i} ({:?}) decoded to {} colors, expected {}", vector.format, decoded.len(), vector.expected.len());
+        
+        for (pixel, (color, expected)) in decoded.iter().zip(vector.expected).enumerate() {
+            ensure!(color == expected,
+                "Pixel {pixel} of vector {i} ({:?}) decoded to {color:?}, expected {expected:?}", vector.format);
+        }
+    }
+    
+    Ok(())
+}
+
+/// Checks that `decode`, which works like [`decode_swizzled_buffer`], puts
+/// the pixels of a tile in the right place, see [`SWIZZLE_ORDER`]
+///
+/// [`decode_swizzled_buffer`]: crate::image_codec::decode_swizzled_buffer
+pub fn verify_swizzling(decode: impl Fn(&[u8], PicaTextureFormat, u32, u32) -> Result<Vec<RgbaColor>>) -> Result<()> {
+    let tile: Vec<u8> = (0..64).collect();
+    let decoded = decode(&tile, PicaTextureFormat::L8, 8, 8)?;
+    
+    ensure!(decoded.len() == 64, "Tile decoded to {} colors, expected 64", decoded.len());
+    
+    for (pixel, (color, &expected)) in decoded.iter().zip(&SWIZZLE_ORDER).enumerate() {
+        ensure!(*color == gray(expected),
+            "Pixel ({}, {}) of the tile decoded to {color:?}, expected {:?}", pixel % 8, pixel / 8, gray(expected));
+    }
+    
+    Ok(())
+}
@@ -0,0 +1,117 @@
+// quality metrics for comparing decoded images, for example an original
+// texture against the same texture after re-encoding it in a lossy format
+
+use anyhow::{ensure, Result};
+
+use crate::image_codec::RgbaColor;
+
+fn ensure_same_size(a: &[RgbaColor], b: &[RgbaColor]) -> Result<()> {
+    ensure!(a.len() == b.len(), "Images have different sizes ({} and {} pixels)", a.len(), b.len());
+    ensure!(!a.is_empty(), "Images are empty");
+    Ok(())
+}
+
+/// Peak signal-to-noise ratio in decibels over all four channels, infinite for
+/// identical images. Higher is better, above 40 dB differences are hard to see.
+///
+/// ```
+/// use ctr_bcres::{image_codec::{decode_swizzled_buffer, encode_swizzled_buffer, RgbaColor},
+///     image_metrics::{psnr, ssim}, texture::PicaTextureFormat};
+///
+/// let pixels: Vec<RgbaColor> = (0..64u8).map(|i| RgbaColor::new(i * 4, 255 - i * 4, i, 255)).collect();
+///
+/// let encoded = encode_swizzled_buffer(&pixels, PicaTextureFormat::RGB565, 8, 8).unwrap();
+/// let decoded = decode_swizzled_buffer(&encoded, PicaTextureFormat::RGB565, 8, 8).unwrap();
+///
+/// assert!(psnr(&pixels, &pixels).unwrap().is_infinite());
+/// assert!(psnr(&pixels, &decoded).unwrap() > 35.0);
+///
+/// assert!((ssim(&pixels, &pixels, 8, 8).unwrap() - 1.0).abs() < 1e-9);
+/// assert!(ssim(&pixels, &decoded, 8, 8).unwrap() > 0.9);
+/// ```
+pub fn psnr(a: &[RgbaColor], b: &[RgbaColor]) -> Result<f64> {
+    ensure_same_size(a, b)?;
+    
+    let squared_error: f64 = a.iter()
+        .zip(b)
+        .flat_map(|(a, b)| a.to_f32().into_iter().zip(b.to_f32()))
+        .map(|(a, b)| f64::from(a - b).powi(2))
+        .sum();
+    
+    let mse = squared_error / (a.len() * 4) as f64;
+    
+    if mse == 0.0 {
+        Ok(f64::INFINITY)
+    } else {
+        Ok(-10.0 * mse.log10())
+    }
+}
+
+// size of the windows structural similarity is computed over and the distance between them
+const SSIM_WINDOW: usize = 8;
+const SSIM_STEP: usize = 4;
+
+/// Mean structural similarity of the luminance of both images, between -1 and 1
+/// where 1 means identical. Unlike [`psnr`], this tracks how different
+/// images look to humans, like blockiness from compression.
+pub fn ssim(a: &[RgbaColor], b: &[RgbaColor], width: u32, height: u32) -> Result<f64> {
+    ensure_same_size(a, b)?;
+    ensure!(a.len() == width as usize * height as usize,
+        "Images have {} pixels, expected {width}x{height}", a.len());
+    
+    let width = width as usize;
+    let height = height as usize;
+    
+    let luma = |image: &[RgbaColor]| -> Vec<f64> {
+        image.iter().map(|color| f64::from(color.luminance()) / 255.0).collect()
+    };
+    let a = luma(a);
+    let b = luma(b);
+    
+    // images smaller than a window are compared as a whole
+    let window_width = SSIM_WINDOW.min(width);
+    let window_height = SSIM_WINDOW.min(height);
+    
+    let mut total = 0.0;
+    let mut window_count = 0;
+    
+    for y in (0..=height - window_height).step_by(SSIM_STEP) {
+        for x in (0..=width - window_width).step_by(SSIM_STEP) {
+            total += window_ssim(&a, &b, width, x, y, window_width, window_height);
+            window_count += 1;
+        }
+    }
+    
+    Ok(total / f64::from(window_count))
+}
+
+fn window_ssim(a: &[f64], b: &[f64], stride: usize, x: usize, y: usize, width: usize, height: usize) -> f64 {
+    const C1: f64 = 0.01 * 0.01;
+    const C2: f64 = 0.03 * 0.03;
+    
+    let pixels = || (y..y + height).flat_map(move |row| (x..x + width).map(move |column| row * stride + column));
+    let count = (width * height) as f64;
+    
+    let mean_a = pixels().map(|i| a[i]).sum::<f64>() / count;
+    let mean_b = pixels().map(|i| b[i]).sum::<f64>() / count;
+    
+    let mut variance_a = 0.0;
+    let mut variance_b = 0.0;
+    let mut covariance = 0.0;
+    
+    for i in pixels() {
+        let da = a[i] - mean_a;
+        let db = b[i] - mean_b;
+        
+        variance_a += da * da;
+        variance_b += db * db;
+        covariance += da * db;
+    }
+    
+    variance_a /= count;
+    variance_b /= count;
+    covariance /= count;
+    
+    ((2.0 * mean_a * mean_b + C1) * (2.0 * covariance + C2))
+        / ((mean_a * mean_a + mean_b * mean_b + C1) * (variance_a + variance_b + C2))
+}
@@ -1,25 +1,62 @@
+#![forbid(unsafe_code)]
+
 use std::{
     collections::HashMap,
+    fmt::Display,
+    mem,
     io::{Cursor, Read, Seek, SeekFrom, Write},
     str::from_utf8,
 };
 
-use anyhow::Result;
+use anyhow::{anyhow, bail, ensure, Result};
 use binrw::{BinRead, BinWrite};
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
-use util::{pointer::Pointer, util::read_string};
+use cgfx_container::CgfxContainer;
+use error::with_path;
+use limits::ParseLimits;
+use metadata::MetadataDict;
+use texture::{FormatPolicy, MipmapPolicy};
+use util::{pointer::Pointer, util::{apply_order, read_string, record_content_end, SharedBytes}};
 
+#[cfg(feature = "bch")]
+pub mod bch;
+pub mod batch;
+pub mod cache;
 pub mod cgfx_container;
+pub mod edit;
+mod etc1_encode;
+#[cfg(feature = "simd")]
+mod etc1_simd;
+pub mod error;
+pub mod fixtures;
+pub mod id;
+pub mod image_channels;
 pub mod image_codec;
+pub mod image_conformance;
+pub mod image_metrics;
+pub mod limits;
+pub mod magic;
+pub mod memory;
+pub mod metadata;
 pub mod model;
+pub mod pica;
+pub mod plugin;
+pub mod progress;
+pub mod reflect;
+pub mod rig;
+pub mod scene;
+pub mod stats;
 pub mod texture;
+pub mod texture_import;
 
 pub mod util;
 
+pub use scene::Scene;
+
 pub fn get_4_byte_string(reader: &mut impl Read) -> Result<String> {
     let mut bytes: [u8; 4] = [0; 4];
     reader.read_exact(&mut bytes)?;
-
+    
     Ok(from_utf8(&bytes)?.to_string())
 }
 
@@ -51,7 +88,7 @@ pub struct ReaderGuard<'a, R: Read + Seek> {
 impl<'a, R: Read + Seek> ReaderGuard<'a, R> {
     pub fn new(reader: &'a mut R) -> Self {
         let start_pos = reader.stream_position().unwrap();
-
+        
         Self { reader, start_pos }
     }
 }
@@ -70,8 +107,153 @@ macro_rules! scoped_reader_pos {
     };
 }
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ReadOptions {
+    /// Keep a copy of the parsed file in [`CgfxContainer::source`] so that
+    /// [`CgfxContainer::raw`] can return the original bytes of every object
+    pub retain_source: bool,
+    /// Bounds on counts and sizes, checked while parsing
+    pub limits: ParseLimits,
+    /// Which format textures whose formats disagree get decoded with
+    pub format_policy: FormatPolicy,
+    /// Parse image and vertex data as views into one copy of the file instead of
+    /// allocating a buffer for each of them. The copy is kept alive as long as any
+    /// of them is, and modifying one copies its bytes out first
+    #[cfg(feature = "arena")]
+    pub arena: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WriteOptions {
+    /// Alignment of the image data following the IMAG section header
+    pub section_alignment: u32,
+    /// Alignment of every individual image within the image data
+    pub image_alignment: u32,
+    /// Byte used for all padding
+    pub pad_byte: u8,
+    /// Write the parsed values of [`RuntimeHandle`](util::util::RuntimeHandle)s
+    /// instead of zero
+    pub preserve_runtime_handles: bool,
+    /// What to do with the mipmaps of textures that have no entry in `texture_mipmaps`
+    pub mipmaps: MipmapPolicy,
+    /// Mipmap policy of individual textures, by name
+    pub texture_mipmaps: HashMap<String, MipmapPolicy>,
+    /// Largest file_length the written file may have, like the length of the original
+    /// file when overwriting it in place inside of a ROM image
+    pub max_file_length: Option<u64>,
+    /// What to do when the file would be longer than `max_file_length`
+    pub size_limit_policy: SizeLimitPolicy,
+}
+
+/// See [`WriteOptions::size_limit_policy`]
+///
+/// ```
+/// use ctr_bcres::{cgfx_container::CgfxContainer, fixtures, SizeLimitPolicy, WriteOptions};
+///
+/// let container = CgfxContainer::new(&fixtures::minimal_bcres()).unwrap();
+///
+/// let mut options = WriteOptions::from_container(&container);
+/// let file_length = container.to_buffer_with_options(&options).unwrap().len() as u64;
+/// options.max_file_length = Some(file_length - 1);
+/// assert!(container.to_buffer_with_options(&options).is_err());
+///
+/// // the RGBA8 texture gets re-encoded as RGBA4
+/// options.size_limit_policy = SizeLimitPolicy::CompressTextures;
+/// assert!(container.to_buffer_with_options(&options).unwrap().len() as u64 <= file_length - 1);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SizeLimitPolicy {
+    /// Fail with an error
+    #[default]
+    Error,
+    /// Strip mipmaps and then re-encode textures in formats with fewer bits per pixel,
+    /// largest textures first, until the file fits. Fails if that isn't enough
+    CompressTextures,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        Self {
+            section_alignment: 128,
+            image_alignment: 128,
+            pad_byte: 0,
+            preserve_runtime_handles: false,
+            mipmaps: MipmapPolicy::Keep,
+            texture_mipmaps: HashMap::new(),
+            max_file_length: None,
+            size_limit_policy: SizeLimitPolicy::Error,
+        }
+    }
+}
+
+fn largest_alignment(offset: u32, max_alignment: u32) -> u32 {
+    if offset == 0 {
+        max_alignment
+    } else {
+        (1 << offset.trailing_zeros()).min(max_alignment)
+    }
+}
+
+impl WriteOptions {
+    pub fn mipmap_policy(&self, texture_name: Option<&str>) -> MipmapPolicy {
+        texture_name
+            .and_then(|name| self.texture_mipmaps.get(name))
+            .copied()
+            .unwrap_or(self.mipmaps)
+    }
+    
+    /// Guesses the options a parsed file was written with so that writing it
+    /// again produces matching output. Falls back to the defaults for
+    /// everything that can't be derived.
+    ///
+    /// `max_file_length` stays `None`, since edited files are allowed to grow. Set it to
+    /// the parsed `header.file_length` to overwrite the original file in place:
+    ///
+    /// ```
+    /// use ctr_bcres::{cgfx_container::CgfxContainer, fixtures, WriteOptions};
+    ///
+    /// let container = CgfxContainer::new(&fixtures::minimal_bcres()).unwrap();
+    ///
+    /// let mut options = WriteOptions::from_container(&container);
+    /// assert_eq!(options.max_file_length, None);
+    ///
+    /// options.max_file_length = Some(container.header.file_length.into());
+    /// assert!(container.calculate_size(&options).is_ok());
+    /// ```
+    pub fn from_container(container: &CgfxContainer) -> Self {
+        let mut options = Self::default();
+        
+        let image_pointers: Vec<u32> = container.textures.iter()
+            .flat_map(|textures| textures.values())
+            .flat_map(|texture| texture.images())
+            .filter_map(|image| image.buffer_pointer())
+            .map(u32::from)
+            .collect();
+        
+        if image_pointers.is_empty() {
+            return options;
+        }
+        
+        let image_data_start = u32::from(container.header.header_length) + container.header.content_length + 8;
+        options.section_alignment = largest_alignment(image_data_start, options.section_alignment);
+        
+        let image_offsets = image_pointers.iter()
+            .filter_map(|pointer| pointer.checked_sub(image_data_start));
+        
+        for offset in image_offsets {
+            if offset != 0 {
+                options.image_alignment = largest_alignment(offset, options.image_alignment);
+            }
+        }
+        
+        options
+    }
+}
+
 #[derive(Default)]
 pub struct WriteContext {
+    options: WriteOptions,
+    
     string_section: String,
     string_references: HashMap<Pointer, String>,
     
@@ -79,6 +261,27 @@ pub struct WriteContext {
     // keys in image_references are relative to entire file
     // values are relative to the image section
     image_references: HashMap<Pointer, Pointer>,
+    
+    // every dict value written so far and the indices of the ones still being written
+    written_objects: Vec<WrittenValue>,
+    open_objects: Vec<usize>,
+    
+    // vertex data written so far and where, so that shared buffers only get written once
+    vertex_bytes: Vec<(SharedBytes, Pointer)>,
+    
+    // metadata dicts and the pointers to them, written after all objects
+    metadata_references: Vec<(Pointer, MetadataDict)>,
+}
+
+// byte ranges of a dict value, image offsets are relative to the image section
+#[derive(Clone, Debug)]
+pub(crate) struct WrittenValue {
+    pub name: Option<String>,
+    /// Amount of values this one was written inside of
+    pub depth: usize,
+    pub start: u64,
+    pub end: u64,
+    pub image_range: Option<(u64, u64)>,
 }
 
 impl WriteContext {
@@ -86,8 +289,20 @@ impl WriteContext {
         Self::default()
     }
     
+    pub fn with_options(options: WriteOptions) -> Self {
+        Self {
+            options,
+            ..Default::default()
+        }
+    }
+    
+    pub fn options(&self) -> &WriteOptions {
+        &self.options
+    }
+    
     pub fn add_string(&mut self, string: &str) -> Result<()> {
-        if self.string_section.contains(string) {
+        // match the terminator too so that "tex" doesn't get merged into "texture"
+        if self.string_section.contains(&format!("{string}\0")) {
             // string exists already, exiting early
             return Ok(());
         }
@@ -101,15 +316,118 @@ impl WriteContext {
         self.string_references.insert(origin, target_string);
     }
     
+    /// Writes `metadata` once all objects are written and points the relative pointer at `origin` to it
+    pub fn add_metadata_reference(&mut self, origin: Pointer, metadata: MetadataDict) {
+        self.metadata_references.push((origin, metadata));
+    }
+    
+    pub(crate) fn take_metadata_references(&mut self) -> Vec<(Pointer, MetadataDict)> {
+        mem::take(&mut self.metadata_references)
+    }
+    
     pub fn append_to_image_section(&mut self, content: &[u8]) -> Result<()> {
+        let start = self.image_section.len() as u64;
+        
         // because binrw overwrites Vec::write
         // that's why you don't use "write" as a function name for a method
         // you are extending almost every fucking collection with
         Write::write(&mut self.image_section, content)?;
+        
+        let end = self.image_section.len() as u64;
+        
+        for &index in &self.open_objects {
+            let range = &mut self.written_objects[index].image_range;
+            *range = Some(range.map_or((start, end), |(start, _)| (start, end)));
+        }
+        
+        Ok(())
+    }
+    
+    fn begin_object(&mut self, name: Option<String>, start: u64) {
+        self.written_objects.push(WrittenValue {
+            name,
+            depth: self.open_objects.len(),
+            start,
+            end: start,
+            image_range: None,
+        });
+        self.open_objects.push(self.written_objects.len() - 1);
+    }
+    
+    fn end_object(&mut self, end: u64) {
+        if let Some(index) = self.open_objects.pop() {
+            self.written_objects[index].end = end;
+        }
+    }
+    
+    /// Takes all dict values written since the last call, in the order they were started
+    pub(crate) fn take_written_objects(&mut self) -> Vec<WrittenValue> {
+        self.open_objects.clear();
+        mem::take(&mut self.written_objects)
+    }
+    
+    /// Where vertex data that shares its memory with `bytes` was written already, see
+    /// [`CgfxModelCommon::share_vertex_buffers`](model::CgfxModelCommon::share_vertex_buffers)
+    pub fn written_vertex_bytes(&self, bytes: &SharedBytes) -> Option<Pointer> {
+        self.vertex_bytes.iter()
+            .find(|(written, _)| written.is_shared_with(bytes))
+            .map(|&(_, location)| location)
+    }
+    
+    pub fn add_written_vertex_bytes(&mut self, bytes: &SharedBytes, location: Pointer) {
+        self.vertex_bytes.push((bytes.clone(), location));
+    }
+    
+    /// Takes over everything `other` collected while writing a buffer starting at zero,
+    /// for when that buffer gets written at `offset`. Leads to the same state as if
+    /// it had been written with this context directly
+    #[cfg(feature = "parallel")]
+    fn append(&mut self, offset: Pointer, other: WriteContext) -> Result<()> {
+        for string in other.string_section.split_terminator('\0') {
+            self.add_string(string)?;
+        }
+        
+        for (location, string) in other.string_references {
+            self.string_references.insert(location + offset, string);
+        }
+        
+        // images are aligned relative to the start of the image section
+        let mut image_offset = self.image_section.len();
+        
+        if !other.image_section.is_empty() {
+            let alignment: usize = self.options.image_alignment.try_into()?;
+            image_offset = image_offset.next_multiple_of(alignment);
+            self.image_section.resize(image_offset, self.options.pad_byte);
+            self.append_to_image_section(&other.image_section)?;
+        }
+        
+        for (location, image_location) in other.image_references {
+            self.image_references.insert(location + offset, image_location + Pointer::from(image_offset));
+        }
+        
+        let depth = self.open_objects.len();
+        let image_offset = image_offset as u64;
+        
+        self.written_objects.extend(other.written_objects.into_iter().map(|object| WrittenValue {
+            depth: object.depth + depth,
+            start: object.start + u64::from(offset),
+            end: object.end + u64::from(offset),
+            image_range: object.image_range.map(|(start, end)| (start + image_offset, end + image_offset)),
+            ..object
+        }));
+        
+        self.vertex_bytes.extend(other.vertex_bytes.into_iter().map(|(bytes, location)| (bytes, location + offset)));
+        self.metadata_references.extend(other.metadata_references.into_iter().map(|(location, metadata)| (location + offset, metadata)));
+        
         Ok(())
     }
     
+    /// Marks the start of a new image, so the image section gets padded to image_alignment first
     pub fn add_image_reference_to_current_end(&mut self, origin: Pointer) -> Result<()> {
+        let alignment: usize = self.options.image_alignment.try_into()?;
+        let padded_length = self.image_section.len().next_multiple_of(alignment);
+        self.image_section.resize(padded_length, self.options.pad_byte);
+        
         self.image_references.insert(origin, self.image_section.len().into());
         Ok(())
     }
@@ -129,13 +447,30 @@ where
     fn read_dict_value<R: Read + Seek>(reader: &mut R) -> Result<Self> {
         Ok(Self::read_le(reader)?)
     }
-
+    
     fn write_dict_value<W: Write + Seek>(&self, writer: &mut W, _ctx: &mut WriteContext) -> Result<()> {
         self.write_le(writer)?;
         Ok(())
     }
 }
 
+/// Location of a parsed object in the file it was read from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SourceSpan {
+    pub offset: u64,
+    /// Amount of bytes of the object itself, not including the objects it points to
+    pub length: u64,
+}
+
+impl SourceSpan {
+    pub fn slice<'a>(&self, source: &'a [u8]) -> Option<&'a [u8]> {
+        let start: usize = self.offset.try_into().ok()?;
+        let end = start.checked_add(self.length.try_into().ok()?)?;
+        
+        source.get(start..end)
+    }
+}
+
 #[derive(Debug, Default, Clone, PartialEq)]
 pub struct CgfxNode<T: CgfxCollectionValue> {
     pub reference_bit: u32,
@@ -145,6 +480,8 @@ pub struct CgfxNode<T: CgfxCollectionValue> {
     pub name: Option<String>,
     
     pub value_pointer: Option<Pointer>,
+    /// Where the value was parsed from, None if it wasn't parsed
+    pub value_span: Option<SourceSpan>,
     pub value: Option<T>,
 }
 
@@ -166,13 +503,24 @@ impl<T: CgfxCollectionValue> CgfxNode<T> {
             None
         };
         
-        let value = if let Some(value_pointer) = value_pointer {
+        let (value, value_span) = if let Some(value_pointer) = value_pointer {
             scoped_reader_pos!(reader);
             reader.seek(SeekFrom::Start(value_pointer.into()))?;
             
-            Some(T::read_dict_value(reader)?)
+            #[cfg(feature = "tracing")]
+            let _span = tracing::trace_span!("node", name = name.as_deref(), offset = u64::from(value_pointer)).entered();
+            
+            let segment = || name.clone().unwrap_or_else(|| "<unnamed>".to_string());
+            let value = with_path(T::read_dict_value(reader), segment, value_pointer.into())?;
+            
+            let span = SourceSpan {
+                offset: value_pointer.into(),
+                length: reader.stream_position()? - u64::from(value_pointer),
+            };
+            
+            (Some(value), Some(span))
         } else {
-            None
+            (None, None)
         };
         
         Ok(CgfxNode {
@@ -183,10 +531,16 @@ impl<T: CgfxCollectionValue> CgfxNode<T> {
             name,
             
             value_pointer,
+            value_span,
             value,
         })
     }
     
+    /// Original bytes of the value inside of `source`, the file it was parsed from
+    pub fn raw<'a>(&self, source: &'a [u8]) -> Option<&'a [u8]> {
+        self.value_span?.slice(source)
+    }
+    
     pub fn to_writer<W: Write + Seek>(&self, writer: &mut W, ctx: &mut WriteContext) -> Result<Pointer> {
         writer.write_u32::<LittleEndian>(self.reference_bit)?;
         writer.write_u16::<LittleEndian>(self.left_node_index)?;
@@ -207,6 +561,45 @@ impl<T: CgfxCollectionValue> CgfxNode<T> {
     }
 }
 
+/// What to do when a value gets added under a name that already exists
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CollisionPolicy {
+    /// Fail with an error
+    #[default]
+    Error,
+    /// Replace the existing value
+    Overwrite,
+    /// Append `_1`, `_2`, ... to the new name until it's unique
+    AutoSuffix,
+}
+
+/// Inconsistency inside of a dict, see [`CgfxDict::validate`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DictIssue {
+    /// `values_count` doesn't match the amount of nodes besides the root node
+    ValuesCount { values_count: u32, node_count: usize },
+    TreeLength { tree_length: u32, expected: u32 },
+    /// A child index of a node points past the last node
+    ChildOutOfBounds { node: usize, child: u16 },
+    /// A named node can't be found by walking the tree with its name
+    Unreachable { node: usize, name: String },
+}
+
+impl Display for DictIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DictIssue::ValuesCount { values_count, node_count } =>
+                write!(f, "values_count is {values_count} but there are {node_count} nodes besides the root"),
+            DictIssue::TreeLength { tree_length, expected } =>
+                write!(f, "tree_length is {tree_length} but should be {expected}"),
+            DictIssue::ChildOutOfBounds { node, child } =>
+                write!(f, "node {node} has child {child}, which doesn't exist"),
+            DictIssue::Unreachable { node, name } =>
+                write!(f, "node {node} ({name:?}) can't be found through the tree"),
+        }
+    }
+}
+
 #[derive(Debug, Default, Clone, PartialEq)]
 pub struct CgfxDict<T: CgfxCollectionValue> {
     pub magic_number: String,
@@ -216,6 +609,24 @@ pub struct CgfxDict<T: CgfxCollectionValue> {
 }
 
 impl<T: CgfxCollectionValue> CgfxDict<T> {
+    /// Creates a dict containing only the root node
+    pub fn new() -> Self {
+        Self {
+            magic_number: "DICT".to_string(),
+            tree_length: 28,
+            values_count: 0,
+            nodes: vec![CgfxNode {
+                reference_bit: u32::MAX,
+                left_node_index: 0,
+                right_node_index: 0,
+                name: None,
+                value_pointer: None,
+                value_span: None,
+                value: None,
+            }],
+        }
+    }
+    
     pub fn from_buffer(buffer: &[u8], start_position: Pointer) -> Result<Self> {
         let mut cursor = Cursor::new(buffer);
         cursor.set_position(start_position.into());
@@ -224,14 +635,26 @@ impl<T: CgfxCollectionValue> CgfxDict<T> {
     }
     
     pub fn from_reader<R: Read + Seek>(reader: &mut R) -> Result<Self> {
+        Self::from_reader_with_progress(reader, || Ok(()))
+    }
+    
+    /// `on_node` gets called after every parsed node, including the root node
+    pub fn from_reader_with_progress<R: Read + Seek>(reader: &mut R, mut on_node: impl FnMut() -> Result<()>) -> Result<Self> {
         let magic_number = get_4_byte_string(reader)?;
         let tree_length = reader.read_u32::<LittleEndian>()?;
         let values_count = reader.read_u32::<LittleEndian>()?;
+        limits::check_dict_entries(values_count)?;
         
         let nodes = (0..values_count + 1)
-            .map(|_| CgfxNode::from_reader(reader))
+            .map(|_| {
+                let node = CgfxNode::from_reader(reader)?;
+                on_node()?;
+                Ok(node)
+            })
             .collect::<Result<Vec<CgfxNode<T>>>>()?;
         
+        record_content_end(reader.stream_position()?);
+        
         Ok(CgfxDict {
             magic_number,
             tree_length,
@@ -240,17 +663,414 @@ impl<T: CgfxCollectionValue> CgfxDict<T> {
         })
     }
     
+    /// Reads only the value named `name` out of the dict at the reader's position,
+    /// without parsing any of the other values. Only the nodes along the path to the
+    /// key in the radix tree get read. The reader position is restored afterwards.
+    pub fn read_value_on_demand<R: Read + Seek>(reader: &mut R, name: &str) -> Result<Option<T>> {
+        scoped_reader_pos!(reader);
+        
+        let dict_start = reader.stream_position()?;
+        let magic_number = get_4_byte_string(reader)?;
+        ensure!(magic_number == "DICT", "Invalid magic number for dict, expected 'DICT' but got {magic_number:?}");
+        
+        let _tree_length = reader.read_u32::<LittleEndian>()?;
+        let values_count = reader.read_u32::<LittleEndian>()?;
+        
+        let seek_node = |reader: &mut R, index: u16| {
+            ensure!(u32::from(index) <= values_count, "Node index {index} is out of bounds");
+            reader.seek(SeekFrom::Start(dict_start + 12 + u64::from(index) * 16))?;
+            Ok(())
+        };
+        
+        seek_node(reader, 0)?;
+        let mut parent_bit = reader.read_u32::<LittleEndian>()?;
+        let mut index = reader.read_u16::<LittleEndian>()?;
+        
+        // the bits stop decreasing once the closest key is reached, which is the only
+        // one that can match. a valid tree can't be deeper than the amount of nodes
+        for _ in 0..=values_count {
+            seek_node(reader, index)?;
+            let bit = reader.read_u32::<LittleEndian>()?;
+            let left_node_index = reader.read_u16::<LittleEndian>()?;
+            let right_node_index = reader.read_u16::<LittleEndian>()?;
+            
+            if bit < parent_bit {
+                parent_bit = bit;
+                index = if key_bit(name, bit) { right_node_index } else { left_node_index };
+                continue;
+            }
+            
+            let node_name = match Pointer::read_relative(reader)? {
+                Some(name_pointer) => {
+                    reader.seek(SeekFrom::Start(name_pointer.into()))?;
+                    Some(read_string(reader)?)
+                },
+                None => None,
+            };
+            
+            if node_name.as_deref() != Some(name) {
+                return Ok(None);
+            }
+            
+            seek_node(reader, index)?;
+            return Ok(CgfxNode::<T>::from_reader(reader)?.value);
+        }
+        
+        bail!("Dict contains a cycle")
+    }
+    
+    pub fn get(&self, name: &str) -> Option<&T> {
+        self.nodes.iter()
+            .find(|node| node.name.as_deref() == Some(name))
+            .and_then(|node| node.value.as_ref())
+    }
+    
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut T> {
+        self.nodes.iter_mut()
+            .find(|node| node.name.as_deref() == Some(name))
+            .and_then(|node| node.value.as_mut())
+    }
+    
+    pub fn contains(&self, name: &str) -> bool {
+        self.nodes.iter().any(|node| node.name.as_deref() == Some(name))
+    }
+    
+    /// Adds `value` under `name`, returning the name it actually ended up under.
+    /// That only differs from `name` with [`CollisionPolicy::AutoSuffix`]
+    pub fn insert(&mut self, name: &str, value: T, policy: CollisionPolicy) -> Result<String> {
+        if self.nodes.is_empty() {
+            *self = Self::new();
+        }
+        
+        let name = if self.contains(name) {
+            match policy {
+                CollisionPolicy::Error => bail!("Dict already contains an entry named {name:?}"),
+                CollisionPolicy::Overwrite => {
+                    let node = self.nodes.iter_mut()
+                        .find(|node| node.name.as_deref() == Some(name))
+                        .unwrap();
+                    
+                    node.value = Some(value);
+                    node.value_pointer = None;
+                    node.value_span = None;
+                    return Ok(name.to_string());
+                },
+                CollisionPolicy::AutoSuffix => (1..)
+                    .map(|i| format!("{name}_{i}"))
+                    .find(|candidate| !self.contains(candidate))
+                    .unwrap(),
+            }
+        } else {
+            name.to_string()
+        };
+        
+        ensure!(self.nodes.len() < u16::MAX as usize, "Dict can not hold more than {} entries", u16::MAX - 1);
+        
+        self.nodes.push(CgfxNode {
+            reference_bit: 0,
+            left_node_index: 0,
+            right_node_index: 0,
+            name: Some(name.clone()),
+            value_pointer: None,
+            value_span: None,
+            value: Some(value),
+        });
+        self.link_node(self.nodes.len() - 1)?;
+        
+        self.update_counts()?;
+        Ok(name)
+    }
+    
+    /// Renames the entry `from` to `to`, rebuilding the tree
+    pub fn rename(&mut self, from: &str, to: &str) -> Result<()> {
+        ensure!(!to.is_empty(), "Dict keys can not be empty");
+        ensure!(!self.contains(to), "Dict already contains an entry named {to:?}");
+        
+        let node = self.nodes.iter_mut()
+            .find(|node| node.name.as_deref() == Some(from))
+            .ok_or_else(|| anyhow!("Dict has no entry named {from:?}"))?;
+        
+        node.name = Some(to.to_string());
+        self.rebuild_tree()
+    }
+    
+    /// Moves the entries into the order given by `order`, where `order[i]` is the current
+    /// index of the entry that ends up at index `i`, not counting the root node. Returns
+    /// the new index of every entry by its old index
+    pub fn reorder(&mut self, order: &[usize]) -> Result<Vec<usize>> {
+        ensure!(!self.nodes.is_empty(), "Dict has no root node");
+        
+        // entries are left untouched if the order is invalid
+        let mut entries = self.nodes.split_off(1);
+        let new_indices = apply_order(&mut entries, order);
+        
+        self.nodes.extend(entries);
+        let new_indices = new_indices?;
+        self.rebuild_tree()?;
+        
+        Ok(new_indices)
+    }
+    
+    /// Removes the entry `name`, returning its value if it was parsed
+    pub fn remove(&mut self, name: &str) -> Result<Option<T>> {
+        let index = self.nodes.iter()
+            .position(|node| node.name.as_deref() == Some(name))
+            .ok_or_else(|| anyhow!("Dict has no entry named {name:?}"))?;
+        
+        let node = self.nodes.remove(index);
+        self.normalize()?;
+        Ok(node.value)
+    }
+    
+    /// Keeps only the entries for which `keep` returns true. Entries whose value
+    /// wasn't parsed are passed None
+    pub fn retain(&mut self, mut keep: impl FnMut(&str, Option<&T>) -> bool) -> Result<()> {
+        ensure!(!self.nodes.is_empty(), "Dict has no root node");
+        
+        let mut entries = self.nodes.split_off(1);
+        entries.retain(|node| keep(node.name.as_deref().unwrap_or_default(), node.value.as_ref()));
+        
+        self.nodes.extend(entries);
+        self.normalize()
+    }
+    
+    /// Removes all entries, keeping only the root node
+    pub fn clear(&mut self) {
+        let magic_number = std::mem::take(&mut self.magic_number);
+        *self = Self { magic_number, ..Self::new() };
+    }
+    
+    /// Gives `edit` mutable access to all nodes including the root node, then fixes
+    /// up `values_count`, `tree_length` and the tree, so that nodes can be added or
+    /// removed freely
+    ///
+    /// ```
+    /// use ctr_bcres::{cgfx_container::CgfxContainer, fixtures};
+    ///
+    /// let mut container = CgfxContainer::new(&fixtures::minimal_bcres()).unwrap();
+    /// let textures = container.textures.as_mut().unwrap();
+    ///
+    /// let texture = textures.edit_nodes(|nodes| nodes.pop()).unwrap();
+    /// assert!(texture.is_some());
+    /// assert_eq!((textures.values_count, textures.tree_length), (0, 28));
+    /// ```
+    pub fn edit_nodes<R>(&mut self, edit: impl FnOnce(&mut Vec<CgfxNode<T>>) -> R) -> Result<R> {
+        let result = edit(&mut self.nodes);
+        
+        // a root node that got removed comes back, so that the dict stays writable
+        if self.nodes.first().is_none_or(|root| root.name.is_some()) {
+            self.nodes.insert(0, Self::new().nodes.remove(0));
+        }
+        
+        self.normalize()?;
+        Ok(result)
+    }
+    
+    /// Amount of entries, not counting the root node
+    pub fn len(&self) -> usize {
+        self.nodes.len().saturating_sub(1)
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    
+    /// Recomputes `values_count`, `tree_length` and the tree from the nodes,
+    /// needed after adding or removing nodes by hand
+    pub fn normalize(&mut self) -> Result<()> {
+        self.update_counts()?;
+        self.rebuild_tree()
+    }
+    
+    fn update_counts(&mut self) -> Result<()> {
+        self.values_count = self.len().try_into()?;
+        self.tree_length = tree_length(self.values_count);
+        Ok(())
+    }
+    
+    /// Recomputes the reference bits and child indices of all nodes from their names,
+    /// needed after renaming or removing nodes
+    pub fn rebuild_tree(&mut self) -> Result<()> {
+        ensure!(!self.nodes.is_empty(), "Dict has no root node");
+        
+        let root = &mut self.nodes[0];
+        root.reference_bit = u32::MAX;
+        root.left_node_index = 0;
+        root.right_node_index = 0;
+        
+        for i in 1..self.nodes.len() {
+            self.link_node(i)?;
+        }
+        
+        Ok(())
+    }
+    
+    // inserts an already pushed node into the radix tree, keys are compared bitwise
+    // starting from the last character
+    fn link_node(&mut self, index: usize) -> Result<()> {
+        let name = self.nodes[index].name.clone().unwrap_or_default();
+        let bit = |node: usize| self.nodes[node].reference_bit;
+        let child = |node: usize| {
+            let node = &self.nodes[node];
+            
+            if key_bit(&name, node.reference_bit) {
+                node.right_node_index as usize
+            } else {
+                node.left_node_index as usize
+            }
+        };
+        
+        // find the closest existing key, which is where the bits stop decreasing
+        let mut parent = 0;
+        let mut current = self.nodes[0].left_node_index as usize;
+        
+        while bit(parent) > bit(current) {
+            parent = current;
+            current = child(current);
+        }
+        
+        let closest = self.nodes[current].name.as_deref().unwrap_or_default();
+        let reference_bit = reference_bit(&name, closest)
+            .ok_or_else(|| anyhow!("Dict already contains an entry named {name:?}"))?;
+        
+        // find where to insert the node so that bits keep decreasing along the path
+        let mut parent = 0;
+        let mut current = self.nodes[0].left_node_index as usize;
+        
+        while bit(parent) > bit(current) && bit(current) > reference_bit {
+            parent = current;
+            current = child(current);
+        }
+        
+        let goes_right = key_bit(&name, self.nodes[parent].reference_bit);
+        let index_u16: u16 = index.try_into()?;
+        let current_u16: u16 = current.try_into()?;
+        
+        let node = &mut self.nodes[index];
+        node.reference_bit = reference_bit;
+        
+        if key_bit(&name, reference_bit) {
+            node.left_node_index = current_u16;
+            node.right_node_index = index_u16;
+        } else {
+            node.left_node_index = index_u16;
+            node.right_node_index = current_u16;
+        }
+        
+        if goes_right {
+            self.nodes[parent].right_node_index = index_u16;
+        } else {
+            self.nodes[parent].left_node_index = index_u16;
+        }
+        
+        Ok(())
+    }
+    
+    pub fn values(&self) -> impl Iterator<Item = &T> {
+        self.nodes.iter().filter_map(|node| node.value.as_ref())
+    }
+    
+    /// Names and values of all nodes that have both, in node order
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &T)> + Clone {
+        self.nodes.iter().filter_map(|node| Some((node.name.as_deref()?, node.value.as_ref()?)))
+    }
+    
+    /// Checks the counts and the radix tree of the dict, returning every
+    /// inconsistency that was found
+    pub fn validate(&self) -> Vec<DictIssue> {
+        let mut issues = Vec::new();
+        let node_count = self.nodes.len().saturating_sub(1);
+        
+        if self.values_count as usize != node_count {
+            issues.push(DictIssue::ValuesCount { values_count: self.values_count, node_count });
+        }
+        
+        let expected = tree_length(self.values_count);
+        if self.tree_length != expected {
+            issues.push(DictIssue::TreeLength { tree_length: self.tree_length, expected });
+        }
+        
+        let mut tree_valid = true;
+        
+        for (i, node) in self.nodes.iter().enumerate() {
+            for child in [node.left_node_index, node.right_node_index] {
+                if child as usize >= self.nodes.len() {
+                    issues.push(DictIssue::ChildOutOfBounds { node: i, child });
+                    tree_valid = false;
+                }
+            }
+        }
+        
+        // walking a tree with broken links would only produce follow-up errors
+        if tree_valid {
+            for (i, node) in self.nodes.iter().enumerate().skip(1) {
+                let Some(name) = &node.name else {
+                    continue;
+                };
+                
+                if self.lookup(name) != Some(i) {
+                    issues.push(DictIssue::Unreachable { node: i, name: name.clone() });
+                }
+            }
+        }
+        
+        issues
+    }
+    
+    // finds a node the way the game does, by walking the radix tree
+    fn lookup(&self, name: &str) -> Option<usize> {
+        let mut parent = self.nodes.first()?;
+        let mut index = parent.left_node_index as usize;
+        
+        // a valid tree can't be deeper than the amount of nodes
+        for _ in 0..self.nodes.len() {
+            let node = self.nodes.get(index)?;
+            
+            if node.reference_bit >= parent.reference_bit {
+                return (node.name.as_deref() == Some(name)).then_some(index);
+            }
+            
+            parent = node;
+            index = if key_bit(name, node.reference_bit) {
+                node.right_node_index as usize
+            } else {
+                node.left_node_index as usize
+            };
+        }
+        
+        None
+    }
+    
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.nodes.iter_mut().filter_map(|node| node.value.as_mut())
+    }
+    
+    /// Writes the dict with `values_count` and `tree_length` computed from the
+    /// nodes, the stored values are ignored
     pub fn to_writer<W: Write + Seek>(&self, writer: &mut W, ctx: &mut WriteContext) -> Result<()> {
-        assert!(self.values_count + 1 == self.nodes.len() as u32, "values_count does not match node count");
+        self.to_writer_with_locations(writer, ctx)?;
+        Ok(())
+    }
+    
+    // like to_writer, returns where the value of every node was written
+    pub(crate) fn to_writer_with_locations<W: Write + Seek>(&self, writer: &mut W, ctx: &mut WriteContext)
+        -> Result<Vec<Option<Pointer>>>
+    {
+        ensure!(!self.nodes.is_empty(), "Dict has no root node");
+        let values_count: u32 = self.len().try_into()?;
         
         write!(writer, "{}", self.magic_number)?;
-        writer.write_u32::<LittleEndian>(self.tree_length)?;
-        writer.write_u32::<LittleEndian>(self.values_count)?;
+        writer.write_u32::<LittleEndian>(tree_length(values_count))?;
+        writer.write_u32::<LittleEndian>(values_count)?;
         
-        for node in &self.nodes {
-            let value_pointer_location = node.to_writer(writer, ctx)?;
-            
-            // TODO: when are the values serialized? here or in a separate loop
+        // all nodes come first, then the values in the same order
+        let value_pointer_locations = self.nodes.iter()
+            .map(|node| node.to_writer(writer, ctx))
+            .collect::<Result<Vec<Pointer>>>()?;
+        
+        let mut value_locations = vec![None; self.nodes.len()];
+        
+        for ((node, value_pointer_location), value_location) in self.nodes.iter().zip(value_pointer_locations).zip(&mut value_locations) {
             if let Some(value) = &node.value {
                 // update value pointer to point to current location
                 let current_offset = Pointer::current(writer)?;
@@ -259,10 +1079,201 @@ impl<T: CgfxCollectionValue> CgfxDict<T> {
                 write_at_pointer(writer, value_pointer_location, relative_value_offset.into())?;
                 
                 // write value
+                *value_location = Some(current_offset);
+                ctx.begin_object(node.name.clone(), current_offset.into());
                 value.write_dict_value(writer, ctx)?;
+                ctx.end_object(Pointer::current(writer)?.into());
             }
         }
         
+        Ok(value_locations)
+    }
+}
+
+#[cfg(feature = "parallel")]
+impl<T: CgfxCollectionValue + Sync> CgfxDict<T> {
+    /// Writes the dict like [`CgfxDict::to_writer`], giving the same bytes, but with
+    /// every value written into a buffer of its own on one thread per core first.
+    /// Values must not depend on what other values of the dict wrote, like vertex
+    /// data shared between models
+    pub(crate) fn to_writer_parallel<W: Write + Seek>(&self, writer: &mut W, ctx: &mut WriteContext) -> Result<()> {
+        ensure!(!self.nodes.is_empty(), "Dict has no root node");
+        let values_count: u32 = self.len().try_into()?;
+        
+        write!(writer, "{}", self.magic_number)?;
+        writer.write_u32::<LittleEndian>(tree_length(values_count))?;
+        writer.write_u32::<LittleEndian>(values_count)?;
+        
+        let value_pointer_locations = self.nodes.iter()
+            .map(|node| node.to_writer(writer, ctx))
+            .collect::<Result<Vec<Pointer>>>()?;
+        
+        // every value starts at zero in its own buffer and context
+        let options = ctx.options();
+        let write_value = |node: &CgfxNode<T>| -> Result<Option<(Vec<u8>, WriteContext)>> {
+            let Some(value) = &node.value else {
+                return Ok(None);
+            };
+            
+            let mut value_ctx = WriteContext::with_options(options.clone());
+            let mut buffer = Cursor::new(Vec::new());
+            
+            value_ctx.begin_object(node.name.clone(), 0);
+            value.write_dict_value(&mut buffer, &mut value_ctx)?;
+            value_ctx.end_object(buffer.position());
+            
+            Ok(Some((buffer.into_inner(), value_ctx)))
+        };
+        
+        let workers = batch::default_worker_count();
+        let chunk_size = self.nodes.len().div_ceil(workers).max(1);
+        
+        let written: Vec<Option<(Vec<u8>, WriteContext)>> = std::thread::scope(|scope| {
+            let handles: Vec<_> = self.nodes.chunks(chunk_size)
+                .map(|chunk| scope.spawn(|| chunk.iter().map(write_value).collect::<Result<Vec<_>>>()))
+                .collect();
+            
+            handles.into_iter()
+                .map(|handle| handle.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)))
+                .collect::<Result<Vec<_>>>()
+        })?.into_iter().flatten().collect();
+        
+        // then they get appended in order, moving everything they refer to along
+        for (value_pointer_location, written) in value_pointer_locations.into_iter().zip(written) {
+            let Some((buffer, value_ctx)) = written else {
+                continue;
+            };
+            
+            let current_offset = Pointer::current(writer)?;
+            write_at_pointer(writer, value_pointer_location, (current_offset - value_pointer_location).into())?;
+            
+            ctx.append(current_offset, value_ctx)?;
+            writer.write_all(&buffer)?;
+        }
+        
         Ok(())
     }
 }
+
+// size of the dict header and all nodes including the root node in bytes
+fn tree_length(values_count: u32) -> u32 {
+    28 + 16 * values_count
+}
+
+/// Bit `bit` of a dict key, counted from the lowest bit of the key's last character.
+/// Bits past the start of the key are zero
+pub fn key_bit(name: &str, bit: u32) -> bool {
+    let bytes = name.as_bytes();
+    let byte_index = (bit >> 3) as usize;
+    
+    byte_index < bytes.len() && (bytes[bytes.len() - 1 - byte_index] >> (bit & 7)) & 1 != 0
+}
+
+/// Reference bit of a node for `key` in a dict radix tree, which is the highest bit
+/// (see [`key_bit`]) in which `key` differs from `closest_key`, the key that a lookup
+/// of `key` ends at before inserting it (`""` for the root node).
+///
+/// Returns None if both keys are identical.
+///
+/// ```
+/// // a single entry is only compared against the root
+/// assert_eq!(ctr_bcres::reference_bit("texture", ""), Some((7 << 3) - 2));
+/// assert_eq!(ctr_bcres::reference_bit("ab", "bb"), Some(9));
+/// ```
+pub fn reference_bit(key: &str, closest_key: &str) -> Option<u32> {
+    let bit_count: u32 = (key.len().max(closest_key.len()) * 8).try_into().ok()?;
+    
+    (0..bit_count).rev().find(|&bit| key_bit(key, bit) != key_bit(closest_key, bit))
+}
+
+#[cfg(all(test, feature = "parallel"))]
+mod tests {
+    use super::*;
+    use crate::{model::CgfxModel, texture::CgfxTexture};
+    
+    // copies of the fixture's model and texture under other names, each with vertex
+    // and image data of their own so that they can be written independently
+    fn large_container() -> CgfxContainer {
+        let mut container = CgfxContainer::new(&fixtures::minimal_bcres()).unwrap();
+        let model = container.models.as_ref().unwrap().get(fixtures::MODEL_NAME).unwrap().clone();
+        let texture = container.textures.as_ref().unwrap().get(fixtures::TEXTURE_NAME).unwrap().clone();
+        
+        for i in 0..20u8 {
+            let mut model: CgfxModel = model.clone();
+            model.common_mut().cgfx_object_header.name = Some(format!("model_{i}"));
+            
+            for buffer in model.common_mut().shapes.iter_mut().flatten().flat_map(|shape| shape.vertex_buffers.iter_mut().flatten()) {
+                if let Some(bytes) = buffer.raw_bytes_mut() {
+                    bytes.make_mut()[0] = i;
+                }
+            }
+            
+            container.add_model(model, CollisionPolicy::Error).unwrap();
+            
+            let mut texture = texture.clone();
+            texture.metadata_mut().cgfx_object_header.name = Some(format!("texture_{i}"));
+            
+            if let CgfxTexture::Image(_, Some(image)) = &mut texture {
+                image.image_bytes.make_mut()[0] = i;
+            }
+            
+            container.add_texture(texture, CollisionPolicy::Error).unwrap();
+        }
+        
+        container
+    }
+    
+    fn write_dict<T: CgfxCollectionValue + Sync>(dict: &CgfxDict<T>, parallel: bool) -> (Vec<u8>, WriteContext) {
+        let mut writer = Cursor::new(Vec::new());
+        let mut ctx = WriteContext::new();
+        
+        // so that values don't start at zero and some strings exist already
+        writer.write_all(&[0; 12]).unwrap();
+        ctx.add_string(fixtures::TEXTURE_NAME).unwrap();
+        ctx.add_image_reference_to_current_end(Pointer(4)).unwrap();
+        ctx.append_to_image_section(&[1; 3]).unwrap();
+        
+        if parallel {
+            dict.to_writer_parallel(&mut writer, &mut ctx).unwrap();
+        } else {
+            dict.to_writer(&mut writer, &mut ctx).unwrap();
+        }
+        
+        (writer.into_inner(), ctx)
+    }
+    
+    fn assert_same_output<T: CgfxCollectionValue + Sync>(dict: &CgfxDict<T>) {
+        let (sequential, mut sequential_ctx) = write_dict(dict, false);
+        let (parallel, mut parallel_ctx) = write_dict(dict, true);
+        
+        assert_eq!(parallel, sequential);
+        assert_eq!(parallel_ctx.string_section, sequential_ctx.string_section);
+        assert_eq!(parallel_ctx.string_references, sequential_ctx.string_references);
+        assert_eq!(parallel_ctx.image_section, sequential_ctx.image_section);
+        assert_eq!(parallel_ctx.image_references, sequential_ctx.image_references);
+        assert_eq!(format!("{:?}", parallel_ctx.take_written_objects()), format!("{:?}", sequential_ctx.take_written_objects()));
+    }
+    
+    #[test]
+    fn parallel_writing_gives_the_same_bytes() {
+        let container = large_container();
+        let models = container.models.as_ref().unwrap();
+        
+        assert!(!models.shares_vertex_bytes_between_models());
+        assert_same_output(models);
+        assert_same_output(container.textures.as_ref().unwrap());
+        
+        let buffer = container.to_buffer().unwrap();
+        assert_eq!(CgfxContainer::new(&buffer).unwrap().to_buffer().unwrap(), buffer);
+    }
+    
+    #[test]
+    fn models_sharing_vertex_data_are_detected() {
+        let mut container = CgfxContainer::new(&fixtures::minimal_bcres()).unwrap();
+        let mut model = container.models.as_ref().unwrap().get(fixtures::MODEL_NAME).unwrap().clone();
+        model.common_mut().cgfx_object_header.name = Some("copy".to_string());
+        container.add_model(model, CollisionPolicy::Error).unwrap();
+        
+        assert!(container.models.as_ref().unwrap().shares_vertex_bytes_between_models());
+    }
+}
@@ -0,0 +1,80 @@
+// limits on what a file may contain, checked while parsing so that services parsing
+// untrusted files can't be made to allocate or loop for an unbounded amount of time.
+// the limits of the current parse are kept per thread so that they don't have to be
+// passed through every reader
+
+use std::cell::Cell;
+
+use anyhow::{ensure, Result};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParseLimits {
+    /// Maximum amount of entries of a single dict
+    pub max_dict_entries: u32,
+    /// Maximum size of a single vertex buffer in bytes
+    pub max_vertex_buffer_size: u32,
+    /// Maximum width and height of a texture
+    pub max_texture_dimension: u32,
+}
+
+impl ParseLimits {
+    pub const UNLIMITED: Self = Self {
+        max_dict_entries: u32::MAX,
+        max_vertex_buffer_size: u32::MAX,
+        max_texture_dimension: u32::MAX,
+    };
+    
+    /// Conservative limits for parsing untrusted files. The 3DS can't sample
+    /// textures larger than 1024x1024
+    pub const STRICT: Self = Self {
+        max_dict_entries: 4096,
+        max_vertex_buffer_size: 16 * 1024 * 1024,
+        max_texture_dimension: 1024,
+    };
+}
+
+impl Default for ParseLimits {
+    fn default() -> Self {
+        Self::UNLIMITED
+    }
+}
+
+thread_local! {
+    static CURRENT_LIMITS: Cell<ParseLimits> = const { Cell::new(ParseLimits::UNLIMITED) };
+}
+
+/// Runs `f` with `limits` applied to everything parsed on this thread
+pub fn with_limits<T>(limits: ParseLimits, f: impl FnOnce() -> T) -> T {
+    struct Restore(ParseLimits);
+    
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            CURRENT_LIMITS.set(self.0);
+        }
+    }
+    
+    let _restore = Restore(CURRENT_LIMITS.replace(limits));
+    f()
+}
+
+pub fn current_limits() -> ParseLimits {
+    CURRENT_LIMITS.get()
+}
+
+pub(crate) fn check_dict_entries(count: u32) -> Result<()> {
+    let max = current_limits().max_dict_entries;
+    ensure!(count <= max, "Dict has {count} entries, the limit is {max}");
+    Ok(())
+}
+
+pub(crate) fn check_vertex_buffer_size(size: u32) -> Result<()> {
+    let max = current_limits().max_vertex_buffer_size;
+    ensure!(size <= max, "Vertex buffer is {size} bytes large, the limit is {max}");
+    Ok(())
+}
+
+pub(crate) fn check_texture_dimensions(width: u32, height: u32) -> Result<()> {
+    let max = current_limits().max_texture_dimension;
+    ensure!(width <= max && height <= max, "Texture is {width}x{height} pixels large, the limit is {max}x{max}");
+    Ok(())
+}
@@ -0,0 +1,87 @@
+// magic numbers and type discriminants found throughout CGFX files
+use anyhow::{anyhow, Error};
+
+pub const CGFX_MAGIC: &[u8; 4] = b"CGFX";
+pub const DICT_MAGIC: &[u8; 4] = b"DICT";
+pub const IMAG_MAGIC: &[u8; 4] = b"IMAG";
+
+/// "DATA" read as a little endian u32
+pub const DATA_MAGIC: u32 = 0x41544144;
+
+pub const MESH: u32 = 0x01000000;
+pub const SKELETON: u32 = 0x02000000;
+pub const MATERIAL: u32 = 0x08000000;
+pub const SHAPE: u32 = 0x10000001;
+pub const TEXTURE_REFERENCE: u32 = 0x20000004;
+pub const TEXTURE_MAPPER: u32 = 0x80000000;
+pub const TEXTURE_SAMPLER: u32 = 0x80000000;
+
+macro_rules! discriminant_enum {
+    ($name:ident, $description:literal { $($variant:ident = $value:literal),* $(,)? }) => {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+        #[repr(u32)]
+        pub enum $name {
+            $($variant = $value),*
+        }
+        
+        impl $name {
+            pub const ALL: &'static [$name] = &[$($name::$variant),*];
+            
+            pub fn name(self) -> &'static str {
+                match self {
+                    $($name::$variant => stringify!($variant)),*
+                }
+            }
+        }
+        
+        impl TryFrom<u32> for $name {
+            type Error = Error;
+            
+            fn try_from(value: u32) -> Result<Self, Self::Error> {
+                match value {
+                    $($value => Ok($name::$variant),)*
+                    _ => Err(anyhow!(concat!("Invalid ", $description, " discriminant 0x{:x}, expected one of {}"),
+                        value,
+                        Self::ALL.iter()
+                            .map(|variant| format!("{} (0x{:x})", variant.name(), *variant as u32))
+                            .collect::<Vec<String>>()
+                            .join(", "))),
+                }
+            }
+        }
+        
+        impl From<$name> for u32 {
+            fn from(value: $name) -> Self {
+                value as u32
+            }
+        }
+    };
+}
+
+discriminant_enum!(TextureType, "texture" {
+    Cube = 0x20000009,
+    Image = 0x20000011,
+});
+
+discriminant_enum!(ModelType, "model" {
+    Standard = 0x40000012,
+    Skeletal = 0x40000092,
+});
+
+discriminant_enum!(AnimGroupMemberKind, "anim group member" {
+    MeshNodeVisibility = 0x00080000,
+    Mesh = 0x01000000,
+    TextureSampler = 0x02000000,
+    BlendOperation = 0x04000000,
+    MaterialColor = 0x08000000,
+    Model = 0x10000000,
+    TextureMapper = 0x20000000,
+    Bone = 0x40000000,
+    TextureCoordinator = 0x80000000,
+});
+
+discriminant_enum!(VertexBufferKind, "vertex buffer" {
+    Attribute = 0x40000001,
+    Interleaved = 0x40000002,
+    Fixed = 0x80000000,
+});
@@ -0,0 +1,226 @@
+use std::{collections::HashSet, mem::size_of};
+
+use crate::{
+    cgfx_container::{CgfxContainer, CgfxHeader, RawSection},
+    image_codec::RgbaColor,
+    metadata::{Metadata, MetadataValues},
+    model::{
+        anim_group::{AnimGroup, AnimGroupMember},
+        material::{
+            CgfxMaterial, FaceCulling, FragmentOp, MaterialColors, Rasterization, TextureCoord,
+            TextureMapper, TextureReference, TextureSampler, TextureTransformType,
+        },
+        mesh::{
+            AttributeName, BoundingBox, Face, FaceDescriptor, GlDataType, Mesh, Shape, SubMesh, SubMeshSkinning,
+            VertexBuffer, VertexBufferAttribute, VertexBufferCommon, VertexBufferFixed, VertexBufferInterleaved,
+            VertexBufferType,
+        },
+        skeleton::{CgfxBone, CgfxSkeleton, SkeletonScalingRule},
+        CgfxModel, CgfxModelCommon, MeshNodeVisibility,
+    },
+    plugin::SectionObject,
+    texture::{CgfxTexture, CgfxTextureCommon, ImageData, PicaTextureFormat, UnknownTexture},
+    util::{
+        math::{SerializableMatrix, Vec2, Vec3, Vec4},
+        pointer::Pointer,
+        util::{CgfxNodeHeader, CgfxObjectHeader, NodeChild, CgfxTransform, RuntimeHandle, SharedBytes},
+    },
+    CgfxCollectionValue, CgfxDict, CgfxNode,
+};
+
+/// Approximate amount of heap memory owned by a parsed value, not counting
+/// `size_of::<Self>()` itself. Useful for keeping track of memory usage when
+/// parsing many files at once.
+pub trait HeapSize {
+    fn heap_size(&self) -> usize {
+        self.heap_size_with(&mut HashSet::new())
+    }
+    
+    /// Like [`HeapSize::heap_size`], but shared buffers whose allocation is in `seen`
+    /// are skipped and the others get added to it, so that every buffer is only
+    /// counted once no matter how many values share it
+    fn heap_size_with(&self, seen: &mut HashSet<usize>) -> usize;
+}
+
+macro_rules! no_heap {
+    ($($t:ty),* $(,)?) => {
+        $(
+            impl HeapSize for $t {
+                fn heap_size_with(&self, _seen: &mut HashSet<usize>) -> usize {
+                    0
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! heap_fields {
+    ($t:ty { $($field:ident),* $(,)? }) => {
+        impl HeapSize for $t {
+            fn heap_size_with(&self, seen: &mut HashSet<usize>) -> usize {
+                0 $(+ self.$field.heap_size_with(seen))*
+            }
+        }
+    };
+}
+
+no_heap!(u8, u16, u32, i32, f32, bool, (), Pointer, RuntimeHandle, Vec2, Vec3, Vec4, RgbaColor);
+no_heap!(CgfxHeader, CgfxTransform, PicaTextureFormat, BoundingBox, SubMeshSkinning, AttributeName, GlDataType, VertexBufferType);
+no_heap!(VertexBufferCommon, SkeletonScalingRule, MaterialColors, Rasterization, FaceCulling, FragmentOp);
+no_heap!(TextureCoord, TextureTransformType, TextureSampler);
+
+// the heap size of plug-in objects is unknown
+impl HeapSize for SectionObject {
+    fn heap_size_with(&self, seen: &mut HashSet<usize>) -> usize {
+        match self {
+            SectionObject::Raw(object) => object.bytes.capacity()
+                + object.strings.capacity() * size_of::<(usize, String)>()
+                + object.strings.iter().map(|(_, string)| string.heap_size_with(seen)).sum::<usize>(),
+            _ => 0,
+        }
+    }
+}
+
+impl<const R: usize, const C: usize> HeapSize for SerializableMatrix<R, C> {
+    fn heap_size_with(&self, _seen: &mut HashSet<usize>) -> usize {
+        0
+    }
+}
+
+impl HeapSize for String {
+    fn heap_size_with(&self, _seen: &mut HashSet<usize>) -> usize {
+        self.capacity()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Vec<T> {
+    fn heap_size_with(&self, seen: &mut HashSet<usize>) -> usize {
+        self.capacity() * size_of::<T>() + self.iter().map(|value| value.heap_size_with(seen)).sum::<usize>()
+    }
+}
+
+// the whole allocation is counted for the first buffer that refers to it, so
+// views into an arena count the entire file once
+impl HeapSize for SharedBytes {
+    fn heap_size_with(&self, seen: &mut HashSet<usize>) -> usize {
+        let (address, capacity) = self.allocation();
+        if seen.insert(address) { capacity } else { 0 }
+    }
+}
+
+impl<T: HeapSize> HeapSize for Option<T> {
+    fn heap_size_with(&self, seen: &mut HashSet<usize>) -> usize {
+        self.as_ref().map_or(0, |value| value.heap_size_with(seen))
+    }
+}
+
+impl<T: HeapSize> HeapSize for Box<T> {
+    fn heap_size_with(&self, seen: &mut HashSet<usize>) -> usize {
+        size_of::<T>() + self.as_ref().heap_size_with(seen)
+    }
+}
+
+impl<T: HeapSize, const N: usize> HeapSize for [T; N] {
+    fn heap_size_with(&self, seen: &mut HashSet<usize>) -> usize {
+        self.iter().map(|value| value.heap_size_with(seen)).sum()
+    }
+}
+
+impl<T: CgfxCollectionValue + HeapSize> HeapSize for CgfxNode<T> {
+    fn heap_size_with(&self, seen: &mut HashSet<usize>) -> usize {
+        self.name.heap_size_with(seen) + self.value.heap_size_with(seen)
+    }
+}
+
+impl<T: CgfxCollectionValue + HeapSize> HeapSize for CgfxDict<T> {
+    fn heap_size_with(&self, seen: &mut HashSet<usize>) -> usize {
+        self.magic_number.heap_size_with(seen) + self.nodes.heap_size_with(seen)
+    }
+}
+
+heap_fields!(CgfxContainer {
+    models, textures, luts, materials, shaders, cameras, lights, fogs, scenes, skeletal_animations,
+    material_animations, visibility_animations, camera_animations, light_animations, fog_animations, emitters,
+    extra_sections, data_trailing_bytes, source,
+});
+heap_fields!(RawSection { data });
+
+// common headers
+heap_fields!(CgfxObjectHeader { magic, name, metadata });
+heap_fields!(Metadata { name, values });
+
+impl HeapSize for MetadataValues {
+    fn heap_size_with(&self, seen: &mut HashSet<usize>) -> usize {
+        match self {
+            MetadataValues::Floats(values) => values.heap_size_with(seen),
+            MetadataValues::Integers(values) => values.heap_size_with(seen),
+            MetadataValues::Strings { values, .. } => values.heap_size_with(seen),
+            MetadataValues::Vectors(values) => values.heap_size_with(seen),
+            MetadataValues::Colors(values) => values.heap_size_with(seen),
+            MetadataValues::Unknown { .. } => 0,
+        }
+    }
+}
+heap_fields!(CgfxNodeHeader { children, anim_groups });
+heap_fields!(NodeChild { magic, name });
+
+// textures
+heap_fields!(ImageData { image_bytes });
+heap_fields!(CgfxTextureCommon { cgfx_object_header });
+heap_fields!(UnknownTexture { images, fields });
+
+impl HeapSize for CgfxTexture {
+    fn heap_size_with(&self, seen: &mut HashSet<usize>) -> usize {
+        match self {
+            CgfxTexture::Cube(common, images) => common.heap_size_with(seen) + images.heap_size_with(seen),
+            CgfxTexture::Image(common, image) => common.heap_size_with(seen) + image.heap_size_with(seen),
+            CgfxTexture::Unknown(common, unknown) => common.heap_size_with(seen) + unknown.heap_size_with(seen),
+        }
+    }
+}
+
+// models
+heap_fields!(CgfxModelCommon {
+    cgfx_object_header, cgfx_node_header, meshes, materials, shapes, mesh_node_visibilities,
+});
+heap_fields!(MeshNodeVisibility { name });
+heap_fields!(AnimGroup { name, members, blend_operations });
+heap_fields!(AnimGroupMember { path });
+heap_fields!(Mesh { cgfx_object_header });
+
+impl HeapSize for CgfxModel {
+    fn heap_size_with(&self, seen: &mut HashSet<usize>) -> usize {
+        match self {
+            CgfxModel::Standard(common) => common.heap_size_with(seen),
+            CgfxModel::Skeletal(common, skeleton) => common.heap_size_with(seen) + skeleton.heap_size_with(seen),
+        }
+    }
+}
+
+// skeletons
+heap_fields!(CgfxSkeleton { cgfx_object_header, bones });
+heap_fields!(CgfxBone { name, metadata });
+
+// materials
+heap_fields!(CgfxMaterial { cgfx_object_header, texture_mappers });
+heap_fields!(TextureMapper { texture });
+heap_fields!(TextureReference { cgfx_object_header, path });
+
+// shapes
+heap_fields!(Shape { cgfx_object_header, sub_meshes, vertex_buffers });
+heap_fields!(SubMesh { bone_indices, faces });
+heap_fields!(Face { face_descriptors, buffer_objs });
+heap_fields!(FaceDescriptor { indices });
+heap_fields!(VertexBufferAttribute { raw_bytes });
+heap_fields!(VertexBufferInterleaved { raw_bytes, attributes });
+heap_fields!(VertexBufferFixed { vector });
+
+impl HeapSize for VertexBuffer {
+    fn heap_size_with(&self, seen: &mut HashSet<usize>) -> usize {
+        match self {
+            VertexBuffer::Attribute(buffer) => buffer.heap_size_with(seen),
+            VertexBuffer::Interleaved(buffer) => buffer.heap_size_with(seen),
+            VertexBuffer::Fixed(buffer) => buffer.heap_size_with(seen),
+        }
+    }
+}
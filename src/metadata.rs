@@ -0,0 +1,194 @@
+// user data attached to objects and bones, a dict of named lists of values.
+// every entry starts with a discriminant for the type of its values, its name and
+// the data type, followed by a count and relative pointer to the values. lists of
+// strings have their format in front of the count and point to a table of strings
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use anyhow::{bail, ensure, Result};
+use binrw::{parser, BinResult, BinWrite};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::{
+    error::read_field,
+    scoped_reader_pos,
+    util::{
+        math::{Vec3, Vec4},
+        pointer::Pointer,
+        util::{brw_read_string, point_here, read_inline_list, read_string, write_list_header, write_string_pointer},
+    },
+    CgfxCollectionValue, CgfxDict, WriteContext,
+};
+
+pub type MetadataDict = CgfxDict<Metadata>;
+
+pub const FLOATS_DISCRIMINANT: u32 = 0x01000000;
+pub const INTEGERS_DISCRIMINANT: u32 = 0x02000000;
+pub const STRINGS_DISCRIMINANT: u32 = 0x04000000;
+pub const VECTORS_DISCRIMINANT: u32 = 0x08000000;
+pub const COLORS_DISCRIMINANT: u32 = 0x10000000;
+
+// formats of string lists that are stored as zero terminated 8-bit strings
+const ASCII_FORMAT: u32 = 0;
+const UTF8_FORMAT: u32 = 1;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Metadata {
+    pub name: Option<String>,
+    pub data_type: u32,
+    pub values: MetadataValues,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MetadataValues {
+    Floats(Vec<f32>),
+    Integers(Vec<i32>),
+    /// Either ASCII or UTF-8, depending on `format`
+    Strings { format: u32, values: Vec<Option<String>> },
+    Vectors(Vec<Vec3>),
+    Colors(Vec<Vec4>),
+    /// Values this crate can't read, like UTF-16 strings, which can't be written either
+    Unknown { discriminant: u32 },
+}
+
+impl MetadataValues {
+    pub fn discriminant(&self) -> u32 {
+        match self {
+            MetadataValues::Floats(_) => FLOATS_DISCRIMINANT,
+            MetadataValues::Integers(_) => INTEGERS_DISCRIMINANT,
+            MetadataValues::Strings { .. } => STRINGS_DISCRIMINANT,
+            MetadataValues::Vectors(_) => VECTORS_DISCRIMINANT,
+            MetadataValues::Colors(_) => COLORS_DISCRIMINANT,
+            MetadataValues::Unknown { discriminant } => *discriminant,
+        }
+    }
+}
+
+fn read_strings<R: Read + Seek>(reader: &mut R) -> Result<Vec<Option<String>>> {
+    let count = reader.read_u32::<LittleEndian>()?;
+    let Some(table_ptr) = Pointer::read_relative(reader)? else {
+        return Ok(Vec::new());
+    };
+    
+    scoped_reader_pos!(reader);
+    reader.seek(SeekFrom::Start(table_ptr.into()))?;
+    
+    let string_ptrs: Vec<Option<Pointer>> = (0..count)
+        .map(|_| Pointer::read_relative(reader))
+        .collect::<Result<_>>()?;
+    
+    string_ptrs.into_iter()
+        .map(|string_ptr| {
+            let Some(string_ptr) = string_ptr else {
+                return Ok(None);
+            };
+            
+            reader.seek(SeekFrom::Start(string_ptr.into()))?;
+            Ok(Some(read_string(reader)?))
+        })
+        .collect()
+}
+
+impl CgfxCollectionValue for Metadata {
+    fn read_dict_value<R: Read + Seek>(reader: &mut R) -> Result<Self> {
+        let discriminant = reader.read_u32::<LittleEndian>()?;
+        let name = brw_read_string(reader, binrw::Endian::Little, ())?;
+        let data_type = reader.read_u32::<LittleEndian>()?;
+        
+        let values = match discriminant {
+            FLOATS_DISCRIMINANT => MetadataValues::Floats(read_field(reader, "values", read_inline_list)?),
+            INTEGERS_DISCRIMINANT => MetadataValues::Integers(read_field(reader, "values", read_inline_list)?),
+            STRINGS_DISCRIMINANT => {
+                let format = reader.read_u32::<LittleEndian>()?;
+                
+                if format == ASCII_FORMAT || format == UTF8_FORMAT {
+                    MetadataValues::Strings { format, values: read_field(reader, "values", read_strings)? }
+                } else {
+                    MetadataValues::Unknown { discriminant }
+                }
+            },
+            VECTORS_DISCRIMINANT => MetadataValues::Vectors(read_field(reader, "values", read_inline_list)?),
+            COLORS_DISCRIMINANT => MetadataValues::Colors(read_field(reader, "values", read_inline_list)?),
+            _ => MetadataValues::Unknown { discriminant },
+        };
+        
+        Ok(Metadata { name, data_type, values })
+    }
+    
+    fn write_dict_value<W: Write + Seek>(&self, writer: &mut W, ctx: &mut WriteContext) -> Result<()> {
+        if let MetadataValues::Unknown { discriminant } = self.values {
+            bail!("Can not write metadata {:?} with discriminant 0x{discriminant:x}", self.name);
+        }
+        
+        writer.write_u32::<LittleEndian>(self.values.discriminant())?;
+        write_string_pointer(writer, &self.name, ctx)?;
+        writer.write_u32::<LittleEndian>(self.data_type)?;
+        
+        if let MetadataValues::Strings { format, .. } = &self.values {
+            writer.write_u32::<LittleEndian>(*format)?;
+        }
+        
+        let count = match &self.values {
+            MetadataValues::Floats(values) => values.len(),
+            MetadataValues::Integers(values) => values.len(),
+            MetadataValues::Strings { values, .. } => values.len(),
+            MetadataValues::Vectors(values) => values.len(),
+            MetadataValues::Colors(values) => values.len(),
+            MetadataValues::Unknown { .. } => unreachable!(),
+        };
+        
+        let values_location = write_list_header(writer, count)?;
+        
+        if count == 0 {
+            return Ok(());
+        }
+        
+        point_here(writer, values_location)?;
+        
+        match &self.values {
+            MetadataValues::Floats(values) => values.write_le(writer)?,
+            MetadataValues::Integers(values) => values.write_le(writer)?,
+            MetadataValues::Strings { values, .. } => {
+                for value in values {
+                    write_string_pointer(writer, value, ctx)?;
+                }
+            },
+            MetadataValues::Vectors(values) => values.write_le(writer)?,
+            MetadataValues::Colors(values) => values.write_le(writer)?,
+            MetadataValues::Unknown { .. } => unreachable!(),
+        }
+        
+        Ok(())
+    }
+}
+
+/// Reads the metadata dict that the relative pointer right before the reader points to.
+/// `count` is the amount of entries the object says it has, if it stores one
+#[parser(reader)]
+pub fn brw_read_metadata(count: Option<u32>) -> BinResult<Option<MetadataDict>> {
+    let pointer_location = reader.stream_position()? - 4;
+    
+    let metadata = (|| {
+        reader.seek(SeekFrom::Start(pointer_location))?;
+        
+        let Some(metadata_ptr) = Pointer::read_relative(reader)? else {
+            return Ok(None);
+        };
+        
+        scoped_reader_pos!(reader);
+        reader.seek(SeekFrom::Start(metadata_ptr.into()))?;
+        
+        let metadata: MetadataDict = read_field(reader, "metadata", CgfxDict::from_reader)?;
+        
+        if let Some(count) = count {
+            ensure!(metadata.values_count == count,
+                "Metadata dict has {} entries but the object has {count}", metadata.values_count);
+        }
+        
+        Ok(Some(metadata))
+    })();
+    
+    metadata.map_err(|err: anyhow::Error| binrw::Error::Custom {
+        pos: pointer_location,
+        err: Box::new(err),
+    })
+}
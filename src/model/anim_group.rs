@@ -0,0 +1,418 @@
+// anim groups of a model, which list every value of the model that animations of a
+// kind are allowed to change. an animation only affects the members of the model's
+// group for it, so newly added materials, meshes or bones have to be registered in
+// the groups before any animation can change them. members refer to what they
+// animate by path, like Materials["body"].MaterialColor.Diffuse, or by bone name in
+// skeletal anim groups
+use std::{
+    fmt::Display,
+    io::{Read, Seek, SeekFrom, Write},
+};
+
+use anyhow::{anyhow, ensure, Result};
+use binrw::BinRead;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::{
+    error::read_field,
+    magic::AnimGroupMemberKind,
+    scoped_reader_pos,
+    util::{
+        pointer::Pointer,
+        util::{brw_read_string, point_here, write_list_header, write_string_pointer},
+    },
+    CgfxCollectionValue, CgfxDict, CollisionPolicy, WriteContext,
+};
+
+use super::{CgfxModel, CgfxModelCommon};
+
+pub type AnimGroupDict = CgfxDict<AnimGroup>;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnimGroup {
+    pub flags: u32,
+    /// Kind of animation the group is for, like `MaterialAnimation` or `SkeletalAnimation`
+    pub name: Option<String>,
+    pub member_type: u32,
+    pub members: Option<CgfxDict<AnimGroupMember>>,
+    /// Blend operation of every entry [`AnimGroupMember::blend_operation_index`] can refer to
+    pub blend_operations: Vec<u32>,
+    pub evaluation_timing: u32,
+}
+
+impl AnimGroup {
+    /// Adds a member under its own path, returning the path it ended up under
+    pub fn add_member(&mut self, member: AnimGroupMember, policy: CollisionPolicy) -> Result<String> {
+        let path = member.path.clone()
+            .ok_or_else(|| anyhow!("Anim group member has no path"))?;
+        
+        let members = self.members.get_or_insert_with(CgfxDict::new);
+        let new_path = members.insert(&path, member, policy)?;
+        
+        if new_path != path {
+            members.get_mut(&new_path).unwrap().path = Some(new_path.clone());
+        }
+        
+        Ok(new_path)
+    }
+}
+
+impl CgfxCollectionValue for AnimGroup {
+    fn read_dict_value<R: Read + Seek>(reader: &mut R) -> Result<Self> {
+        let flags = reader.read_u32::<LittleEndian>()?;
+        let name = brw_read_string(reader, binrw::Endian::Little, ())?;
+        let member_type = reader.read_u32::<LittleEndian>()?;
+        
+        let members = read_field(reader, "members", |reader| {
+            let member_count = reader.read_u32::<LittleEndian>()?;
+            let Some(members_ptr) = Pointer::read_relative(reader)? else {
+                return Ok(None);
+            };
+            
+            scoped_reader_pos!(reader);
+            reader.seek(SeekFrom::Start(members_ptr.into()))?;
+            let dict: CgfxDict<AnimGroupMember> = CgfxDict::from_reader(reader)?;
+            
+            ensure!(dict.values_count == member_count,
+                "Member dict has {} entries but the anim group has {member_count}", dict.values_count);
+            Ok(Some(dict))
+        })?;
+        
+        let blend_operation_count = reader.read_u32::<LittleEndian>()?;
+        let blend_operations = match Pointer::read_relative(reader)? {
+            Some(blend_operations_ptr) => {
+                scoped_reader_pos!(reader);
+                reader.seek(SeekFrom::Start(blend_operations_ptr.into()))?;
+                
+                (0..blend_operation_count)
+                    .map(|_| reader.read_u32::<LittleEndian>())
+                    .collect::<Result<_, _>>()?
+            },
+            None => Vec::new(),
+        };
+        
+        let evaluation_timing = reader.read_u32::<LittleEndian>()?;
+        
+        Ok(Self {
+            flags,
+            name,
+            member_type,
+            members,
+            blend_operations,
+            evaluation_timing,
+        })
+    }
+    
+    fn write_dict_value<W: Write + Seek>(&self, writer: &mut W, ctx: &mut WriteContext) -> Result<()> {
+        writer.write_u32::<LittleEndian>(self.flags)?;
+        write_string_pointer(writer, &self.name, ctx)?;
+        writer.write_u32::<LittleEndian>(self.member_type)?;
+        
+        let members_location = write_list_header(writer, self.members.as_ref().map_or(0, CgfxDict::len))?;
+        let blend_operations_location = write_list_header(writer, self.blend_operations.len())?;
+        writer.write_u32::<LittleEndian>(self.evaluation_timing)?;
+        
+        if let Some(members) = &self.members {
+            point_here(writer, members_location)?;
+            members.to_writer(writer, ctx)?;
+        }
+        
+        if !self.blend_operations.is_empty() {
+            point_here(writer, blend_operations_location)?;
+            
+            for &blend_operation in &self.blend_operations {
+                writer.write_u32::<LittleEndian>(blend_operation)?;
+            }
+        }
+        
+        Ok(())
+    }
+}
+
+/// A single value of the model that animations can change. Only the fields all
+/// kinds of members share are read, the ones depending on the kind are left out
+/// and don't get written either
+#[derive(Clone, Debug, PartialEq, BinRead)]
+#[br(little)]
+pub struct AnimGroupMember {
+    /// Kind of the member, see [`Self::kind`]
+    pub discriminant: u32,
+    #[br(parse_with = brw_read_string)]
+    pub path: Option<String>,
+    pub member_offset: u32,
+    pub blend_operation_index: u32,
+    pub object_type: u32,
+    pub member_type: u32,
+}
+
+impl AnimGroupMember {
+    pub fn kind(&self) -> Result<AnimGroupMemberKind> {
+        AnimGroupMemberKind::try_from(self.discriminant)
+    }
+    
+    /// Parts of the path, or None if it isn't a valid path
+    pub fn path_segments(&self) -> Option<Vec<PathSegment>> {
+        parse_member_path(self.path.as_deref()?)
+    }
+    
+    /// Name of the material the member belongs to, if its path starts with `Materials["..."]`
+    pub fn material_name(&self) -> Option<String> {
+        match self.path_segments()?.into_iter().next()? {
+            PathSegment::Key(field, name) if field == "Materials" => Some(name),
+            _ => None,
+        }
+    }
+}
+
+impl CgfxCollectionValue for AnimGroupMember {
+    fn read_dict_value<R: Read + Seek>(reader: &mut R) -> Result<Self> {
+        Ok(Self::read(reader)?)
+    }
+    
+    fn write_dict_value<W: Write + Seek>(&self, writer: &mut W, ctx: &mut WriteContext) -> Result<()> {
+        writer.write_u32::<LittleEndian>(self.discriminant)?;
+        write_string_pointer(writer, &self.path, ctx)?;
+        writer.write_u32::<LittleEndian>(self.member_offset)?;
+        writer.write_u32::<LittleEndian>(self.blend_operation_index)?;
+        writer.write_u32::<LittleEndian>(self.object_type)?;
+        writer.write_u32::<LittleEndian>(self.member_type)?;
+        Ok(())
+    }
+}
+
+/// Part of the path of an [`AnimGroupMember`], separated by dots
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PathSegment {
+    /// `Visible`
+    Field(String),
+    /// `Meshes[0]`
+    Index(String, usize),
+    /// `Materials["body"]`
+    Key(String, String),
+}
+
+impl Display for PathSegment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PathSegment::Field(field) => write!(f, "{field}"),
+            PathSegment::Index(field, index) => write!(f, "{field}[{index}]"),
+            PathSegment::Key(field, key) => write!(f, "{field}[\"{key}\"]"),
+        }
+    }
+}
+
+/// Splits the path of an anim group member into its parts, None if it isn't valid.
+/// Keys in quotes may contain dots and brackets
+///
+/// ```
+/// use ctr_bcres::model::anim_group::{parse_member_path, PathSegment};
+///
+/// assert_eq!(parse_member_path("Materials[\"a.b\"].TextureMappers[1].Sampler"), Some(vec![
+///     PathSegment::Key("Materials".to_string(), "a.b".to_string()),
+///     PathSegment::Index("TextureMappers".to_string(), 1),
+///     PathSegment::Field("Sampler".to_string()),
+/// ]));
+/// assert_eq!(parse_member_path("Meshes[0"), None);
+/// ```
+pub fn parse_member_path(path: &str) -> Option<Vec<PathSegment>> {
+    let mut segments = Vec::new();
+    let mut rest = path;
+    
+    loop {
+        let field_end = rest.find(['.', '[']).unwrap_or(rest.len());
+        let (field, after_field) = rest.split_at(field_end);
+        
+        if field.is_empty() {
+            return None;
+        }
+        
+        rest = after_field;
+        
+        let segment = if let Some(key) = rest.strip_prefix("[\"") {
+            let key_end = key.find("\"]")?;
+            rest = &key[key_end + 2..];
+            PathSegment::Key(field.to_string(), key[..key_end].to_string())
+        } else if let Some(index) = rest.strip_prefix('[') {
+            let index_end = index.find(']')?;
+            rest = &index[index_end + 1..];
+            PathSegment::Index(field.to_string(), index[..index_end].parse().ok()?)
+        } else {
+            PathSegment::Field(field.to_string())
+        };
+        
+        segments.push(segment);
+        
+        if rest.is_empty() {
+            return Some(segments);
+        }
+        
+        rest = rest.strip_prefix('.')?;
+    }
+}
+
+/// A member of an anim group that doesn't resolve to anything in its model
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AnimGroupIssue {
+    pub group: Option<String>,
+    pub path: Option<String>,
+    pub kind: AnimGroupIssueKind,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AnimGroupIssueKind {
+    UnknownKind(u32),
+    MissingPath,
+    InvalidPath,
+    /// The member animates a material but its path doesn't start with `Materials["..."]`
+    NotAMaterial,
+    MissingMaterial(String),
+    MissingTextureMapper { material: String, index: usize },
+    MissingTextureCoordinator { material: String, index: usize },
+    MissingMesh(usize),
+    MissingMeshNodeVisibility(String),
+    /// The member animates a bone but the model is a standard model
+    NoSkeleton,
+    MissingBone(String),
+}
+
+impl Display for AnimGroupIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Anim group {:?}, member {:?}: ", self.group, self.path)?;
+        
+        match &self.kind {
+            AnimGroupIssueKind::UnknownKind(discriminant) => write!(f, "Unknown member kind 0x{discriminant:x}"),
+            AnimGroupIssueKind::MissingPath => write!(f, "Member has no path"),
+            AnimGroupIssueKind::InvalidPath => write!(f, "Path can not be parsed"),
+            AnimGroupIssueKind::NotAMaterial => write!(f, "Member animates a material but its path doesn't refer to one"),
+            AnimGroupIssueKind::MissingMaterial(name) => write!(f, "Model has no material {name:?}"),
+            AnimGroupIssueKind::MissingTextureMapper { material, index } =>
+                write!(f, "Material {material:?} has no texture mapper {index}"),
+            AnimGroupIssueKind::MissingTextureCoordinator { material, index } =>
+                write!(f, "Material {material:?} has no texture coordinator {index}"),
+            AnimGroupIssueKind::MissingMesh(index) => write!(f, "Model has no mesh {index}"),
+            AnimGroupIssueKind::MissingMeshNodeVisibility(name) => write!(f, "Model has no mesh node visibility {name:?}"),
+            AnimGroupIssueKind::NoSkeleton => write!(f, "Member animates a bone but the model has no skeleton"),
+            AnimGroupIssueKind::MissingBone(name) => write!(f, "Skeleton has no bone {name:?}"),
+        }
+    }
+}
+
+impl CgfxModel {
+    /// Checks that the path of every member of every anim group of the model refers
+    /// to something the model has. Paths into parts of the model that aren't parsed
+    /// yet, like the properties of the model itself, are not checked
+    pub fn validate_anim_groups(&self) -> Vec<AnimGroupIssue> {
+        let common = self.common();
+        let Some(anim_groups) = &common.cgfx_node_header.anim_groups else {
+            return Vec::new();
+        };
+        
+        let mut issues = Vec::new();
+        
+        for group in anim_groups.values() {
+            for member in group.members.iter().flat_map(|members| members.values()) {
+                if let Err(kind) = self.resolve_member(member) {
+                    issues.push(AnimGroupIssue {
+                        group: group.name.clone(),
+                        path: member.path.clone(),
+                        kind,
+                    });
+                }
+            }
+        }
+        
+        issues
+    }
+    
+    fn resolve_member(&self, member: &AnimGroupMember) -> Result<(), AnimGroupIssueKind> {
+        let common = self.common();
+        let kind = member.kind().map_err(|_| AnimGroupIssueKind::UnknownKind(member.discriminant))?;
+        let path = member.path.as_deref().ok_or(AnimGroupIssueKind::MissingPath)?;
+        
+        // bones are referred to by their name alone
+        if kind == AnimGroupMemberKind::Bone {
+            let CgfxModel::Skeletal(_, skeleton) = self else {
+                return Err(AnimGroupIssueKind::NoSkeleton);
+            };
+            
+            return skeleton.bones.contains(path).then_some(())
+                .ok_or_else(|| AnimGroupIssueKind::MissingBone(path.to_string()));
+        }
+        
+        let segments = parse_member_path(path).ok_or(AnimGroupIssueKind::InvalidPath)?;
+        let is_material_kind = matches!(kind, AnimGroupMemberKind::MaterialColor | AnimGroupMemberKind::TextureSampler
+            | AnimGroupMemberKind::TextureMapper | AnimGroupMemberKind::TextureCoordinator
+            | AnimGroupMemberKind::BlendOperation);
+        
+        match (&segments[0], segments.get(1)) {
+            (PathSegment::Key(field, name), next) if field == "Materials" => {
+                let material = common.materials.as_ref()
+                    .and_then(|materials| materials.get(name))
+                    .ok_or_else(|| AnimGroupIssueKind::MissingMaterial(name.clone()))?;
+                
+                match next {
+                    Some(PathSegment::Index(field, index)) if field == "TextureMappers"
+                        && material.texture_mappers.get(*index).is_none_or(Option::is_none) =>
+                        Err(AnimGroupIssueKind::MissingTextureMapper { material: name.clone(), index: *index }),
+                    Some(PathSegment::Index(field, index)) if field == "TextureCoordinators"
+                        && *index >= material.used_texture_coords_count as usize =>
+                        Err(AnimGroupIssueKind::MissingTextureCoordinator { material: name.clone(), index: *index }),
+                    _ => Ok(()),
+                }
+            },
+            _ if is_material_kind => Err(AnimGroupIssueKind::NotAMaterial),
+            (PathSegment::Index(field, index), _) if field == "Meshes"
+                && common.meshes.get(*index).is_none_or(Option::is_none) =>
+                Err(AnimGroupIssueKind::MissingMesh(*index)),
+            (PathSegment::Key(field, name), _) if field == "MeshNodeVisibilities"
+                && !common.mesh_node_visibilities.as_ref().is_some_and(|visibilities| visibilities.contains(name)) =>
+                Err(AnimGroupIssueKind::MissingMeshNodeVisibility(name.clone())),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl CgfxModelCommon {
+    pub fn anim_group(&self, name: &str) -> Option<&AnimGroup> {
+        self.cgfx_node_header.anim_groups.as_ref()?.get(name)
+    }
+    
+    pub fn anim_group_mut(&mut self, name: &str) -> Option<&mut AnimGroup> {
+        self.cgfx_node_header.anim_groups.as_mut()?.get_mut(name)
+    }
+    
+    /// Registers the material `material` in every anim group the material `template`
+    /// is in, by copying every member of `template` with the path pointed to `material`.
+    /// Call this after adding a material, so that material animations can affect it.
+    /// Returns the amount of members added
+    pub fn register_material_in_anim_groups(&mut self, template: &str, material: &str) -> Result<usize> {
+        let Some(anim_groups) = &mut self.cgfx_node_header.anim_groups else {
+            return Ok(0);
+        };
+        
+        let template_prefix = PathSegment::Key("Materials".to_string(), template.to_string()).to_string();
+        let material_prefix = PathSegment::Key("Materials".to_string(), material.to_string()).to_string();
+        let mut added = 0;
+        
+        for group in anim_groups.values_mut() {
+            let new_members: Vec<AnimGroupMember> = group.members.iter()
+                .flat_map(|members| members.values())
+                .filter(|member| member.material_name().as_deref() == Some(template))
+                .map(|member| {
+                    let path = member.path.as_deref().unwrap_or_default();
+                    AnimGroupMember {
+                        path: Some(path.replacen(&template_prefix, &material_prefix, 1)),
+                        ..member.clone()
+                    }
+                })
+                .collect();
+            
+            for member in new_members {
+                group.add_member(member, CollisionPolicy::Overwrite)?;
+                added += 1;
+            }
+        }
+        
+        Ok(added)
+    }
+}
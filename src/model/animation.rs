@@ -0,0 +1,133 @@
+// texture pattern animations, which switch the texture a texture mapper displays at
+// set frames, like for animated faces or UI elements. material animations aren't
+// parsed yet, so these have to be built by hand until then
+
+use super::material::CgfxMaterial;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LoopMode {
+    /// Stays on the last frame once the animation is over
+    #[default]
+    Once,
+    Repeat,
+}
+
+/// From `frame` on, the texture at `texture_index` of the animation is displayed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PatternKey {
+    pub frame: f32,
+    pub texture_index: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TexturePatternCurve {
+    pub material_name: String,
+    pub mapper_index: usize,
+    /// Sorted by frame
+    pub keys: Vec<PatternKey>,
+}
+
+impl TexturePatternCurve {
+    /// Index of the texture displayed at `frame`. Pattern animations don't
+    /// interpolate, so this is the last key at or before `frame`
+    pub fn texture_index(&self, frame: f32) -> Option<usize> {
+        let first = self.keys.first()?;
+        
+        let key = self.keys.iter()
+            .take_while(|key| key.frame <= frame)
+            .last()
+            .unwrap_or(first);
+        
+        Some(key.texture_index)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TexturePatternAnimation {
+    pub name: String,
+    pub frame_count: f32,
+    pub loop_mode: LoopMode,
+    /// Paths of all textures the curves refer to, in the texture dict of the container
+    pub textures: Vec<String>,
+    pub curves: Vec<TexturePatternCurve>,
+}
+
+impl TexturePatternAnimation {
+    /// Maps a frame since the start of the animation into `0..=frame_count`
+    pub fn local_frame(&self, frame: f32) -> f32 {
+        if self.frame_count <= 0.0 {
+            return 0.0;
+        }
+        
+        match self.loop_mode {
+            LoopMode::Once => frame.clamp(0.0, self.frame_count),
+            LoopMode::Repeat => frame.rem_euclid(self.frame_count),
+        }
+    }
+    
+    /// Path of the texture that the texture mapper at `mapper_index` of the material
+    /// displays at `frame`, or None if this animation doesn't affect that texture mapper.
+    ///
+    /// ```
+    /// use ctr_bcres::model::animation::{LoopMode, PatternKey, TexturePatternAnimation, TexturePatternCurve};
+    ///
+    /// let blink = TexturePatternAnimation {
+    ///     name: "blink".to_string(),
+    ///     frame_count: 60.0,
+    ///     loop_mode: LoopMode::Repeat,
+    ///     textures: vec!["eye_open".to_string(), "eye_closed".to_string()],
+    ///     curves: vec![TexturePatternCurve {
+    ///         material_name: "eye".to_string(),
+    ///         mapper_index: 0,
+    ///         keys: vec![
+    ///             PatternKey { frame: 0.0, texture_index: 0 },
+    ///             PatternKey { frame: 55.0, texture_index: 1 },
+    ///         ],
+    ///     }],
+    /// };
+    ///
+    /// assert_eq!(blink.texture_at("eye", 0, 10.0), Some("eye_open"));
+    /// assert_eq!(blink.texture_at("eye", 0, 57.0), Some("eye_closed"));
+    /// assert_eq!(blink.texture_at("eye", 0, 70.0), Some("eye_open"));
+    /// assert_eq!(blink.texture_at("eye", 1, 10.0), None);
+    /// ```
+    pub fn texture_at(&self, material_name: &str, mapper_index: usize, frame: f32) -> Option<&str> {
+        let curve = self.curves.iter()
+            .find(|curve| curve.material_name == material_name && curve.mapper_index == mapper_index)?;
+        
+        let texture_index = curve.texture_index(self.local_frame(frame))?;
+        self.textures.get(texture_index).map(String::as_str)
+    }
+    
+    /// Points every animated texture mapper of `material` to the texture it displays
+    /// at `frame`, returning how many texture mappers were changed. The texture index
+    /// of changed references is cleared, so they have to be resolved again with
+    /// [`CgfxContainer::resolve_texture_references`].
+    ///
+    /// [`CgfxContainer::resolve_texture_references`]: crate::cgfx_container::CgfxContainer::resolve_texture_references
+    pub fn apply(&self, material: &mut CgfxMaterial, frame: f32) -> usize {
+        let Some(material_name) = material.cgfx_object_header.name.clone() else {
+            return 0;
+        };
+        
+        let mut changed = 0;
+        
+        for (i, mapper) in material.texture_mappers.iter_mut().enumerate() {
+            let Some(reference) = mapper.as_mut().and_then(|mapper| mapper.texture.as_mut()) else {
+                continue;
+            };
+            
+            let Some(path) = self.texture_at(&material_name, i, frame) else {
+                continue;
+            };
+            
+            if reference.path.as_deref() != Some(path) {
+                reference.path = Some(path.to_string());
+                reference.texture_index = None;
+                changed += 1;
+            }
+        }
+        
+        changed
+    }
+}
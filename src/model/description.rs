@@ -0,0 +1,164 @@
+// flattened descriptions of materials for exporting them to formats like glTF or OBJ,
+// which only know a few fixed texture slots instead of texture combiners
+
+use anyhow::{bail, Result};
+
+use crate::{image_codec::RgbaColor, util::math::Vec4};
+
+use super::{
+    material::{CgfxMaterial, FaceCulling, SamplerSettings, TextureCoord},
+    mesh::AttributeName,
+    CgfxModelCommon,
+};
+
+/// What a texture is used for. Texture combiners aren't parsed yet, so this is
+/// guessed from the texture mapper's slot and how its coordinates are generated
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextureRole {
+    /// The first UV mapped texture
+    Diffuse,
+    /// Any other UV mapped texture, like the tiling detail layers of terrain
+    Detail,
+    /// Sphere or cube mapped from the camera
+    Reflection,
+    /// Projected from a camera
+    Projection,
+}
+
+impl TextureRole {
+    fn from_coord(texture_coord: &TextureCoord, has_diffuse: bool) -> Self {
+        match texture_coord.mapping_type {
+            1 | 2 => TextureRole::Reflection,
+            3 => TextureRole::Projection,
+            _ if has_diffuse => TextureRole::Detail,
+            _ => TextureRole::Diffuse,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextureDescription {
+    /// Index of the texture mapper within the material
+    pub mapper_index: usize,
+    /// Path of the texture in the texture dict of the container
+    pub path: String,
+    /// Texture coordinate channel the texture is sampled with, None if its
+    /// coordinates aren't UV mapped or the mapper uses no texture coordinate
+    pub coord_index: Option<u32>,
+    pub role: TextureRole,
+    /// None if the texture mapper doesn't set its sampler through commands,
+    /// see [`TextureMapper::sampler_settings`]
+    ///
+    /// [`TextureMapper::sampler_settings`]: super::material::TextureMapper::sampler_settings
+    pub sampler: Option<SamplerSettings>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MaterialDescription {
+    pub name: Option<String>,
+    pub diffuse_color: RgbaColor,
+    /// Textures of all texture mappers that have one, in mapper order
+    pub textures: Vec<TextureDescription>,
+    /// Whether back faces are visible too
+    pub double_sided: bool,
+}
+
+impl MaterialDescription {
+    pub fn diffuse_texture(&self) -> Option<&TextureDescription> {
+        self.textures.iter().find(|texture| texture.role == TextureRole::Diffuse)
+    }
+}
+
+impl CgfxMaterial {
+    pub fn describe(&self) -> MaterialDescription {
+        let mut textures: Vec<TextureDescription> = Vec::new();
+        
+        for (mapper_index, mapper) in self.texture_mappers.iter().enumerate() {
+            let Some(mapper) = mapper else {
+                continue;
+            };
+            
+            let Some(path) = mapper.texture.as_ref().and_then(|texture| texture.path.clone()) else {
+                continue;
+            };
+            
+            let texture_coord = &self.texture_coords[mapper_index];
+            let has_diffuse = textures.iter().any(|texture| texture.role == TextureRole::Diffuse);
+            let role = TextureRole::from_coord(texture_coord, has_diffuse);
+            
+            let uv_mapped = matches!(role, TextureRole::Diffuse | TextureRole::Detail);
+            let coord_index = self.texture_coord_source(mapper_index)
+                .filter(|_| uv_mapped)
+                .map(|_| texture_coord.source_coord_index);
+            
+            textures.push(TextureDescription {
+                mapper_index,
+                path,
+                coord_index,
+                role,
+                sampler: mapper.sampler_settings(),
+            });
+        }
+        
+        MaterialDescription {
+            name: self.cgfx_object_header.name.clone(),
+            diffuse_color: self.colors.diffuse,
+            textures,
+            double_sided: self.rasterization.face_culling == FaceCulling::Never,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MeshDescription {
+    pub name: Option<String>,
+    pub shape_index: usize,
+    pub material: Option<MaterialDescription>,
+    /// Attributes the shape stores once for all vertices, see [`Shape::constant_attributes`]
+    ///
+    /// [`Shape::constant_attributes`]: super::mesh::Shape::constant_attributes
+    pub constant_attributes: Vec<(AttributeName, Vec<f32>)>,
+    /// Color every vertex has if the shape has a constant color attribute, which
+    /// tints the mesh. Exporters without constant attributes can multiply it into
+    /// the material color or write it as the color of every vertex
+    pub constant_color: Option<Vec4>,
+}
+
+impl CgfxModelCommon {
+    /// Description of the mesh at `mesh_index`, with its material and the
+    /// constant attributes of its shape
+    pub fn describe_mesh(&self, mesh_index: usize) -> Result<MeshDescription> {
+        let Some(Some(mesh)) = self.meshes.get(mesh_index) else {
+            bail!("Model has no mesh at index {mesh_index}");
+        };
+        
+        let shape_index = mesh.shape_index as usize;
+        let Some(Some(shape)) = self.shapes.get(shape_index) else {
+            bail!("Mesh {mesh_index} refers to shape {shape_index}, which doesn't exist");
+        };
+        
+        let material = self.materials.as_ref()
+            .and_then(|materials| materials.values().nth(mesh.material_index as usize))
+            .map(CgfxMaterial::describe);
+        
+        let constant_attributes: Vec<(AttributeName, Vec<f32>)> = shape.constant_attributes().into_iter()
+            .map(|(name, value)| (name, value.to_vec()))
+            .collect();
+        
+        // colors without alpha are opaque
+        let constant_color = constant_attributes.iter()
+            .find(|(name, _)| *name == AttributeName::Color)
+            .map(|(_, value)| {
+                let component = |i: usize, default: f32| value.get(i).copied().unwrap_or(default);
+                Vec4::new(component(0, 1.0), component(1, 1.0), component(2, 1.0), component(3, 1.0))
+            });
+        
+        Ok(MeshDescription {
+            name: mesh.cgfx_object_header.name.clone(),
+            shape_index,
+            material,
+            constant_attributes,
+            constant_color,
+        })
+    }
+}
@@ -0,0 +1,289 @@
+// rebuilding the faces of a shape from triangle lists with 32-bit indices, like the ones
+// imported meshes come with. face descriptors can only hold 16-bit indices, so meshes
+// with more vertices than that either fail or get split into several meshes and shapes
+
+use std::collections::HashMap;
+
+use anyhow::{bail, ensure, Result};
+
+use super::{
+    mesh::{GlDataType, Shape},
+    CgfxModelCommon,
+};
+
+/// Amount of vertices 16-bit indices can address
+pub const MAX_INDEXED_VERTICES: usize = u16::MAX as usize + 1;
+
+/// What to do with triangles referring to vertices that 16-bit indices can't address
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IndexOverflowPolicy {
+    #[default]
+    Error,
+    /// Splits the mesh and its shape into several, each with at most
+    /// [`MAX_INDEXED_VERTICES`] vertices
+    Split,
+}
+
+impl Shape {
+    /// Replaces the faces of the shape with a single triangle list of `indices`, using the
+    /// first face and face descriptor as a template for everything but the indices.
+    /// The shape needs to have a single sub mesh, and every index has to fit into 16 bits,
+    /// [`CgfxModelCommon::set_mesh_triangles`] can split meshes with more vertices
+    pub fn set_triangles(&mut self, indices: &[u32]) -> Result<()> {
+        ensure!(indices.len().is_multiple_of(3), "Triangle list has an incomplete triangle");
+        
+        let vertex_count = self.vertex_count();
+        if let Some(&index) = indices.iter().max() {
+            ensure!((index as usize) < vertex_count, "Vertex index {index} is out of bounds");
+            ensure!((index as usize) < MAX_INDEXED_VERTICES,
+                "Vertex index {index} doesn't fit into 16 bits, the shape needs to be split");
+        }
+        
+        let sub_mesh_count = self.sub_meshes.iter().flatten().count();
+        ensure!(sub_mesh_count == 1, "Shape has {sub_mesh_count} sub meshes, triangles can only be set on shapes with one");
+        
+        let sub_mesh = self.sub_meshes.iter_mut().flatten().next().unwrap();
+        
+        let Some(mut face) = sub_mesh.faces.iter().flatten().next().cloned() else {
+            bail!("Shape has no face to use as a template");
+        };
+        
+        let Some(mut face_descriptor) = face.face_descriptors.iter().flatten().next().cloned() else {
+            bail!("Shape has no face descriptor to use as a template");
+        };
+        
+        face_descriptor.format = if indices.iter().all(|&index| index <= u8::MAX as u32) {
+            GlDataType::UByte
+        } else {
+            GlDataType::UShort
+        };
+        face_descriptor.primitive_mode = 0;
+        face_descriptor.indices = indices.iter().map(|&index| index as u16).collect();
+        
+        face.face_descriptors = vec![Some(face_descriptor)];
+        sub_mesh.faces = vec![Some(face)];
+        
+        self.recompute_bounding_volumes()
+    }
+}
+
+// splits a triangle list into runs of triangles using at most MAX_INDEXED_VERTICES
+// vertices each, with the vertices every run uses and its indices into them
+fn split_triangles(indices: &[u32]) -> Vec<(Vec<usize>, Vec<u32>)> {
+    let mut parts: Vec<(Vec<usize>, Vec<u32>)> = Vec::new();
+    let mut new_indices: HashMap<u32, u32> = HashMap::new();
+    
+    for triangle in indices.chunks_exact(3) {
+        let new_vertices = triangle.iter().filter(|index| !new_indices.contains_key(index)).count();
+        
+        if parts.is_empty() || new_indices.len() + new_vertices > MAX_INDEXED_VERTICES {
+            parts.push((Vec::new(), Vec::new()));
+            new_indices.clear();
+        }
+        
+        let (vertices, part_indices) = parts.last_mut().unwrap();
+        
+        for &index in triangle {
+            let new_index = *new_indices.entry(index).or_insert_with(|| {
+                vertices.push(index as usize);
+                vertices.len() as u32 - 1
+            });
+            
+            part_indices.push(new_index);
+        }
+    }
+    
+    parts
+}
+
+impl CgfxModelCommon {
+    /// Replaces the triangles of the mesh at `mesh_index` with `indices`, see
+    /// [`Shape::set_triangles`]. If they refer to vertices past what 16-bit indices can
+    /// address, `policy` decides whether to fail or to split the mesh. Split off meshes
+    /// get a copy of the shape with only the vertices they use, and are named
+    /// `<name>_part1`, `<name>_part2` and so on. If other meshes share the shape, the mesh
+    /// gets its own copy to change instead. Returns the indices of all meshes drawing
+    /// the triangles, starting with `mesh_index`
+    pub fn set_mesh_triangles(&mut self, mesh_index: usize, indices: &[u32], policy: IndexOverflowPolicy) -> Result<Vec<usize>> {
+        let Some(Some(mesh)) = self.meshes.get(mesh_index) else {
+            bail!("Model has no mesh at index {mesh_index}");
+        };
+        
+        let shape_index = mesh.shape_index as usize;
+        let Some(Some(shape)) = self.shapes.get(shape_index) else {
+            bail!("Mesh {mesh_index} refers to shape {shape_index}, which doesn't exist");
+        };
+        
+        let fits = indices.iter().all(|&index| (index as usize) < MAX_INDEXED_VERTICES);
+        
+        // build every part before changing anything
+        let shapes = if fits || policy == IndexOverflowPolicy::Error {
+            let mut shape = shape.clone();
+            shape.set_triangles(indices)?;
+            vec![shape]
+        } else {
+            ensure!(indices.len().is_multiple_of(3), "Triangle list has an incomplete triangle");
+            
+            let vertex_count = shape.vertex_count();
+            if let Some(&index) = indices.iter().max() {
+                ensure!((index as usize) < vertex_count, "Vertex index {index} is out of bounds");
+            }
+            
+            split_triangles(indices).into_iter()
+                .map(|(vertices, part_indices)| {
+                    let mut part = shape.clone();
+                    part.retain_vertices(&vertices)?;
+                    part.set_triangles(&part_indices)?;
+                    Ok(part)
+                })
+                .collect::<Result<Vec<Shape>>>()?
+        };
+        
+        let shared = self.meshes.iter().enumerate()
+            .any(|(i, mesh)| i != mesh_index && mesh.as_ref().is_some_and(|mesh| mesh.shape_index as usize == shape_index));
+        
+        // indices past 16 bits that don't need more vertices than fit into one part still
+        // give a single one, and so do empty ones. that part takes the place of the shape
+        // unless other meshes keep drawing it
+        let mut shapes = shapes.into_iter();
+        
+        if shared {
+            self.meshes[mesh_index].as_mut().unwrap().shape_index = self.shapes.len().try_into()?;
+            self.shapes.push(shapes.next());
+        } else {
+            self.shapes[shape_index] = shapes.next();
+        }
+        
+        let mesh = self.meshes[mesh_index].clone().unwrap();
+        let mut mesh_indices = vec![mesh_index];
+        
+        for (i, shape) in shapes.enumerate() {
+            let mut part = mesh.clone();
+            part.shape_index = self.shapes.len().try_into()?;
+            part.cgfx_object_header.name = mesh.cgfx_object_header.name.as_ref()
+                .map(|name| format!("{name}_part{}", i + 1));
+            
+            self.shapes.push(Some(shape));
+            mesh_indices.push(self.meshes.len());
+            self.meshes.push(Some(part));
+        }
+        
+        Ok(mesh_indices)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cgfx_container::CgfxContainer, fixtures, util::math::Vec3};
+    
+    // the fixture model with its triangle repeated until the shape has `vertex_count` vertices
+    fn fixture_model(vertex_count: usize) -> CgfxModelCommon {
+        let container = CgfxContainer::new(&fixtures::minimal_bcres()).unwrap();
+        let mut common = container.models.unwrap().get(fixtures::MODEL_NAME).unwrap().common().clone();
+        let shape = common.shapes[0].as_mut().unwrap();
+        
+        for buffer in shape.vertex_buffers.iter_mut().flatten() {
+            if let Some(bytes) = buffer.raw_bytes_mut() {
+                let vertex_size = bytes.len() / fixtures::TRIANGLE_POSITIONS.len();
+                let bytes = bytes.make_mut();
+                *bytes = bytes.iter().copied().cycle().take(vertex_count * vertex_size).collect();
+            }
+        }
+        
+        assert_eq!(shape.vertex_count(), vertex_count);
+        common
+    }
+    
+    fn triangles(shape: &Shape) -> Vec<u32> {
+        shape.sub_meshes.iter().flatten()
+            .flat_map(|sub_mesh| sub_mesh.faces.iter().flatten())
+            .flat_map(|face| face.face_descriptors.iter().flatten())
+            .flat_map(|face_descriptor| face_descriptor.indices.iter().map(|&index| index as u32))
+            .collect()
+    }
+    
+    fn positions(common: &CgfxModelCommon, mesh_index: usize) -> Vec<Vec3> {
+        let shape = common.shapes[common.meshes[mesh_index].as_ref().unwrap().shape_index as usize].as_ref().unwrap();
+        let positions = shape.positions().unwrap();
+        
+        triangles(shape).iter().map(|&index| positions[index as usize]).collect()
+    }
+    
+    #[test]
+    fn meshes_past_16_bits_are_split() {
+        let vertex_count = MAX_INDEXED_VERTICES + 5;
+        let mut common = fixture_model(vertex_count);
+        let original = fixture_model(vertex_count).shapes[0].clone().unwrap().positions().unwrap();
+        
+        let indices: Vec<u32> = (0..vertex_count as u32).collect();
+        let meshes = common.set_mesh_triangles(0, &indices, IndexOverflowPolicy::Split).unwrap();
+        assert_eq!(meshes, vec![0, 1]);
+        
+        // the first part gets as many whole triangles as fit, the second one the rest
+        let shapes: Vec<&Shape> = common.shapes.iter().flatten().collect();
+        assert_eq!(shapes.iter().map(|shape| shape.vertex_count()).collect::<Vec<_>>(), vec![MAX_INDEXED_VERTICES - 1, 6]);
+        
+        for shape in &shapes {
+            assert!(triangles(shape).iter().all(|&index| (index as usize) < shape.vertex_count()));
+        }
+        
+        let part = common.meshes[1].as_ref().unwrap();
+        assert_eq!(part.shape_index, 1);
+        assert_eq!(part.cgfx_object_header.name, common.meshes[0].as_ref().unwrap().cgfx_object_header.name.as_ref()
+            .map(|name| format!("{name}_part1")));
+        
+        // both meshes together still draw the same triangles
+        let drawn: Vec<Vec3> = meshes.iter().flat_map(|&mesh| positions(&common, mesh)).collect();
+        assert_eq!(drawn, original);
+    }
+    
+    #[test]
+    fn high_indices_that_fit_into_one_part_are_not_split() {
+        let vertex_count = MAX_INDEXED_VERTICES + 3;
+        let mut common = fixture_model(vertex_count);
+        
+        let indices = [vertex_count as u32 - 3, vertex_count as u32 - 2, vertex_count as u32 - 1];
+        assert_eq!(common.set_mesh_triangles(0, &indices, IndexOverflowPolicy::Split).unwrap(), vec![0]);
+        
+        let shape = common.shapes[0].as_ref().unwrap();
+        assert_eq!(shape.vertex_count(), 3);
+        assert_eq!(triangles(shape), vec![0, 1, 2]);
+        assert_eq!(common.meshes.len(), 1);
+    }
+    
+    #[test]
+    fn error_policy_leaves_the_model_alone() {
+        let vertex_count = MAX_INDEXED_VERTICES + 3;
+        let mut common = fixture_model(vertex_count);
+        let original = common.clone();
+        
+        let indices: Vec<u32> = (0..vertex_count as u32).collect();
+        assert!(common.set_mesh_triangles(0, &indices, IndexOverflowPolicy::Error).is_err());
+        assert_eq!(common, original);
+        
+        // indices that fit are fine either way
+        assert_eq!(common.set_mesh_triangles(0, &[2, 1, 0], IndexOverflowPolicy::Error).unwrap(), vec![0]);
+        assert_eq!(triangles(common.shapes[0].as_ref().unwrap()), vec![2, 1, 0]);
+    }
+    
+    #[test]
+    fn shared_shapes_are_copied() {
+        let mut common = fixture_model(3);
+        let mesh = common.meshes[0].clone();
+        common.meshes.push(mesh);
+        let shape = common.shapes[0].clone();
+        
+        assert_eq!(common.set_mesh_triangles(1, &[2, 1, 0], IndexOverflowPolicy::Error).unwrap(), vec![1]);
+        
+        // the other mesh still draws the old shape
+        assert_eq!(common.shapes[0], shape);
+        assert_eq!(common.meshes[0].as_ref().unwrap().shape_index, 0);
+        assert_eq!(common.meshes[1].as_ref().unwrap().shape_index, 1);
+        assert_eq!(triangles(common.shapes[1].as_ref().unwrap()), vec![2, 1, 0]);
+        
+        // once it's the only one using its shape, it gets changed in place
+        common.set_mesh_triangles(1, &[0, 1, 2], IndexOverflowPolicy::Error).unwrap();
+        assert_eq!(common.shapes.len(), 2);
+    }
+}
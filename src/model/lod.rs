@@ -0,0 +1,386 @@
+// levels of detail, which games store either as meshes or models named with a `_lod<n>`
+// suffix or as models that share a name and tell their level apart by layer id. there
+// is no lod structure in the format itself, so all of this works off those conventions
+
+use std::collections::{BTreeMap, HashSet};
+
+use anyhow::{bail, ensure, Result};
+
+use crate::cgfx_container::CgfxContainer;
+
+use super::{mesh::Shape, CgfxModelCommon};
+
+/// How the levels of detail of a model are told apart
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LodConvention {
+    /// Names ending in `_lod<n>`, like `tree_lod1`. Names without a suffix that
+    /// match the rest of such a name are level 0
+    NameSuffix,
+    /// Models whose names only differ in a trailing number, like `tree_0` and
+    /// `tree_1`, with their layer id as the level
+    LayerId,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LodLevel {
+    pub level: u32,
+    /// Name of the mesh or model
+    pub name: String,
+}
+
+/// Meshes or models that are different levels of detail of the same object
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LodSet {
+    pub base_name: String,
+    /// Sorted by level, most detailed first
+    pub levels: Vec<LodLevel>,
+}
+
+/// Splits a name like `tree_lod1` into `tree` and its level, ignoring case
+pub fn parse_lod_name(name: &str) -> Option<(&str, u32)> {
+    let (base, suffix) = name.rsplit_once('_')?;
+    
+    if suffix.len() <= 3 || !suffix[..3].eq_ignore_ascii_case("lod") || base.is_empty() {
+        return None;
+    }
+    
+    Some((base, suffix[3..].parse().ok()?))
+}
+
+// strips a trailing number and the underscore before it
+fn strip_number(name: &str) -> &str {
+    let base = name.trim_end_matches(|c: char| c.is_ascii_digit());
+    
+    if base.len() == name.len() {
+        name
+    } else {
+        base.strip_suffix('_').unwrap_or(base)
+    }
+}
+
+// groups names that have a level by base name, only keeping groups of names with a suffix
+fn group_by_suffix<'a>(names: impl Iterator<Item = &'a str> + Clone) -> Vec<LodSet> {
+    let mut sets: BTreeMap<&str, Vec<LodLevel>> = BTreeMap::new();
+    
+    for name in names.clone() {
+        if let Some((base, level)) = parse_lod_name(name) {
+            sets.entry(base).or_default().push(LodLevel { level, name: name.to_string() });
+        }
+    }
+    
+    for name in names {
+        if let Some(levels) = sets.get_mut(name) {
+            levels.push(LodLevel { level: 0, name: name.to_string() });
+        }
+    }
+    
+    into_sets(sets)
+}
+
+fn into_sets(sets: BTreeMap<&str, Vec<LodLevel>>) -> Vec<LodSet> {
+    sets.into_iter()
+        .map(|(base_name, mut levels)| {
+            levels.sort_by(|a, b| a.level.cmp(&b.level).then_with(|| a.name.cmp(&b.name)));
+            LodSet { base_name: base_name.to_string(), levels }
+        })
+        .collect()
+}
+
+// names of all entries of `sets` whose level `keep` rejects
+fn rejected_names(sets: &[LodSet], keep: impl Fn(u32) -> bool) -> Vec<String> {
+    sets.iter()
+        .flat_map(|set| &set.levels)
+        .filter(|level| !keep(level.level))
+        .map(|level| level.name.clone())
+        .collect()
+}
+
+impl CgfxModelCommon {
+    /// Groups the meshes of the model by [`LodConvention::NameSuffix`]
+    pub fn mesh_lod_sets(&self) -> Vec<LodSet> {
+        group_by_suffix(self.meshes.iter()
+            .flatten()
+            .filter_map(|mesh| mesh.cgfx_object_header.name.as_deref()))
+    }
+    
+    /// Removes all meshes that are part of a LOD set and whose level `keep` returns false
+    /// for, so that they don't get written. Returns how many meshes were removed. Their
+    /// shapes stay, because removing those would change the shape index of other meshes
+    pub fn retain_mesh_lods(&mut self, keep: impl Fn(u32) -> bool) -> usize {
+        let rejected = rejected_names(&self.mesh_lod_sets(), keep);
+        let mesh_count = self.meshes.len();
+        
+        self.meshes.retain(|mesh| {
+            let name = mesh.as_ref().and_then(|mesh| mesh.cgfx_object_header.name.as_ref());
+            !name.is_some_and(|name| rejected.contains(name))
+        });
+        
+        mesh_count - self.meshes.len()
+    }
+    
+    /// Adds a decimated copy of the mesh at `mesh_index` and its shape for every entry of
+    /// `ratios`, each keeping about that fraction of triangles (see [`Shape::decimate`]).
+    /// The copies are named `<name>_lod1`, `<name>_lod2` and so on. Returns their indices
+    pub fn generate_mesh_lods(&mut self, mesh_index: usize, ratios: &[f32]) -> Result<Vec<usize>> {
+        let Some(Some(mesh)) = self.meshes.get(mesh_index) else {
+            bail!("Model has no mesh at index {mesh_index}");
+        };
+        
+        let Some(name) = mesh.cgfx_object_header.name.clone() else {
+            bail!("Mesh {mesh_index} has no name to derive the names of its LODs from");
+        };
+        
+        let Some(Some(shape)) = self.shapes.get(mesh.shape_index as usize) else {
+            bail!("Mesh {name:?} refers to shape {}, which doesn't exist", mesh.shape_index);
+        };
+        
+        let mesh = mesh.clone();
+        let decimated: Vec<Shape> = ratios.iter().map(|&ratio| shape.decimate(ratio)).collect::<Result<_>>()?;
+        
+        let mut indices = Vec::with_capacity(ratios.len());
+        
+        for (i, shape) in decimated.into_iter().enumerate() {
+            let mut mesh = mesh.clone();
+            mesh.cgfx_object_header.name = Some(format!("{name}_lod{}", i + 1));
+            mesh.shape_index = self.shapes.len().try_into()?;
+            
+            self.shapes.push(Some(shape));
+            self.meshes.push(Some(mesh));
+            indices.push(self.meshes.len() - 1);
+        }
+        
+        Ok(indices)
+    }
+}
+
+impl CgfxContainer {
+    /// Groups the models of the container into LOD sets
+    pub fn model_lod_sets(&self, convention: LodConvention) -> Vec<LodSet> {
+        let Some(models) = &self.models else {
+            return Vec::new();
+        };
+        
+        let models = models.entries();
+        
+        match convention {
+            LodConvention::NameSuffix => group_by_suffix(models.map(|(name, _)| name)),
+            LodConvention::LayerId => {
+                let mut sets: BTreeMap<&str, Vec<LodLevel>> = BTreeMap::new();
+                
+                for (name, model) in models {
+                    let level = LodLevel { level: model.common().layer_id, name: name.to_string() };
+                    sets.entry(strip_number(name)).or_default().push(level);
+                }
+                
+                sets.retain(|_, levels| levels.len() > 1);
+                into_sets(sets)
+            },
+        }
+    }
+    
+    /// Removes all models that are part of a LOD set and whose level `keep` returns
+    /// false for, so that they don't get written. Returns how many models were removed
+    pub fn retain_model_lods(&mut self, convention: LodConvention, keep: impl Fn(u32) -> bool) -> Result<usize> {
+        let rejected = rejected_names(&self.model_lod_sets(convention), keep);
+        
+        if let Some(models) = &mut self.models {
+            for name in &rejected {
+                models.remove(name)?;
+            }
+        }
+        
+        Ok(rejected.len())
+    }
+}
+
+// vertex every vertex has been collapsed into so far
+fn find(targets: &mut [u32], vertex: u32) -> u32 {
+    let mut root = vertex;
+    
+    while targets[root as usize] != root {
+        root = targets[root as usize];
+    }
+    
+    targets[vertex as usize] = root;
+    root
+}
+
+impl Shape {
+    /// Copy of the shape with about `ratio` of its triangles, made by collapsing the
+    /// shortest edges into one of their vertices until few enough triangles are left.
+    /// Vertices don't move and stay in the vertex buffers, only the indices change.
+    /// Edges are never collapsed across sub meshes, and face descriptors need to be
+    /// triangle lists
+    pub fn decimate(&self, ratio: f32) -> Result<Shape> {
+        ensure!((0.0..=1.0).contains(&ratio), "Decimation ratio has to be between 0 and 1, got {ratio}");
+        
+        let positions = self.positions()?;
+        let mut shape = self.clone();
+        
+        let face_descriptors = shape.sub_meshes.iter_mut()
+            .flatten()
+            .flat_map(|sub_mesh| sub_mesh.faces.iter_mut().flatten())
+            .flat_map(|face| face.face_descriptors.iter_mut().flatten());
+        
+        for face_descriptor in face_descriptors {
+            ensure!(face_descriptor.primitive_mode == 0,
+                "Can only decimate triangle lists, got primitive mode {}", face_descriptor.primitive_mode);
+            ensure!(face_descriptor.indices.len().is_multiple_of(3), "Triangle list has an incomplete triangle");
+            
+            if let Some(&index) = face_descriptor.indices.iter().max() {
+                ensure!((index as usize) < positions.len(), "Vertex index {index} is out of bounds");
+            }
+            
+            let target = (face_descriptor.indices.len() / 3) as f32 * ratio;
+            let mut targets: Vec<u32> = (0..positions.len() as u32).collect();
+            let mut triangles: Vec<[u32; 3]> = face_descriptor.indices.chunks_exact(3)
+                .map(|triangle| [triangle[0].into(), triangle[1].into(), triangle[2].into()])
+                .collect();
+            
+            while triangles.len() as f32 > target {
+                let mut edges: Vec<(f32, u32, u32)> = triangles.iter()
+                    .flat_map(|&[a, b, c]| [(a, b), (b, c), (c, a)])
+                    .map(|(a, b)| (a.min(b), a.max(b)))
+                    .map(|(a, b)| {
+                        let (pa, pb) = (positions[a as usize], positions[b as usize]);
+                        let length = (pa.x - pb.x).powi(2) + (pa.y - pb.y).powi(2) + (pa.z - pb.z).powi(2);
+                        (length, a, b)
+                    })
+                    .collect();
+                
+                if edges.is_empty() {
+                    break;
+                }
+                
+                edges.sort_by(|a, b| a.0.total_cmp(&b.0).then_with(|| (a.1, a.2).cmp(&(b.1, b.2))));
+                edges.dedup_by_key(|edge| (edge.1, edge.2));
+                
+                // every collapse removes at least one triangle, so collapse about as many
+                // short edges as triangles are left to remove, each vertex at most once
+                let mut touched: HashSet<u32> = HashSet::new();
+                let mut collapses = (triangles.len() as f32 - target).ceil() as usize;
+                
+                for (_, a, b) in edges {
+                    if collapses == 0 {
+                        break;
+                    }
+                    
+                    if touched.contains(&a) || touched.contains(&b) {
+                        continue;
+                    }
+                    
+                    touched.insert(a);
+                    touched.insert(b);
+                    targets[b as usize] = a;
+                    collapses -= 1;
+                }
+                
+                triangles = triangles.into_iter()
+                    .map(|triangle| triangle.map(|vertex| find(&mut targets, vertex)))
+                    .filter(|[a, b, c]| a != b && b != c && c != a)
+                    .collect();
+            }
+            
+            // collapsed vertices have a lower index, so they still fit
+            face_descriptor.indices = triangles.into_iter().flatten().map(|vertex| vertex as u16).collect();
+        }
+        
+        Ok(shape)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fixtures, model::mesh::{AttributeName, VertexBuffer}};
+    
+    // the fixture model with its shape replaced by a grid of `size` by `size` vertices,
+    // two triangles per cell
+    fn grid_model(size: u32) -> CgfxModelCommon {
+        let container = CgfxContainer::new(&fixtures::minimal_bcres()).unwrap();
+        let mut common = container.models.as_ref().unwrap().get(fixtures::MODEL_NAME).unwrap().common().clone();
+        let shape = common.shapes[0].as_mut().unwrap();
+        
+        let Some(Some(VertexBuffer::Attribute(buffer))) = shape.vertex_buffers.first_mut() else {
+            panic!("fixture shape should start with an attribute vertex buffer");
+        };
+        buffer.raw_bytes = vec![0; (size * size) as usize * 12].into();
+        
+        // cells get slightly wider to the right, so that not all edges are the same length
+        let positions: Vec<f32> = (0..size * size)
+            .flat_map(|i| [(i % size) as f32 * (1.0 + (i % size) as f32 * 0.1), (i / size) as f32, 0.0])
+            .collect();
+        shape.set_attribute(AttributeName::Position, &positions).unwrap();
+        
+        let indices: Vec<u32> = (0..size - 1)
+            .flat_map(|y| (0..size - 1).map(move |x| y * size + x))
+            .flat_map(|i| [i, i + 1, i + size, i + 1, i + size + 1, i + size])
+            .collect();
+        shape.set_triangles(&indices).unwrap();
+        
+        common
+    }
+    
+    fn triangles(shape: &Shape) -> Vec<[u16; 3]> {
+        shape.sub_meshes.iter().flatten()
+            .flat_map(|sub_mesh| sub_mesh.faces.iter().flatten())
+            .flat_map(|face| face.face_descriptors.iter().flatten())
+            .flat_map(|face_descriptor| face_descriptor.indices.chunks_exact(3))
+            .map(|triangle| [triangle[0], triangle[1], triangle[2]])
+            .collect()
+    }
+    
+    #[test]
+    fn decimation_reduces_triangle_count() {
+        let common = grid_model(8);
+        let shape = common.shapes[0].as_ref().unwrap();
+        assert_eq!(shape.triangle_count(), 98);
+        
+        assert_eq!(shape.decimate(1.0).unwrap().triangle_count(), 98);
+        
+        for ratio in [0.75, 0.5, 0.25] {
+            let count = shape.decimate(ratio).unwrap().triangle_count();
+            assert!(count as f32 <= 98.0 * ratio, "{count} triangles left with a ratio of {ratio}");
+            assert!(count > 0, "no triangles left with a ratio of {ratio}");
+        }
+        
+        assert!(shape.decimate(1.5).is_err());
+    }
+    
+    #[test]
+    fn decimated_indices_stay_in_bounds() {
+        let common = grid_model(8);
+        let shape = common.shapes[0].as_ref().unwrap();
+        
+        for ratio in [0.75, 0.5, 0.25, 0.0] {
+            let decimated = shape.decimate(ratio).unwrap();
+            
+            // vertices stay where they are, only the indices change
+            assert_eq!(decimated.vertex_count(), shape.vertex_count());
+            assert_eq!(decimated.positions().unwrap(), shape.positions().unwrap());
+            
+            for [a, b, c] in triangles(&decimated) {
+                assert!([a, b, c].iter().all(|&index| (index as usize) < shape.vertex_count()));
+                assert!(a != b && b != c && c != a, "degenerate triangle {:?} with a ratio of {ratio}", [a, b, c]);
+            }
+        }
+    }
+    
+    #[test]
+    fn generated_lods_get_their_own_meshes() {
+        let mut common = grid_model(8);
+        common.meshes[0].as_mut().unwrap().cgfx_object_header.name = Some("grid".to_string());
+        
+        assert_eq!(common.generate_mesh_lods(0, &[0.5, 0.25]).unwrap(), vec![1, 2]);
+        
+        let triangle_counts: Vec<usize> = common.meshes.iter().flatten()
+            .map(|mesh| common.shapes[mesh.shape_index as usize].as_ref().unwrap().triangle_count())
+            .collect();
+        assert_eq!(triangle_counts[0], 98);
+        assert!(triangle_counts[1] <= 49 && triangle_counts[2] <= 24, "{triangle_counts:?}");
+        
+        let sets = common.mesh_lod_sets();
+        let names: Vec<&str> = sets[0].levels.iter().map(|level| level.name.as_str()).collect();
+        assert_eq!(names, vec!["grid", "grid_lod1", "grid_lod2"]);
+    }
+}
@@ -1,21 +1,30 @@
 use std::io::{Read, Seek, SeekFrom, Write};
 
-use anyhow::{bail, Result};
+use anyhow::{anyhow, bail, Result};
 use array_init::try_array_init;
 use binrw::{BinRead, BinWrite};
-use byteorder::{LittleEndian, ReadBytesExt};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 
 use crate::{
+    error::with_path,
     image_codec::RgbaColor,
+    magic,
+    pica::CommandDecoder,
+    reflect::{Reflect, Value},
     scoped_reader_pos,
     util::{
         math::{Mat3x4, Vec2, Vec4},
         pointer::Pointer,
-        util::{brw_read_string, brw_relative_pointer, brw_write_zero, CgfxBox, CgfxObjectHeader},
+        util::{
+            add_object_header_name, brw_read_string, brw_relative_pointer, brw_write_zero, point_here,
+            write_string_pointer, CgfxBox, CgfxObjectHeader,
+        },
     },
-    CgfxCollectionValue, WriteContext,
+    CgfxCollectionValue, CgfxDict, WriteContext,
 };
 
+use super::mesh::AttributeName;
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct CgfxMaterial {
     // object header
@@ -37,8 +46,8 @@ pub struct CgfxMaterial {
 impl CgfxCollectionValue for CgfxMaterial {
     fn read_dict_value<R: Read + Seek>(reader: &mut R) -> Result<Self> {
         let magic = reader.read_u32::<LittleEndian>()?;
-        if magic != 0x8000000 {
-            bail!("Incorrect magic number, expected 0x8000000 for Material but got 0x{magic:x}")
+        if magic != magic::MATERIAL {
+            bail!("Incorrect magic number, expected 0x{:x} for Material but got 0x{magic:x}", magic::MATERIAL)
         }
         
         let cgfx_object_header = CgfxObjectHeader::read(reader)?;
@@ -61,7 +70,8 @@ impl CgfxCollectionValue for CgfxMaterial {
                 scoped_reader_pos!(reader);
                 reader.seek(SeekFrom::Start(ptr.into()))?;
                 
-                texture_mappers[i] = Some(TextureMapper::read(reader)?);
+                let texture_mapper = TextureMapper::read(reader).map_err(anyhow::Error::from);
+                texture_mappers[i] = Some(with_path(texture_mapper, || format!("texture_mappers[{i}]"), ptr.into())?);
             }
         }
         
@@ -79,8 +89,158 @@ impl CgfxCollectionValue for CgfxMaterial {
         })
     }
 
-    fn write_dict_value<W: Write + Seek>(&self, _writer: &mut W, _ctx: &mut WriteContext) -> Result<()> {
-        todo!()
+    fn write_dict_value<W: Write + Seek>(&self, writer: &mut W, ctx: &mut WriteContext) -> Result<()> {
+        writer.write_u32::<LittleEndian>(magic::MATERIAL)?;
+        add_object_header_name(writer, &self.cgfx_object_header, ctx)?;
+        self.cgfx_object_header.write(writer)?;
+        
+        writer.write_u32::<LittleEndian>(self.flags)?;
+        writer.write_u32::<LittleEndian>(self.tex_coord_config)?;
+        writer.write_u32::<LittleEndian>(self.render_layer)?;
+        self.colors.write(writer)?;
+        self.rasterization.write(writer)?;
+        self.fragment_operation.write(writer)?;
+        writer.write_u32::<LittleEndian>(self.used_texture_coords_count)?;
+        
+        for texture_coord in &self.texture_coords {
+            texture_coord.write(writer)?;
+        }
+        
+        let texture_mappers_location = Pointer::current(writer)?;
+        
+        for _ in &self.texture_mappers {
+            writer.write_u32::<LittleEndian>(0)?;
+        }
+        
+        for (i, texture_mapper) in self.texture_mappers.iter().enumerate() {
+            if let Some(texture_mapper) = texture_mapper {
+                point_here(writer, texture_mappers_location + Pointer::from(i as u32 * 4))?;
+                texture_mapper.to_writer(writer, ctx)?;
+            }
+        }
+        
+        Ok(())
+    }
+}
+
+impl CgfxMaterial {
+    /// Recomputes `used_texture_coords_count` from the texture mappers
+    pub fn normalize(&mut self) {
+        let used_count = self.texture_mappers.iter().rposition(Option::is_some).map_or(0, |i| i + 1);
+        self.used_texture_coords_count = used_count as u32;
+    }
+    
+    /// Texture coordinate attribute sampled by the texture mapper at `mapper_index`,
+    /// or None if that texture coordinate isn't used or its source is out of range.
+    ///
+    /// This only applies to UV mapped textures, check the `mapping_type` of
+    /// the corresponding [`TextureCoord`] for that.
+    pub fn texture_coord_source(&self, mapper_index: usize) -> Option<AttributeName> {
+        if mapper_index >= self.used_texture_coords_count as usize {
+            return None;
+        }
+        
+        AttributeName::tex_coord(self.texture_coords.get(mapper_index)?.source_coord_index)
+    }
+    
+    /// Copies this material under a new name and sets fields by their dotted
+    /// reflection path, e.g. `("fragment_operation.blend_mode", Value::Int(1))`
+    pub fn clone_with(&self, name: &str, overrides: &[(&str, Value)]) -> Result<Self> {
+        let mut material = self.clone();
+        material.cgfx_object_header.name = Some(name.to_string());
+        
+        for (path, value) in overrides {
+            let path: Vec<&str> = path.split('.').collect();
+            (&mut material as &mut dyn Reflect).set_path(&path, value.clone())
+                .map_err(|error| anyhow!("Could not override field of material {name:?}: {error}"))?;
+        }
+        
+        Ok(material)
+    }
+    
+    /// Copies this material under a new name with the render state of `preset`
+    pub fn clone_with_preset(&self, name: &str, preset: MaterialPreset) -> Self {
+        let mut material = self.clone();
+        material.cgfx_object_header.name = Some(name.to_string());
+        preset.apply(&mut material);
+        material
+    }
+}
+
+/// Common combinations of culling, depth testing and blending. Presets only change
+/// the render state of a material, textures, colors and texture coordinates
+/// still have to come from a material of the same game
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MaterialPreset {
+    /// Back face culling, depth test and write, no blending
+    OpaqueTextured,
+    /// Like [`MaterialPreset::OpaqueTextured`] but visible from both sides, for
+    /// leaves and grass whose cutout comes from the alpha test of the shader
+    AlphaTestedFoliage,
+    /// Drawn after everything else and added onto what is behind it, without
+    /// writing depth, for glows and particles
+    AdditiveEffect,
+}
+
+// PICA command headers: register id, byte mask and the amount of parameters following the first
+const FACE_CULLING_HEADER: u32 = 0x0001_0040;
+const DEPTH_COLOR_MASK_HEADER: u32 = 0x000F_0107;
+const DEPTH_BUFFER_WRITE_HEADER: u32 = 0x000F_0115;
+// color operation, blend function, logic op and blend color in one go
+const BLEND_HEADER: u32 = 0x803F_0100;
+
+// blend factors of the blend function register
+const BLEND_ZERO: u32 = 0;
+const BLEND_ONE: u32 = 1;
+const BLEND_SRC_ALPHA: u32 = 6;
+
+const LOGIC_OP_COPY: u32 = 3;
+
+impl MaterialPreset {
+    pub fn apply(self, material: &mut CgfxMaterial) {
+        let (face_culling, culling_mode) = match self {
+            MaterialPreset::OpaqueTextured | MaterialPreset::AdditiveEffect => (FaceCulling::BackFace, 2),
+            MaterialPreset::AlphaTestedFoliage => (FaceCulling::Never, 0),
+        };
+        
+        material.rasterization.face_culling = face_culling;
+        material.rasterization.face_culling_command = [culling_mode, FACE_CULLING_HEADER];
+        
+        let (depth_write, render_layer, blend_mode, source, destination) = match self {
+            MaterialPreset::OpaqueTextured | MaterialPreset::AlphaTestedFoliage =>
+                (true, 0, 0, BLEND_ONE, BLEND_ZERO),
+            MaterialPreset::AdditiveEffect =>
+                (false, 3, 1, BLEND_SRC_ALPHA, BLEND_ONE),
+        };
+        
+        material.render_layer = render_layer;
+        
+        let fragment_operation = &mut material.fragment_operation;
+        
+        // depth test enabled, less or equal, all color channels written
+        let depth_color_mask = 1 | (5 << 4) | (0xF << 8) | (u32::from(depth_write) << 12);
+        fragment_operation.depth_flags = 1 | (u32::from(depth_write) << 1);
+        fragment_operation.depth_commands = [
+            depth_color_mask, DEPTH_COLOR_MASK_HEADER,
+            if depth_write { 3 } else { 0 }, DEPTH_BUFFER_WRITE_HEADER,
+        ];
+        
+        let blend_function = (source << 16) | (destination << 20) | (source << 24) | (destination << 28);
+        fragment_operation.blend_mode = blend_mode;
+        fragment_operation.blend_color = Vec4::new(0.0, 0.0, 0.0, 0.0);
+        // the last word only pads the command to eight bytes
+        fragment_operation.blend_commands = [0x00E4_0100, BLEND_HEADER, blend_function, LOGIC_OP_COPY, 0, 0];
+    }
+}
+
+pub type MaterialDict = CgfxDict<CgfxMaterial>;
+
+impl MaterialDict {
+    /// Paths of all textures referenced by any material in this dict
+    pub fn texture_paths(&self) -> impl Iterator<Item = &str> {
+        self.values()
+            .flat_map(|material| material.texture_mappers.iter().flatten())
+            .filter_map(|mapper| mapper.texture.as_ref()?.path.as_deref())
     }
 }
 
@@ -171,7 +331,7 @@ pub enum TextureTransformType {
 }
 
 #[derive(Clone, Debug, PartialEq, BinRead, BinWrite)]
-#[brw(little, magic = 0x80000000u32)]
+#[brw(little, magic = 0x80000000u32)] // magic::TEXTURE_MAPPER
 pub struct TextureMapper {
     pub dynamic_alloc: u32,
     
@@ -186,7 +346,7 @@ pub struct TextureMapper {
 }
 
 #[derive(Clone, Debug, PartialEq, BinRead, BinWrite)]
-#[brw(little, magic = 0x20000004u32)]
+#[brw(little, magic = 0x20000004u32)] // magic::TEXTURE_REFERENCE
 pub struct TextureReference {
     pub cgfx_object_header: CgfxObjectHeader,
     
@@ -194,10 +354,17 @@ pub struct TextureReference {
     #[bw(write_with = brw_write_zero)]
     pub path: Option<String>,
     pub texture_ptr: u32,
+    
+    /// Index of the referenced texture's node in the container's texture dict,
+    /// set by [`CgfxContainer::resolve_texture_references`]
+    ///
+    /// [`CgfxContainer::resolve_texture_references`]: crate::cgfx_container::CgfxContainer::resolve_texture_references
+    #[brw(ignore)]
+    pub texture_index: Option<usize>,
 }
 
 #[derive(Clone, Debug, PartialEq, BinRead, BinWrite)]
-#[brw(little, magic = 0x80000000u32)]
+#[brw(little, magic = 0x80000000u32)] // magic::TEXTURE_SAMPLER
 pub struct TextureSampler {
     #[br(parse_with = brw_relative_pointer)]
     #[bw(map = |_| 0u32)]
@@ -208,3 +375,115 @@ pub struct TextureSampler {
     /// (TextureMapper.commands field)
     pub min_filter: u32,
 }
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WrapMode {
+    ClampToEdge,
+    ClampToBorder,
+    Repeat,
+    MirroredRepeat,
+}
+
+impl WrapMode {
+    fn from_raw(raw: u32) -> Option<Self> {
+        match raw {
+            0 => Some(WrapMode::ClampToEdge),
+            1 => Some(WrapMode::ClampToBorder),
+            2 => Some(WrapMode::Repeat),
+            3 => Some(WrapMode::MirroredRepeat),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextureFilter {
+    Nearest,
+    Linear,
+}
+
+impl TextureFilter {
+    fn from_bit(bit: u32) -> Self {
+        if bit != 0 { TextureFilter::Linear } else { TextureFilter::Nearest }
+    }
+}
+
+/// How a texture mapper samples its texture, see [`TextureMapper::sampler_settings`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SamplerSettings {
+    pub wrap_u: WrapMode,
+    pub wrap_v: WrapMode,
+    pub mag_filter: TextureFilter,
+    pub min_filter: TextureFilter,
+    /// Filter between mipmap levels
+    pub mip_filter: TextureFilter,
+}
+
+impl TextureMapper {
+    /// Writes the texture mapper followed by its texture reference and sampler
+    pub fn to_writer<W: Write + Seek>(&self, writer: &mut W, ctx: &mut WriteContext) -> Result<()> {
+        let start = Pointer::current(writer)?;
+        
+        writer.write_u32::<LittleEndian>(magic::TEXTURE_MAPPER)?;
+        writer.write_u32::<LittleEndian>(self.dynamic_alloc)?;
+        
+        let texture_location = Pointer::current(writer)?;
+        writer.write_u32::<LittleEndian>(0)?;
+        let sampler_location = Pointer::current(writer)?;
+        writer.write_u32::<LittleEndian>(0)?;
+        
+        for &command in &self.commands {
+            writer.write_u32::<LittleEndian>(command)?;
+        }
+        
+        writer.write_u32::<LittleEndian>(self.commands_len)?;
+        
+        if let Some(texture) = &self.texture {
+            point_here(writer, texture_location)?;
+            
+            writer.write_u32::<LittleEndian>(magic::TEXTURE_REFERENCE)?;
+            add_object_header_name(writer, &texture.cgfx_object_header, ctx)?;
+            texture.cgfx_object_header.write(writer)?;
+            write_string_pointer(writer, &texture.path, ctx)?;
+            writer.write_u32::<LittleEndian>(texture.texture_ptr)?;
+        }
+        
+        if let Some(sampler) = &self.sampler {
+            point_here(writer, sampler_location)?;
+            
+            // the parent pointer points back to this texture mapper
+            writer.write_u32::<LittleEndian>(magic::TEXTURE_SAMPLER)?;
+            let parent_location = Pointer::current(writer)?;
+            writer.write_i32::<LittleEndian>(i32::try_from(start.0)? - i32::try_from(parent_location.0)?)?;
+            writer.write_u32::<LittleEndian>(sampler.min_filter)?;
+        }
+        
+        Ok(())
+    }
+}
+
+// texture parameter registers of the three texture units
+const TEXTURE_PARAM_REGISTERS: [u16; 3] = [0x083, 0x093, 0x09B];
+
+impl TextureMapper {
+    /// Wrap modes and filters from the texture parameter register set by `commands`,
+    /// or None if the commands don't set it
+    pub fn sampler_settings(&self) -> Option<SamplerSettings> {
+        // commands_len is in bytes
+        let word_count = (self.commands_len as usize / 4).min(self.commands.len());
+        
+        let param = CommandDecoder::new(&self.commands[..word_count])
+            .map_while(Result::ok)
+            .flat_map(|command| command.writes().collect::<Vec<_>>())
+            .filter(|write| TEXTURE_PARAM_REGISTERS.contains(&write.register))
+            .fold(None, |param, write| Some(write.apply(param.unwrap_or(0))))?;
+        
+        Some(SamplerSettings {
+            wrap_u: WrapMode::from_raw(param >> 12 & 7)?,
+            wrap_v: WrapMode::from_raw(param >> 8 & 7)?,
+            mag_filter: TextureFilter::from_bit(param >> 1 & 1),
+            min_filter: TextureFilter::from_bit(param >> 2 & 1),
+            mip_filter: TextureFilter::from_bit(param >> 24 & 1),
+        })
+    }
+}
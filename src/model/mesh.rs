@@ -1,25 +1,34 @@
 use std::{
+    cmp::Ordering,
     io::{Read, Seek, SeekFrom, Write},
     ops::{Deref, DerefMut},
-    slice::from_raw_parts,
 };
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, ensure, Result};
 use binrw::{BinRead, BinWrite};
-use byteorder::{LittleEndian, ReadBytesExt};
+use byteorder::{ByteOrder, LittleEndian, ReadBytesExt, WriteBytesExt};
 
 use crate::{
+    error::read_field,
+    limits,
+    magic::{self, VertexBufferKind},
     scoped_reader_pos,
     util::{
-        math::{Mat3, Vec3},
+        math::{Mat3, Units, Vec2, Vec3, Vec4},
         pointer::Pointer,
-        util::{read_inline_list, read_pointer_list, read_pointer_list_ext, CgfxObjectHeader},
+        util::{
+            add_object_header_name, point_here, read_inline_list, read_pointer_list, read_pointer_list_ext, read_shared_bytes,
+            write_list_header, write_pointer_list_header, write_relative_pointer_at, write_pointer_list_table, write_pointer_list_table_ext,
+            CgfxObjectHeader, RuntimeHandle, RuntimeHandles, SharedBytes,
+        },
     },
     CgfxCollectionValue, WriteContext,
 };
 
-#[derive(Clone, Debug, PartialEq, BinRead, BinWrite)]
-#[brw(little, magic = 0x01000000u32)]
+use super::material::CgfxMaterial;
+
+#[derive(Clone, Debug, PartialEq, BinRead)]
+#[br(little, magic = 0x01000000u32)] // magic::MESH
 pub struct Mesh {
     // object header
     pub cgfx_object_header: CgfxObjectHeader,
@@ -28,10 +37,10 @@ pub struct Mesh {
     pub shape_index: u32,
     pub material_index: u32,
     
+    // relative pointer to the model the mesh belongs to
     parent_ptr: i32,
     
     #[br(map = |value: u8| value != 0)]
-    #[bw(map = |&value: &bool| value as u8)]
     pub visible: bool,
     pub render_priority: u8,
     
@@ -42,6 +51,115 @@ pub struct Mesh {
     // ...
 }
 
+impl Mesh {
+    /// Visible mesh drawing `shape_index` with `material_index`
+    pub fn new(shape_index: u32, material_index: u32) -> Self {
+        Self {
+            cgfx_object_header: CgfxObjectHeader {
+                magic: "SOBJ".to_string(),
+                revision: 0,
+                name: None,
+                metadata_count: 0,
+                metadata_pointer: None,
+                metadata: None,
+            },
+            shape_index,
+            material_index,
+            parent_ptr: 0,
+            visible: true,
+            render_priority: 0,
+            mesh_node_index: 0,
+            primitive_index: 0,
+        }
+    }
+    
+    /// Writes the mesh with its parent pointer pointing to `model`, the start of the
+    /// model it is written for
+    pub fn to_writer<W: Write + Seek>(&self, writer: &mut W, model: Pointer, ctx: &mut WriteContext) -> Result<()> {
+        writer.write_u32::<LittleEndian>(magic::MESH)?;
+        add_object_header_name(writer, &self.cgfx_object_header, ctx)?;
+        self.cgfx_object_header.write(writer)?;
+        
+        writer.write_u32::<LittleEndian>(self.shape_index)?;
+        writer.write_u32::<LittleEndian>(self.material_index)?;
+        
+        let parent_location = Pointer::current(writer)?;
+        writer.write_i32::<LittleEndian>(i32::try_from(model.0)? - i32::try_from(parent_location.0)?)?;
+        
+        writer.write_u8(self.visible as u8)?;
+        writer.write_u8(self.render_priority)?;
+        writer.write_u16::<LittleEndian>(self.mesh_node_index)?;
+        writer.write_u32::<LittleEndian>(self.primitive_index)?;
+        
+        Ok(())
+    }
+}
+
+impl CgfxCollectionValue for Mesh {
+    fn read_dict_value<R: Read + Seek>(reader: &mut R) -> Result<Self> {
+        Ok(Self::read(reader)?)
+    }
+    
+    // the parent pointer needs to know where the model starts
+    fn write_dict_value<W: Write + Seek>(&self, _writer: &mut W, _ctx: &mut WriteContext) -> Result<()> {
+        bail!("Meshes can only be written as part of their model, see Mesh::to_writer")
+    }
+}
+
+/// Sorts meshes into the order the game draws them in
+#[derive(Clone, Copy, Debug)]
+pub struct RenderSortKey {
+    /// Render layer of the mesh's material, which is its translucency kind
+    /// (opaque, translucent, subtractive, additive)
+    pub layer: u32,
+    /// Meshes with a lower priority get drawn first
+    pub priority: u8,
+    pub translucent: bool,
+    /// Distance from the camera, every layer except the opaque one gets drawn
+    /// back to front, the opaque one front to back
+    pub depth: f32,
+}
+
+impl RenderSortKey {
+    pub fn new(mesh: &Mesh, material: &CgfxMaterial, depth: f32) -> Self {
+        Self {
+            layer: material.render_layer,
+            priority: mesh.render_priority,
+            translucent: material.render_layer != 0,
+            depth,
+        }
+    }
+}
+
+impl Ord for RenderSortKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let depth_order = if self.translucent {
+            other.depth.total_cmp(&self.depth)
+        } else {
+            self.depth.total_cmp(&other.depth)
+        };
+        
+        self.layer.cmp(&other.layer)
+            .then(self.translucent.cmp(&other.translucent))
+            .then(self.priority.cmp(&other.priority))
+            .then(depth_order)
+    }
+}
+
+impl PartialOrd for RenderSortKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for RenderSortKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for RenderSortKey {}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct Shape {
     // object header
@@ -52,16 +170,25 @@ pub struct Shape {
     pub bounding_box: Option<BoundingBox>,
     pub position_offset: Vec3,
     
-    pub sub_meshes: Vec<SubMesh>,
+    pub sub_meshes: Vec<Option<SubMesh>>,
     pub base_address: u32,
-    pub vertex_buffers: Vec<VertexBuffer>,
+    pub vertex_buffers: Vec<Option<VertexBuffer>>,
     
-    // TODO: blend shape
+    // TODO: blend shapes aren't parsed yet, this is only where one would be
+    pub blend_shape_pointer: Option<Pointer>,
+}
+
+impl RuntimeHandles for Shape {
+    fn clear_runtime_handles(&mut self) {
+        self.sub_meshes.clear_runtime_handles();
+        self.vertex_buffers.clear_runtime_handles();
+    }
 }
 
 impl Shape {
     pub fn from_reader<R: Read + Seek>(reader: &mut R) -> Result<Self> {
-        assert!(reader.read_u32::<LittleEndian>()? == 0x10000001);
+        let magic = reader.read_u32::<LittleEndian>()?;
+        ensure!(magic == magic::SHAPE, "Expected magic number 0x{:x} for Shape, got 0x{magic:x}", magic::SHAPE);
         
         let cgfx_object_header = CgfxObjectHeader::read(reader)?;
         let flags = reader.read_u32::<LittleEndian>()?;
@@ -70,17 +197,17 @@ impl Shape {
         let bounding_box = if let Some(bounding_box_ptr) = bounding_box_ptr {
             scoped_reader_pos!(reader);
             reader.seek(SeekFrom::Start(bounding_box_ptr.into()))?;
-            Some(BoundingBox::read(reader)?)
+            Some(read_field(reader, "bounding_box", |reader| Ok(BoundingBox::read(reader)?))?)
         } else {
             None
         };
         
         let position_offset = Vec3::read(reader)?;
-        assert!(position_offset == Vec3::default());
         
-        let sub_meshes: Vec<SubMesh> = read_pointer_list(reader)?;
+        let sub_meshes: Vec<Option<SubMesh>> = read_field(reader, "sub_meshes", read_pointer_list)?;
         let base_address = reader.read_u32::<LittleEndian>()?;
-        let vertex_buffers: Vec<VertexBuffer> = read_pointer_list(reader)?;
+        let vertex_buffers: Vec<Option<VertexBuffer>> = read_field(reader, "vertex_buffers", read_pointer_list)?;
+        let blend_shape_pointer = Pointer::read_relative(reader)?;
         
         Ok(Self {
             cgfx_object_header,
@@ -90,12 +217,572 @@ impl Shape {
             sub_meshes,
             base_address,
             vertex_buffers,
+            blend_shape_pointer,
         })
     }
     
-    pub fn to_writer<W: Write + Seek>(&self, _writer: &mut W) -> Result<()> {
-        todo!()
+    /// Writes the shape followed by everything it points to, in the order of the
+    /// fields pointing to them
+    ///
+    /// ```
+    /// use std::io::Cursor;
+    /// use ctr_bcres::{cgfx_container::CgfxContainer, fixtures, model::mesh::Shape, WriteContext};
+    ///
+    /// let container = CgfxContainer::new(&fixtures::minimal_bcres()).unwrap();
+    /// let model = container.models.as_ref().unwrap().values().next().unwrap();
+    /// let shape = model.common().shapes[0].as_ref().unwrap();
+    ///
+    /// let mut writer = Cursor::new(Vec::new());
+    /// shape.to_writer(&mut writer, &mut WriteContext::new()).unwrap();
+    ///
+    /// writer.set_position(0);
+    /// assert_eq!(&Shape::from_reader(&mut writer).unwrap(), shape);
+    /// ```
+    pub fn to_writer<W: Write + Seek>(&self, writer: &mut W, ctx: &mut WriteContext) -> Result<()> {
+        let header = &self.cgfx_object_header;
+        ensure!(self.blend_shape_pointer.is_none(), "Shape {:?} has a blend shape, which can't be written yet", header.name);
+        
+        writer.write_u32::<LittleEndian>(magic::SHAPE)?;
+        add_object_header_name(writer, header, ctx)?;
+        header.write(writer)?;
+        writer.write_u32::<LittleEndian>(self.flags)?;
+        
+        let bounding_box_location = Pointer::current(writer)?;
+        writer.write_u32::<LittleEndian>(0)?;
+        
+        self.position_offset.write(writer)?;
+        
+        let sub_meshes_location = write_pointer_list_header(writer, &self.sub_meshes)?;
+        writer.write_u32::<LittleEndian>(self.base_address)?;
+        let vertex_buffers_location = write_pointer_list_header(writer, &self.vertex_buffers)?;
+        writer.write_u32::<LittleEndian>(0)?;
+        
+        if let Some(bounding_box) = &self.bounding_box {
+            point_here(writer, bounding_box_location)?;
+            bounding_box.write(writer)?;
+        }
+        
+        write_pointer_list_table(writer, sub_meshes_location, &self.sub_meshes, ctx)?;
+        write_pointer_list_table(writer, vertex_buffers_location, &self.vertex_buffers, ctx)?;
+        
+        Ok(())
+    }
+    
+    /// Decodes all values of an attribute, with the scale applied. This is the
+    /// reverse of [`Shape::set_attribute`]. Fixed attributes only contain a single
+    /// value that applies to all vertices.
+    pub fn attribute(&self, name: AttributeName) -> Result<Option<Vec<f32>>> {
+        ensure!(name != AttributeName::Interleave, "Interleave is not a vertex attribute");
+        
+        for vertex_buffer in self.vertex_buffers.iter().flatten() {
+            match vertex_buffer {
+                VertexBuffer::Attribute(buffer) if buffer.attribute_name == name => {
+                    let vertex_size = buffer.format.byte_size() as usize * buffer.elements as usize;
+                    ensure!(vertex_size != 0, "Attribute {name:?} has no elements");
+                    
+                    let mut values = Vec::with_capacity(buffer.raw_bytes.len() / buffer.format.byte_size() as usize);
+                    
+                    for chunk in buffer.raw_bytes.chunks_exact(vertex_size) {
+                        read_components(chunk, buffer.format, buffer.scale, &mut values)?;
+                    }
+                    
+                    return Ok(Some(values));
+                },
+                VertexBuffer::Interleaved(buffer) => {
+                    let Some(attribute) = buffer.attributes.iter().flatten().find(|attribute| attribute.attribute_name == name) else {
+                        continue;
+                    };
+                    
+                    let stride = buffer.vertex_stride as usize;
+                    ensure!(stride != 0, "Interleaved vertex buffer has a stride of zero");
+                    
+                    let offset = attribute.offset as usize;
+                    let attribute_size = attribute.format.byte_size() as usize * attribute.elements as usize;
+                    ensure!(offset + attribute_size <= stride,
+                        "Attribute {name:?} does not fit into vertex stride of {stride} bytes");
+                    
+                    let mut values = Vec::new();
+                    
+                    for chunk in buffer.raw_bytes.chunks_exact(stride) {
+                        read_components(&chunk[offset..offset + attribute_size], attribute.format, attribute.scale, &mut values)?;
+                    }
+                    
+                    return Ok(Some(values));
+                },
+                VertexBuffer::Fixed(buffer) if buffer.vertex_buffer_common.attribute_name == name => {
+                    return Ok(Some(buffer.vector.clone()));
+                },
+                _ => {},
+            }
+        }
+        
+        Ok(None)
+    }
+    
+    pub fn positions(&self) -> Result<Vec<Vec3>> {
+        let Some(values) = self.attribute(AttributeName::Position)? else {
+            bail!("Shape has no position attribute");
+        };
+        
+        ensure!(values.len().is_multiple_of(3), "Positions of shape don't have three components");
+        
+        Ok(values.chunks_exact(3).map(|chunk| Vec3::new(chunk[0], chunk[1], chunk[2])).collect())
+    }
+    
+    /// Vertex positions converted to meters, for exporting to formats with a fixed unit
+    pub fn positions_in_meters(&self, units: Units) -> Result<Vec<Vec3>> {
+        Ok(self.positions()?.into_iter().map(|position| units.to_meters(position)).collect())
+    }
+    
+    /// Replaces the vertex positions with `positions` given in meters, which is the
+    /// reverse of [`Shape::positions_in_meters`]
+    pub fn set_positions_from_meters(&mut self, positions: &[Vec3], units: Units) -> Result<()> {
+        let values: Vec<f32> = positions.iter()
+            .map(|&position| units.from_meters(position))
+            .flat_map(|position| [position.x, position.y, position.z])
+            .collect();
+        
+        self.set_attribute(AttributeName::Position, &values)
+    }
+    
+    /// Texture coordinates of the given channel (0 to 2), or None if the shape doesn't
+    /// have that channel. Which channel a texture uses is decided by the material,
+    /// see [`CgfxMaterial::texture_coord_source`].
+    ///
+    /// [`CgfxMaterial::texture_coord_source`]: super::material::CgfxMaterial::texture_coord_source
+    pub fn tex_coords(&self, channel: u32) -> Result<Option<Vec<Vec2>>> {
+        let Some(name) = AttributeName::tex_coord(channel) else {
+            bail!("Texture coordinate channel {channel} does not exist");
+        };
+        
+        let Some(values) = self.attribute(name)? else {
+            return Ok(None);
+        };
+        
+        ensure!(values.len().is_multiple_of(2), "{name:?} of shape doesn't have two components");
+        
+        Ok(Some(values.chunks_exact(2).map(|chunk| Vec2::new(chunk[0], chunk[1])).collect()))
+    }
+    
+    /// All texture coordinate channels that the shape has values for
+    pub fn tex_coord_channels(&self) -> Vec<u32> {
+        let layout = self.layout();
+        
+        (0..3)
+            .filter(|&channel| AttributeName::tex_coord(channel).is_some_and(|name| layout.attribute(name).is_some()))
+            .collect()
+    }
+    
+    /// Attributes that have the same value for every vertex, stored once as a fixed
+    /// vertex buffer instead of per vertex, like a constant color to tint the shape with
+    pub fn constant_attributes(&self) -> Vec<(AttributeName, &[f32])> {
+        self.vertex_buffers.iter()
+            .flatten()
+            .filter_map(|vertex_buffer| match vertex_buffer {
+                VertexBuffer::Fixed(buffer) => Some((buffer.vertex_buffer_common.attribute_name, buffer.vector.as_slice())),
+                _ => None,
+            })
+            .collect()
+    }
+    
+    /// Vertex normals with a length of one, or None if the shape doesn't have normals.
+    /// Normals are usually quantized to bytes or shorts, whose scale doesn't map them
+    /// back onto unit vectors exactly
+    pub fn normals(&self) -> Result<Option<Vec<Vec3>>> {
+        let Some(values) = self.attribute(AttributeName::Normal)? else {
+            return Ok(None);
+        };
+        
+        ensure!(values.len().is_multiple_of(3), "Normals of shape don't have three components");
+        
+        Ok(Some(values.chunks_exact(3).map(|chunk| Vec3::new(chunk[0], chunk[1], chunk[2]).normalized()).collect()))
+    }
+    
+    /// Vertex colors converted to linear RGBA, or None if the shape doesn't have colors.
+    /// Colors without alpha are opaque, and a constant color is repeated for every vertex
+    pub fn vertex_colors(&self, options: &VertexColorOptions) -> Result<Option<Vec<Vec4>>> {
+        let Some(values) = self.attribute(AttributeName::Color)? else {
+            return Ok(None);
+        };
+        
+        let is_fixed = self.layout().attribute(AttributeName::Color)
+            .is_some_and(|attribute| attribute.storage == AttributeStorage::Fixed);
+        
+        let components = if is_fixed { values.len() } else {
+            let vertex_count = self.vertex_count();
+            ensure!(vertex_count != 0 && values.len().is_multiple_of(vertex_count), "Colors of shape don't match its vertex count");
+            values.len() / vertex_count
+        };
+        
+        ensure!(matches!(components, 3 | 4), "Colors of shape have {components} components instead of three or four");
+        
+        let mut colors = values.chunks_exact(components)
+            .map(|chunk| options.apply(Vec4::new(chunk[0], chunk[1], chunk[2], chunk.get(3).copied().unwrap_or(1.0))));
+        
+        if is_fixed {
+            let color = colors.next().unwrap();
+            Ok(Some(vec![color; self.vertex_count()]))
+        } else {
+            Ok(Some(colors.collect()))
+        }
+    }
+    
+    /// Replaces the vertex normals, keeping the format of the normal attribute. Normals
+    /// get normalized and clamped to what the format can hold, because quantized
+    /// formats usually can't represent a component of exactly one
+    pub fn set_normals(&mut self, normals: &[Vec3]) -> Result<()> {
+        let Some(attribute) = self.layout().attribute(AttributeName::Normal).cloned() else {
+            bail!("Shape has no normal attribute");
+        };
+        
+        ensure!(attribute.elements == 3, "Normal attribute has {} components instead of three", attribute.elements);
+        
+        let (min, max) = match attribute.format {
+            GlDataType::Byte => (i8::MIN as f32, i8::MAX as f32),
+            GlDataType::UByte => (u8::MIN as f32, u8::MAX as f32),
+            GlDataType::Short => (i16::MIN as f32, i16::MAX as f32),
+            GlDataType::UShort => (u16::MIN as f32, u16::MAX as f32),
+            GlDataType::Float | GlDataType::Fixed => (f32::MIN, f32::MAX),
+        };
+        let (min, max) = (min * attribute.scale, max * attribute.scale);
+        
+        let values: Vec<f32> = normals.iter()
+            .map(|normal| normal.normalized())
+            .flat_map(|normal| [normal.x, normal.y, normal.z])
+            .map(|value| value.clamp(min.min(max), max.max(min)))
+            .collect();
+        
+        self.set_attribute(AttributeName::Normal, &values)
+    }
+    
+    /// Recalculates the bounding box of the shape and of every face descriptor
+    /// from the vertex positions, which is needed for frustum culling to work
+    /// after vertices or indices were changed
+    pub fn recompute_bounding_volumes(&mut self) -> Result<()> {
+        let positions = self.positions()?;
+        
+        self.bounding_box = BoundingBox::from_points(positions.iter().copied());
+        
+        for sub_mesh in self.sub_meshes.iter_mut().flatten() {
+            for face in sub_mesh.faces.iter_mut().flatten() {
+                for face_descriptor in face.face_descriptors.iter_mut().flatten() {
+                    let points = face_descriptor.indices.iter()
+                        .map(|&index| positions.get(index as usize).copied()
+                            .ok_or_else(|| anyhow!("Vertex index {index} is out of bounds")))
+                        .collect::<Result<Vec<Vec3>>>()?;
+                    
+                    face_descriptor.bounding_volume = BoundingBox::from_points(points);
+                }
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Replaces the values of an attribute in whichever vertex buffer holds it,
+    /// keeping the existing format, scale and layout intact. `values` contains
+    /// all components of all vertices, so for example `[u0, v0, u1, v1, ...]`
+    /// for texture coordinates.
+    pub fn set_attribute(&mut self, name: AttributeName, values: &[f32]) -> Result<()> {
+        ensure!(name != AttributeName::Interleave, "Interleave is not a vertex attribute");
+        
+        for vertex_buffer in self.vertex_buffers.iter_mut().flatten() {
+            match vertex_buffer {
+                VertexBuffer::Attribute(buffer) if buffer.attribute_name == name => {
+                    let component_size = buffer.format.byte_size() as usize;
+                    let vertex_size = component_size * buffer.elements as usize;
+                    ensure!(vertex_size != 0, "Attribute {name:?} has no elements");
+                    
+                    ensure!(values.len() * component_size == buffer.raw_bytes.len(),
+                        "Expected {} values for attribute {name:?}, got {}",
+                        buffer.raw_bytes.len() / component_size, values.len());
+                    
+                    for (vertex, chunk) in buffer.raw_bytes.make_mut().chunks_exact_mut(vertex_size).enumerate() {
+                        let components = &values[vertex * buffer.elements as usize..];
+                        write_components(chunk, buffer.format, buffer.scale, buffer.elements, components)?;
+                    }
+                    
+                    return Ok(());
+                },
+                VertexBuffer::Interleaved(buffer) => {
+                    let Some(attribute) = buffer.attributes.iter().flatten().find(|attribute| attribute.attribute_name == name) else {
+                        continue;
+                    };
+                    
+                    let stride = buffer.vertex_stride as usize;
+                    ensure!(stride != 0, "Interleaved vertex buffer has a stride of zero");
+                    
+                    let elements = attribute.elements as usize;
+                    let vertex_count = buffer.raw_bytes.len() / stride;
+                    
+                    ensure!(values.len() == vertex_count * elements,
+                        "Expected {} values for attribute {name:?}, got {}", vertex_count * elements, values.len());
+                    
+                    let offset = attribute.offset as usize;
+                    let attribute_size = attribute.format.byte_size() as usize * elements;
+                    ensure!(offset + attribute_size <= stride,
+                        "Attribute {name:?} does not fit into vertex stride of {stride} bytes");
+                    
+                    for (vertex, chunk) in buffer.raw_bytes.make_mut().chunks_exact_mut(stride).enumerate() {
+                        write_components(&mut chunk[offset..offset + attribute_size], attribute.format,
+                            attribute.scale, attribute.elements, &values[vertex * elements..])?;
+                    }
+                    
+                    return Ok(());
+                },
+                VertexBuffer::Fixed(buffer) if buffer.vertex_buffer_common.attribute_name == name => {
+                    ensure!(values.len() == buffer.vector.len(),
+                        "Expected {} values for fixed attribute {name:?}, got {}", buffer.vector.len(), values.len());
+                    
+                    buffer.vector.copy_from_slice(values);
+                    return Ok(());
+                },
+                _ => {},
+            }
+        }
+        
+        bail!("Shape has no vertex buffer containing attribute {name:?}")
+    }
+    
+    /// Amount of vertices stored in the vertex buffers, fixed attributes don't count
+    pub fn vertex_count(&self) -> usize {
+        self.vertex_buffers.iter()
+            .flatten()
+            .find_map(|vertex_buffer| match vertex_buffer {
+                // its format doesn't describe a whole vertex
+                VertexBuffer::Attribute(buffer) if buffer.attribute_name == AttributeName::Interleave => None,
+                VertexBuffer::Attribute(buffer) => {
+                    let vertex_size = buffer.format.byte_size() as usize * buffer.elements as usize;
+                    buffer.raw_bytes.len().checked_div(vertex_size)
+                },
+                VertexBuffer::Interleaved(buffer) => buffer.raw_bytes.len().checked_div(buffer.vertex_stride as usize),
+                VertexBuffer::Fixed(_) => None,
+            })
+            .unwrap_or(0)
+    }
+    
+    /// Amount of indices over all face descriptors of all sub meshes
+    pub fn index_count(&self) -> usize {
+        self.sub_meshes.iter()
+            .flatten()
+            .flat_map(|sub_mesh| sub_mesh.faces.iter().flatten())
+            .flat_map(|face| face.face_descriptors.iter().flatten())
+            .map(|face_descriptor| face_descriptor.indices.len())
+            .sum()
+    }
+    
+    /// Amount of triangles drawn over all face descriptors. Primitive mode 0 is a
+    /// triangle list, 1 and 2 are strips and fans, other modes are counted as lists
+    pub fn triangle_count(&self) -> usize {
+        self.sub_meshes.iter()
+            .flatten()
+            .flat_map(|sub_mesh| sub_mesh.faces.iter().flatten())
+            .flat_map(|face| face.face_descriptors.iter().flatten())
+            .map(|face_descriptor| match face_descriptor.primitive_mode {
+                1 | 2 => face_descriptor.indices.len().saturating_sub(2),
+                _ => face_descriptor.indices.len() / 3,
+            })
+            .sum()
+    }
+    
+    /// Describes how every attribute of the shape is stored, along with the amount
+    /// of vertices and indices. Buffers and attributes named [`AttributeName::Interleave`]
+    /// are left out since they don't hold an attribute themselves.
+    pub fn layout(&self) -> ShapeLayout {
+        let mut attributes = Vec::new();
+        
+        for (buffer_index, vertex_buffer) in self.vertex_buffers.iter().enumerate() {
+            let Some(vertex_buffer) = vertex_buffer else {
+                continue;
+            };
+            
+            match vertex_buffer {
+                VertexBuffer::Attribute(buffer) if buffer.attribute_name == AttributeName::Interleave => {},
+                VertexBuffer::Attribute(buffer) => attributes.push(AttributeLayout {
+                    name: buffer.attribute_name,
+                    format: buffer.format,
+                    elements: buffer.elements,
+                    scale: buffer.scale,
+                    offset: 0,
+                    stride: buffer.format.byte_size() * buffer.elements,
+                    buffer_index,
+                    storage: AttributeStorage::Separate,
+                }),
+                VertexBuffer::Interleaved(buffer) => {
+                    let buffer_attributes = buffer.attributes.iter()
+                        .flatten()
+                        .filter(|attribute| attribute.attribute_name != AttributeName::Interleave);
+                    
+                    attributes.extend(buffer_attributes.map(|attribute| AttributeLayout {
+                        name: attribute.attribute_name,
+                        format: attribute.format,
+                        elements: attribute.elements,
+                        scale: attribute.scale,
+                        offset: attribute.offset,
+                        stride: buffer.vertex_stride,
+                        buffer_index,
+                        storage: AttributeStorage::Interleaved,
+                    }));
+                },
+                VertexBuffer::Fixed(buffer) => attributes.push(AttributeLayout {
+                    name: buffer.vertex_buffer_common.attribute_name,
+                    format: buffer.format,
+                    elements: buffer.elements,
+                    scale: buffer.scale,
+                    offset: 0,
+                    stride: 0,
+                    buffer_index,
+                    storage: AttributeStorage::Fixed,
+                }),
+            }
+        }
+        
+        ShapeLayout {
+            attributes,
+            vertex_count: self.vertex_count(),
+            index_count: self.index_count(),
+        }
+    }
+}
+
+/// How the color channels of vertex colors are encoded. Games differ in this, most
+/// store colors as they were painted, in sRGB
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorSpace {
+    #[default]
+    Linear,
+    Srgb,
+}
+
+impl ColorSpace {
+    fn to_linear(self, value: f32) -> f32 {
+        match self {
+            ColorSpace::Linear => value,
+            ColorSpace::Srgb if value <= 0.04045 => value / 12.92,
+            ColorSpace::Srgb => ((value + 0.055) / 1.055).powf(2.4),
+        }
+    }
+}
+
+/// What to do with the alpha of vertex colors
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VertexAlpha {
+    #[default]
+    Keep,
+    /// Multiplies the color channels by alpha, after converting them to linear
+    Premultiply,
+    /// Makes every vertex opaque, for games that use alpha for something other than opacity
+    Drop,
+}
+
+/// Options for [`Shape::vertex_colors`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct VertexColorOptions {
+    pub color_space: ColorSpace,
+    pub alpha: VertexAlpha,
+}
+
+impl VertexColorOptions {
+    fn apply(&self, color: Vec4) -> Vec4 {
+        let [r, g, b] = [color.x, color.y, color.z].map(|value| self.color_space.to_linear(value));
+        
+        match self.alpha {
+            VertexAlpha::Keep => Vec4::new(r, g, b, color.w),
+            VertexAlpha::Premultiply => Vec4::new(r * color.w, g * color.w, b * color.w, color.w),
+            VertexAlpha::Drop => Vec4::new(r, g, b, 1.0),
+        }
+    }
+}
+
+/// Overview of the vertex data of a [`Shape`], see [`Shape::layout`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShapeLayout {
+    pub attributes: Vec<AttributeLayout>,
+    pub vertex_count: usize,
+    pub index_count: usize,
+}
+
+impl ShapeLayout {
+    pub fn attribute(&self, name: AttributeName) -> Option<&AttributeLayout> {
+        self.attributes.iter().find(|attribute| attribute.name == name)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AttributeStorage {
+    /// The attribute has a vertex buffer of its own
+    Separate,
+    /// The attribute shares a vertex buffer with other attributes
+    Interleaved,
+    /// One value that applies to every vertex
+    Fixed,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AttributeLayout {
+    pub name: AttributeName,
+    pub format: GlDataType,
+    pub elements: u32,
+    pub scale: f32,
+    /// Offset in bytes from the start of a vertex
+    pub offset: u32,
+    /// Distance in bytes between two vertices, zero for fixed attributes
+    pub stride: u32,
+    /// Index of the vertex buffer in [`Shape::vertex_buffers`]
+    pub buffer_index: usize,
+    pub storage: AttributeStorage,
+}
+
+// encodes the first `elements` values into `out` the way the 3DS' GPU expects them,
+// reversing the scale that gets applied when decoding
+fn write_components(out: &mut [u8], format: GlDataType, scale: f32, elements: u32, values: &[f32]) -> Result<()> {
+    ensure!(scale != 0.0, "Can not encode attribute with a scale of zero");
+    
+    let component_size = format.byte_size() as usize;
+    
+    for (chunk, value) in out.chunks_exact_mut(component_size).zip(&values[..elements as usize]) {
+        let raw = value / scale;
+        
+        match format {
+            GlDataType::Byte => chunk[0] = integer_component::<i8>(raw)? as u8,
+            GlDataType::UByte => chunk[0] = integer_component::<u8>(raw)?,
+            GlDataType::Short => LittleEndian::write_i16(chunk, integer_component(raw)?),
+            GlDataType::UShort => LittleEndian::write_u16(chunk, integer_component(raw)?),
+            GlDataType::Float => LittleEndian::write_f32(chunk, raw),
+            GlDataType::Fixed => bail!("Encoding attributes of type Fixed is not supported"),
+        }
+    }
+    
+    Ok(())
+}
+
+// decodes one vertex worth of components, applying the scale
+fn read_components(bytes: &[u8], format: GlDataType, scale: f32, out: &mut Vec<f32>) -> Result<()> {
+    let component_size = format.byte_size() as usize;
+    
+    for chunk in bytes.chunks_exact(component_size) {
+        let raw = match format {
+            GlDataType::Byte => chunk[0] as i8 as f32,
+            GlDataType::UByte => chunk[0] as f32,
+            GlDataType::Short => LittleEndian::read_i16(chunk) as f32,
+            GlDataType::UShort => LittleEndian::read_u16(chunk) as f32,
+            GlDataType::Float => LittleEndian::read_f32(chunk),
+            GlDataType::Fixed => bail!("Decoding attributes of type Fixed is not supported"),
+        };
+        
+        out.push(raw * scale);
     }
+    
+    Ok(())
+}
+
+fn integer_component<T: TryFrom<i64>>(value: f32) -> Result<T> {
+    let rounded = value.round();
+    
+    T::try_from(rounded as i64)
+        .ok()
+        .filter(|_| rounded.is_finite())
+        .ok_or_else(|| anyhow!("Value {value} is out of range for the attribute's format"))
 }
 
 impl CgfxCollectionValue for Shape {
@@ -103,8 +790,8 @@ impl CgfxCollectionValue for Shape {
         Self::from_reader(reader)
     }
 
-    fn write_dict_value<W: Write + Seek>(&self, writer: &mut W, _: &mut WriteContext) -> Result<()> {
-        self.to_writer(writer)
+    fn write_dict_value<W: Write + Seek>(&self, writer: &mut W, ctx: &mut WriteContext) -> Result<()> {
+        self.to_writer(writer, ctx)
     }
 }
 
@@ -115,7 +802,27 @@ pub struct BoundingBox {
     
     pub center: Vec3,
     pub orientation: Mat3,
-    pub size: Vec3,
+    pub size: Vec3, // full extents, not half
+}
+
+impl BoundingBox {
+    /// Smallest axis aligned box containing all points, or None if there are none
+    pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        
+        let (min, max) = points.fold((first, first), |(min, max), point| (
+            Vec3::new(min.x.min(point.x), min.y.min(point.y), min.z.min(point.z)),
+            Vec3::new(max.x.max(point.x), max.y.max(point.y), max.z.max(point.z)),
+        ));
+        
+        Some(Self {
+            flags: 0,
+            center: Vec3::new((min.x + max.x) / 2.0, (min.y + max.y) / 2.0, (min.z + max.z) / 2.0),
+            orientation: Mat3::IDENTITY,
+            size: Vec3::new(max.x - min.x, max.y - min.y, max.z - min.z),
+        })
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, BinRead, BinWrite)]
@@ -126,11 +833,17 @@ pub enum SubMeshSkinning {
     Smooth,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct SubMesh {
     pub bone_indices: Vec<u32>,
     pub skinning: SubMeshSkinning,
-    pub faces: Vec<Face>,
+    pub faces: Vec<Option<Face>>,
+}
+
+impl RuntimeHandles for SubMesh {
+    fn clear_runtime_handles(&mut self) {
+        self.faces.clear_runtime_handles();
+    }
 }
 
 impl SubMesh {
@@ -151,7 +864,7 @@ impl SubMesh {
         };
         
         let skinning: SubMeshSkinning = SubMeshSkinning::read(reader)?;
-        let faces: Vec<Face> = read_pointer_list(reader)?;
+        let faces: Vec<Option<Face>> = read_field(reader, "faces", read_pointer_list)?;
 
         Ok(Self {
             bone_indices,
@@ -160,8 +873,20 @@ impl SubMesh {
         })
     }
     
-    pub fn to_writer<W: Write + Seek>(&self, _writer: &mut W) -> Result<()> {
-        todo!()
+    pub fn to_writer<W: Write + Seek>(&self, writer: &mut W, ctx: &mut WriteContext) -> Result<()> {
+        let bone_indices_location = write_list_header(writer, self.bone_indices.len())?;
+        self.skinning.write(writer)?;
+        let faces_location = write_pointer_list_header(writer, &self.faces)?;
+        
+        if !self.bone_indices.is_empty() {
+            point_here(writer, bone_indices_location)?;
+            
+            for &bone_index in &self.bone_indices {
+                writer.write_u32::<LittleEndian>(bone_index)?;
+            }
+        }
+        
+        write_pointer_list_table(writer, faces_location, &self.faces, ctx)
     }
 }
 
@@ -170,23 +895,30 @@ impl CgfxCollectionValue for SubMesh {
         Self::from_reader(reader)
     }
 
-    fn write_dict_value<W: Write + Seek>(&self, writer: &mut W, _: &mut WriteContext) -> Result<()> {
-        self.to_writer(writer)
+    fn write_dict_value<W: Write + Seek>(&self, writer: &mut W, ctx: &mut WriteContext) -> Result<()> {
+        self.to_writer(writer, ctx)
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Face {
-    pub face_descriptors: Vec<FaceDescriptor>,
-    pub buffer_objs: Vec<u32>,
+    pub face_descriptors: Vec<Option<FaceDescriptor>>,
+    pub buffer_objs: Vec<RuntimeHandle>,
     pub flags: u32,
     pub command_alloc: u32,
 }
 
+impl RuntimeHandles for Face {
+    fn clear_runtime_handles(&mut self) {
+        self.face_descriptors.clear_runtime_handles();
+        self.buffer_objs.clear_runtime_handles();
+    }
+}
+
 impl Face {
     pub fn from_reader<R: Read + Seek>(reader: &mut R) -> Result<Self> {
-        let face_descriptors: Vec<FaceDescriptor> = read_pointer_list(reader)?;
-        let buffer_objs: Vec<u32> = read_inline_list(reader)?;
+        let face_descriptors: Vec<Option<FaceDescriptor>> = read_field(reader, "face_descriptors", read_pointer_list)?;
+        let buffer_objs: Vec<RuntimeHandle> = read_inline_list(reader)?;
         let flags = reader.read_u32::<LittleEndian>()?;
         let command_alloc = reader.read_u32::<LittleEndian>()?;
         
@@ -198,8 +930,23 @@ impl Face {
         })
     }
     
-    pub fn to_writer<W: Write + Seek>(&self, _writer: &mut W) -> Result<()> {
-        todo!()
+    pub fn to_writer<W: Write + Seek>(&self, writer: &mut W, ctx: &mut WriteContext) -> Result<()> {
+        let face_descriptors_location = write_pointer_list_header(writer, &self.face_descriptors)?;
+        let buffer_objs_location = write_list_header(writer, self.buffer_objs.len())?;
+        writer.write_u32::<LittleEndian>(self.flags)?;
+        writer.write_u32::<LittleEndian>(self.command_alloc)?;
+        
+        write_pointer_list_table(writer, face_descriptors_location, &self.face_descriptors, ctx)?;
+        
+        if !self.buffer_objs.is_empty() {
+            point_here(writer, buffer_objs_location)?;
+            
+            for buffer_obj in &self.buffer_objs {
+                buffer_obj.write_le_args(writer, (ctx.options().preserve_runtime_handles,))?;
+            }
+        }
+        
+        Ok(())
     }
 }
 
@@ -208,12 +955,12 @@ impl CgfxCollectionValue for Face {
         Self::from_reader(reader)
     }
 
-    fn write_dict_value<W: Write + Seek>(&self, writer: &mut W, _: &mut WriteContext) -> Result<()> {
-        self.to_writer(writer)
+    fn write_dict_value<W: Write + Seek>(&self, writer: &mut W, ctx: &mut WriteContext) -> Result<()> {
+        self.to_writer(writer, ctx)
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct FaceDescriptor {
     pub format: GlDataType,
     pub primitive_mode: u8, // TODO: make this an enum
@@ -221,15 +968,30 @@ pub struct FaceDescriptor {
     
     pub indices: Vec<u16>, // TODO: implement special case for format == Short or UShort
     
-    // more fields
+    // runtime initialized data
+    pub buffer_obj: RuntimeHandle,
+    pub location_flag: u32,
+    pub command_cache: RuntimeHandle,
+    pub command_cache_size: u32,
+    pub location_ptr: u32,
+    pub memory_area: u32,
     
-    pub bounding_volume: u32,
+    // same structure as the bounding box of a shape, but only covering
+    // the vertices used by this face descriptor
+    pub bounding_volume: Option<BoundingBox>,
+}
+
+impl RuntimeHandles for FaceDescriptor {
+    fn clear_runtime_handles(&mut self) {
+        self.buffer_obj.clear_runtime_handles();
+        self.command_cache.clear_runtime_handles();
+    }
 }
 
 impl FaceDescriptor {
     pub fn from_reader<R: Read + Seek>(reader: &mut R) -> Result<Self> {
         let format = GlDataType::read(reader)?;
-        assert!(format.byte_size() == 1 || format.byte_size() == 2);
+        ensure!(format.byte_size() == 1 || format.byte_size() == 2, "Index buffer can't have format {format:?}");
         
         let primitive_mode = reader.read_u8()?;
         
@@ -243,36 +1005,84 @@ impl FaceDescriptor {
             match format.byte_size() {
                 1 => raw_buffer.iter().map(|i| *i as u16).collect(),
                 2 => {
-                    assert!(raw_buffer.len() % 2 == 0);
+                    ensure!(raw_buffer.len().is_multiple_of(2),
+                        "Index buffer of 16-bit indices has odd length {}", raw_buffer.len());
                     
-                    unsafe {
-                        let raw_buffer_pointer = (&raw_buffer[0] as *const u8) as *const u16;
-                        from_raw_parts(raw_buffer_pointer, raw_buffer.len() / 2).to_owned()
-                    }
+                    raw_buffer.chunks_exact(2).map(LittleEndian::read_u16).collect()
                 },
-                _ => panic!("Invalid byte size"),
+                _ => bail!("Index buffer can't have format {format:?}"),
             }
         } else {
             Vec::new()
         };
         
-        // skip 6 32-bit integers (fields aren't relevant here)
-        // TODO: they will be necessary for serializing though
-        reader.seek(SeekFrom::Current(6 * 4))?;
+        let buffer_obj = RuntimeHandle::read_le(reader)?;
+        let location_flag = reader.read_u32::<LittleEndian>()?;
+        let command_cache = RuntimeHandle::read_le(reader)?;
+        let command_cache_size = reader.read_u32::<LittleEndian>()?;
+        let location_ptr = reader.read_u32::<LittleEndian>()?;
+        let memory_area = reader.read_u32::<LittleEndian>()?;
         
-        let bounding_volume = reader.read_u32::<LittleEndian>()?;
+        let bounding_volume_ptr = Pointer::read_relative(reader)?;
+        let bounding_volume = if let Some(bounding_volume_ptr) = bounding_volume_ptr {
+            scoped_reader_pos!(reader);
+            reader.seek(SeekFrom::Start(bounding_volume_ptr.into()))?;
+            Some(read_field(reader, "bounding_volume", |reader| Ok(BoundingBox::read(reader)?))?)
+        } else {
+            None
+        };
         
         Ok(Self {
             format,
             primitive_mode,
             visible,
             indices,
+            buffer_obj,
+            location_flag,
+            command_cache,
+            command_cache_size,
+            location_ptr,
+            memory_area,
             bounding_volume,
         })
     }
     
-    pub fn to_writer<W: Write + Seek>(&self, _writer: &mut W) -> Result<()> {
-        todo!()
+    pub fn to_writer<W: Write + Seek>(&self, writer: &mut W, ctx: &mut WriteContext) -> Result<()> {
+        let raw_buffer: Vec<u8> = match self.format.byte_size() {
+            1 => self.indices.iter()
+                .map(|&index| u8::try_from(index).map_err(|_| anyhow!("Index {index} doesn't fit into {:?}", self.format)))
+                .collect::<Result<_>>()?,
+            2 => self.indices.iter().flat_map(|index| index.to_le_bytes()).collect(),
+            _ => bail!("Index buffer can't have format {:?}", self.format),
+        };
+        
+        let preserve = ctx.options().preserve_runtime_handles;
+        
+        self.format.write(writer)?;
+        writer.write_u8(self.primitive_mode)?;
+        writer.write_u8(self.visible)?;
+        writer.write_u16::<LittleEndian>(0)?;
+        
+        let raw_buffer_location = write_list_header(writer, raw_buffer.len())?;
+        
+        self.buffer_obj.write_le_args(writer, (preserve,))?;
+        writer.write_u32::<LittleEndian>(self.location_flag)?;
+        self.command_cache.write_le_args(writer, (preserve,))?;
+        writer.write_u32::<LittleEndian>(self.command_cache_size)?;
+        writer.write_u32::<LittleEndian>(self.location_ptr)?;
+        writer.write_u32::<LittleEndian>(self.memory_area)?;
+        
+        let bounding_volume_location = Pointer::current(writer)?;
+        writer.write_u32::<LittleEndian>(0)?;
+        
+        write_list_bytes(writer, raw_buffer_location, &raw_buffer, ctx)?;
+        
+        if let Some(bounding_volume) = &self.bounding_volume {
+            point_here(writer, bounding_volume_location)?;
+            bounding_volume.write(writer)?;
+        }
+        
+        Ok(())
     }
 }
 
@@ -281,8 +1091,8 @@ impl CgfxCollectionValue for FaceDescriptor {
         Self::from_reader(reader)
     }
 
-    fn write_dict_value<W: Write + Seek>(&self, writer: &mut W, _: &mut WriteContext) -> Result<()> {
-        self.to_writer(writer)
+    fn write_dict_value<W: Write + Seek>(&self, writer: &mut W, ctx: &mut WriteContext) -> Result<()> {
+        self.to_writer(writer, ctx)
     }
 }
 
@@ -317,9 +1127,23 @@ pub enum AttributeName {
     UserAttribute9,
     UserAttribute10,
     UserAttribute11,
+    /// Name of an interleaved vertex buffer as a whole, the attributes it consists
+    /// of have their own names
     Interleave,
 }
 
+impl AttributeName {
+    /// The attribute holding texture coordinate channel `channel`
+    pub fn tex_coord(channel: u32) -> Option<Self> {
+        match channel {
+            0 => Some(AttributeName::TexCoord0),
+            1 => Some(AttributeName::TexCoord1),
+            2 => Some(AttributeName::TexCoord2),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, Debug, BinRead, BinWrite)]
 #[brw(little, repr = u32)]
 pub enum GlDataType {
@@ -339,7 +1163,7 @@ impl GlDataType {
             GlDataType::Short => 2,
             GlDataType::UShort => 2,
             GlDataType::Float => 4,
-            GlDataType::Fixed => todo!(), // wtf is Fixed?
+            GlDataType::Fixed => 4, // GL_FIXED, 16.16 fixed point
         }
     }
 }
@@ -360,22 +1184,60 @@ pub enum VertexBuffer {
     Fixed(VertexBufferFixed),
 }
 
+impl RuntimeHandles for VertexBuffer {
+    fn clear_runtime_handles(&mut self) {
+        match self {
+            VertexBuffer::Attribute(buffer) => buffer.clear_runtime_handles(),
+            VertexBuffer::Interleaved(buffer) => buffer.clear_runtime_handles(),
+            VertexBuffer::Fixed(_) => {},
+        }
+    }
+}
+
 impl VertexBuffer {
     fn from_reader<R: Read + Seek>(reader: &mut R) -> Result<Self> {
-        let discriminant = reader.read_u32::<LittleEndian>()?;
+        let kind = VertexBufferKind::try_from(reader.read_u32::<LittleEndian>()?)?;
         
-        let vertex_buffer = match discriminant {
-            0x40000001 => Self::Attribute(VertexBufferAttribute::from_reader(reader)?),
-            0x40000002 => Self::Interleaved(VertexBufferInterleaved::from_reader(reader)?),
-            0x80000000 => Self::Fixed(VertexBufferFixed::from_reader(reader)?),
-            _ => return Err(anyhow!("Invalid model type discriminant {:x}", discriminant)),
+        let vertex_buffer = match kind {
+            VertexBufferKind::Attribute => Self::Attribute(VertexBufferAttribute::from_reader(reader)?),
+            VertexBufferKind::Interleaved => Self::Interleaved(VertexBufferInterleaved::from_reader(reader)?),
+            VertexBufferKind::Fixed => Self::Fixed(VertexBufferFixed::from_reader(reader)?),
         };
         
         Ok(vertex_buffer)
     }
     
-    fn to_writer<W: Write + Seek>(&self, _writer: &mut W) -> Result<()> {
-        todo!()
+    fn to_writer<W: Write + Seek>(&self, writer: &mut W, ctx: &mut WriteContext) -> Result<()> {
+        let kind = match self {
+            Self::Attribute(_) => VertexBufferKind::Attribute,
+            Self::Interleaved(_) => VertexBufferKind::Interleaved,
+            Self::Fixed(_) => VertexBufferKind::Fixed,
+        };
+        
+        writer.write_u32::<LittleEndian>(kind.into())?;
+        
+        match self {
+            Self::Attribute(buffer) => buffer.to_writer(writer, ctx),
+            Self::Interleaved(buffer) => buffer.to_writer(writer, ctx),
+            Self::Fixed(buffer) => buffer.to_writer(writer),
+        }
+    }
+    
+    /// Vertex data of the buffer, fixed attributes don't have any
+    pub fn raw_bytes(&self) -> Option<&SharedBytes> {
+        match self {
+            VertexBuffer::Attribute(buffer) => Some(&buffer.raw_bytes),
+            VertexBuffer::Interleaved(buffer) => Some(&buffer.raw_bytes),
+            VertexBuffer::Fixed(_) => None,
+        }
+    }
+    
+    pub fn raw_bytes_mut(&mut self) -> Option<&mut SharedBytes> {
+        match self {
+            VertexBuffer::Attribute(buffer) => Some(&mut buffer.raw_bytes),
+            VertexBuffer::Interleaved(buffer) => Some(&mut buffer.raw_bytes),
+            VertexBuffer::Fixed(_) => None,
+        }
     }
 }
 
@@ -384,8 +1246,8 @@ impl CgfxCollectionValue for VertexBuffer {
         Self::from_reader(reader)
     }
 
-    fn write_dict_value<W: Write + Seek>(&self, writer: &mut W, _: &mut WriteContext) -> Result<()> {
-        self.to_writer(writer)
+    fn write_dict_value<W: Write + Seek>(&self, writer: &mut W, ctx: &mut WriteContext) -> Result<()> {
+        self.to_writer(writer, ctx)
     }
 }
 
@@ -393,10 +1255,10 @@ impl CgfxCollectionValue for VertexBuffer {
 pub struct VertexBufferAttribute {
     pub vertex_buffer_common: VertexBufferCommon,
     
-    pub buffer_obj: u32,
+    pub buffer_obj: RuntimeHandle,
     pub location_flag: u32,
     
-    pub raw_bytes: Vec<u8>,
+    pub raw_bytes: SharedBytes,
     
     pub location_ptr: u32,
     pub memory_area: u32,
@@ -407,13 +1269,65 @@ pub struct VertexBufferAttribute {
     pub offset: u32,
 }
 
+// index and vertex data, padded so that the next object is four byte aligned again
+fn write_list_bytes<W: Write + Seek>(writer: &mut W, pointer_location: Pointer, bytes: &[u8], ctx: &WriteContext) -> Result<()> {
+    if bytes.is_empty() {
+        return Ok(());
+    }
+    
+    point_here(writer, pointer_location)?;
+    writer.write_all(bytes)?;
+    
+    let padding = bytes.len().next_multiple_of(4) - bytes.len();
+    writer.write_all(&vec![ctx.options().pad_byte; padding])?;
+    
+    Ok(())
+}
+
+// vertex data shared with a buffer that was written already only gets pointed to,
+// see CgfxModelCommon::share_vertex_buffers
+fn write_vertex_bytes<W: Write + Seek>(writer: &mut W, pointer_location: Pointer, bytes: &SharedBytes,
+    ctx: &mut WriteContext) -> Result<()>
+{
+    if let Some(location) = ctx.written_vertex_bytes(bytes) {
+        return write_relative_pointer_at(writer, pointer_location, Some(location));
+    }
+    
+    if !bytes.is_empty() {
+        ctx.add_written_vertex_bytes(bytes, Pointer::current(writer)?);
+    }
+    
+    write_list_bytes(writer, pointer_location, bytes, ctx)
+}
+
+// goes through the parse limits before anything gets read
+fn read_vertex_bytes<R: Read + Seek>(reader: &mut R) -> Result<SharedBytes> {
+    let length = reader.read_u32::<LittleEndian>()?;
+    limits::check_vertex_buffer_size(length)?;
+    
+    let Some(bytes_ptr) = Pointer::read(reader)? else {
+        return Ok(SharedBytes::default());
+    };
+    
+    // points backwards if the data is shared with a buffer written before this one
+    scoped_reader_pos!(reader);
+    reader.seek(SeekFrom::Current(i64::from(bytes_ptr.0 as i32) - 4))?;
+    read_shared_bytes(reader, length.try_into()?)
+}
+
+impl RuntimeHandles for VertexBufferAttribute {
+    fn clear_runtime_handles(&mut self) {
+        self.buffer_obj.clear_runtime_handles();
+    }
+}
+
 impl VertexBufferAttribute {
     fn from_reader<R: Read + Seek>(reader: &mut R) -> Result<Self> {
         let vertex_buffer_common = VertexBufferCommon::read(reader)?;
-        let buffer_obj = reader.read_u32::<LittleEndian>()?;
+        let buffer_obj = RuntimeHandle::read_le(reader)?;
         let location_flag = reader.read_u32::<LittleEndian>()?;
         
-        let raw_bytes: Vec<u8> = read_inline_list(reader)?;
+        let raw_bytes = read_vertex_bytes(reader)?;
         
         let location_ptr = reader.read_u32::<LittleEndian>()?;
         let memory_area = reader.read_u32::<LittleEndian>()?;
@@ -437,8 +1351,22 @@ impl VertexBufferAttribute {
         })
     }
     
-    fn to_writer<W: Write + Seek>(&self, _writer: &mut W) -> Result<()> {
-        todo!()
+    fn to_writer<W: Write + Seek>(&self, writer: &mut W, ctx: &mut WriteContext) -> Result<()> {
+        self.vertex_buffer_common.write(writer)?;
+        self.buffer_obj.write_le_args(writer, (ctx.options().preserve_runtime_handles,))?;
+        writer.write_u32::<LittleEndian>(self.location_flag)?;
+        
+        let raw_bytes_location = write_list_header(writer, self.raw_bytes.len())?;
+        
+        writer.write_u32::<LittleEndian>(self.location_ptr)?;
+        writer.write_u32::<LittleEndian>(self.memory_area)?;
+        
+        self.format.write(writer)?;
+        writer.write_u32::<LittleEndian>(self.elements)?;
+        writer.write_f32::<LittleEndian>(self.scale)?;
+        writer.write_u32::<LittleEndian>(self.offset)?;
+        
+        write_vertex_bytes(writer, raw_bytes_location, &self.raw_bytes, ctx)
     }
 }
 
@@ -447,8 +1375,8 @@ impl CgfxCollectionValue for VertexBufferAttribute {
         Self::from_reader(reader)
     }
 
-    fn write_dict_value<W: Write + Seek>(&self, writer: &mut W, _: &mut WriteContext) -> Result<()> {
-        self.to_writer(writer)
+    fn write_dict_value<W: Write + Seek>(&self, writer: &mut W, ctx: &mut WriteContext) -> Result<()> {
+        self.to_writer(writer, ctx)
     }
 }
 
@@ -470,31 +1398,39 @@ impl DerefMut for VertexBufferAttribute {
 pub struct VertexBufferInterleaved {
     pub vertex_buffer_common: VertexBufferCommon,
     
-    pub buffer_obj: u32,
+    pub buffer_obj: RuntimeHandle,
     pub location_flag: u32,
     
-    pub raw_bytes: Vec<u8>,
+    pub raw_bytes: SharedBytes,
     
     pub location_ptr: u32,
     pub memory_area: u32,
     
     pub vertex_stride: u32,
-    pub attributes: Vec<VertexBufferAttribute>,
+    pub attributes: Vec<Option<VertexBufferAttribute>>,
+}
+
+impl RuntimeHandles for VertexBufferInterleaved {
+    fn clear_runtime_handles(&mut self) {
+        self.buffer_obj.clear_runtime_handles();
+        self.attributes.clear_runtime_handles();
+    }
 }
 
 impl VertexBufferInterleaved {
     fn from_reader<R: Read + Seek>(reader: &mut R) -> Result<Self> {
         let vertex_buffer_common = VertexBufferCommon::read(reader)?;
-        let buffer_obj = reader.read_u32::<LittleEndian>()?;
+        let buffer_obj = RuntimeHandle::read_le(reader)?;
         let location_flag = reader.read_u32::<LittleEndian>()?;
         
-        let raw_bytes: Vec<u8> = read_inline_list(reader)?;
+        let raw_bytes = read_vertex_bytes(reader)?;
         
         let location_ptr = reader.read_u32::<LittleEndian>()?;
         let memory_area = reader.read_u32::<LittleEndian>()?;
         
         let vertex_stride = reader.read_u32::<LittleEndian>()?;
-        let attributes: Vec<VertexBufferAttribute> = read_pointer_list_ext(reader, Some(0x40000001))?;
+        let attributes: Vec<Option<VertexBufferAttribute>> = read_field(reader, "attributes",
+            |reader| read_pointer_list_ext(reader, Some(VertexBufferKind::Attribute.into())))?;
         
         Ok(Self {
             vertex_buffer_common,
@@ -507,6 +1443,23 @@ impl VertexBufferInterleaved {
             attributes,
         })
     }
+    
+    fn to_writer<W: Write + Seek>(&self, writer: &mut W, ctx: &mut WriteContext) -> Result<()> {
+        self.vertex_buffer_common.write(writer)?;
+        self.buffer_obj.write_le_args(writer, (ctx.options().preserve_runtime_handles,))?;
+        writer.write_u32::<LittleEndian>(self.location_flag)?;
+        
+        let raw_bytes_location = write_list_header(writer, self.raw_bytes.len())?;
+        
+        writer.write_u32::<LittleEndian>(self.location_ptr)?;
+        writer.write_u32::<LittleEndian>(self.memory_area)?;
+        writer.write_u32::<LittleEndian>(self.vertex_stride)?;
+        
+        let attributes_location = write_pointer_list_header(writer, &self.attributes)?;
+        
+        write_vertex_bytes(writer, raw_bytes_location, &self.raw_bytes, ctx)?;
+        write_pointer_list_table_ext(writer, attributes_location, &self.attributes, Some(VertexBufferKind::Attribute.into()), ctx)
+    }
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -535,4 +1488,23 @@ impl VertexBufferFixed {
             vector,
         })
     }
+    
+    pub fn to_writer<W: Write + Seek>(&self, writer: &mut W) -> Result<()> {
+        self.vertex_buffer_common.write(writer)?;
+        self.format.write(writer)?;
+        writer.write_u32::<LittleEndian>(self.elements)?;
+        writer.write_f32::<LittleEndian>(self.scale)?;
+        
+        let vector_location = write_list_header(writer, self.vector.len())?;
+        
+        if !self.vector.is_empty() {
+            point_here(writer, vector_location)?;
+            
+            for &value in &self.vector {
+                writer.write_f32::<LittleEndian>(value)?;
+            }
+        }
+        
+        Ok(())
+    }
 }
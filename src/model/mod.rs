@@ -1,6 +1,16 @@
+pub mod anim_group;
+pub mod animation;
+pub mod description;
+pub mod indices;
+pub mod lod;
 pub mod material;
 pub mod mesh;
+pub mod reorder;
 pub mod skeleton;
+pub mod skinning;
+#[cfg(feature = "bytemuck")]
+pub mod vertex_view;
+pub mod weld;
 
 #[allow(clippy::module_inception)]
 mod model;
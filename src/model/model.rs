@@ -1,21 +1,31 @@
-use std::io::{Read, Seek, SeekFrom, Write};
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt::Display,
+    io::{Read, Seek, SeekFrom, Write},
+};
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, ensure, Context, Result};
 use binrw::{BinRead, BinWrite};
-use byteorder::{LittleEndian, ReadBytesExt};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 
 use crate::{
+    error::read_field,
+    magic::ModelType,
     scoped_reader_pos,
     util::{
         pointer::Pointer,
-        util::{brw_read_string, brw_write_zero, read_pointer_list, CgfxNodeHeader, CgfxObjectHeader, CgfxTransform},
+        util::{
+            add_object_header_name, brw_read_string, point_here, read_pointer_list, write_list_header,
+            write_pointer_list_header, write_pointer_list_table, write_pointer_list_table_with,
+            CgfxNodeHeader, CgfxObjectHeader, CgfxTransform, RuntimeHandles, SharedBytes,
+        },
     },
-    CgfxCollectionValue, CgfxDict, WriteContext,
+    CgfxCollectionValue, CgfxDict, CollisionPolicy, WriteContext,
 };
 
 use super::{
-    material::CgfxMaterial,
-    mesh::{Mesh, Shape},
+    material::{CgfxMaterial, MaterialDict},
+    mesh::{AttributeName, AttributeStorage, Mesh, RenderSortKey, Shape, VertexBuffer},
     skeleton::CgfxSkeleton,
 };
 
@@ -27,72 +37,230 @@ pub struct CgfxModelCommon {
     pub transform_node_header: CgfxTransform,
     
     // model data
-    pub meshes: Vec<Mesh>,
-    pub materials: Option<CgfxDict<CgfxMaterial>>,
-    pub shapes: Vec<Shape>,
-    pub mesh_node_visibilities: Option<CgfxDict<MeshNodeVisibility>>, // TODO: implement
+    pub meshes: Vec<Option<Mesh>>,
+    pub materials: Option<MaterialDict>,
+    pub shapes: Vec<Option<Shape>>,
+    pub mesh_node_visibilities: Option<MeshNodeVisibilityDict>,
     
     pub flags: u32,
     pub face_culling: u32,
     pub layer_id: u32,
 }
 
+impl CgfxModelCommon {
+    /// Adds a material under its own name, returning the name it ended up under
+    pub fn add_material(&mut self, material: CgfxMaterial, policy: CollisionPolicy) -> Result<String> {
+        let name = material.cgfx_object_header.name.clone()
+            .ok_or_else(|| anyhow!("Material has no name"))?;
+        
+        let materials = self.materials.get_or_insert_with(CgfxDict::new);
+        let new_name = materials.insert(&name, material, policy)?;
+        
+        if new_name != name {
+            let material = materials.get_mut(&new_name).unwrap();
+            material.cgfx_object_header.name = Some(new_name.clone());
+        }
+        
+        Ok(new_name)
+    }
+    
+    /// Indices of all visible meshes in the order they are drawn in, `depth` returns
+    /// the distance of a mesh to the camera. Meshes whose material is missing are skipped
+    pub fn draw_order(&self, depth: impl Fn(&Mesh) -> f32) -> Vec<usize> {
+        let materials: Vec<&CgfxMaterial> = self.materials.iter().flat_map(|materials| materials.values()).collect();
+        
+        let mut keys: Vec<(RenderSortKey, usize)> = self.meshes.iter()
+            .enumerate()
+            .filter_map(|(i, mesh)| Some((i, mesh.as_ref()?)))
+            .filter(|(_, mesh)| mesh.visible)
+            .filter_map(|(i, mesh)| {
+                let material = materials.get(mesh.material_index as usize)?;
+                Some((RenderSortKey::new(mesh, material, depth(mesh)), i))
+            })
+            .collect();
+        
+        // stable so that meshes with equal keys stay in file order
+        keys.sort_by_key(|&(key, _)| key);
+        keys.into_iter().map(|(_, i)| i).collect()
+    }
+    
+    /// Groups of vertex buffers across all shapes that contain identical data.
+    /// Buffers that aren't shared with any other buffer are left out
+    pub fn shared_vertex_buffers(&self) -> Vec<Vec<VertexBufferLocation>> {
+        let mut group_indices: HashMap<&[u8], usize> = HashMap::new();
+        let mut groups: Vec<Vec<VertexBufferLocation>> = Vec::new();
+        
+        for (shape_index, shape) in self.shapes.iter().enumerate() {
+            let Some(shape) = shape else {
+                continue;
+            };
+            
+            for (buffer_index, vertex_buffer) in shape.vertex_buffers.iter().enumerate() {
+                let Some(raw_bytes) = vertex_buffer.as_ref()
+                    .and_then(VertexBuffer::raw_bytes)
+                    .filter(|bytes| !bytes.is_empty()) else {
+                    continue;
+                };
+                
+                let location = VertexBufferLocation { shape_index, buffer_index };
+                let group_index = *group_indices.entry(raw_bytes).or_insert_with(|| {
+                    groups.push(Vec::new());
+                    groups.len() - 1
+                });
+                
+                groups[group_index].push(location);
+            }
+        }
+        
+        groups.retain(|group| group.len() > 1);
+        groups
+    }
+    
+    /// Makes vertex buffers with identical data share the same memory, so that
+    /// they stay identical until one of them gets modified. Shared data is only
+    /// written once, with every buffer pointing to it. This is done when reading
+    /// already
+    pub fn share_vertex_buffers(&mut self) {
+        for group in self.shared_vertex_buffers() {
+            let first = self.vertex_buffer_bytes(group[0]).clone();
+            
+            for &location in &group[1..] {
+                *self.vertex_buffer_bytes(location) = first.clone();
+            }
+        }
+    }
+    
+    /// Adds an entry to the mesh node visibilities, returning the name it ended up
+    /// under and its index, which is what [`Mesh::mesh_node_index`] refers to
+    pub fn add_mesh_node_visibility(&mut self, name: &str, visible: bool, policy: CollisionPolicy) -> Result<(String, u16)> {
+        let visibilities = self.mesh_node_visibilities.get_or_insert_with(CgfxDict::new);
+        
+        let visibility = MeshNodeVisibility {
+            name: Some(name.to_string()),
+            visible,
+        };
+        let new_name = visibilities.insert(name, visibility, policy)?;
+        
+        if new_name != name {
+            visibilities.get_mut(&new_name).unwrap().name = Some(new_name.clone());
+        }
+        
+        // skip the root node
+        let index = visibilities.nodes.iter()
+            .skip(1)
+            .position(|node| node.name.as_deref() == Some(new_name.as_str()))
+            .unwrap();
+        
+        Ok((new_name, index.try_into()?))
+    }
+    
+    /// Adds a mesh node visibility named after the mesh at `mesh_index` and makes the mesh
+    /// use it, so that the game can show and hide newly added meshes by name
+    pub fn add_mesh_node_for_mesh(&mut self, mesh_index: usize, visible: bool, policy: CollisionPolicy) -> Result<String> {
+        let mesh = self.meshes.get(mesh_index)
+            .and_then(Option::as_ref)
+            .ok_or_else(|| anyhow!("Model has no mesh {mesh_index}"))?;
+        let name = mesh.cgfx_object_header.name.clone()
+            .ok_or_else(|| anyhow!("Mesh {mesh_index} has no name"))?;
+        
+        let (new_name, index) = self.add_mesh_node_visibility(&name, visible, policy)?;
+        self.meshes[mesh_index].as_mut().unwrap().mesh_node_index = index;
+        
+        Ok(new_name)
+    }
+    
+    pub fn mesh_node_visibility(&self, mesh: &Mesh) -> Option<&MeshNodeVisibility> {
+        self.mesh_node_visibilities.as_ref()?
+            .values()
+            .nth(mesh.mesh_node_index as usize)
+    }
+    
+    // only called with locations that have data
+    fn vertex_buffer_bytes(&mut self, location: VertexBufferLocation) -> &mut SharedBytes {
+        let shape = self.shapes[location.shape_index].as_mut().unwrap();
+        shape.vertex_buffers[location.buffer_index].as_mut().unwrap().raw_bytes_mut().unwrap()
+    }
+}
+
+/// Position of a vertex buffer inside of a model
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct VertexBufferLocation {
+    /// Index into [`CgfxModelCommon::shapes`]
+    pub shape_index: usize,
+    /// Index into [`Shape::vertex_buffers`]
+    pub buffer_index: usize,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum CgfxModel {
     Standard(CgfxModelCommon),
     Skeletal(CgfxModelCommon, CgfxSkeleton),
 }
 
+impl RuntimeHandles for CgfxModel {
+    fn clear_runtime_handles(&mut self) {
+        self.common_mut().shapes.clear_runtime_handles();
+    }
+}
+
 impl CgfxModel {
     pub fn from_reader<R: Read + Seek>(reader: &mut R) -> Result<Self> {
-        let discriminant = reader.read_u32::<LittleEndian>()?;
+        let model_type = ModelType::try_from(reader.read_u32::<LittleEndian>()?)?;
         let cgfx_object_header = CgfxObjectHeader::read(reader)?;
-        let cgfx_node_header = CgfxNodeHeader::read(reader)?;
+        let cgfx_node_header = CgfxNodeHeader::from_reader(reader)?;
         let transform_node_header = CgfxTransform::read(reader)?;
         
-        // TODO: anim groups in node header
-        
         // meshes
-        let meshes: Vec<Mesh> = read_pointer_list(reader)?;
+        let meshes: Vec<Option<Mesh>> = read_field(reader, "meshes", read_pointer_list)?;
         
         // materials
-        let material_count = reader.read_u32::<LittleEndian>()?;
-        let material_ptr = Pointer::read_relative(reader)?;
-        
-        let materials = if let Some(material_ptr) = material_ptr {
-            scoped_reader_pos!(reader);
-            reader.seek(SeekFrom::Start(material_ptr.into()))?;
-            let dict: CgfxDict<CgfxMaterial> = CgfxDict::from_reader(reader)?;
+        let materials = read_field(reader, "materials", |reader| {
+            let material_count = reader.read_u32::<LittleEndian>()?;
+            let material_ptr = Pointer::read_relative(reader)?;
             
-            assert!(dict.values_count == material_count);
-            Some(dict)
-        } else {
-            None
-        };
+            let materials = if let Some(material_ptr) = material_ptr {
+                scoped_reader_pos!(reader);
+                reader.seek(SeekFrom::Start(material_ptr.into()))?;
+                let dict: CgfxDict<CgfxMaterial> = CgfxDict::from_reader(reader)?;
+                
+                ensure!(dict.values_count == material_count,
+                    "Material dict has {} entries but the model has {material_count}", dict.values_count);
+                Some(dict)
+            } else {
+                None
+            };
+            
+            Ok(materials)
+        })?;
         
         // shapes
-        let shapes: Vec<Shape> = read_pointer_list(reader)?;
+        let shapes: Vec<Option<Shape>> = read_field(reader, "shapes", read_pointer_list)?;
         
         // mesh node visibilities
-        let mesh_node_visibility_count = reader.read_u32::<LittleEndian>()?;
-        let mesh_node_visibility_ptr = Pointer::read_relative(reader)?;
-        
-        let mesh_node_visibilities = if let Some(mesh_node_visibility_ptr) = mesh_node_visibility_ptr {
-            scoped_reader_pos!(reader);
-            reader.seek(SeekFrom::Start(mesh_node_visibility_ptr.into()))?;
-            let dict: CgfxDict<MeshNodeVisibility> = CgfxDict::from_reader(reader)?;
+        let mesh_node_visibilities = read_field(reader, "mesh_node_visibilities", |reader| {
+            let mesh_node_visibility_count = reader.read_u32::<LittleEndian>()?;
+            let mesh_node_visibility_ptr = Pointer::read_relative(reader)?;
             
-            assert!(dict.values_count == mesh_node_visibility_count);
-            Some(dict)
-        } else {
-            None
-        };
+            let mesh_node_visibilities = if let Some(mesh_node_visibility_ptr) = mesh_node_visibility_ptr {
+                scoped_reader_pos!(reader);
+                reader.seek(SeekFrom::Start(mesh_node_visibility_ptr.into()))?;
+                let dict: CgfxDict<MeshNodeVisibility> = CgfxDict::from_reader(reader)?;
+                
+                ensure!(dict.values_count == mesh_node_visibility_count,
+                    "Mesh node visibility dict has {} entries but the model has {mesh_node_visibility_count}", dict.values_count);
+                Some(dict)
+            } else {
+                None
+            };
+            
+            Ok(mesh_node_visibilities)
+        })?;
         
         let flags = reader.read_u32::<LittleEndian>()?;
         let face_culling = reader.read_u32::<LittleEndian>()?;
         let layer_id = reader.read_u32::<LittleEndian>()?;
         
-        let common = CgfxModelCommon {
+        let mut common = CgfxModelCommon {
             cgfx_object_header,
             cgfx_node_header,
             transform_node_header,
@@ -105,25 +273,61 @@ impl CgfxModel {
             layer_id,
         };
         
-        let model = match discriminant {
-            0x40000012 => CgfxModel::Standard(common),
-            0x40000092 => {
+        common.share_vertex_buffers();
+        
+        let model = match model_type {
+            ModelType::Standard => CgfxModel::Standard(common),
+            ModelType::Skeletal => {
                 let skeleton_ptr = Pointer::read_relative(reader)?
                     .ok_or_else(|| anyhow!("Skeleton can not be null"))?;
                 
                 scoped_reader_pos!(reader);
                 reader.seek(SeekFrom::Start(skeleton_ptr.into()))?;
                 
-                let skeleton = CgfxSkeleton::from_reader(reader)?;
+                let skeleton = read_field(reader, "skeleton", CgfxSkeleton::from_reader)?;
                 
                 CgfxModel::Skeletal(common, skeleton)
             },
-            _ => return Err(anyhow!("Invalid model type discriminant {:x}", discriminant)),
         };
         
         Ok(model)
     }
 
+    pub fn model_type(&self) -> ModelType {
+        match self {
+            CgfxModel::Standard(_) => ModelType::Standard,
+            CgfxModel::Skeletal(_, _) => ModelType::Skeletal,
+        }
+    }
+    
+    /// Turns a standard model into a skeletal one whose skeleton only has a bone named
+    /// `root_bone`, so that it can be attached to bones of other models. Its vertices
+    /// are left unskinned. Skeletal models are returned as they are
+    pub fn into_skeletal(self, root_bone: &str) -> Result<CgfxModel> {
+        match self {
+            CgfxModel::Standard(common) => Ok(CgfxModel::Skeletal(common, CgfxSkeleton::with_root_bone(root_bone)?)),
+            model => Ok(model),
+        }
+    }
+    
+    /// Turns a skeletal model into a standard one by baking the bind pose into the
+    /// vertices of all of its shapes, see [`Shape::bake_bind_pose`], and dropping the
+    /// skeleton. Standard models are returned as they are
+    pub fn into_standard(self) -> Result<CgfxModel> {
+        match self {
+            CgfxModel::Skeletal(mut common, skeleton) => {
+                for (index, shape) in common.shapes.iter_mut().enumerate() {
+                    if let Some(shape) = shape {
+                        shape.bake_bind_pose(&skeleton).with_context(|| format!("Failed to bake shape {index}"))?;
+                    }
+                }
+                
+                Ok(CgfxModel::Standard(common))
+            },
+            model => Ok(model),
+        }
+    }
+    
     pub fn common(&self) -> &CgfxModelCommon {
         match self {
             CgfxModel::Standard(common) => common,
@@ -137,6 +341,194 @@ impl CgfxModel {
             CgfxModel::Skeletal(common, _) => common,
         }
     }
+    
+    /// Recomputes the counts of all dicts and children of this model and
+    /// the derived fields of its materials
+    pub fn normalize(&mut self) -> Result<()> {
+        if let CgfxModel::Skeletal(_, skeleton) = self {
+            skeleton.bones.normalize()?;
+        }
+        
+        let common = self.common_mut();
+        common.cgfx_node_header.normalize()?;
+        
+        if let Some(materials) = &mut common.materials {
+            materials.normalize()?;
+            materials.values_mut().for_each(CgfxMaterial::normalize);
+        }
+        
+        if let Some(visibilities) = &mut common.mesh_node_visibilities {
+            visibilities.normalize()?;
+        }
+        
+        Ok(())
+    }
+    
+    /// Checks that every bone palette of every sub mesh only refers to bones of the
+    /// skeleton and that the BoneIndex attribute of every vertex only refers to entries
+    /// of the palette of the sub mesh it is in. Bone palettes of standard models are
+    /// not checked against anything because they have no skeleton.
+    pub fn validate_bone_bindings(&self) -> Result<Vec<BoneBindingIssue>> {
+        let (common, bone_count) = match self {
+            CgfxModel::Standard(common) => (common, None),
+            CgfxModel::Skeletal(common, skeleton) => (common, Some(skeleton.bones.values().count())),
+        };
+        
+        let mut issues = Vec::new();
+        
+        for (shape_index, shape) in common.shapes.iter().enumerate() {
+            let Some(shape) = shape else {
+                continue;
+            };
+            
+            let mesh_names: Vec<String> = common.meshes.iter()
+                .flatten()
+                .filter(|mesh| mesh.shape_index as usize == shape_index)
+                .filter_map(|mesh| mesh.cgfx_object_header.name.clone())
+                .collect();
+            
+            let layout = shape.layout();
+            let bone_index_layout = layout.attribute(AttributeName::BoneIndex);
+            let bone_index_elements = bone_index_layout.map_or(0, |attribute| attribute.elements as usize);
+            let bone_index_fixed = bone_index_layout.is_some_and(|attribute| attribute.storage == AttributeStorage::Fixed);
+            let bone_index_values = shape.attribute(AttributeName::BoneIndex)?;
+            
+            for (sub_mesh_index, sub_mesh) in shape.sub_meshes.iter().enumerate() {
+                let Some(sub_mesh) = sub_mesh else {
+                    continue;
+                };
+                
+                let mut push = |kind| issues.push(BoneBindingIssue {
+                    shape_index,
+                    sub_mesh_index,
+                    mesh_names: mesh_names.clone(),
+                    kind,
+                });
+                
+                if let Some(bone_count) = bone_count {
+                    for &bone_index in &sub_mesh.bone_indices {
+                        if bone_index as usize >= bone_count {
+                            push(BoneBindingIssueKind::BoneOutOfBounds { bone_index, bone_count });
+                        }
+                    }
+                }
+                
+                let Some(values) = &bone_index_values else {
+                    continue;
+                };
+                
+                if bone_index_elements == 0 {
+                    continue;
+                }
+                
+                let palette_length = sub_mesh.bone_indices.len();
+                
+                // fixed attributes don't depend on the vertex
+                let vertices: Vec<Option<usize>> = if bone_index_fixed {
+                    vec![None]
+                } else {
+                    sub_mesh.faces.iter()
+                        .flatten()
+                        .flat_map(|face| face.face_descriptors.iter().flatten())
+                        .flat_map(|face_descriptor| &face_descriptor.indices)
+                        .map(|&index| index as usize)
+                        .collect::<BTreeSet<usize>>()
+                        .into_iter()
+                        .map(Some)
+                        .collect()
+                };
+                
+                for vertex in vertices {
+                    let start = vertex.unwrap_or(0) * bone_index_elements;
+                    let Some(vertex_values) = values.get(start..start + bone_index_elements) else {
+                        continue;
+                    };
+                    
+                    for &value in vertex_values {
+                        let palette_index = value.round();
+                        
+                        if palette_index < 0.0 || palette_index as usize >= palette_length {
+                            push(BoneBindingIssueKind::VertexOutOfPalette { vertex, palette_index, palette_length });
+                        }
+                    }
+                }
+            }
+        }
+        
+        Ok(issues)
+    }
+}
+
+/// A sub mesh of a shape that refers to bones that don't exist
+#[derive(Clone, Debug, PartialEq)]
+pub struct BoneBindingIssue {
+    /// Index into [`CgfxModelCommon::shapes`]
+    pub shape_index: usize,
+    /// Index into [`Shape::sub_meshes`]
+    pub sub_mesh_index: usize,
+    /// Names of all meshes that draw the shape
+    pub mesh_names: Vec<String>,
+    pub kind: BoneBindingIssueKind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BoneBindingIssueKind {
+    /// The bone palette of the sub mesh contains a bone the skeleton doesn't have
+    BoneOutOfBounds { bone_index: u32, bone_count: usize },
+    /// The BoneIndex attribute of a vertex points past the end of the bone palette.
+    /// The vertex is None if the attribute is fixed and applies to all vertices
+    VertexOutOfPalette { vertex: Option<usize>, palette_index: f32, palette_length: usize },
+}
+
+impl Display for BoneBindingIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Shape {}", self.shape_index)?;
+        
+        if !self.mesh_names.is_empty() {
+            write!(f, " (used by {})", self.mesh_names.join(", "))?;
+        }
+        
+        write!(f, ", sub mesh {}: ", self.sub_mesh_index)?;
+        
+        match &self.kind {
+            BoneBindingIssueKind::BoneOutOfBounds { bone_index, bone_count } =>
+                write!(f, "Bone palette refers to bone {bone_index}, but the skeleton only has {bone_count} bones"),
+            BoneBindingIssueKind::VertexOutOfPalette { vertex: Some(vertex), palette_index, palette_length } =>
+                write!(f, "Vertex {vertex} refers to palette entry {palette_index}, but the palette only has {palette_length} entries"),
+            BoneBindingIssueKind::VertexOutOfPalette { vertex: None, palette_index, palette_length } =>
+                write!(f, "All vertices refer to palette entry {palette_index}, but the palette only has {palette_length} entries"),
+        }
+    }
+}
+
+pub type ModelDict = CgfxDict<CgfxModel>;
+
+impl ModelDict {
+    pub fn skeletal_models(&self) -> impl Iterator<Item = (&CgfxModelCommon, &CgfxSkeleton)> {
+        self.values().filter_map(|model| match model {
+            CgfxModel::Standard(_) => None,
+            CgfxModel::Skeletal(common, skeleton) => Some((common, skeleton)),
+        })
+    }
+    
+    pub fn total_mesh_count(&self) -> usize {
+        self.values().map(|model| model.common().meshes.len()).sum()
+    }
+    
+    // whether a model points to vertex data written by an earlier one, in which
+    // case the models can't be written independently of each other
+    #[cfg(feature = "parallel")]
+    pub(crate) fn shares_vertex_bytes_between_models(&self) -> bool {
+        let mut owners = HashMap::new();
+        
+        self.values().enumerate().any(|(i, model)| {
+            model.common().shapes.iter().flatten()
+                .flat_map(|shape| shape.vertex_buffers.iter().flatten())
+                .filter_map(VertexBuffer::raw_bytes)
+                .filter(|bytes| !bytes.is_empty())
+                .any(|bytes| *owners.entry(bytes.share_key()).or_insert(i) != i)
+        })
+    }
 }
 
 impl CgfxCollectionValue for CgfxModel {
@@ -144,25 +536,88 @@ impl CgfxCollectionValue for CgfxModel {
         Self::from_reader(reader)
     }
 
-    fn write_dict_value<W: Write + Seek>(&self, _writer: &mut W, _ctx: &mut WriteContext) -> Result<()> {
-        todo!()
+    // same order as the fields pointing to them: meshes, materials, shapes, mesh node
+    // visibilities and the skeleton, followed by the anim groups of the node header
+    fn write_dict_value<W: Write + Seek>(&self, writer: &mut W, ctx: &mut WriteContext) -> Result<()> {
+        let start = Pointer::current(writer)?;
+        let common = self.common();
+        
+        writer.write_u32::<LittleEndian>(self.model_type().into())?;
+        add_object_header_name(writer, &common.cgfx_object_header, ctx)?;
+        common.cgfx_object_header.write(writer)?;
+        let node_header_location = common.cgfx_node_header.to_writer(writer)?;
+        common.transform_node_header.write(writer)?;
+        
+        let meshes_location = write_pointer_list_header(writer, &common.meshes)?;
+        let materials_location = write_list_header(writer, common.materials.as_ref().map_or(0, CgfxDict::len))?;
+        let shapes_location = write_pointer_list_header(writer, &common.shapes)?;
+        let visibilities_location = write_list_header(writer, common.mesh_node_visibilities.as_ref().map_or(0, CgfxDict::len))?;
+        
+        writer.write_u32::<LittleEndian>(common.flags)?;
+        writer.write_u32::<LittleEndian>(common.face_culling)?;
+        writer.write_u32::<LittleEndian>(common.layer_id)?;
+        
+        let skeleton_location = Pointer::current(writer)?;
+        
+        if let CgfxModel::Skeletal(..) = self {
+            writer.write_u32::<LittleEndian>(0)?;
+        }
+        
+        write_pointer_list_table_with(writer, meshes_location, &common.meshes, ctx,
+            |mesh, writer, ctx| mesh.to_writer(writer, start, ctx))?;
+        
+        if let Some(materials) = &common.materials {
+            point_here(writer, materials_location)?;
+            materials.to_writer(writer, ctx)?;
+        }
+        
+        write_pointer_list_table(writer, shapes_location, &common.shapes, ctx)?;
+        
+        if let Some(visibilities) = &common.mesh_node_visibilities {
+            point_here(writer, visibilities_location)?;
+            visibilities.to_writer(writer, ctx)?;
+        }
+        
+        if let CgfxModel::Skeletal(_, skeleton) = self {
+            point_here(writer, skeleton_location)?;
+            skeleton.to_writer(writer, ctx)?;
+        }
+        
+        common.cgfx_node_header.write_children_and_anim_groups(writer, node_header_location, ctx)
     }
 }
 
-impl<'a> Into<&'a CgfxModelCommon> for &'a CgfxModel {
-    fn into(self) -> &'a CgfxModelCommon {
-        self.common()
+impl<'a> From<&'a CgfxModel> for &'a CgfxModelCommon {
+    fn from(value: &'a CgfxModel) -> Self {
+        value.common()
     }
 }
 
-#[derive(Clone, Debug, PartialEq, BinRead, BinWrite)]
-#[brw(little)]
+pub type MeshNodeVisibilityDict = CgfxDict<MeshNodeVisibility>;
+
+#[derive(Clone, Debug, PartialEq, BinRead)]
+#[br(little)]
 pub struct MeshNodeVisibility {
     #[br(parse_with = brw_read_string)]
-    #[bw(write_with = brw_write_zero)]
     pub name: Option<String>,
     
     #[br(map = |value: u32| value != 0)]
-    #[bw(map = |&value: &bool| value as u32)]
     pub visible: bool,
 }
+
+impl CgfxCollectionValue for MeshNodeVisibility {
+    fn read_dict_value<R: Read + Seek>(reader: &mut R) -> Result<Self> {
+        Ok(Self::read(reader)?)
+    }
+    
+    fn write_dict_value<W: Write + Seek>(&self, writer: &mut W, ctx: &mut WriteContext) -> Result<()> {
+        if let Some(name) = &self.name {
+            ctx.add_string(name)?;
+            ctx.add_string_reference(Pointer::current(writer)?, name.clone());
+        }
+        
+        writer.write_u32::<LittleEndian>(0)?;
+        writer.write_u32::<LittleEndian>(self.visible as u32)?;
+        Ok(())
+    }
+}
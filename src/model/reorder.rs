@@ -0,0 +1,181 @@
+// moving meshes, materials and shapes of a model around while keeping the indices that
+// refer to them intact. meshes are drawn in order within the same render layer, which
+// games and other tools rely on, so this is needed for render order fixes and for
+// matching the output of other tools
+
+use anyhow::{anyhow, ensure, Result};
+
+use crate::util::util::apply_order;
+
+use super::CgfxModelCommon;
+
+impl CgfxModelCommon {
+    /// Moves the meshes into the order given by `order`, where `order[i]` is the current
+    /// index of the mesh that ends up at index `i`
+    pub fn reorder_meshes(&mut self, order: &[usize]) -> Result<()> {
+        apply_order(&mut self.meshes, order)?;
+        Ok(())
+    }
+    
+    /// Moves the materials into the order given by `order` (see [`CgfxModelCommon::reorder_meshes`])
+    /// and rebuilds their dict, updating the material index of every mesh
+    pub fn reorder_materials(&mut self, order: &[usize]) -> Result<()> {
+        let Some(materials) = &mut self.materials else {
+            ensure!(order.is_empty(), "Model has no materials to reorder");
+            return Ok(());
+        };
+        
+        let new_indices = materials.reorder(order)?;
+        
+        for mesh in self.meshes.iter_mut().flatten() {
+            if let Some(&new_index) = new_indices.get(mesh.material_index as usize) {
+                mesh.material_index = new_index as u32;
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Moves the shapes into the order given by `order` (see [`CgfxModelCommon::reorder_meshes`]),
+    /// updating the shape index of every mesh
+    pub fn reorder_shapes(&mut self, order: &[usize]) -> Result<()> {
+        let new_indices = apply_order(&mut self.shapes, order)?;
+        
+        for mesh in self.meshes.iter_mut().flatten() {
+            if let Some(&new_index) = new_indices.get(mesh.shape_index as usize) {
+                mesh.shape_index = new_index as u32;
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Sorts the meshes by their material index, keeping meshes with the same material
+    /// in their current order. Removed meshes end up at the end
+    pub fn sort_meshes_by_material(&mut self) -> Result<()> {
+        let mut order: Vec<usize> = (0..self.meshes.len()).collect();
+        order.sort_by_key(|&i| self.meshes[i].as_ref().map_or(u32::MAX, |mesh| mesh.material_index));
+        
+        self.reorder_meshes(&order)
+    }
+    
+    /// Moves the material `name` to index 0, keeping the order of all other materials
+    pub fn move_material_to_front(&mut self, name: &str) -> Result<()> {
+        let index = self.materials.iter()
+            .flat_map(|materials| materials.nodes.iter().skip(1))
+            .position(|node| node.name.as_deref() == Some(name))
+            .ok_or_else(|| anyhow!("Model has no material named {name:?}"))?;
+        
+        let material_count = self.materials.as_ref().map_or(0, |materials| materials.nodes.len() - 1);
+        let order: Vec<usize> = [index].into_iter()
+            .chain((0..material_count).filter(|&i| i != index))
+            .collect();
+        
+        self.reorder_materials(&order)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cgfx_container::CgfxContainer, fixtures, model::mesh::AttributeName, util::math::Vec3, CollisionPolicy};
+    
+    // the fixture model with three meshes over two materials, each mesh drawing a copy
+    // of the fixture triangle that is moved by a different offset
+    fn test_model() -> CgfxModelCommon {
+        let container = CgfxContainer::new(&fixtures::minimal_bcres()).unwrap();
+        let mut common = container.models.as_ref().unwrap().get(fixtures::MODEL_NAME).unwrap().common().clone();
+        
+        let materials = common.materials.as_mut().unwrap();
+        let material = materials.get(fixtures::MATERIAL_NAME).unwrap().clone();
+        materials.insert("second_material", material, CollisionPolicy::Error).unwrap();
+        
+        let shape = common.shapes[0].clone().unwrap();
+        let mesh = common.meshes[0].clone().unwrap();
+        common.meshes.clear();
+        common.shapes.clear();
+        
+        for (i, material_index) in [0, 1, 0].into_iter().enumerate() {
+            let mut shape = shape.clone();
+            let positions: Vec<f32> = fixtures::TRIANGLE_POSITIONS.iter()
+                .flat_map(|&[x, y, z]| [x + i as f32, y, z])
+                .collect();
+            shape.set_attribute(AttributeName::Position, &positions).unwrap();
+            common.shapes.push(Some(shape));
+            
+            let mut mesh = mesh.clone();
+            mesh.cgfx_object_header.name = Some(format!("mesh_{i}"));
+            mesh.shape_index = i as u32;
+            mesh.material_index = material_index;
+            common.meshes.push(Some(mesh));
+        }
+        
+        common
+    }
+    
+    // name, material and triangle positions of every mesh, sorted by name
+    fn drawn_triangles(common: &CgfxModelCommon) -> Vec<(String, String, Vec<Vec3>)> {
+        let mut drawn: Vec<(String, String, Vec<Vec3>)> = common.meshes.iter().flatten()
+            .map(|mesh| {
+                let shape = common.shapes[mesh.shape_index as usize].as_ref().unwrap();
+                let positions = shape.positions().unwrap();
+                let triangles = shape.sub_meshes.iter().flatten()
+                    .flat_map(|sub_mesh| sub_mesh.faces.iter().flatten())
+                    .flat_map(|face| face.face_descriptors.iter().flatten())
+                    .flat_map(|face_descriptor| &face_descriptor.indices)
+                    .map(|&index| positions[index as usize])
+                    .collect();
+                let material = common.materials.as_ref().unwrap().nodes[mesh.material_index as usize + 1].name.clone().unwrap();
+                
+                (mesh.cgfx_object_header.name.clone().unwrap(), material, triangles)
+            })
+            .collect();
+        
+        drawn.sort_by(|a, b| a.0.cmp(&b.0));
+        drawn
+    }
+    
+    fn mesh_names(common: &CgfxModelCommon) -> Vec<&str> {
+        common.meshes.iter().flatten().map(|mesh| mesh.cgfx_object_header.name.as_deref().unwrap()).collect()
+    }
+    
+    #[test]
+    fn reordered_meshes_draw_the_same_triangles() {
+        let mut common = test_model();
+        let drawn = drawn_triangles(&common);
+        assert_eq!(drawn.len(), 3);
+        
+        common.reorder_shapes(&[2, 0, 1]).unwrap();
+        assert_eq!(common.meshes.iter().flatten().map(|mesh| mesh.shape_index).collect::<Vec<_>>(), vec![1, 2, 0]);
+        assert_eq!(drawn_triangles(&common), drawn);
+        
+        common.reorder_materials(&[1, 0]).unwrap();
+        assert_eq!(drawn_triangles(&common), drawn);
+        
+        common.reorder_meshes(&[1, 2, 0]).unwrap();
+        assert_eq!(mesh_names(&common), vec!["mesh_1", "mesh_2", "mesh_0"]);
+        assert_eq!(drawn_triangles(&common), drawn);
+        
+        // second_material is at index 0 after reordering the materials
+        common.sort_meshes_by_material().unwrap();
+        assert_eq!(mesh_names(&common), vec!["mesh_1", "mesh_2", "mesh_0"]);
+        assert_eq!(drawn_triangles(&common), drawn);
+        
+        common.move_material_to_front(fixtures::MATERIAL_NAME).unwrap();
+        common.sort_meshes_by_material().unwrap();
+        assert_eq!(mesh_names(&common), vec!["mesh_2", "mesh_0", "mesh_1"]);
+        assert_eq!(drawn_triangles(&common), drawn);
+    }
+    
+    #[test]
+    fn invalid_orders_change_nothing() {
+        let mut common = test_model();
+        let original = common.clone();
+        
+        assert!(common.reorder_shapes(&[0, 0, 1]).is_err());
+        assert!(common.reorder_materials(&[0]).is_err());
+        assert!(common.reorder_meshes(&[0, 1, 3]).is_err());
+        assert!(common.move_material_to_front("missing").is_err());
+        assert_eq!(common, original);
+    }
+}
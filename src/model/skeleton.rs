@@ -1,49 +1,87 @@
-use std::io::{Read, Seek, SeekFrom, Write};
+use std::{
+    collections::HashMap,
+    io::{Read, Seek, SeekFrom, Write},
+};
 
 use anyhow::{anyhow, bail, ensure, Result};
 use binrw::{BinRead, BinWrite};
-use byteorder::{LittleEndian, ReadBytesExt};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 
 use crate::{
+    error::read_field,
+    magic,
+    metadata::{brw_read_metadata, MetadataDict},
     scoped_reader_pos,
     util::{
         math::{Mat3x4, Vec3},
         pointer::Pointer,
-        util::{brw_read_string, brw_relative_pointer, brw_write_zero, CgfxObjectHeader},
+        util::{
+            add_object_header_name, brw_read_string, brw_relative_pointer, point_here, write_list_header,
+            write_relative_pointer_at, write_string_pointer, CgfxObjectHeader,
+        },
     },
-    CgfxDict, WriteContext,
+    CgfxCollectionValue, CgfxDict, CollisionPolicy, WriteContext,
 };
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct CgfxSkeleton {
     pub cgfx_object_header: CgfxObjectHeader,
     
-    pub bones: CgfxDict<CgfxBone>,
+    pub bones: BoneDict,
     pub root_bone: Pointer,
     pub scaling_rule: SkeletonScalingRule,
     pub flags: u32,
 }
 
 impl CgfxSkeleton {
+    /// Skeleton with a single bone named `root_bone` that doesn't transform anything
+    pub fn with_root_bone(root_bone: &str) -> Result<Self> {
+        let mut bones = BoneDict::new();
+        bones.insert(root_bone, CgfxBone::new(root_bone, 0, u32::MAX), CollisionPolicy::Error)?;
+        
+        Ok(Self::new(bones))
+    }
+    
+    pub fn new(bones: BoneDict) -> Self {
+        Self {
+            cgfx_object_header: CgfxObjectHeader {
+                magic: "SOBJ".to_string(),
+                revision: 0,
+                name: None,
+                metadata_count: 0,
+                metadata_pointer: None,
+                metadata: None,
+            },
+            bones,
+            // only points to the root bone in a parsed file
+            root_bone: Pointer::default(),
+            scaling_rule: SkeletonScalingRule::Standard,
+            flags: 0,
+        }
+    }
+    
     pub fn from_reader<R: Read + Seek>(reader: &mut R) -> Result<Self> {
         let magic = reader.read_u32::<LittleEndian>()?;
-        assert!(magic == 0x02000000u32, "Expected magic number 0x02000000, got 0x{magic:x}");
+        ensure!(magic == magic::SKELETON, "Expected magic number 0x{:x} for Skeleton, got 0x{magic:x}", magic::SKELETON);
         
         let cgfx_object_header = CgfxObjectHeader::read(reader)?;
         
-        let bone_count = reader.read_u32::<LittleEndian>()?;
-        let bone_ptr = Pointer::read_relative(reader)?;
-        
-        let bones = if let Some(bone_ptr) = bone_ptr {
-            scoped_reader_pos!(reader);
-            reader.seek(SeekFrom::Start(bone_ptr.into()))?;
-            let dict: CgfxDict<CgfxBone> = CgfxDict::from_reader(reader)?;
+        let bones = read_field(reader, "bones", |reader| {
+            let bone_count = reader.read_u32::<LittleEndian>()?;
+            let bone_ptr = Pointer::read_relative(reader)?;
             
-            ensure!(dict.values_count == bone_count);
-            dict
-        } else {
-            bail!("Cgfx Skeleton is missing a bone dictionary");
-        };
+            if let Some(bone_ptr) = bone_ptr {
+                scoped_reader_pos!(reader);
+                reader.seek(SeekFrom::Start(bone_ptr.into()))?;
+                let dict: CgfxDict<CgfxBone> = CgfxDict::from_reader(reader)?;
+                
+                ensure!(dict.values_count == bone_count,
+                    "Bone dict has {} entries but the skeleton has {bone_count}", dict.values_count);
+                Ok(dict)
+            } else {
+                bail!("Cgfx Skeleton is missing a bone dictionary");
+            }
+        })?;
         
         let root_bone = Pointer::read_relative(reader)?
             .ok_or_else(|| anyhow!("Cgfx Skeleton is missing a root bone"))?;
@@ -60,8 +98,122 @@ impl CgfxSkeleton {
         })
     }
     
-    pub fn to_writer<W: Write + Seek>(&self, _writer: &mut W, _ctx: &mut WriteContext) -> Result<()> {
-        todo!()
+    /// Writes the skeleton followed by its bones. The links between bones and the root
+    /// bone pointer are recomputed from the parent indices, with the order of the bones
+    /// in the dict as the order of siblings
+    pub fn to_writer<W: Write + Seek>(&self, writer: &mut W, ctx: &mut WriteContext) -> Result<()> {
+        writer.write_u32::<LittleEndian>(magic::SKELETON)?;
+        add_object_header_name(writer, &self.cgfx_object_header, ctx)?;
+        self.cgfx_object_header.write(writer)?;
+        
+        let bones_location = write_list_header(writer, self.bones.len())?;
+        let root_bone_location = Pointer::current(writer)?;
+        writer.write_u32::<LittleEndian>(0)?;
+        
+        self.scaling_rule.write(writer)?;
+        writer.write_u32::<LittleEndian>(self.flags)?;
+        
+        point_here(writer, bones_location)?;
+        let value_locations = self.bones.to_writer_with_locations(writer, ctx)?;
+        
+        let bones: Vec<(&CgfxBone, Pointer)> = self.bones.nodes.iter()
+            .zip(value_locations)
+            .filter_map(|(node, location)| Some((node.value.as_ref()?, location?)))
+            .collect();
+        
+        let location_of = |index: u32| bones.iter()
+            .find(|(bone, _)| bone.index == index)
+            .map(|&(_, location)| location);
+        
+        for (i, &(bone, location)) in bones.iter().enumerate() {
+            let parent = bone.parent_bone_index();
+            let mut siblings_before = bones[..i].iter().rev().filter(|(other, _)| other.parent_bone_index() == parent);
+            let mut siblings_after = bones[i + 1..].iter().filter(|(other, _)| other.parent_bone_index() == parent);
+            let first_child = bones.iter().find(|(other, _)| other.parent_bone_index() == Some(bone.index));
+            
+            // parent_ptr, child_ptr, prev_sibling_ptr and next_sibling_ptr
+            let links = [
+                parent.and_then(location_of),
+                first_child.map(|&(_, location)| location),
+                siblings_before.next().map(|&(_, location)| location),
+                siblings_after.next().map(|&(_, location)| location),
+            ];
+            
+            for (j, link) in links.into_iter().enumerate() {
+                write_relative_pointer_at(writer, location + Pointer::from(16 + j as u32 * 4), link)?;
+            }
+        }
+        
+        let root_bone = bones.iter()
+            .find(|(bone, _)| bone.parent_bone_index().is_none())
+            .or(bones.first())
+            .ok_or_else(|| anyhow!("Skeleton has no bones"))?;
+        write_relative_pointer_at(writer, root_bone_location, Some(root_bone.1))?;
+        
+        Ok(())
+    }
+    
+    /// Recalculates the local, world and inverse world matrices of every bone
+    /// from its scale, rotation and translation. Needs to be called after editing
+    /// those, otherwise the game keeps using the old matrices.
+    ///
+    /// Scale is always inherited from the parent bone, regardless of the scaling rule.
+    pub fn recompute_matrices(&mut self) -> Result<()> {
+        let mut world_transforms: HashMap<u32, Mat3x4> = HashMap::new();
+        let bone_count = self.bones.values().count();
+        
+        // parents usually come before their children, but don't rely on it
+        while world_transforms.len() < bone_count {
+            let previous_len = world_transforms.len();
+            
+            for bone in self.bones.values_mut() {
+                if world_transforms.contains_key(&bone.index) {
+                    continue;
+                }
+                
+                let parent_world = if bone.parent_index == bone.index || bone.parent_index == u32::MAX {
+                    Some(Mat3x4::IDENTITY)
+                } else {
+                    world_transforms.get(&bone.parent_index).copied()
+                };
+                
+                let Some(parent_world) = parent_world else {
+                    continue;
+                };
+                
+                let local_transform = Mat3x4::from_srt(bone.scale, bone.rotation, bone.translation);
+                let world_transform = parent_world * local_transform;
+                
+                bone.local_transform = local_transform;
+                bone.world_transform = world_transform;
+                bone.inv_world_transform = world_transform.inverse()
+                    .ok_or_else(|| anyhow!("World transform of bone {:?} can not be inverted", bone.name))?;
+                
+                world_transforms.insert(bone.index, world_transform);
+            }
+            
+            ensure!(world_transforms.len() > previous_len,
+                "Skeleton contains bones whose parent is missing or that are their own ancestor");
+        }
+        
+        Ok(())
+    }
+    
+    /// Scales the whole skeleton by `factor` by scaling every bone's translation
+    /// and then recomputing all matrices. Useful for moving bones between models
+    /// that use different world scales.
+    pub fn apply_global_scale(&mut self, factor: f32) -> Result<()> {
+        ensure!(factor != 0.0 && factor.is_finite(), "Invalid scale factor {factor}");
+        
+        for bone in self.bones.values_mut() {
+            bone.translation = Vec3::new(
+                bone.translation.x * factor,
+                bone.translation.y * factor,
+                bone.translation.z * factor,
+            );
+        }
+        
+        self.recompute_matrices()
     }
 }
 
@@ -73,29 +225,131 @@ pub enum SkeletonScalingRule {
     SoftImage, // rip
 }
 
-#[derive(Clone, Debug, BinRead, BinWrite, PartialEq)]
-#[brw(little)]
+/// How a bone turns towards the camera, like for grass or sprites. Viewpoint modes face
+/// the camera's position, the others only its view direction, which looks the same
+/// for bones in the middle of the screen but avoids distortion at its edges
+#[derive(Clone, Copy, Debug, PartialEq, Eq, BinRead, BinWrite)]
+#[brw(repr = u32, little)]
+pub enum BillboardMode {
+    Off,
+    /// Faces the camera, keeping the bone's own up axis as close as possible
+    World,
+    WorldViewpoint,
+    /// Faces the camera with the camera's up axis, so it's aligned with the screen
+    Screen,
+    ScreenViewpoint,
+    /// Only rotates around the bone's own Y axis, like trees
+    YAxial,
+    YAxialViewpoint,
+}
+
+// `vector` without its part along the unit vector `axis`, normalized. None if
+// both point the same way
+fn orthogonalize(vector: Vec3, axis: Vec3) -> Option<Vec3> {
+    let orthogonal = vector - axis * vector.dot(axis);
+    (orthogonal.length() > 1e-6).then(|| orthogonal.normalized())
+}
+
+pub type BoneDict = CgfxDict<CgfxBone>;
+
+impl BoneDict {
+    pub fn by_index(&self, index: u32) -> Option<&CgfxBone> {
+        self.values().find(|bone| bone.index == index)
+    }
+    
+    pub fn children_of(&self, parent_index: u32) -> impl Iterator<Item = &CgfxBone> {
+        self.values().filter(move |bone| bone.parent_index == parent_index && bone.index != parent_index)
+    }
+}
+
+impl CgfxBone {
+    /// Index of the parent bone, None for root bones
+    pub fn parent_bone_index(&self) -> Option<u32> {
+        (self.parent_index != self.index && self.parent_index != u32::MAX).then_some(self.parent_index)
+    }
+    
+    /// Bone without a transformation, `parent_index` is `u32::MAX` for root bones
+    pub fn new(name: &str, index: u32, parent_index: u32) -> Self {
+        Self {
+            name: Some(name.to_string()),
+            flags: 0,
+            index,
+            parent_index,
+            parent_ptr: None,
+            child_ptr: None,
+            prev_sibling_ptr: None,
+            next_sibling_ptr: None,
+            scale: Vec3::new(1.0, 1.0, 1.0),
+            rotation: Vec3::default(),
+            translation: Vec3::default(),
+            local_transform: Mat3x4::IDENTITY,
+            world_transform: Mat3x4::IDENTITY,
+            inv_world_transform: Mat3x4::IDENTITY,
+            billboard_mode: BillboardMode::Off,
+            metadata_ptr: None,
+            metadata: None,
+        }
+    }
+    
+    /// World transform of the bone after turning it towards the camera according to its
+    /// [`BillboardMode`], with `camera_view` being the camera's view matrix, which maps
+    /// world space to camera space. The bone keeps its position and scale. Bones whose
+    /// mode is off or whose axes can't be turned without flipping keep their world
+    /// transform
+    pub fn billboard_matrix(&self, camera_view: &Mat3x4) -> Mat3x4 {
+        let world = self.world_transform;
+        
+        let Some(camera) = camera_view.inverse() else {
+            return world;
+        };
+        
+        let position = world.translation();
+        let scale = [0, 1, 2].map(|axis| world.column(axis).length());
+        let bone_y = world.column(1).normalized();
+        
+        // unit vectors towards the viewer and upwards on the screen
+        let camera_back = camera.column(2).normalized();
+        let camera_up = camera.column(1).normalized();
+        let to_camera = (camera.translation() - position).normalized();
+        
+        let axes = match self.billboard_mode {
+            BillboardMode::Off => None,
+            BillboardMode::World => orthogonalize(bone_y, camera_back).map(|y| (y, camera_back)),
+            BillboardMode::WorldViewpoint => orthogonalize(bone_y, to_camera).map(|y| (y, to_camera)),
+            BillboardMode::Screen => Some((camera_up, camera_back)),
+            BillboardMode::ScreenViewpoint => orthogonalize(camera_up, to_camera).map(|y| (y, to_camera)),
+            BillboardMode::YAxial => orthogonalize(camera_back, bone_y).map(|z| (bone_y, z)),
+            BillboardMode::YAxialViewpoint => orthogonalize(to_camera, bone_y).map(|z| (bone_y, z)),
+        };
+        
+        let Some((y, z)) = axes else {
+            return world;
+        };
+        
+        let x = y.cross(z);
+        Mat3x4::from_columns(x * scale[0], y * scale[1], z * scale[2], position)
+    }
+}
+
+#[derive(Clone, Debug, BinRead, PartialEq)]
+#[br(little)]
 pub struct CgfxBone {
     #[br(parse_with = brw_read_string)]
-    #[bw(write_with = brw_write_zero)]
     pub name: Option<String>,
     
     pub flags: u32,
     pub index: u32,
     pub parent_index: u32,
     
-    // TODO
+    // links to other bones, recomputed from the parent indices when writing,
+    // see CgfxSkeleton::to_writer
     #[br(parse_with = brw_relative_pointer)]
-    #[bw(map = |_| 0u32)]
     pub parent_ptr: Option<Pointer>,
     #[br(parse_with = brw_relative_pointer)]
-    #[bw(map = |_| 0u32)]
     child_ptr: Option<Pointer>,
     #[br(parse_with = brw_relative_pointer)]
-    #[bw(map = |_| 0u32)]
     prev_sibling_ptr: Option<Pointer>,
     #[br(parse_with = brw_relative_pointer)]
-    #[bw(map = |_| 0u32)]
     next_sibling_ptr: Option<Pointer>,
     
     pub scale: Vec3,
@@ -106,9 +360,46 @@ pub struct CgfxBone {
     pub world_transform: Mat3x4,
     pub inv_world_transform: Mat3x4,
     
-    pub billboard_mode: u32,
+    pub billboard_mode: BillboardMode,
     
     #[br(parse_with = brw_relative_pointer)]
-    #[bw(map = |_| 0u32)]
     pub metadata_ptr: Option<Pointer>,
+    #[br(parse_with = brw_read_metadata, args(None))]
+    pub metadata: Option<MetadataDict>,
+}
+
+impl CgfxCollectionValue for CgfxBone {
+    fn read_dict_value<R: Read + Seek>(reader: &mut R) -> Result<Self> {
+        Ok(Self::read(reader)?)
+    }
+    
+    // the links to other bones are left null, CgfxSkeleton::to_writer fills them in
+    fn write_dict_value<W: Write + Seek>(&self, writer: &mut W, ctx: &mut WriteContext) -> Result<()> {
+        write_string_pointer(writer, &self.name, ctx)?;
+        writer.write_u32::<LittleEndian>(self.flags)?;
+        writer.write_u32::<LittleEndian>(self.index)?;
+        writer.write_u32::<LittleEndian>(self.parent_index)?;
+        
+        for _ in 0..4 {
+            writer.write_u32::<LittleEndian>(0)?;
+        }
+        
+        self.scale.write(writer)?;
+        self.rotation.write(writer)?;
+        self.translation.write(writer)?;
+        
+        self.local_transform.write_le(writer)?;
+        self.world_transform.write_le(writer)?;
+        self.inv_world_transform.write_le(writer)?;
+        
+        self.billboard_mode.write(writer)?;
+        
+        if let Some(metadata) = &self.metadata {
+            ctx.add_metadata_reference(Pointer::current(writer)?, metadata.clone());
+        }
+        
+        writer.write_u32::<LittleEndian>(0)?;
+        
+        Ok(())
+    }
 }
@@ -0,0 +1,580 @@
+// reading and importing skin weights. interchange formats store them per bone name,
+// shapes store them as BoneIndex/BoneWeight attributes indexing into the bone palette of
+// every sub mesh. rigid sub meshes bind each vertex to a single bone and store positions
+// relative to that bone, smooth ones store them in model space
+
+use std::collections::HashSet;
+
+use anyhow::{anyhow, bail, ensure, Result};
+
+use crate::util::math::Vec3;
+
+use super::{
+    mesh::{AttributeName, AttributeStorage, GlDataType, Shape, SubMesh, SubMeshSkinning, VertexBuffer,
+        VertexBufferAttribute, VertexBufferCommon, VertexBufferType},
+    skeleton::{CgfxBone, CgfxSkeleton},
+};
+
+/// Most bones a single vertex can be influenced by
+pub const MAX_INFLUENCES: usize = 4;
+
+// weights are stored as percentages
+const WEIGHT_SCALE: f32 = 0.01;
+
+/// Bone indices and weights of a single vertex, strongest first. The weights add up to one
+pub type Influences = Vec<(u32, f32)>;
+
+/// Replaces the bone names of every vertex's influences with the index of the bone
+/// in `skeleton`, keeping the strongest [`MAX_INFLUENCES`] and normalizing their weights
+pub fn resolve_influences(skeleton: &CgfxSkeleton, influences: &[Vec<(&str, f32)>]) -> Result<Vec<Influences>> {
+    influences.iter()
+        .enumerate()
+        .map(|(vertex, influences)| {
+            let mut resolved: Influences = Vec::new();
+            
+            for &(name, weight) in influences.iter().filter(|(_, weight)| *weight > 0.0) {
+                let bone = skeleton.bones.get(name)
+                    .ok_or_else(|| anyhow!("Vertex {vertex} is influenced by bone {name:?}, which doesn't exist"))?;
+                
+                // the same bone listed twice counts once with both weights
+                match resolved.iter_mut().find(|(index, _)| *index == bone.index) {
+                    Some((_, existing)) => *existing += weight,
+                    None => resolved.push((bone.index, weight)),
+                }
+            }
+            
+            resolved.sort_by(|a, b| b.1.total_cmp(&a.1));
+            resolved.truncate(MAX_INFLUENCES);
+            
+            let total: f32 = resolved.iter().map(|(_, weight)| weight).sum();
+            ensure!(total > 0.0 && total.is_finite(), "Vertex {vertex} has no influences");
+            
+            for (_, weight) in &mut resolved {
+                *weight /= total;
+            }
+            
+            Ok(resolved)
+        })
+        .collect()
+}
+
+// quantizes weights into percentages that still add up to exactly 100
+fn quantize_weights(influences: &Influences, count: usize) -> Vec<f32> {
+    let mut weights: Vec<f32> = influences.iter().map(|(_, weight)| (weight * 100.0).round()).collect();
+    weights.resize(count, 0.0);
+    
+    let total: f32 = weights.iter().sum();
+    weights[0] += 100.0 - total;
+    
+    weights.into_iter().map(|weight| weight * WEIGHT_SCALE).collect()
+}
+
+fn skinned_bone(skeleton: &CgfxSkeleton, bone: u32) -> Result<&CgfxBone> {
+    skeleton.bones.by_index(bone).ok_or_else(|| anyhow!("Vertices are bound to bone {bone}, which doesn't exist"))
+}
+
+// every vertex the faces of `sub_mesh` use, sorted
+fn sub_mesh_vertices(sub_mesh: &SubMesh) -> Vec<usize> {
+    let mut vertices: Vec<usize> = sub_mesh.faces.iter()
+        .flatten()
+        .flat_map(|face| face.face_descriptors.iter().flatten())
+        .flat_map(|face_descriptor| face_descriptor.indices.iter().map(|&index| index as usize))
+        .collect();
+    
+    vertices.sort_unstable();
+    vertices.dedup();
+    vertices
+}
+
+// which sub meshes can use rigid skinning, which needs all of their vertices to have a
+// single influence and none of them to be shared with a sub mesh using smooth skinning
+fn rigid_sub_meshes(vertex_lists: &[Vec<usize>], influences: &[Influences]) -> Vec<bool> {
+    let mut rigid: Vec<bool> = vertex_lists.iter()
+        .map(|vertices| vertices.iter().all(|&vertex| influences[vertex].len() == 1))
+        .collect();
+    
+    loop {
+        let smooth_vertices: HashSet<usize> = vertex_lists.iter()
+            .zip(&rigid)
+            .filter(|(_, &rigid)| !rigid)
+            .flat_map(|(vertices, _)| vertices.iter().copied())
+            .collect();
+        
+        let mut changed = false;
+        
+        for (vertices, rigid) in vertex_lists.iter().zip(&mut rigid) {
+            if *rigid && vertices.iter().any(|vertex| smooth_vertices.contains(vertex)) {
+                *rigid = false;
+                changed = true;
+            }
+        }
+        
+        if !changed {
+            return rigid;
+        }
+    }
+}
+
+// values of an attribute for every vertex, fixed attributes have the same ones for all of them
+struct VertexValues {
+    values: Vec<f32>,
+    elements: usize,
+    fixed: bool,
+}
+
+impl VertexValues {
+    fn read(shape: &Shape, name: AttributeName) -> Result<Option<Self>> {
+        let Some(attribute) = shape.layout().attribute(name).cloned() else {
+            return Ok(None);
+        };
+        
+        let values = shape.attribute(name)?.unwrap_or_default();
+        let fixed = attribute.storage == AttributeStorage::Fixed;
+        let elements = if fixed { values.len() } else { attribute.elements as usize };
+        
+        Ok(Some(Self { values, elements, fixed }))
+    }
+    
+    fn get(&self, vertex: usize) -> &[f32] {
+        let start = if self.fixed { 0 } else { vertex * self.elements };
+        self.values.get(start..start + self.elements).unwrap_or_default()
+    }
+}
+
+impl Shape {
+    /// Bones influencing every vertex, by their index in the skeleton instead of in the
+    /// palette of their sub mesh. Vertices of rigid sub meshes are bound to a single bone
+    /// with a weight of one, vertices that no skinned sub mesh uses have no influences
+    pub fn influences(&self) -> Result<Vec<Influences>> {
+        let vertex_count = self.vertex_count();
+        let bone_indices = VertexValues::read(self, AttributeName::BoneIndex)?;
+        let bone_weights = VertexValues::read(self, AttributeName::BoneWeight)?;
+        
+        let mut influences: Vec<Influences> = vec![Vec::new(); vertex_count];
+        
+        for (sub_mesh_index, sub_mesh) in self.sub_meshes.iter().enumerate() {
+            let Some(sub_mesh) = sub_mesh.as_ref().filter(|sub_mesh| sub_mesh.skinning != SubMeshSkinning::None) else {
+                continue;
+            };
+            
+            for vertex in sub_mesh_vertices(sub_mesh) {
+                ensure!(vertex < vertex_count, "Vertex index {vertex} is out of bounds");
+                
+                let indices = bone_indices.as_ref().map_or(&[][..], |indices| indices.get(vertex));
+                let weights = bone_weights.as_ref().map_or(&[][..], |weights| weights.get(vertex));
+                
+                // rigid vertices only use their first bone index, and vertices without
+                // one use the first entry of the palette
+                let local: Vec<(f32, f32)> = match sub_mesh.skinning {
+                    SubMeshSkinning::Smooth if indices.len() > 1 => indices.iter()
+                        .zip(weights)
+                        .map(|(&index, &weight)| (index, weight))
+                        .filter(|&(_, weight)| weight > 0.0)
+                        .collect(),
+                    _ => vec![(indices.first().copied().unwrap_or(0.0), 1.0)],
+                };
+                
+                let total: f32 = local.iter().map(|(_, weight)| weight).sum();
+                let mut resolved: Influences = Vec::with_capacity(local.len());
+                
+                for (index, weight) in local {
+                    let bone = sub_mesh.bone_indices.get(index.round() as usize).ok_or_else(|| anyhow!(
+                        "Vertex {vertex} of sub mesh {sub_mesh_index} uses palette entry {index}, but the palette only has {}",
+                        sub_mesh.bone_indices.len()))?;
+                    
+                    resolved.push((*bone, weight / total));
+                }
+                
+                resolved.sort_by(|a, b| b.1.total_cmp(&a.1));
+                influences[vertex] = resolved;
+            }
+        }
+        
+        Ok(influences)
+    }
+    
+    // the bone of every vertex of a rigid sub mesh
+    fn rigid_bones(&self) -> Result<Vec<Option<u32>>> {
+        let mut bones: Vec<Option<u32>> = vec![None; self.vertex_count()];
+        let influences = self.influences()?;
+        
+        for sub_mesh in self.sub_meshes.iter().flatten().filter(|sub_mesh| sub_mesh.skinning == SubMeshSkinning::Rigid) {
+            for vertex in sub_mesh_vertices(sub_mesh) {
+                bones[vertex] = influences[vertex].first().map(|&(bone, _)| bone);
+            }
+        }
+        
+        Ok(bones)
+    }
+    
+    /// Vertex positions in model space. Rigid sub meshes store their vertices relative to
+    /// their bone, so those get transformed by the world matrix of the bone, otherwise
+    /// rigidly skinned meshes end up scattered around the origin
+    pub fn model_space_positions(&self, skeleton: &CgfxSkeleton) -> Result<Vec<Vec3>> {
+        let mut positions = self.positions()?;
+        
+        for (position, bone) in positions.iter_mut().zip(self.rigid_bones()?) {
+            if let Some(bone) = bone {
+                *position = skinned_bone(skeleton, bone)?.world_transform.transform_point(*position);
+            }
+        }
+        
+        Ok(positions)
+    }
+    
+    /// Vertex normals in model space, see [`Shape::model_space_positions`]
+    pub fn model_space_normals(&self, skeleton: &CgfxSkeleton) -> Result<Option<Vec<Vec3>>> {
+        let Some(mut normals) = self.normals()? else {
+            return Ok(None);
+        };
+        
+        for (normal, bone) in normals.iter_mut().zip(self.rigid_bones()?) {
+            if let Some(bone) = bone {
+                *normal = skinned_bone(skeleton, bone)?.world_transform.transform_vector(*normal).normalized();
+            }
+        }
+        
+        Ok(Some(normals))
+    }
+    
+    /// Moves all vertices into model space as they are in the bind pose of `skeleton` and
+    /// removes all skinning, so the shape no longer needs a skeleton. Separate BoneIndex
+    /// and BoneWeight attributes are removed, interleaved ones stay but aren't used anymore
+    pub fn bake_bind_pose(&mut self, skeleton: &CgfxSkeleton) -> Result<()> {
+        let positions = self.model_space_positions(skeleton)?;
+        let normals = self.model_space_normals(skeleton)?;
+        
+        let values: Vec<f32> = positions.iter().flat_map(|position| [position.x, position.y, position.z]).collect();
+        self.set_attribute(AttributeName::Position, &values)?;
+        
+        if let Some(normals) = normals {
+            self.set_normals(&normals)?;
+        }
+        
+        for sub_mesh in self.sub_meshes.iter_mut().flatten() {
+            sub_mesh.skinning = SubMeshSkinning::None;
+            sub_mesh.bone_indices.clear();
+        }
+        
+        self.vertex_buffers.retain(|vertex_buffer| {
+            let name = match vertex_buffer {
+                Some(VertexBuffer::Attribute(buffer)) => buffer.attribute_name,
+                Some(VertexBuffer::Fixed(buffer)) => buffer.vertex_buffer_common.attribute_name,
+                _ => return true,
+            };
+            
+            !matches!(name, AttributeName::BoneIndex | AttributeName::BoneWeight)
+        });
+        
+        Ok(())
+    }
+    
+    /// Binds every vertex to the bones given by name in `influences`, one list per vertex.
+    /// The bone palette of every sub mesh is rebuilt from the bones its vertices use, which
+    /// has to fit into `max_palette_len` entries, and the BoneIndex and BoneWeight attributes
+    /// are replaced, or added if the shape doesn't have them.
+    ///
+    /// Positions and normals have to be in model space, like [`Shape::model_space_positions`]
+    /// returns them. Sub meshes whose vertices all have a single influence use rigid
+    /// skinning, so their vertices get moved into the space of their bone, all other
+    /// sub meshes use smooth skinning.
+    pub fn import_skinning(&mut self, skeleton: &CgfxSkeleton, influences: &[Vec<(&str, f32)>],
+        max_palette_len: usize) -> Result<()>
+    {
+        let vertex_count = self.vertex_count();
+        ensure!(influences.len() == vertex_count,
+            "Got influences for {} vertices, but the shape has {vertex_count}", influences.len());
+        
+        let influences = resolve_influences(skeleton, influences)?;
+        let influence_count = influences.iter().map(Vec::len).max().unwrap_or(1);
+        
+        let vertex_lists: Vec<Vec<usize>> = self.sub_meshes.iter()
+            .map(|sub_mesh| sub_mesh.as_ref().map(sub_mesh_vertices).unwrap_or_default())
+            .collect();
+        
+        if let Some(&vertex) = vertex_lists.iter().filter_map(|vertices| vertices.last()).max() {
+            ensure!(vertex < vertex_count, "Vertex index {vertex} is out of bounds");
+        }
+        
+        let rigid = rigid_sub_meshes(&vertex_lists, &influences);
+        let mut rigid_bones: Vec<Option<u32>> = vec![None; vertex_count];
+        
+        // index into the palette of its sub mesh for every influence of every vertex
+        let mut local_indices: Vec<Option<Vec<u32>>> = vec![None; vertex_count];
+        
+        for (sub_mesh_index, sub_mesh) in self.sub_meshes.iter_mut().enumerate() {
+            let Some(sub_mesh) = sub_mesh else {
+                continue;
+            };
+            
+            let vertices = &vertex_lists[sub_mesh_index];
+            
+            let mut palette: Vec<u32> = vertices.iter()
+                .flat_map(|&vertex| influences[vertex].iter().map(|&(bone, _)| bone))
+                .collect();
+            
+            palette.sort_unstable();
+            palette.dedup();
+            
+            ensure!(palette.len() <= max_palette_len,
+                "Sub mesh {sub_mesh_index} uses {} bones, but its palette can only hold {max_palette_len}", palette.len());
+            
+            for &vertex in vertices {
+                let indices: Vec<u32> = influences[vertex].iter()
+                    .map(|(bone, _)| palette.iter().position(|entry| entry == bone).unwrap() as u32)
+                    .collect();
+                
+                match &local_indices[vertex] {
+                    Some(existing) if *existing != indices =>
+                        bail!("Vertex {vertex} is shared between sub meshes whose palettes order its bones differently"),
+                    _ => local_indices[vertex] = Some(indices),
+                }
+            }
+            
+            sub_mesh.bone_indices = palette;
+            
+            if rigid[sub_mesh_index] {
+                sub_mesh.skinning = SubMeshSkinning::Rigid;
+                
+                for &vertex in vertices {
+                    rigid_bones[vertex] = Some(influences[vertex][0].0);
+                }
+            } else {
+                sub_mesh.skinning = SubMeshSkinning::Smooth;
+            }
+        }
+        
+        self.move_into_bone_space(skeleton, &rigid_bones)?;
+        
+        let mut bone_index_values = Vec::with_capacity(vertex_count * influence_count);
+        let mut bone_weight_values = Vec::with_capacity(vertex_count * influence_count);
+        
+        for (vertex, indices) in local_indices.into_iter().enumerate() {
+            // vertices no sub mesh uses don't matter, so they get bound to the first palette entry
+            let mut indices: Vec<f32> = indices.unwrap_or_default().into_iter().map(|index| index as f32).collect();
+            indices.resize(influence_count, 0.0);
+            
+            bone_index_values.extend(indices);
+            bone_weight_values.extend(quantize_weights(&influences[vertex], influence_count));
+        }
+        
+        self.replace_attribute(AttributeName::BoneIndex, GlDataType::UByte, influence_count as u32, 1.0, &bone_index_values)?;
+        self.replace_attribute(AttributeName::BoneWeight, GlDataType::UByte, influence_count as u32, WEIGHT_SCALE, &bone_weight_values)
+    }
+    
+    // transforms the positions and normals of vertices with a bone from model space into
+    // the space of that bone
+    fn move_into_bone_space(&mut self, skeleton: &CgfxSkeleton, bones: &[Option<u32>]) -> Result<()> {
+        if bones.iter().all(Option::is_none) {
+            return Ok(());
+        }
+        
+        let mut positions = self.positions()?;
+        let mut normals = self.normals()?;
+        
+        for (vertex, bone) in bones.iter().enumerate() {
+            let Some(bone) = bone else {
+                continue;
+            };
+            
+            let inverse = skinned_bone(skeleton, *bone)?.inv_world_transform;
+            positions[vertex] = inverse.transform_point(positions[vertex]);
+            
+            if let Some(normal) = normals.as_mut().and_then(|normals| normals.get_mut(vertex)) {
+                *normal = inverse.transform_vector(*normal);
+            }
+        }
+        
+        let values: Vec<f32> = positions.iter().flat_map(|position| [position.x, position.y, position.z]).collect();
+        self.set_attribute(AttributeName::Position, &values)?;
+        
+        match normals {
+            Some(normals) => self.set_normals(&normals),
+            None => Ok(()),
+        }
+    }
+    
+    // sets the values of an attribute if it already has `elements` components,
+    // otherwise it gets stored in a new vertex buffer of its own
+    fn replace_attribute(&mut self, name: AttributeName, format: GlDataType, elements: u32, scale: f32,
+        values: &[f32]) -> Result<()>
+    {
+        if let Some(attribute) = self.layout().attribute(name) {
+            if attribute.elements == elements && attribute.storage != AttributeStorage::Fixed {
+                return self.set_attribute(name, values);
+            }
+            
+            ensure!(attribute.storage != AttributeStorage::Interleaved,
+                "Can not change the amount of components of interleaved attribute {name:?}");
+            
+            self.vertex_buffers.retain(|vertex_buffer| match vertex_buffer {
+                Some(VertexBuffer::Attribute(buffer)) => buffer.attribute_name != name,
+                Some(VertexBuffer::Fixed(buffer)) => buffer.vertex_buffer_common.attribute_name != name,
+                _ => true,
+            });
+        }
+        
+        let vertex_size = format.byte_size() as usize * elements as usize;
+        
+        self.vertex_buffers.push(Some(VertexBuffer::Attribute(VertexBufferAttribute {
+            vertex_buffer_common: VertexBufferCommon {
+                attribute_name: name,
+                vertex_buffer_type: VertexBufferType::None,
+            },
+            buffer_obj: Default::default(),
+            location_flag: 0,
+            raw_bytes: vec![0; values.len() / elements as usize * vertex_size].into(),
+            location_ptr: 0,
+            memory_area: 0,
+            format,
+            elements,
+            scale,
+            offset: 0,
+        })));
+        
+        self.set_attribute(name, values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cgfx_container::CgfxContainer, fixtures, model::CgfxModel, util::math::Mat3x4, CollisionPolicy};
+    
+    // the shape and skeleton of the fixture model, with two more bones below the root
+    fn skinned_fixture() -> (Shape, CgfxSkeleton) {
+        let container = CgfxContainer::new(&fixtures::minimal_bcres()).unwrap();
+        let Some(CgfxModel::Skeletal(common, skeleton)) = container.models.as_ref().unwrap().get(fixtures::MODEL_NAME) else {
+            panic!("fixture model should be skeletal");
+        };
+        
+        // child_a sits above the root, so positions relative to it differ from model space
+        let mut child_a = CgfxBone::new("child_a", 1, 0);
+        child_a.world_transform = Mat3x4::from_srt(Vec3::new(1.0, 1.0, 1.0), Vec3::default(), Vec3::new(0.0, 10.0, 0.0));
+        child_a.inv_world_transform = child_a.world_transform.inverse().unwrap();
+        
+        let mut skeleton = skeleton.clone();
+        skeleton.bones.insert("child_a", child_a, CollisionPolicy::Error).unwrap();
+        skeleton.bones.insert("child_b", CgfxBone::new("child_b", 2, 0), CollisionPolicy::Error).unwrap();
+        
+        (common.shapes[0].clone().unwrap(), skeleton)
+    }
+    
+    fn assert_normalized(influences: &Influences) {
+        let total: f32 = influences.iter().map(|(_, weight)| weight).sum();
+        assert!((total - 1.0).abs() < 1e-5, "weights of {influences:?} add up to {total}");
+    }
+    
+    #[test]
+    fn resolved_weights_are_normalized() {
+        let (_, skeleton) = skinned_fixture();
+        let root = fixtures::BONE_NAME;
+        
+        let resolved = resolve_influences(&skeleton, &[
+            vec![(root, 0.2), ("child_a", 0.6)],
+            // bones listed twice count once, weights of zero not at all
+            vec![("child_b", 1.0), ("child_b", 2.0), ("child_a", 0.0)],
+            vec![(root, 5.0), ("child_a", 5.0), ("child_b", 10.0)],
+        ]).unwrap();
+        
+        assert_eq!(resolved[0], vec![(1, 0.75), (0, 0.25)]);
+        assert_eq!(resolved[1], vec![(2, 1.0)]);
+        assert_eq!(resolved[2][0], (2, 0.5));
+        resolved.iter().for_each(assert_normalized);
+        
+        assert!(resolve_influences(&skeleton, &[vec![(root, 0.0)]]).is_err());
+        assert!(resolve_influences(&skeleton, &[vec![("missing", 1.0)]]).is_err());
+    }
+    
+    #[test]
+    fn only_the_strongest_influences_are_kept() {
+        let (_, mut skeleton) = skinned_fixture();
+        
+        for index in 3..6 {
+            let name = format!("bone_{index}");
+            skeleton.bones.insert(&name, CgfxBone::new(&name, index, 0), CollisionPolicy::Error).unwrap();
+        }
+        
+        let influences: Vec<(&str, f32)> = ["bone_3", "bone_4", "bone_5", "child_a", "child_b", fixtures::BONE_NAME].into_iter()
+            .zip([1.0, 2.0, 3.0, 4.0, 5.0, 6.0])
+            .collect();
+        let resolved = resolve_influences(&skeleton, &[influences]).unwrap();
+        
+        assert_eq!(resolved[0].iter().map(|&(bone, _)| bone).collect::<Vec<_>>(), vec![0, 2, 1, 5]);
+        assert_normalized(&resolved[0]);
+    }
+    
+    #[test]
+    fn quantized_weights_add_up_to_one_hundred_percent() {
+        let thirds: Influences = vec![(0, 1.0 / 3.0), (1, 1.0 / 3.0), (2, 1.0 / 3.0)];
+        let weights = quantize_weights(&thirds, 4);
+        
+        assert_eq!(weights.len(), 4);
+        assert_eq!(weights.iter().map(|weight| (weight / WEIGHT_SCALE).round()).sum::<f32>(), 100.0);
+        assert_eq!(weights[3], 0.0);
+    }
+    
+    #[test]
+    fn imported_bone_indices_stay_within_the_palette() {
+        let (mut shape, skeleton) = skinned_fixture();
+        let root = fixtures::BONE_NAME;
+        
+        shape.import_skinning(&skeleton, &[
+            vec![("child_b", 0.5), ("child_a", 0.5)],
+            vec![("child_b", 1.0)],
+            vec![(root, 1.0), ("child_b", 3.0)],
+        ], 20).unwrap();
+        
+        let sub_mesh = shape.sub_meshes[0].as_ref().unwrap();
+        assert_eq!(sub_mesh.bone_indices, vec![0, 1, 2]);
+        assert_eq!(sub_mesh.skinning, SubMeshSkinning::Smooth);
+        
+        let palette_len = sub_mesh.bone_indices.len() as f32;
+        let bone_indices = shape.attribute(AttributeName::BoneIndex).unwrap().unwrap();
+        assert!(bone_indices.iter().all(|&index| index < palette_len), "{bone_indices:?}");
+        
+        // reading them back gives the bones by their index in the skeleton again
+        let influences = shape.influences().unwrap();
+        assert_eq!(influences[1], vec![(2, 1.0)]);
+        assert_eq!(influences[2][0].0, 2);
+        influences.iter().for_each(assert_normalized);
+        
+        // palette entries past its end are an error instead of a wrong bone
+        let mut broken = shape.clone();
+        broken.sub_meshes[0].as_mut().unwrap().bone_indices.truncate(1);
+        assert!(broken.influences().is_err());
+        
+        assert!(shape.import_skinning(&skeleton, &[vec![("child_a", 1.0)], vec![("child_b", 1.0)], vec![(root, 1.0)]], 2).is_err());
+    }
+    
+    #[test]
+    fn single_influences_use_rigid_skinning() {
+        let (mut shape, skeleton) = skinned_fixture();
+        let positions = shape.positions().unwrap();
+        
+        shape.import_skinning(&skeleton, &vec![vec![("child_a", 1.0)]; 3], 20).unwrap();
+        assert_eq!(shape.sub_meshes[0].as_ref().unwrap().skinning, SubMeshSkinning::Rigid);
+        
+        // rigid vertices are stored relative to their bone and come back in model space
+        let stored: Vec<Vec3> = positions.iter().map(|position| Vec3::new(position.x, position.y - 10.0, position.z)).collect();
+        assert_eq!(shape.positions().unwrap(), stored);
+        assert_eq!(shape.model_space_positions(&skeleton).unwrap(), positions);
+        assert_eq!(shape.influences().unwrap(), vec![vec![(1, 1.0)]; 3]);
+        
+        shape.bake_bind_pose(&skeleton).unwrap();
+        assert_eq!(shape.positions().unwrap(), positions);
+        assert_eq!(shape.sub_meshes[0].as_ref().unwrap().skinning, SubMeshSkinning::None);
+    }
+    
+    #[test]
+    fn several_influences_use_smooth_skinning() {
+        let (mut shape, skeleton) = skinned_fixture();
+        let positions = shape.positions().unwrap();
+        
+        // one vertex with two bones makes the whole sub mesh smooth, which stays in model space
+        shape.import_skinning(&skeleton, &[vec![("child_a", 1.0)], vec![("child_a", 1.0)], vec![("child_a", 0.5), ("child_b", 0.5)]], 20).unwrap();
+        assert_eq!(shape.sub_meshes[0].as_ref().unwrap().skinning, SubMeshSkinning::Smooth);
+        assert_eq!(shape.positions().unwrap(), positions);
+        assert_eq!(shape.model_space_positions(&skeleton).unwrap(), positions);
+    }
+}
@@ -0,0 +1,108 @@
+// borrowed views of vertex buffers as typed slices, for consumers that upload vertex
+// data as it is instead of going through the f32 copies of Shape::attribute. the
+// 3ds stores vertex data in little endian, so this only works on little endian hosts
+
+use std::mem::size_of;
+
+use anyhow::{anyhow, bail, ensure, Result};
+use bytemuck::Pod;
+
+use crate::util::math::{Vec2, Vec3, Vec4};
+
+use super::mesh::{AttributeName, GlDataType, Shape, VertexBuffer, VertexBufferAttribute, VertexBufferInterleaved};
+
+/// Types a vertex of a single attribute can be viewed as, with the format and
+/// amount of components the attribute needs to have for that
+pub trait VertexElement: Pod {
+    const FORMAT: GlDataType;
+    const ELEMENTS: u32;
+}
+
+macro_rules! vertex_element {
+    ($ty:ty, $format:ident) => {
+        vertex_element!($ty, $format, 1);
+        vertex_element!([$ty; 2], $format, 2);
+        vertex_element!([$ty; 3], $format, 3);
+        vertex_element!([$ty; 4], $format, 4);
+    };
+    ($ty:ty, $format:ident, $elements:literal) => {
+        impl VertexElement for $ty {
+            const FORMAT: GlDataType = GlDataType::$format;
+            const ELEMENTS: u32 = $elements;
+        }
+    };
+}
+
+vertex_element!(i8, Byte);
+vertex_element!(u8, UByte);
+vertex_element!(i16, Short);
+vertex_element!(u16, UShort);
+vertex_element!(f32, Float);
+vertex_element!(Vec2, Float, 2);
+vertex_element!(Vec3, Float, 3);
+vertex_element!(Vec4, Float, 4);
+
+fn cast_bytes<T: Pod>(bytes: &[u8]) -> Result<&[T]> {
+    ensure!(cfg!(target_endian = "little"), "Vertex data can only be viewed in place on little endian hosts");
+    
+    bytemuck::try_cast_slice(bytes)
+        .map_err(|error| anyhow!("Can not view {} bytes of vertex data as {}: {error:?}",
+            bytes.len(), std::any::type_name::<T>()))
+}
+
+impl VertexBufferAttribute {
+    /// Vertices of the buffer as `T` without copying them, like `&[[f32; 3]]` for
+    /// positions stored as three floats. Fails if `T` doesn't match the format and
+    /// amount of components of the attribute, or if the bytes aren't aligned for `T`.
+    /// Values are raw, so the attribute's scale still has to be applied to them
+    pub fn as_slice<T: VertexElement>(&self) -> Result<&[T]> {
+        ensure!(self.format == T::FORMAT && self.elements == T::ELEMENTS,
+            "Attribute {:?} has {} components of type {:?}, but {} has {} of type {:?}",
+            self.attribute_name, self.elements, self.format, std::any::type_name::<T>(), T::ELEMENTS, T::FORMAT);
+        
+        cast_bytes(&self.raw_bytes)
+    }
+}
+
+impl VertexBufferInterleaved {
+    /// Vertices of the buffer as `T` without copying them, where `T` is a `#[repr(C)]`
+    /// struct laid out like the attributes. Only the size of `T` can be checked against
+    /// the vertex stride, every attribute can be checked with [`Shape::attribute_layout_matches`]
+    pub fn as_slice<T: Pod>(&self) -> Result<&[T]> {
+        ensure!(size_of::<T>() == self.vertex_stride as usize,
+            "Interleaved vertices are {} bytes long, but {} has {} bytes",
+            self.vertex_stride, std::any::type_name::<T>(), size_of::<T>());
+        
+        cast_bytes(&self.raw_bytes)
+    }
+}
+
+impl Shape {
+    /// Vertices of attribute `name` as `T` without copying them, see
+    /// [`VertexBufferAttribute::as_slice`]. Only works for attributes that have a
+    /// vertex buffer of their own, [`Shape::attribute`] reads any attribute
+    pub fn attribute_slice<T: VertexElement>(&self, name: AttributeName) -> Result<&[T]> {
+        for vertex_buffer in self.vertex_buffers.iter().flatten() {
+            match vertex_buffer {
+                VertexBuffer::Attribute(buffer) if buffer.attribute_name == name => return buffer.as_slice(),
+                VertexBuffer::Interleaved(buffer) if buffer.attributes.iter().flatten().any(|attribute| attribute.attribute_name == name) => {
+                    bail!("Attribute {name:?} is interleaved with other attributes, so it can't be viewed on its own");
+                },
+                VertexBuffer::Fixed(buffer) if buffer.vertex_buffer_common.attribute_name == name => {
+                    bail!("Attribute {name:?} is fixed and has no vertex data");
+                },
+                _ => {},
+            }
+        }
+        
+        bail!("Shape has no attribute {name:?}")
+    }
+    
+    /// Whether attribute `name` is stored as `T` at `offset` bytes into each vertex, for
+    /// checking the fields of structs used with [`VertexBufferInterleaved::as_slice`]
+    pub fn attribute_layout_matches<T: VertexElement>(&self, name: AttributeName, offset: usize) -> bool {
+        self.layout().attribute(name).is_some_and(|attribute| {
+            attribute.format == T::FORMAT && attribute.elements == T::ELEMENTS && attribute.offset as usize == offset
+        })
+    }
+}
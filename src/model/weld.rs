@@ -0,0 +1,318 @@
+// finding and merging vertices that are identical within a tolerance, which is common
+// in imported meshes that store every vertex once for every triangle it is part of
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, ensure, Result};
+
+use crate::util::util::SharedBytes;
+
+use super::mesh::{AttributeName, AttributeStorage, Shape, VertexBuffer};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WeldTolerance {
+    /// Maximum difference of positions on every axis, in model units
+    pub position: f32,
+    /// Maximum difference of every component of all other attributes
+    pub attribute: f32,
+}
+
+impl Default for WeldTolerance {
+    fn default() -> Self {
+        Self {
+            position: 1e-4,
+            attribute: 1e-4,
+        }
+    }
+}
+
+/// Duplicate vertices of a shape, see [`Shape::find_duplicate_vertices`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DuplicateReport {
+    pub vertex_count: usize,
+    /// Indices of vertices that can be merged into one, in ascending order.
+    /// Vertices without duplicates aren't listed
+    pub groups: Vec<Vec<usize>>,
+}
+
+impl DuplicateReport {
+    /// Amount of vertices left after welding
+    pub fn unique_vertex_count(&self) -> usize {
+        self.vertex_count - self.groups.iter().map(|group| group.len() - 1).sum::<usize>()
+    }
+}
+
+// values are compared by the tolerance sized cell they fall into,
+// or exactly with a tolerance of zero
+fn quantize(value: f32, tolerance: f32) -> i64 {
+    if tolerance > 0.0 {
+        (value / tolerance).round() as i64
+    } else {
+        // adding zero turns -0.0 into 0.0
+        i64::from((value + 0.0).to_bits())
+    }
+}
+
+fn compact(bytes: &SharedBytes, vertex_size: usize, kept: &[usize]) -> SharedBytes {
+    let bytes: Vec<u8> = kept.iter()
+        .flat_map(|&vertex| &bytes[vertex * vertex_size..(vertex + 1) * vertex_size])
+        .copied()
+        .collect();
+    
+    bytes.into()
+}
+
+impl Shape {
+    /// Finds vertices whose attributes are all equal within `tolerance`. Vertices that
+    /// differ in any attribute, like texture coordinates along a UV seam or normals along
+    /// a hard edge, are kept apart. So are vertices of different sub meshes, because
+    /// their bone indices refer to different bone palettes.
+    pub fn find_duplicate_vertices(&self, tolerance: WeldTolerance) -> Result<DuplicateReport> {
+        let vertex_count = self.vertex_count();
+        
+        // the first value of every key is the sub mesh using the vertex, -1 if no sub mesh
+        // does and unique for vertices used by several so that those never get merged
+        let mut keys: Vec<Vec<i64>> = vec![vec![-1]; vertex_count];
+        
+        for (i, sub_mesh) in self.sub_meshes.iter().enumerate() {
+            let Some(sub_mesh) = sub_mesh else {
+                continue;
+            };
+            
+            let indices = sub_mesh.faces.iter()
+                .flatten()
+                .flat_map(|face| face.face_descriptors.iter().flatten())
+                .flat_map(|face_descriptor| &face_descriptor.indices);
+            
+            for &index in indices {
+                let key = keys.get_mut(index as usize)
+                    .ok_or_else(|| anyhow!("Vertex index {index} is out of bounds"))?;
+                
+                if key[0] == -1 {
+                    key[0] = i as i64;
+                } else if key[0] != i as i64 {
+                    key[0] = -2 - i64::from(index);
+                }
+            }
+        }
+        
+        for attribute in self.layout().attributes {
+            if attribute.storage == AttributeStorage::Fixed {
+                continue;
+            }
+            
+            let values = self.attribute(attribute.name)?.unwrap_or_default();
+            let elements = attribute.elements as usize;
+            
+            ensure!(values.len() == vertex_count * elements,
+                "Attribute {:?} has {} values, expected {}", attribute.name, values.len(), vertex_count * elements);
+            
+            let tolerance = match attribute.name {
+                AttributeName::Position => tolerance.position,
+                _ => tolerance.attribute,
+            };
+            
+            for (key, components) in keys.iter_mut().zip(values.chunks_exact(elements)) {
+                key.extend(components.iter().map(|&value| quantize(value, tolerance)));
+            }
+        }
+        
+        let mut group_indices: HashMap<Vec<i64>, usize> = HashMap::new();
+        let mut groups: Vec<Vec<usize>> = Vec::new();
+        
+        for (vertex, key) in keys.into_iter().enumerate() {
+            let group = *group_indices.entry(key).or_insert_with(|| {
+                groups.push(Vec::new());
+                groups.len() - 1
+            });
+            
+            groups[group].push(vertex);
+        }
+        
+        groups.retain(|group| group.len() > 1);
+        
+        Ok(DuplicateReport { vertex_count, groups })
+    }
+    
+    /// Merges every group of duplicate vertices found by [`Shape::find_duplicate_vertices`]
+    /// into its first vertex, removing the others from all vertex buffers and updating the
+    /// indices of all face descriptors. Returns the duplicates that were merged
+    pub fn weld_vertices(&mut self, tolerance: WeldTolerance) -> Result<DuplicateReport> {
+        let report = self.find_duplicate_vertices(tolerance)?;
+        
+        if report.groups.is_empty() {
+            return Ok(report);
+        }
+        
+        let vertex_count = report.vertex_count;
+        
+        // vertex every vertex gets merged into
+        let mut targets: Vec<usize> = (0..vertex_count).collect();
+        
+        for group in &report.groups {
+            for &vertex in &group[1..] {
+                targets[vertex] = group[0];
+            }
+        }
+        
+        let kept: Vec<usize> = (0..vertex_count).filter(|&vertex| targets[vertex] == vertex).collect();
+        
+        let mut new_indices = vec![0; vertex_count];
+        for (new_index, &vertex) in kept.iter().enumerate() {
+            new_indices[vertex] = new_index;
+        }
+        
+        self.retain_vertices(&kept)?;
+        
+        let face_descriptors = self.sub_meshes.iter_mut()
+            .flatten()
+            .flat_map(|sub_mesh| sub_mesh.faces.iter_mut().flatten())
+            .flat_map(|face| face.face_descriptors.iter_mut().flatten());
+        
+        for face_descriptor in face_descriptors {
+            for index in &mut face_descriptor.indices {
+                // indices only get smaller, so they still fit
+                *index = new_indices[targets[*index as usize]] as u16;
+            }
+        }
+        
+        Ok(report)
+    }
+    
+    /// Keeps only the vertices `kept` in all vertex buffers, in that order. Indices
+    /// of face descriptors are left alone
+    pub(super) fn retain_vertices(&mut self, kept: &[usize]) -> Result<()> {
+        let vertex_count = self.vertex_count();
+        
+        // check every buffer before changing any of them
+        let mut buffers: Vec<(&mut SharedBytes, usize)> = Vec::new();
+        
+        for vertex_buffer in self.vertex_buffers.iter_mut().flatten() {
+            let (raw_bytes, vertex_size) = match vertex_buffer {
+                VertexBuffer::Attribute(buffer) if buffer.attribute_name == AttributeName::Interleave => {
+                    ensure!(buffer.raw_bytes.is_empty(), "Can not remove vertices from separate buffers of interleaved data");
+                    continue;
+                },
+                VertexBuffer::Attribute(buffer) => {
+                    let vertex_size = buffer.format.byte_size() as usize * buffer.elements as usize;
+                    (&mut buffer.raw_bytes, vertex_size)
+                },
+                VertexBuffer::Interleaved(buffer) => (&mut buffer.raw_bytes, buffer.vertex_stride as usize),
+                VertexBuffer::Fixed(_) => continue,
+            };
+            
+            ensure!(raw_bytes.len() == vertex_count * vertex_size,
+                "Vertex buffer has {} bytes, expected {} for {vertex_count} vertices",
+                raw_bytes.len(), vertex_count * vertex_size);
+            
+            buffers.push((raw_bytes, vertex_size));
+        }
+        
+        if let Some(&vertex) = kept.iter().max() {
+            ensure!(vertex < vertex_count, "Vertex index {vertex} is out of bounds");
+        }
+        
+        for (raw_bytes, vertex_size) in buffers {
+            *raw_bytes = compact(raw_bytes, vertex_size, kept);
+        }
+        
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cgfx_container::CgfxContainer, fixtures, util::math::Vec3};
+    
+    // the fixture shape with `positions` and optionally a second buffer with texture
+    // coordinates, drawing `indices` as a triangle list
+    fn test_shape(positions: &[[f32; 3]], tex_coords: Option<&[[f32; 2]]>, indices: &[u32]) -> Shape {
+        let container = CgfxContainer::new(&fixtures::minimal_bcres()).unwrap();
+        let model = container.models.as_ref().unwrap().get(fixtures::MODEL_NAME).unwrap();
+        let mut shape = model.common().shapes[0].clone().unwrap();
+        
+        let Some(Some(VertexBuffer::Attribute(position_buffer))) = shape.vertex_buffers.first_mut() else {
+            panic!("fixture shape should start with an attribute vertex buffer");
+        };
+        position_buffer.raw_bytes = vec![0; positions.len() * 12].into();
+        
+        if tex_coords.is_some() {
+            let mut tex_coord_buffer = position_buffer.clone();
+            tex_coord_buffer.attribute_name = AttributeName::TexCoord0;
+            tex_coord_buffer.elements = 2;
+            tex_coord_buffer.raw_bytes = vec![0; positions.len() * 8].into();
+            shape.vertex_buffers.push(Some(VertexBuffer::Attribute(tex_coord_buffer)));
+        }
+        
+        shape.set_attribute(AttributeName::Position, positions.as_flattened()).unwrap();
+        if let Some(tex_coords) = tex_coords {
+            shape.set_attribute(AttributeName::TexCoord0, tex_coords.as_flattened()).unwrap();
+        }
+        
+        shape.set_triangles(indices).unwrap();
+        shape
+    }
+    
+    fn indices(shape: &Shape) -> Vec<u16> {
+        shape.sub_meshes.iter().flatten()
+            .flat_map(|sub_mesh| sub_mesh.faces.iter().flatten())
+            .flat_map(|face| face.face_descriptors.iter().flatten())
+            .flat_map(|face_descriptor| face_descriptor.indices.iter().copied())
+            .collect()
+    }
+    
+    // two triangles sharing an edge, with every vertex stored once per triangle. the
+    // second copy of vertex 1 is slightly off
+    const POSITIONS: [[f32; 3]; 6] = [
+        [0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0],
+        [1.0, 0.0, 0.00003], [0.0, 1.0, 0.0], [1.0, 1.0, 0.0],
+    ];
+    
+    #[test]
+    fn vertices_within_tolerance_are_merged() {
+        let shape = test_shape(&POSITIONS, None, &[0, 1, 2, 3, 4, 5]);
+        
+        let report = shape.find_duplicate_vertices(WeldTolerance::default()).unwrap();
+        assert_eq!(report.groups, vec![vec![1, 3], vec![2, 4]]);
+        assert_eq!(report.unique_vertex_count(), 4);
+        
+        // a tolerance of zero only merges exact copies
+        let exact = WeldTolerance { position: 0.0, attribute: 0.0 };
+        assert_eq!(shape.find_duplicate_vertices(exact).unwrap().groups, vec![vec![2, 4]]);
+    }
+    
+    #[test]
+    fn vertices_with_other_attributes_are_kept_apart() {
+        // vertex 1 lies on a UV seam, so its copy has other texture coordinates
+        let tex_coords = [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0], [0.0, 0.0], [0.0, 1.0], [1.0, 1.0]];
+        let shape = test_shape(&POSITIONS, Some(&tex_coords), &[0, 1, 2, 3, 4, 5]);
+        
+        let report = shape.find_duplicate_vertices(WeldTolerance::default()).unwrap();
+        assert_eq!(report.groups, vec![vec![2, 4]]);
+        
+        // unless both values fall into the same tolerance sized cell
+        let tolerance = WeldTolerance { position: 1e-4, attribute: 4.0 };
+        assert_eq!(shape.find_duplicate_vertices(tolerance).unwrap().groups, vec![vec![1, 3], vec![2, 4]]);
+    }
+    
+    #[test]
+    fn welding_remaps_indices() {
+        let mut shape = test_shape(&POSITIONS, None, &[0, 1, 2, 3, 4, 5]);
+        
+        let report = shape.weld_vertices(WeldTolerance::default()).unwrap();
+        assert_eq!(report.unique_vertex_count(), 4);
+        assert_eq!(shape.vertex_count(), 4);
+        
+        // merged vertices keep the values of the first vertex of their group
+        let positions: Vec<Vec3> = [POSITIONS[0], POSITIONS[1], POSITIONS[2], POSITIONS[5]].iter()
+            .map(|&[x, y, z]| Vec3::new(x, y, z))
+            .collect();
+        assert_eq!(shape.positions().unwrap(), positions);
+        assert_eq!(indices(&shape), vec![0, 1, 2, 1, 2, 3]);
+        
+        // welding again doesn't find anything left to merge
+        assert!(shape.weld_vertices(WeldTolerance::default()).unwrap().groups.is_empty());
+        assert_eq!(indices(&shape), vec![0, 1, 2, 1, 2, 3]);
+    }
+}
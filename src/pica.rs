@@ -0,0 +1,343 @@
+// raw PICA200 command lists, which several structures store to set GPU registers
+// directly. every command is a header word, telling which register gets written and
+// how, with its first parameter before it and all other parameters after it. the
+// whole command is padded to a multiple of eight bytes
+
+use std::collections::BTreeMap;
+
+use anyhow::{ensure, Result};
+
+/// Command header, see [`Command`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CommandHeader {
+    pub register: u16,
+    /// Which bytes of the parameters get written, one bit per byte
+    pub mask: u8,
+    /// Amount of parameters after the first one
+    pub extra_params: u8,
+    /// Whether every parameter goes into the register after the previous one,
+    /// instead of all of them into the same register
+    pub consecutive: bool,
+}
+
+impl CommandHeader {
+    pub fn from_raw(raw: u32) -> Self {
+        Self {
+            register: raw as u16,
+            mask: (raw >> 16 & 0xF) as u8,
+            extra_params: (raw >> 20) as u8,
+            consecutive: raw >> 31 != 0,
+        }
+    }
+    
+    pub fn to_raw(self) -> u32 {
+        u32::from(self.register)
+            | u32::from(self.mask & 0xF) << 16
+            | u32::from(self.extra_params) << 20
+            | u32::from(self.consecutive) << 31
+    }
+}
+
+/// Write of `value` into `register`, only changing the bytes selected by `mask`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RegisterWrite {
+    pub register: u16,
+    pub mask: u8,
+    pub value: u32,
+}
+
+impl RegisterWrite {
+    /// Value the register has after this write if it was `previous` before
+    pub fn apply(self, previous: u32) -> u32 {
+        let mask = (0..4)
+            .filter(|byte| self.mask & (1 << byte) != 0)
+            .fold(0, |mask, byte| mask | 0xFF << (byte * 8));
+        
+        previous & !mask | self.value & mask
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Command {
+    pub register: u16,
+    pub mask: u8,
+    pub consecutive: bool,
+    /// Never empty, a command has at least one parameter
+    pub params: Vec<u32>,
+}
+
+impl Command {
+    /// Register writes this command consists of, in order
+    pub fn writes(&self) -> impl Iterator<Item = RegisterWrite> + '_ {
+        self.params.iter().enumerate().map(|(i, &value)| RegisterWrite {
+            register: if self.consecutive { self.register.wrapping_add(i as u16) } else { self.register },
+            mask: self.mask,
+            value,
+        })
+    }
+    
+    pub fn header(&self) -> CommandHeader {
+        CommandHeader {
+            register: self.register,
+            mask: self.mask,
+            extra_params: (self.params.len() - 1) as u8,
+            consecutive: self.consecutive,
+        }
+    }
+    
+    /// Appends the command to `words`, including padding
+    pub fn encode_into(&self, words: &mut Vec<u32>) {
+        words.push(self.params[0]);
+        words.push(self.header().to_raw());
+        words.extend(&self.params[1..]);
+        
+        if self.params.len().is_multiple_of(2) {
+            words.push(0);
+        }
+    }
+}
+
+/// Iterates over the commands of a command list, see [`CommandDecoder::new`]
+pub struct CommandDecoder<'a> {
+    words: &'a [u32],
+}
+
+impl<'a> CommandDecoder<'a> {
+    /// Decodes the command list `words`, like the command fields of materials.
+    ///
+    /// ```
+    /// use ctr_bcres::pica::{encode_commands, CommandDecoder, RegisterWrite};
+    ///
+    /// // color operation, blend function and logic op of a material
+    /// let words = [0x00E4_0100, 0x803F_0100, 0x0106_0106, 3, 0, 0];
+    /// let commands = CommandDecoder::new(&words).collect::<Result<Vec<_>, _>>().unwrap();
+    ///
+    /// assert_eq!(commands.len(), 1);
+    /// assert_eq!(commands[0].writes().nth(1), Some(RegisterWrite { register: 0x101, mask: 0xF, value: 0x0106_0106 }));
+    /// assert_eq!(encode_commands(&commands), words);
+    /// ```
+    pub fn new(words: &'a [u32]) -> Self {
+        Self { words }
+    }
+    
+    /// All commands of the list
+    pub fn commands(self) -> Result<Vec<Command>> {
+        self.collect()
+    }
+    
+    /// All register writes of the list, in the order the GPU executes them
+    pub fn register_writes(self) -> Result<Vec<RegisterWrite>> {
+        Ok(self.commands()?.iter().flat_map(Command::writes).collect())
+    }
+    
+    /// Value of every register the list writes to after executing all of it,
+    /// starting from 0 for bytes that are never written
+    pub fn registers(self) -> Result<BTreeMap<u16, u32>> {
+        let mut registers = BTreeMap::new();
+        
+        for write in self.register_writes()? {
+            let value = registers.entry(write.register).or_insert(0);
+            *value = write.apply(*value);
+        }
+        
+        Ok(registers)
+    }
+    
+    fn next_command(&mut self) -> Result<Command> {
+        ensure!(self.words.len() >= 2, "Command list ends in the middle of a command");
+        
+        let header = CommandHeader::from_raw(self.words[1]);
+        let extra_params = usize::from(header.extra_params);
+        
+        // header and parameters, padded to an even amount of words
+        let length = (2 + extra_params).next_multiple_of(2);
+        ensure!(self.words.len() >= length,
+            "Command writing to register 0x{:x} has {extra_params} extra parameters, but only {} words are left",
+            header.register, self.words.len() - 2);
+        
+        let mut params = Vec::with_capacity(1 + extra_params);
+        params.push(self.words[0]);
+        params.extend(&self.words[2..2 + extra_params]);
+        
+        self.words = &self.words[length..];
+        
+        Ok(Command {
+            register: header.register,
+            mask: header.mask,
+            consecutive: header.consecutive,
+            params,
+        })
+    }
+}
+
+impl Iterator for CommandDecoder<'_> {
+    type Item = Result<Command>;
+    
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.words.is_empty() {
+            return None;
+        }
+        
+        let command = self.next_command();
+        
+        // there is no way to find the next command after a broken one
+        if command.is_err() {
+            self.words = &[];
+        }
+        
+        Some(command)
+    }
+}
+
+/// Encodes `commands` into a command list, the reverse of [`CommandDecoder`]
+pub fn encode_commands(commands: &[Command]) -> Vec<u32> {
+    let mut words = Vec::new();
+    
+    for command in commands {
+        command.encode_into(&mut words);
+    }
+    
+    words
+}
+
+/// Encodes `writes` into as few commands as possible, merging writes with the same mask
+/// into one command if they go into the same or consecutive registers
+pub fn encode_writes(writes: &[RegisterWrite]) -> Vec<u32> {
+    let mut commands: Vec<Command> = Vec::new();
+    
+    for write in writes {
+        if let Some(command) = commands.last_mut() {
+            // a command holds at most 256 parameters
+            let fits = command.mask == write.mask && command.params.len() < 256;
+            let last_register = command.writes().last().unwrap().register;
+            
+            let same = write.register == last_register && (!command.consecutive || command.params.len() == 1);
+            let next = write.register == last_register.wrapping_add(1) && (command.consecutive || command.params.len() == 1);
+            
+            if fits && (same || next) {
+                command.consecutive = next;
+                command.params.push(write.value);
+                continue;
+            }
+        }
+        
+        commands.push(Command {
+            register: write.register,
+            mask: write.mask,
+            consecutive: false,
+            params: vec![write.value],
+        });
+    }
+    
+    encode_commands(&commands)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn command(register: u16, mask: u8, consecutive: bool, params: &[u32]) -> Command {
+        Command { register, mask, consecutive, params: params.to_vec() }
+    }
+    
+    fn write(register: u16, mask: u8, value: u32) -> RegisterWrite {
+        RegisterWrite { register, mask, value }
+    }
+    
+    #[test]
+    fn headers_round_trip() {
+        let header = CommandHeader::from_raw(0x803F_0100);
+        assert_eq!(header, CommandHeader { register: 0x100, mask: 0xF, extra_params: 3, consecutive: true });
+        assert_eq!(header.to_raw(), 0x803F_0100);
+        
+        for header in [
+            CommandHeader { register: 0, mask: 0, extra_params: 0, consecutive: false },
+            CommandHeader { register: 0xFFFF, mask: 0x5, extra_params: 255, consecutive: true },
+            CommandHeader { register: 0x2B1, mask: 0x3, extra_params: 1, consecutive: false },
+        ] {
+            assert_eq!(CommandHeader::from_raw(header.to_raw()), header);
+        }
+    }
+    
+    #[test]
+    fn commands_round_trip() {
+        // odd amounts of parameters need a padding word, even ones don't
+        let commands = vec![
+            command(0x100, 0xF, false, &[1]),
+            command(0x101, 0x3, true, &[2, 3]),
+            command(0x110, 0xF, false, &[4, 5, 6]),
+            command(0x2B0, 0x1, true, &[7, 8, 9, 10]),
+        ];
+        
+        let words = encode_commands(&commands);
+        assert_eq!(words.len(), 2 + 4 + 4 + 6);
+        assert_eq!(&words[..4], &[1, 0x000F_0100, 2, 0x8013_0101]);
+        assert_eq!(CommandDecoder::new(&words).commands().unwrap(), commands);
+    }
+    
+    #[test]
+    fn writes_are_merged_into_few_commands() {
+        let writes = vec![
+            write(0x100, 0xF, 1),
+            write(0x101, 0xF, 2),
+            write(0x102, 0xF, 3),
+            // another mask needs a command of its own
+            write(0x103, 0x1, 4),
+            write(0x110, 0xF, 5),
+            write(0x110, 0xF, 6),
+        ];
+        
+        let words = encode_writes(&writes);
+        let commands = CommandDecoder::new(&words).commands().unwrap();
+        
+        assert_eq!(commands, vec![
+            command(0x100, 0xF, true, &[1, 2, 3]),
+            command(0x103, 0x1, false, &[4]),
+            command(0x110, 0xF, false, &[5, 6]),
+        ]);
+        assert_eq!(CommandDecoder::new(&words).register_writes().unwrap(), writes);
+    }
+    
+    #[test]
+    fn long_runs_are_split_into_commands_of_256_parameters() {
+        let writes: Vec<RegisterWrite> = (0..300).map(|value| write(0x200, 0xF, value)).collect();
+        
+        let words = encode_writes(&writes);
+        let commands = CommandDecoder::new(&words).commands().unwrap();
+        
+        assert_eq!(commands.iter().map(|command| command.params.len()).collect::<Vec<_>>(), vec![256, 44]);
+        assert_eq!(CommandDecoder::new(&words).register_writes().unwrap(), writes);
+    }
+    
+    #[test]
+    fn masks_only_change_their_bytes() {
+        let words = encode_commands(&[
+            command(0x100, 0xF, false, &[0x1122_3344]),
+            command(0x100, 0x5, false, &[0xAABB_CCDD]),
+            command(0x101, 0x8, false, &[0xFF00_0000]),
+        ]);
+        
+        let registers = CommandDecoder::new(&words).registers().unwrap();
+        assert_eq!(registers, BTreeMap::from([(0x100, 0x11BB_33DD), (0x101, 0xFF00_0000)]));
+    }
+    
+    #[test]
+    fn malformed_commands_are_errors() {
+        // a parameter without a header
+        assert!(CommandDecoder::new(&[1]).commands().is_err());
+        
+        // three extra parameters, but only two words after the header
+        assert!(CommandDecoder::new(&[1, 0x003F_0100, 2, 3]).commands().is_err());
+        
+        // one extra parameter without the padding word after it
+        assert!(CommandDecoder::new(&[1, 0x001F_0100, 2]).commands().is_err());
+        
+        // commands before a broken one still decode, nothing after it does
+        let mut decoder = CommandDecoder::new(&[1, 0x000F_0100, 2, 0x003F_0101, 3, 4]);
+        assert_eq!(decoder.next().unwrap().unwrap(), command(0x100, 0xF, false, &[1]));
+        assert!(decoder.next().unwrap().is_err());
+        assert!(decoder.next().is_none());
+        
+        assert_eq!(CommandDecoder::new(&[]).commands().unwrap(), Vec::new());
+    }
+}
@@ -0,0 +1,179 @@
+// parsers and writers for the objects of sections this crate doesn't parse itself,
+// installed by downstream crates for the (often game specific) object types they
+// know. objects are recognized by the discriminant they start with
+
+use std::{
+    any::Any,
+    collections::BTreeMap,
+    fmt::Debug,
+    io::{Read, Seek, SeekFrom, Write},
+    sync::{Arc, RwLock},
+};
+
+use anyhow::{bail, Result};
+use byteorder::{ByteOrder, LittleEndian, ReadBytesExt};
+
+use crate::{util::pointer::Pointer, CgfxCollectionValue, WriteContext};
+
+pub trait ReadSeek: Read + Seek {}
+impl<T: Read + Seek> ReadSeek for T {}
+
+pub trait WriteSeek: Write + Seek {}
+impl<T: Write + Seek> WriteSeek for T {}
+
+/// Parses every object starting with the discriminant it was registered for
+pub trait ObjectPlugin: Send + Sync {
+    /// The reader is at the start of the object, before its discriminant
+    fn read(&self, reader: &mut dyn ReadSeek) -> Result<Arc<dyn CustomObject>>;
+}
+
+/// Object parsed by an [`ObjectPlugin`], downcast it with [`CustomObject::as_any`]
+pub trait CustomObject: Debug + Send + Sync {
+    /// Writes the object including its discriminant. Strings and image data
+    /// go through `ctx` like for all other objects
+    fn write(&self, writer: &mut dyn WriteSeek, ctx: &mut WriteContext) -> Result<()>;
+    
+    fn as_any(&self) -> &dyn Any;
+}
+
+static PLUGINS: RwLock<BTreeMap<u32, Arc<dyn ObjectPlugin>>> = RwLock::new(BTreeMap::new());
+
+/// Installs `plugin` for all objects starting with `discriminant`, replacing the
+/// plug-in previously installed for it
+pub fn register_plugin(discriminant: u32, plugin: impl ObjectPlugin + 'static) {
+    PLUGINS.write().unwrap().insert(discriminant, Arc::new(plugin));
+}
+
+pub fn unregister_plugin(discriminant: u32) {
+    PLUGINS.write().unwrap().remove(&discriminant);
+}
+
+pub fn plugin(discriminant: u32) -> Option<Arc<dyn ObjectPlugin>> {
+    PLUGINS.read().unwrap().get(&discriminant).cloned()
+}
+
+/// Bytes of an object no plug-in is installed for, copied as they are when writing.
+///
+/// They reach from the object's start up to the next object or dict or the strings,
+/// so they include everything the object points to if it comes before those.
+/// Pointers to strings get pointed to the written strings, while all other
+/// pointers out of the bytes would point to wherever they were in the original file
+#[derive(Clone, Debug, PartialEq)]
+pub struct RawObject {
+    pub bytes: Vec<u8>,
+    /// Strings pointed to from `bytes`, by the offset of the pointer in `bytes`.
+    /// The pointers themselves are zero in `bytes` and only filled in when writing
+    pub strings: Vec<(usize, String)>,
+}
+
+impl RawObject {
+    /// Copies `buffer[start..end]`, finding the pointers to strings at or after `strings_start`
+    pub(crate) fn from_buffer(buffer: &[u8], start: usize, end: usize, strings_start: usize) -> Result<Self> {
+        let Some(bytes) = buffer.get(start..end) else {
+            bail!("Object at 0x{start:x} reaches past the end of the file at 0x{end:x}");
+        };
+        
+        let mut bytes = bytes.to_vec();
+        let mut strings = Vec::new();
+        
+        for offset in (0..bytes.len().saturating_sub(3)).step_by(4) {
+            let relative = LittleEndian::read_u32(&bytes[offset..offset + 4]);
+            let target = (start + offset).wrapping_add(relative as usize);
+            
+            if relative == 0 || target < strings_start.max(end) {
+                continue;
+            }
+            
+            // anything else that happens to point into the strings would have to end
+            // in a zero and only contain printable characters to be taken for one
+            let Some(length) = buffer.get(target..).and_then(|rest| rest.iter().position(|&byte| byte == 0)) else {
+                continue;
+            };
+            let string = &buffer[target..target + length];
+            
+            if length > 0 && string.iter().all(|byte| (0x20..0x7f).contains(byte)) {
+                strings.push((offset, String::from_utf8(string.to_vec())?));
+                bytes[offset..offset + 4].fill(0);
+            }
+        }
+        
+        Ok(RawObject { bytes, strings })
+    }
+    
+    pub fn discriminant(&self) -> Option<u32> {
+        Some(LittleEndian::read_u32(self.bytes.get(..4)?))
+    }
+}
+
+/// Entry of a section this crate has no parser for
+#[derive(Clone, Debug)]
+pub enum SectionObject {
+    /// No plug-in is installed for the discriminant, so the object was skipped
+    Unknown { discriminant: u32 },
+    /// No plug-in is installed for the discriminant, but the object's bytes were kept
+    Raw(RawObject),
+    Custom(Arc<dyn CustomObject>),
+}
+
+impl SectionObject {
+    pub fn custom(&self) -> Option<&dyn CustomObject> {
+        match self {
+            SectionObject::Unknown { .. } | SectionObject::Raw(_) => None,
+            SectionObject::Custom(object) => Some(object.as_ref()),
+        }
+    }
+    
+    pub fn raw(&self) -> Option<&RawObject> {
+        match self {
+            SectionObject::Raw(object) => Some(object),
+            _ => None,
+        }
+    }
+    
+    /// The plug-in object if it is of type `T`
+    pub fn downcast_ref<T: 'static>(&self) -> Option<&T> {
+        self.custom()?.as_any().downcast_ref()
+    }
+}
+
+// custom objects can't be compared, so they are only equal to themselves
+impl PartialEq for SectionObject {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (SectionObject::Unknown { discriminant: a }, SectionObject::Unknown { discriminant: b }) => a == b,
+            (SectionObject::Raw(a), SectionObject::Raw(b)) => a == b,
+            (SectionObject::Custom(a), SectionObject::Custom(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl CgfxCollectionValue for SectionObject {
+    fn read_dict_value<R: Read + Seek>(reader: &mut R) -> Result<Self> {
+        let discriminant = reader.read_u32::<LittleEndian>()?;
+        reader.seek(SeekFrom::Current(-4))?;
+        
+        match plugin(discriminant) {
+            Some(plugin) => Ok(SectionObject::Custom(plugin.read(reader)?)),
+            None => Ok(SectionObject::Unknown { discriminant }),
+        }
+    }
+    
+    fn write_dict_value<W: Write + Seek>(&self, writer: &mut W, ctx: &mut WriteContext) -> Result<()> {
+        match self {
+            SectionObject::Unknown { discriminant } =>
+                bail!("Can not write object with discriminant 0x{discriminant:x} without a plug-in for it"),
+            SectionObject::Raw(object) => {
+                let start = Pointer::current(writer)?;
+                writer.write_all(&object.bytes)?;
+                
+                for (offset, string) in &object.strings {
+                    ctx.add_string_reference(start + Pointer::from(*offset), string.clone());
+                }
+                
+                Ok(())
+            },
+            SectionObject::Custom(object) => object.write(writer, ctx),
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use std::{error::Error, fmt::Display};
+
+use anyhow::Result;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProgressStage {
+    Parsing,
+    DecodingTextures,
+}
+
+/// Receives progress updates from long running operations.
+///
+/// Returning false cancels the operation, which then fails with [`Cancelled`].
+pub trait Progress {
+    fn report(&mut self, stage: ProgressStage, done: usize, total: usize) -> bool;
+}
+
+impl<F: FnMut(ProgressStage, usize, usize) -> bool> Progress for F {
+    fn report(&mut self, stage: ProgressStage, done: usize, total: usize) -> bool {
+        self(stage, done, total)
+    }
+}
+
+/// Progress implementation that ignores all updates
+pub struct NoProgress;
+
+impl Progress for NoProgress {
+    fn report(&mut self, _: ProgressStage, _: usize, _: usize) -> bool {
+        true
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Operation was cancelled")
+    }
+}
+
+impl Error for Cancelled {}
+
+pub(crate) fn report_progress(progress: &mut impl Progress, stage: ProgressStage, done: usize, total: usize) -> Result<()> {
+    if progress.report(stage, done, total) {
+        Ok(())
+    } else {
+        Err(Cancelled.into())
+    }
+}
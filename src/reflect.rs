@@ -0,0 +1,581 @@
+use std::borrow::Cow;
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::{
+    cgfx_container::{CgfxContainer, CgfxHeader},
+    image_codec::RgbaColor,
+    model::{
+        anim_group::{AnimGroup, AnimGroupMember},
+        material::{
+            CgfxMaterial, FaceCulling, FragmentOp, MaterialColors, Rasterization, TextureCoord,
+            TextureMapper, TextureReference, TextureSampler, TextureTransformType,
+        },
+        mesh::{
+            AttributeName, BoundingBox, Face, FaceDescriptor, GlDataType, Mesh, Shape, SubMesh, SubMeshSkinning,
+            VertexBuffer, VertexBufferAttribute, VertexBufferCommon, VertexBufferFixed, VertexBufferInterleaved,
+            VertexBufferType,
+        },
+        skeleton::{BillboardMode, CgfxBone, CgfxSkeleton, SkeletonScalingRule},
+        CgfxModel, CgfxModelCommon, MeshNodeVisibility,
+    },
+    plugin::SectionObject,
+    texture::{CgfxTexture, CgfxTextureCommon, ImageData, MemoryArea, PicaTextureFormat},
+    util::{
+        math::{SerializableMatrix, Vec2, Vec3, Vec4},
+        pointer::Pointer,
+        util::{CgfxNodeHeader, CgfxObjectHeader, NodeChild, CgfxTransform, RuntimeHandle, SharedBytes},
+    },
+    CgfxCollectionValue, CgfxDict,
+};
+
+/// Value of a primitive field
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    Int(i64),
+    Float(f32),
+    String(String),
+    /// Empty optional value
+    None,
+    /// Value that can only be displayed, not changed through reflection
+    ReadOnly(String),
+}
+
+pub struct Field<'a> {
+    pub name: Cow<'static, str>,
+    /// Offset from the start of the parent object as written in the file, if known
+    pub offset: Option<u32>,
+    pub value: &'a dyn Reflect,
+}
+
+/// Generic access to the fields of parsed objects, e.g. for property editors.
+///
+/// Structs and collections have fields, primitives have a value.
+pub trait Reflect {
+    fn type_name(&self) -> &'static str;
+    
+    fn fields(&self) -> Vec<Field<'_>> {
+        Vec::new()
+    }
+    
+    fn field_mut(&mut self, _name: &str) -> Option<&mut dyn Reflect> {
+        None
+    }
+    
+    fn value(&self) -> Option<Value> {
+        None
+    }
+    
+    fn set_value(&mut self, _value: Value) -> Result<()> {
+        bail!("{} can not be set directly", self.type_name())
+    }
+    
+    /// Value to start from when setting an empty `Option<Self>`
+    fn empty_value() -> Option<Self> where Self: Sized {
+        None
+    }
+}
+
+impl dyn Reflect + '_ {
+    pub fn field(&self, name: &str) -> Option<&dyn Reflect> {
+        self.fields().into_iter()
+            .find(|field| field.name == name)
+            .map(|field| field.value)
+    }
+    
+    /// Follows `path` through nested fields, e.g. `["textures", "my_texture", "common", "width"]`
+    pub fn path(&self, path: &[&str]) -> Option<&dyn Reflect> {
+        path.iter().try_fold(self, |current, name| current.field(name))
+    }
+    
+    pub fn path_mut(&mut self, path: &[&str]) -> Option<&mut dyn Reflect> {
+        let mut current: &mut dyn Reflect = self;
+        
+        for name in path {
+            current = current.field_mut(name)?;
+        }
+        
+        Some(current)
+    }
+    
+    pub fn set_path(&mut self, path: &[&str], value: Value) -> Result<()> {
+        self.path_mut(path)
+            .ok_or_else(|| anyhow!("Field {:?} does not exist", path.join(".")))?
+            .set_value(value)
+    }
+}
+
+macro_rules! reflect_int {
+    ($($t:ty),* $(,)?) => {
+        $(
+            impl Reflect for $t {
+                fn type_name(&self) -> &'static str {
+                    stringify!($t)
+                }
+                
+                fn value(&self) -> Option<Value> {
+                    Some(Value::Int((*self).into()))
+                }
+                
+                fn set_value(&mut self, value: Value) -> Result<()> {
+                    let Value::Int(value) = value else {
+                        bail!("Expected an integer for {} but got {value:?}", stringify!($t));
+                    };
+                    
+                    *self = value.try_into()
+                        .map_err(|_| anyhow!("{value} is out of range for {}", stringify!($t)))?;
+                    Ok(())
+                }
+                
+                fn empty_value() -> Option<Self> {
+                    Some(0)
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! reflect_read_only {
+    ($($t:ty),* $(,)?) => {
+        $(
+            impl Reflect for $t {
+                fn type_name(&self) -> &'static str {
+                    stringify!($t)
+                }
+                
+                fn value(&self) -> Option<Value> {
+                    Some(Value::ReadOnly(format!("{self:?}")))
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! offset {
+    () => { None };
+    ($offset:literal) => { Some($offset) };
+}
+
+macro_rules! reflect_fields {
+    ($t:ident { $($field:ident $(@ $offset:literal)?),* $(,)? }) => {
+        impl Reflect for $t {
+            fn type_name(&self) -> &'static str {
+                stringify!($t)
+            }
+            
+            fn fields(&self) -> Vec<Field<'_>> {
+                vec![
+                    $(Field {
+                        name: Cow::Borrowed(stringify!($field)),
+                        offset: offset!($($offset)?),
+                        value: &self.$field,
+                    },)*
+                ]
+            }
+            
+            fn field_mut(&mut self, name: &str) -> Option<&mut dyn Reflect> {
+                match name {
+                    $(stringify!($field) => Some(&mut self.$field),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+reflect_int!(u8, u16, u32, i32);
+
+reflect_read_only!((), Pointer, RuntimeHandle, PicaTextureFormat, MemoryArea, FaceCulling, TextureTransformType);
+reflect_read_only!(SubMeshSkinning, AttributeName, GlDataType, VertexBufferType, SkeletonScalingRule, SectionObject, BillboardMode);
+
+impl Reflect for bool {
+    fn type_name(&self) -> &'static str {
+        "bool"
+    }
+    
+    fn value(&self) -> Option<Value> {
+        Some(Value::Bool(*self))
+    }
+    
+    fn set_value(&mut self, value: Value) -> Result<()> {
+        let Value::Bool(value) = value else {
+            bail!("Expected a bool but got {value:?}");
+        };
+        
+        *self = value;
+        Ok(())
+    }
+    
+    fn empty_value() -> Option<Self> {
+        Some(false)
+    }
+}
+
+impl Reflect for f32 {
+    fn type_name(&self) -> &'static str {
+        "f32"
+    }
+    
+    fn value(&self) -> Option<Value> {
+        Some(Value::Float(*self))
+    }
+    
+    fn set_value(&mut self, value: Value) -> Result<()> {
+        *self = match value {
+            Value::Float(value) => value,
+            Value::Int(value) => value as f32,
+            _ => bail!("Expected a number but got {value:?}"),
+        };
+        Ok(())
+    }
+    
+    fn empty_value() -> Option<Self> {
+        Some(0.0)
+    }
+}
+
+impl Reflect for String {
+    fn type_name(&self) -> &'static str {
+        "String"
+    }
+    
+    fn value(&self) -> Option<Value> {
+        Some(Value::String(self.clone()))
+    }
+    
+    fn set_value(&mut self, value: Value) -> Result<()> {
+        let Value::String(value) = value else {
+            bail!("Expected a string but got {value:?}");
+        };
+        
+        *self = value;
+        Ok(())
+    }
+    
+    fn empty_value() -> Option<Self> {
+        Some(String::new())
+    }
+}
+
+impl Reflect for SharedBytes {
+    fn type_name(&self) -> &'static str {
+        "SharedBytes"
+    }
+    
+    fn value(&self) -> Option<Value> {
+        Some(Value::ReadOnly(format!("<buffer, {} bytes>", self.len())))
+    }
+}
+
+impl<const R: usize, const C: usize> Reflect for SerializableMatrix<R, C> {
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+    
+    fn value(&self) -> Option<Value> {
+        Some(Value::ReadOnly(format!("{self:?}")))
+    }
+}
+
+impl<T: Reflect> Reflect for Option<T> {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Some(value) => value.type_name(),
+            None => std::any::type_name::<Self>(),
+        }
+    }
+    
+    fn fields(&self) -> Vec<Field<'_>> {
+        self.as_ref().map_or_else(Vec::new, |value| value.fields())
+    }
+    
+    fn field_mut(&mut self, name: &str) -> Option<&mut dyn Reflect> {
+        self.as_mut()?.field_mut(name)
+    }
+    
+    fn value(&self) -> Option<Value> {
+        match self {
+            Some(value) => value.value(),
+            None => Some(Value::None),
+        }
+    }
+    
+    // setting a value on an empty option only works for primitives
+    fn set_value(&mut self, value: Value) -> Result<()> {
+        if value == Value::None {
+            *self = None;
+            return Ok(());
+        }
+        
+        if self.is_none() {
+            *self = Some(T::empty_value()
+                .ok_or_else(|| anyhow!("Can not set a value on an empty {}", self.type_name()))?);
+        }
+        
+        self.as_mut().unwrap().set_value(value)
+    }
+}
+
+impl<T: Reflect> Reflect for Box<T> {
+    fn type_name(&self) -> &'static str {
+        self.as_ref().type_name()
+    }
+    
+    fn fields(&self) -> Vec<Field<'_>> {
+        self.as_ref().fields()
+    }
+    
+    fn field_mut(&mut self, name: &str) -> Option<&mut dyn Reflect> {
+        self.as_mut().field_mut(name)
+    }
+    
+    fn value(&self) -> Option<Value> {
+        self.as_ref().value()
+    }
+    
+    fn set_value(&mut self, value: Value) -> Result<()> {
+        self.as_mut().set_value(value)
+    }
+}
+
+fn index_fields<T: Reflect>(items: &[T]) -> Vec<Field<'_>> {
+    items.iter()
+        .enumerate()
+        .map(|(i, item)| Field {
+            name: Cow::Owned(i.to_string()),
+            offset: None,
+            value: item,
+        })
+        .collect()
+}
+
+fn index_field_mut<'a, T: Reflect>(items: &'a mut [T], name: &str) -> Option<&'a mut dyn Reflect> {
+    let index: usize = name.parse().ok()?;
+    Some(items.get_mut(index)?)
+}
+
+impl<T: Reflect> Reflect for Vec<T> {
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+    
+    fn fields(&self) -> Vec<Field<'_>> {
+        index_fields(self)
+    }
+    
+    fn field_mut(&mut self, name: &str) -> Option<&mut dyn Reflect> {
+        index_field_mut(self, name)
+    }
+}
+
+impl<T: Reflect, const N: usize> Reflect for [T; N] {
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+    
+    fn fields(&self) -> Vec<Field<'_>> {
+        index_fields(self)
+    }
+    
+    fn field_mut(&mut self, name: &str) -> Option<&mut dyn Reflect> {
+        index_field_mut(self, name)
+    }
+}
+
+// dicts are presented by their entries' names, the tree itself is left out
+impl<T: CgfxCollectionValue + Reflect> Reflect for CgfxDict<T> {
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+    
+    fn fields(&self) -> Vec<Field<'_>> {
+        self.nodes.iter()
+            .filter_map(|node| Some(Field {
+                name: Cow::Owned(node.name.clone()?),
+                offset: None,
+                value: node.value.as_ref()?,
+            }))
+            .collect()
+    }
+    
+    fn field_mut(&mut self, name: &str) -> Option<&mut dyn Reflect> {
+        Some(self.get_mut(name)?)
+    }
+}
+
+// container
+reflect_fields!(CgfxHeader {
+    byte_order_mark @ 4, header_length @ 6, revision @ 8, file_length @ 12, sections_count @ 16,
+    content_magic_number @ 20, content_length @ 24,
+});
+reflect_fields!(CgfxContainer {
+    header, models, textures, luts, materials, shaders, cameras, lights, fogs, scenes, skeletal_animations,
+    material_animations, visibility_animations, camera_animations, light_animations, fog_animations, emitters,
+});
+
+// math
+reflect_fields!(Vec2 { x @ 0, y @ 4 });
+reflect_fields!(Vec3 { x @ 0, y @ 4, z @ 8 });
+reflect_fields!(Vec4 { x @ 0, y @ 4, z @ 8, w @ 12 });
+reflect_fields!(RgbaColor { r @ 0, g @ 1, b @ 2, a @ 3 });
+
+// common headers
+reflect_fields!(CgfxObjectHeader { magic @ 0, revision @ 4, name @ 8, metadata_count @ 12, metadata_pointer @ 16 });
+reflect_fields!(CgfxNodeHeader { branch_visible @ 0, is_branch_visible @ 4, child_count @ 8, children_pointer @ 12, children, anim_groups });
+reflect_fields!(NodeChild { discriminant, magic, revision, name });
+reflect_fields!(CgfxTransform { scale @ 0, rotation @ 12, translation @ 24, local_transform @ 36, world_transform @ 84 });
+
+// textures
+reflect_fields!(ImageData { height @ 0, width @ 4, image_bytes, bits_per_pixel @ 20, memory_area @ 28 });
+reflect_fields!(CgfxTextureCommon {
+    cgfx_object_header @ 0, height @ 20, width @ 24, gl_format @ 28, gl_type @ 32, mipmap_size @ 36,
+    texture_obj @ 40, location_flag @ 44, texture_format @ 48,
+});
+
+impl Reflect for CgfxTexture {
+    fn type_name(&self) -> &'static str {
+        "CgfxTexture"
+    }
+    
+    fn fields(&self) -> Vec<Field<'_>> {
+        let (common, images): (_, &dyn Reflect) = match self {
+            CgfxTexture::Cube(common, images) => (common, images),
+            CgfxTexture::Image(common, image) => (common, image),
+            CgfxTexture::Unknown(common, unknown) => (common, &unknown.images),
+        };
+        
+        vec![
+            Field { name: Cow::Borrowed("common"), offset: Some(4), value: common },
+            Field { name: Cow::Borrowed("images"), offset: None, value: images },
+        ]
+    }
+    
+    fn field_mut(&mut self, name: &str) -> Option<&mut dyn Reflect> {
+        match (self, name) {
+            (CgfxTexture::Cube(common, _) | CgfxTexture::Image(common, _) | CgfxTexture::Unknown(common, _), "common") => Some(common),
+            (CgfxTexture::Cube(_, images), "images") => Some(images),
+            (CgfxTexture::Image(_, image), "images") => Some(image),
+            (CgfxTexture::Unknown(_, unknown), "images") => Some(&mut unknown.images),
+            _ => None,
+        }
+    }
+}
+
+// models
+reflect_fields!(CgfxModelCommon {
+    cgfx_object_header @ 4, cgfx_node_header, transform_node_header, meshes, materials, shapes,
+    mesh_node_visibilities, flags, face_culling, layer_id,
+});
+reflect_fields!(MeshNodeVisibility { name @ 0, visible @ 4 });
+reflect_fields!(AnimGroup { flags @ 0, name @ 4, member_type @ 8, members, blend_operations, evaluation_timing @ 28 });
+reflect_fields!(AnimGroupMember {
+    discriminant @ 0, path @ 4, member_offset @ 8, blend_operation_index @ 12, object_type @ 16, member_type @ 20,
+});
+reflect_fields!(Mesh {
+    cgfx_object_header @ 4, shape_index @ 24, material_index @ 28, visible @ 36, render_priority @ 37,
+    mesh_node_index @ 38, primitive_index @ 40,
+});
+
+impl Reflect for CgfxModel {
+    fn type_name(&self) -> &'static str {
+        "CgfxModel"
+    }
+    
+    fn fields(&self) -> Vec<Field<'_>> {
+        let mut fields = vec![Field { name: Cow::Borrowed("common"), offset: None, value: self.common() }];
+        
+        if let CgfxModel::Skeletal(_, skeleton) = self {
+            fields.push(Field { name: Cow::Borrowed("skeleton"), offset: None, value: skeleton });
+        }
+        
+        fields
+    }
+    
+    fn field_mut(&mut self, name: &str) -> Option<&mut dyn Reflect> {
+        match (self, name) {
+            (model, "common") => Some(model.common_mut()),
+            (CgfxModel::Skeletal(_, skeleton), "skeleton") => Some(skeleton),
+            _ => None,
+        }
+    }
+}
+
+// skeletons
+reflect_fields!(CgfxSkeleton { cgfx_object_header, bones, root_bone, scaling_rule, flags });
+reflect_fields!(CgfxBone {
+    name, flags, index, parent_index, parent_ptr,
+    scale, rotation, translation, local_transform, world_transform, inv_world_transform, billboard_mode, metadata_ptr,
+});
+
+// materials
+reflect_fields!(CgfxMaterial {
+    cgfx_object_header @ 4, flags @ 24, tex_coord_config @ 28, render_layer @ 32, colors @ 36,
+    rasterization @ 260, fragment_operation @ 280, used_texture_coords_count @ 360, texture_coords @ 364,
+    texture_mappers @ 628,
+});
+reflect_fields!(MaterialColors {
+    emission_float @ 0, ambient_float @ 16, diffuse_float @ 32, specular0_float @ 48, specular1_float @ 64,
+    constant0_float @ 80, constant1_float @ 96, constant2_float @ 112, constant3_float @ 128,
+    constant4_float @ 144, constant5_float @ 160,
+    emission @ 176, ambient @ 180, diffuse @ 184, specular0 @ 188, specular1 @ 192, constant0 @ 196,
+    constant1 @ 200, constant2 @ 204, constant3 @ 208, constant4 @ 212, constant5 @ 216,
+    command_cache @ 220,
+});
+reflect_fields!(Rasterization { is_polygon_offset_enabled @ 0, face_culling @ 4, polygon_offset_unit @ 8, face_culling_command @ 12 });
+reflect_fields!(FragmentOp {
+    depth_flags @ 0, depth_commands @ 4, blend_mode @ 20, blend_color @ 24, blend_commands @ 40, stencil_commands @ 64,
+});
+reflect_fields!(TextureCoord {
+    source_coord_index @ 0, mapping_type @ 4, reference_camera_index @ 8, transform_type @ 12, scale @ 16,
+    rotation @ 24, translation @ 28, flags @ 36, transform @ 40,
+});
+reflect_fields!(TextureMapper { dynamic_alloc @ 4, texture @ 8, sampler @ 12, commands @ 16, commands_len @ 72 });
+reflect_fields!(TextureReference { cgfx_object_header @ 4, path @ 24, texture_ptr @ 28 });
+reflect_fields!(TextureSampler { parent_mapper @ 4, min_filter @ 8 });
+
+// shapes
+reflect_fields!(Shape {
+    cgfx_object_header, flags, bounding_box, position_offset, sub_meshes, base_address, vertex_buffers, blend_shape_pointer,
+});
+reflect_fields!(BoundingBox { flags, center, orientation, size });
+reflect_fields!(SubMesh { bone_indices, skinning, faces });
+reflect_fields!(Face { face_descriptors, buffer_objs, flags, command_alloc });
+reflect_fields!(FaceDescriptor {
+    format, primitive_mode, visible, indices, buffer_obj, location_flag, command_cache, command_cache_size, location_ptr,
+    memory_area, bounding_volume,
+});
+reflect_fields!(VertexBufferCommon { attribute_name, vertex_buffer_type });
+reflect_fields!(VertexBufferAttribute {
+    vertex_buffer_common, buffer_obj, location_flag, raw_bytes, location_ptr, memory_area, format, elements, scale, offset,
+});
+reflect_fields!(VertexBufferInterleaved {
+    vertex_buffer_common, buffer_obj, location_flag, raw_bytes, location_ptr, memory_area, vertex_stride, attributes,
+});
+reflect_fields!(VertexBufferFixed { vertex_buffer_common, format, elements, scale, vector });
+
+impl Reflect for VertexBuffer {
+    fn type_name(&self) -> &'static str {
+        match self {
+            VertexBuffer::Attribute(buffer) => buffer.type_name(),
+            VertexBuffer::Interleaved(buffer) => buffer.type_name(),
+            VertexBuffer::Fixed(buffer) => buffer.type_name(),
+        }
+    }
+    
+    fn fields(&self) -> Vec<Field<'_>> {
+        match self {
+            VertexBuffer::Attribute(buffer) => buffer.fields(),
+            VertexBuffer::Interleaved(buffer) => buffer.fields(),
+            VertexBuffer::Fixed(buffer) => buffer.fields(),
+        }
+    }
+    
+    fn field_mut(&mut self, name: &str) -> Option<&mut dyn Reflect> {
+        match self {
+            VertexBuffer::Attribute(buffer) => buffer.field_mut(name),
+            VertexBuffer::Interleaved(buffer) => buffer.field_mut(name),
+            VertexBuffer::Fixed(buffer) => buffer.field_mut(name),
+        }
+    }
+}
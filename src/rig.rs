@@ -0,0 +1,179 @@
+use std::fmt::Write;
+
+use anyhow::{anyhow, ensure, Result};
+
+use crate::{model::skeleton::CgfxSkeleton, util::math::{Units, Vec3}};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct JointPose {
+    pub translation: Vec3,
+    /// Euler angles in radians, applied in XYZ order
+    pub rotation: Vec3,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Joint {
+    pub name: String,
+    /// Index into [`Rig::joints`], always lower than the index of this joint
+    pub parent: Option<usize>,
+    pub bind_pose: JointPose,
+}
+
+/// Joint hierarchy of a skeleton, independent of how it's stored in CGFX
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Rig {
+    pub joints: Vec<Joint>,
+}
+
+impl Rig {
+    /// Collects all bones of `skeleton`, ordered so that every joint comes after its parent
+    pub fn from_skeleton(skeleton: &CgfxSkeleton) -> Result<Self> {
+        let mut bones: Vec<_> = skeleton.bones.values().collect();
+        bones.sort_by_key(|bone| bone.index);
+        
+        let mut joints: Vec<Joint> = Vec::with_capacity(bones.len());
+        // bone index of every joint
+        let mut joint_bones: Vec<u32> = Vec::with_capacity(bones.len());
+        
+        while !bones.is_empty() {
+            let previous_len = bones.len();
+            
+            bones.retain(|bone| {
+                let is_root = bone.parent_index == bone.index || bone.parent_index == u32::MAX;
+                let parent = joint_bones.iter().position(|&index| index == bone.parent_index);
+                
+                if !is_root && parent.is_none() {
+                    return true;
+                }
+                
+                joints.push(Joint {
+                    name: bone.name.clone().unwrap_or_else(|| format!("bone_{}", bone.index)),
+                    parent: parent.filter(|_| !is_root),
+                    bind_pose: JointPose {
+                        translation: bone.translation,
+                        rotation: bone.rotation,
+                    },
+                });
+                joint_bones.push(bone.index);
+                false
+            });
+            
+            ensure!(bones.len() < previous_len,
+                "Skeleton contains bones whose parent is missing or that are their own ancestor");
+        }
+        
+        Ok(Self { joints })
+    }
+    
+    /// Same as [`Rig::from_skeleton`], but with all translations converted to meters
+    pub fn from_skeleton_in_meters(skeleton: &CgfxSkeleton, units: Units) -> Result<Self> {
+        let mut rig = Self::from_skeleton(skeleton)?;
+        
+        for joint in &mut rig.joints {
+            joint.bind_pose.translation = units.to_meters(joint.bind_pose.translation);
+        }
+        
+        Ok(rig)
+    }
+    
+    pub fn joint(&self, name: &str) -> Option<usize> {
+        self.joints.iter().position(|joint| joint.name == name)
+    }
+    
+    /// Writes the rig and `clip` as a text SMD file. Without a clip, only the bind pose is written,
+    /// which is what modeling tools expect as a reference skeleton
+    pub fn to_smd(&self, clip: Option<&Clip>) -> Result<String> {
+        let bind_pose;
+        let clip = match clip {
+            Some(clip) => clip,
+            None => {
+                bind_pose = Clip::bind_pose(self);
+                &bind_pose
+            },
+        };
+        
+        let mut out = String::from("version 1\nnodes\n");
+        
+        for (i, joint) in self.joints.iter().enumerate() {
+            let parent = joint.parent.map_or(-1, |parent| parent as i64);
+            writeln!(out, "{i} \"{}\" {parent}", joint.name.replace('"', "_"))?;
+        }
+        
+        out.push_str("end\nskeleton\n");
+        
+        for (time, poses) in clip.frames.iter().enumerate() {
+            ensure!(poses.len() == self.joints.len(),
+                "Frame {time} of clip {:?} has {} poses, but the rig has {} joints", clip.name, poses.len(), self.joints.len());
+            
+            writeln!(out, "time {time}")?;
+            
+            for (i, pose) in poses.iter().enumerate() {
+                let JointPose { translation: t, rotation: r } = pose;
+                writeln!(out, "{i} {:.6} {:.6} {:.6} {:.6} {:.6} {:.6}", t.x, t.y, t.z, r.x, r.y, r.z)?;
+            }
+        }
+        
+        out.push_str("end\n");
+        Ok(out)
+    }
+}
+
+/// Animation sampled at a fixed rate, with the pose of every joint of a [`Rig`] in every frame
+#[derive(Clone, Debug, PartialEq)]
+pub struct Clip {
+    pub name: String,
+    pub frame_rate: f32,
+    /// One pose per joint for every frame
+    pub frames: Vec<Vec<JointPose>>,
+}
+
+impl Clip {
+    /// Clip with a single frame containing the bind pose
+    pub fn bind_pose(rig: &Rig) -> Self {
+        Self {
+            name: "bind_pose".to_string(),
+            frame_rate: 30.0,
+            frames: vec![rig.joints.iter().map(|joint| joint.bind_pose).collect()],
+        }
+    }
+    
+    /// Samples `duration` seconds of an animation at `frame_rate` frames per second,
+    /// including both the first and the last frame. `sample` returns the pose of a joint
+    /// at a point in time in seconds, or None to keep its bind pose
+    pub fn sample(name: &str, rig: &Rig, frame_rate: f32, duration: f32,
+        mut sample: impl FnMut(usize, f32) -> Option<JointPose>) -> Result<Self>
+    {
+        ensure!(frame_rate > 0.0 && frame_rate.is_finite(), "Invalid frame rate {frame_rate}");
+        ensure!(duration >= 0.0 && duration.is_finite(), "Invalid duration {duration}");
+        
+        let frame_count = (duration * frame_rate).round() as usize + 1;
+        
+        let frames = (0..frame_count)
+            .map(|frame| {
+                let time = frame as f32 / frame_rate;
+                
+                rig.joints.iter()
+                    .enumerate()
+                    .map(|(i, joint)| sample(i, time).unwrap_or(joint.bind_pose))
+                    .collect()
+            })
+            .collect();
+        
+        Ok(Self {
+            name: name.to_string(),
+            frame_rate,
+            frames,
+        })
+    }
+    
+    pub fn duration(&self) -> f32 {
+        self.frames.len().saturating_sub(1) as f32 / self.frame_rate
+    }
+    
+    pub fn pose(&self, frame: usize, joint: usize) -> Result<JointPose> {
+        self.frames.get(frame)
+            .and_then(|poses| poses.get(joint))
+            .copied()
+            .ok_or_else(|| anyhow!("Clip {:?} has no pose for joint {joint} in frame {frame}", self.name))
+    }
+}
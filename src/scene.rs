@@ -0,0 +1,202 @@
+// high level view of a container for the common case of looking at models, meshes,
+// materials and textures by name. indices and texture references are resolved into
+// the objects they point to, and every view keeps the raw structure it wraps, so
+// anything the views don't cover is still one field away
+use anyhow::Result;
+
+use crate::{
+    cgfx_container::CgfxContainer,
+    model::{material::CgfxMaterial, mesh::{Mesh, Shape}, skeleton::CgfxSkeleton, CgfxModel},
+    texture::CgfxTexture,
+};
+
+/// A parsed bcres file with its models, meshes, materials and textures linked to each other
+///
+/// ```
+/// use ctr_bcres::{fixtures, Scene};
+///
+/// let scene = Scene::new(&fixtures::minimal_bcres()).unwrap();
+/// let model = scene.model(fixtures::MODEL_NAME).unwrap();
+///
+/// for mesh in model.meshes() {
+///     let material = mesh.material().unwrap();
+///     let texture = material.textures().next().unwrap();
+///
+///     assert_eq!(material.name, fixtures::MATERIAL_NAME);
+///     assert_eq!(texture.name, fixtures::TEXTURE_NAME);
+///     assert_eq!(texture.materials().count(), 1);
+/// }
+///
+/// // the raw structures are still there
+/// assert_eq!(scene.container().textures.as_ref().unwrap().len(), 1);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scene {
+    container: CgfxContainer,
+}
+
+impl Scene {
+    pub fn new(buffer: &[u8]) -> Result<Self> {
+        Ok(Self::from_container(CgfxContainer::new(buffer)?))
+    }
+    
+    pub fn from_container(container: CgfxContainer) -> Self {
+        Self { container }
+    }
+    
+    pub fn container(&self) -> &CgfxContainer {
+        &self.container
+    }
+    
+    /// The wrapped container for editing, views borrowing the scene see the changes
+    /// once the borrow ends
+    pub fn container_mut(&mut self) -> &mut CgfxContainer {
+        &mut self.container
+    }
+    
+    pub fn into_container(self) -> CgfxContainer {
+        self.container
+    }
+    
+    pub fn to_buffer(&self) -> Result<Vec<u8>> {
+        self.container.to_buffer()
+    }
+    
+    pub fn models(&self) -> impl Iterator<Item = ModelView<'_>> {
+        self.container.models_iter().map(|(name, raw)| ModelView { name, raw, scene: self })
+    }
+    
+    pub fn model(&self, name: &str) -> Option<ModelView<'_>> {
+        self.models().find(|model| model.name == name)
+    }
+    
+    pub fn textures(&self) -> impl Iterator<Item = TextureView<'_>> {
+        self.container.textures_iter().map(|(name, raw)| TextureView { name, raw, scene: self })
+    }
+    
+    pub fn texture(&self, name: &str) -> Option<TextureView<'_>> {
+        let (name, raw) = self.container.textures.as_ref()?.entries().find(|&(entry, _)| entry == name)?;
+        Some(TextureView { name, raw, scene: self })
+    }
+    
+    /// Materials of all models, model by model
+    pub fn materials(&self) -> impl Iterator<Item = MaterialView<'_>> {
+        self.models().flat_map(|model| model.materials())
+    }
+}
+
+impl From<CgfxContainer> for Scene {
+    fn from(container: CgfxContainer) -> Self {
+        Self::from_container(container)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ModelView<'a> {
+    pub name: &'a str,
+    pub raw: &'a CgfxModel,
+    scene: &'a Scene,
+}
+
+impl<'a> ModelView<'a> {
+    pub fn scene(self) -> &'a Scene {
+        self.scene
+    }
+    
+    /// Meshes in file order, skipping null entries
+    pub fn meshes(self) -> impl Iterator<Item = MeshView<'a>> {
+        self.raw.common().meshes.iter()
+            .enumerate()
+            .filter_map(move |(index, raw)| Some(MeshView { index, raw: raw.as_ref()?, model: self }))
+    }
+    
+    pub fn materials(self) -> impl Iterator<Item = MaterialView<'a>> {
+        self.raw.common().materials.iter()
+            .flat_map(|materials| materials.entries())
+            .map(move |(name, raw)| MaterialView { name, raw, model: self })
+    }
+    
+    pub fn material(self, name: &str) -> Option<MaterialView<'a>> {
+        self.materials().find(|material| material.name == name)
+    }
+    
+    pub fn skeleton(self) -> Option<&'a CgfxSkeleton> {
+        match self.raw {
+            CgfxModel::Standard(_) => None,
+            CgfxModel::Skeletal(_, skeleton) => Some(skeleton),
+        }
+    }
+    
+    /// Models attached to this one as its children, see [`CgfxContainer::child_models`]
+    pub fn children(self) -> impl Iterator<Item = ModelView<'a>> {
+        self.raw.common().cgfx_node_header.children.iter()
+            .flatten()
+            .filter_map(move |child| self.scene.model(child.name.as_deref()?))
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MeshView<'a> {
+    /// Index into [`CgfxModelCommon::meshes`](crate::model::CgfxModelCommon::meshes)
+    pub index: usize,
+    pub raw: &'a Mesh,
+    pub model: ModelView<'a>,
+}
+
+impl<'a> MeshView<'a> {
+    pub fn name(self) -> Option<&'a str> {
+        self.raw.cgfx_object_header.name.as_deref()
+    }
+    
+    pub fn shape(self) -> Option<&'a Shape> {
+        self.model.raw.common().shapes.get(self.raw.shape_index as usize)?.as_ref()
+    }
+    
+    pub fn material(self) -> Option<MaterialView<'a>> {
+        self.model.materials().nth(self.raw.material_index as usize)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MaterialView<'a> {
+    pub name: &'a str,
+    pub raw: &'a CgfxMaterial,
+    pub model: ModelView<'a>,
+}
+
+impl<'a> MaterialView<'a> {
+    /// Texture the texture mapper at `mapper_index` displays, None if the mapper
+    /// doesn't exist or its texture isn't in the container
+    pub fn texture(self, mapper_index: usize) -> Option<TextureView<'a>> {
+        let reference = self.raw.texture_mappers.get(mapper_index)?.as_ref()?.texture.as_ref()?;
+        self.model.scene.texture(reference.path.as_deref()?)
+    }
+    
+    /// Textures of all texture mappers that resolve, in mapper order
+    pub fn textures(self) -> impl Iterator<Item = TextureView<'a>> {
+        (0..self.raw.texture_mappers.len()).filter_map(move |index| self.texture(index))
+    }
+    
+    /// Meshes of the model that are drawn with this material
+    pub fn meshes(self) -> impl Iterator<Item = MeshView<'a>> {
+        let index = self.model.materials().position(|material| material.name == self.name);
+        
+        self.model.meshes()
+            .filter(move |mesh| Some(mesh.raw.material_index as usize) == index)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TextureView<'a> {
+    pub name: &'a str,
+    pub raw: &'a CgfxTexture,
+    scene: &'a Scene,
+}
+
+impl<'a> TextureView<'a> {
+    /// Materials of all models with a texture mapper displaying this texture
+    pub fn materials(self) -> impl Iterator<Item = MaterialView<'a>> {
+        self.scene.materials()
+            .filter(move |material| material.textures().any(|texture| texture.name == self.name))
+    }
+}
@@ -0,0 +1,263 @@
+// scene statistics like triangle counts and texture memory, and a check of them against
+// a budget of what the 3DS can draw at a reasonable frame rate, so that imported models
+// can be judged before trying them on hardware. the default budget is a rough guideline
+// taken from what games of the time used, not a hard limit of the hardware
+
+use std::fmt::Display;
+
+use crate::{
+    cgfx_container::CgfxContainer,
+    model::{CgfxModel, CgfxModelCommon},
+    texture::{CgfxTexture, PicaTextureFormat},
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MeshStats {
+    pub name: Option<String>,
+    pub shape_index: usize,
+    pub triangle_count: usize,
+    pub vertex_count: usize,
+    /// Largest bone palette of all sub meshes of the shape
+    pub max_palette_length: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ModelStats {
+    pub name: String,
+    pub meshes: Vec<MeshStats>,
+    /// Zero for models without a skeleton
+    pub bone_count: usize,
+}
+
+impl ModelStats {
+    fn new(name: &str, model: &CgfxModel) -> Self {
+        let bone_count = match model {
+            CgfxModel::Standard(_) => 0,
+            CgfxModel::Skeletal(_, skeleton) => skeleton.bones.values().count(),
+        };
+        
+        Self {
+            name: name.to_string(),
+            meshes: mesh_stats(model.common()),
+            bone_count,
+        }
+    }
+    
+    /// Triangles of all meshes, shapes drawn by several meshes count once for each
+    pub fn triangle_count(&self) -> usize {
+        self.meshes.iter().map(|mesh| mesh.triangle_count).sum()
+    }
+    
+    pub fn vertex_count(&self) -> usize {
+        self.meshes.iter().map(|mesh| mesh.vertex_count).sum()
+    }
+}
+
+fn mesh_stats(common: &CgfxModelCommon) -> Vec<MeshStats> {
+    common.meshes.iter()
+        .flatten()
+        .map(|mesh| {
+            let shape_index = mesh.shape_index as usize;
+            let shape = common.shapes.get(shape_index).and_then(Option::as_ref);
+            
+            let max_palette_length = shape.into_iter()
+                .flat_map(|shape| shape.sub_meshes.iter().flatten())
+                .map(|sub_mesh| sub_mesh.bone_indices.len())
+                .max()
+                .unwrap_or(0);
+            
+            MeshStats {
+                name: mesh.cgfx_object_header.name.clone(),
+                shape_index,
+                triangle_count: shape.map_or(0, |shape| shape.triangle_count()),
+                vertex_count: shape.map_or(0, |shape| shape.vertex_count()),
+                max_palette_length,
+            }
+        })
+        .collect()
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TextureStats {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub format: PicaTextureFormat,
+    /// Size of all images including mipmaps, see [`CgfxTexture::size`]
+    pub byte_size: u32,
+}
+
+impl TextureStats {
+    fn new(name: &str, texture: &CgfxTexture) -> Self {
+        let common = texture.metadata();
+        
+        Self {
+            name: name.to_string(),
+            width: common.width,
+            height: common.height,
+            format: common.texture_format,
+            byte_size: texture.size(),
+        }
+    }
+}
+
+/// Statistics of a whole container, see [`CgfxContainer::stats`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SceneStats {
+    pub models: Vec<ModelStats>,
+    pub textures: Vec<TextureStats>,
+}
+
+impl SceneStats {
+    pub fn triangle_count(&self) -> usize {
+        self.models.iter().map(ModelStats::triangle_count).sum()
+    }
+    
+    /// Bytes all textures take up once loaded onto the GPU
+    pub fn texture_memory(&self) -> u64 {
+        self.textures.iter().map(|texture| u64::from(texture.byte_size)).sum()
+    }
+    
+    /// Everything that exceeds `budget`, in the order of models and textures
+    pub fn check(&self, budget: &ComplexityBudget) -> Vec<BudgetWarning> {
+        let mut warnings = Vec::new();
+        
+        for model in &self.models {
+            let triangle_count = model.triangle_count();
+            if triangle_count > budget.max_model_triangles {
+                warnings.push(BudgetWarning::ModelTriangles {
+                    model: model.name.clone(),
+                    triangle_count,
+                    max: budget.max_model_triangles,
+                });
+            }
+            
+            if model.bone_count > budget.max_bones {
+                warnings.push(BudgetWarning::Bones {
+                    model: model.name.clone(),
+                    bone_count: model.bone_count,
+                    max: budget.max_bones,
+                });
+            }
+            
+            let mut shape_indices: Vec<usize> = Vec::new();
+            
+            for mesh in model.meshes.iter().filter(|mesh| mesh.max_palette_length > budget.max_palette_length) {
+                // shapes drawn by several meshes only get one warning
+                if shape_indices.contains(&mesh.shape_index) {
+                    continue;
+                }
+                
+                shape_indices.push(mesh.shape_index);
+                warnings.push(BudgetWarning::PaletteLength {
+                    model: model.name.clone(),
+                    shape_index: mesh.shape_index,
+                    palette_length: mesh.max_palette_length,
+                    max: budget.max_palette_length,
+                });
+            }
+        }
+        
+        let triangle_count = self.triangle_count();
+        if triangle_count > budget.max_scene_triangles {
+            warnings.push(BudgetWarning::SceneTriangles { triangle_count, max: budget.max_scene_triangles });
+        }
+        
+        for texture in &self.textures {
+            if texture.width > budget.max_texture_dimension || texture.height > budget.max_texture_dimension {
+                warnings.push(BudgetWarning::TextureDimensions {
+                    texture: texture.name.clone(),
+                    width: texture.width,
+                    height: texture.height,
+                    max: budget.max_texture_dimension,
+                });
+            }
+        }
+        
+        let texture_memory = self.texture_memory();
+        if texture_memory > budget.max_texture_memory {
+            warnings.push(BudgetWarning::TextureMemory { byte_size: texture_memory, max: budget.max_texture_memory });
+        }
+        
+        warnings
+    }
+}
+
+/// Limits checked by [`SceneStats::check`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ComplexityBudget {
+    pub max_model_triangles: usize,
+    /// Maximum amount of triangles of all models together
+    pub max_scene_triangles: usize,
+    /// Maximum amount of bones of a single skeleton
+    pub max_bones: usize,
+    /// Maximum amount of bones a single sub mesh can refer to, which is limited by
+    /// the uniforms the vertex shader has for bone matrices
+    pub max_palette_length: usize,
+    pub max_texture_dimension: u32,
+    /// Maximum amount of bytes of all textures together. The 3DS has 6 MiB of VRAM,
+    /// which is shared with the frame buffers
+    pub max_texture_memory: u64,
+}
+
+impl Default for ComplexityBudget {
+    fn default() -> Self {
+        Self {
+            max_model_triangles: 15_000,
+            max_scene_triangles: 40_000,
+            max_bones: 128,
+            max_palette_length: 20,
+            max_texture_dimension: 1024,
+            max_texture_memory: 4 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BudgetWarning {
+    ModelTriangles { model: String, triangle_count: usize, max: usize },
+    SceneTriangles { triangle_count: usize, max: usize },
+    Bones { model: String, bone_count: usize, max: usize },
+    PaletteLength { model: String, shape_index: usize, palette_length: usize, max: usize },
+    TextureDimensions { texture: String, width: u32, height: u32, max: u32 },
+    TextureMemory { byte_size: u64, max: u64 },
+}
+
+impl Display for BudgetWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BudgetWarning::ModelTriangles { model, triangle_count, max } =>
+                write!(f, "Model {model} has {triangle_count} triangles, the budget is {max}"),
+            BudgetWarning::SceneTriangles { triangle_count, max } =>
+                write!(f, "All models together have {triangle_count} triangles, the budget is {max}"),
+            BudgetWarning::Bones { model, bone_count, max } =>
+                write!(f, "Model {model} has {bone_count} bones, the budget is {max}"),
+            BudgetWarning::PaletteLength { model, shape_index, palette_length, max } =>
+                write!(f, "Model {model}, shape {shape_index}: A sub mesh refers to {palette_length} bones, the budget is {max}"),
+            BudgetWarning::TextureDimensions { texture, width, height, max } =>
+                write!(f, "Texture {texture} is {width}x{height} pixels large, the budget is {max}x{max}"),
+            BudgetWarning::TextureMemory { byte_size, max } =>
+                write!(f, "All textures together take up {byte_size} bytes, the budget is {max}"),
+        }
+    }
+}
+
+impl CgfxContainer {
+    /// Triangle, bone and texture statistics of all models and textures
+    ///
+    /// ```
+    /// use ctr_bcres::{cgfx_container::CgfxContainer, fixtures, stats::ComplexityBudget};
+    ///
+    /// let container = CgfxContainer::new(&fixtures::minimal_bcres()).unwrap();
+    /// let stats = container.stats();
+    ///
+    /// assert_eq!(stats.triangle_count(), 1);
+    /// assert!(stats.check(&ComplexityBudget::default()).is_empty());
+    /// ```
+    pub fn stats(&self) -> SceneStats {
+        SceneStats {
+            models: self.models_iter().map(|(name, model)| ModelStats::new(name, model)).collect(),
+            textures: self.textures_iter().map(|(name, texture)| TextureStats::new(name, texture)).collect(),
+        }
+    }
+}
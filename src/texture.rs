@@ -1,9 +1,10 @@
 use std::{
-    fmt::Debug,
+    collections::HashMap,
+    fmt::{Debug, Display},
     io::{Read, Seek, SeekFrom, Write},
 };
 
-use anyhow::{Error, Result};
+use anyhow::{anyhow, bail, ensure, Error, Result};
 use array_init::try_array_init;
 use binrw::{BinRead, BinWrite};
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
@@ -12,12 +13,18 @@ use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    image_codec::{
+        decode_swizzled_buffer, encode_swizzled_buffer, encode_swizzled_buffer_with_options, format_support,
+        EncodeOptions, EncodeQuality, FormatSupport, RgbaColor,
+    },
+    limits,
+    magic::TextureType,
     scoped_reader_pos,
     util::{
         pointer::Pointer,
-        util::{brw_relative_pointer, CgfxObjectHeader},
+        util::{add_object_header_name, brw_relative_pointer, read_shared_bytes, CgfxObjectHeader, RuntimeHandle, RuntimeHandles, SharedBytes},
     },
-    CgfxCollectionValue, WriteContext,
+    write_at_pointer, CgfxCollectionValue, CgfxDict, CgfxNode, WriteContext, WriteOptions,
 };
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, BinRead, BinWrite)]
@@ -41,6 +48,78 @@ pub enum PicaTextureFormat {
 }
 
 impl PicaTextureFormat {
+    pub const ALL: [PicaTextureFormat; 14] = [
+        PicaTextureFormat::RGBA8,
+        PicaTextureFormat::RGB8,
+        PicaTextureFormat::RGBA5551,
+        PicaTextureFormat::RGB565,
+        PicaTextureFormat::RGBA4,
+        PicaTextureFormat::LA8,
+        PicaTextureFormat::HiLo8,
+        PicaTextureFormat::L8,
+        PicaTextureFormat::A8,
+        PicaTextureFormat::LA4,
+        PicaTextureFormat::L4,
+        PicaTextureFormat::A4,
+        PicaTextureFormat::ETC1,
+        PicaTextureFormat::ETC1A4,
+    ];
+    
+    pub fn support(self) -> FormatSupport {
+        format_support(self)
+    }
+    
+    /// OpenGL format and type that get stored alongside the Pica format, None for
+    /// HiLo8, whose pair isn't known
+    pub fn gl_format_and_type(self) -> Option<(u32, u32)> {
+        let pair = match self {
+            PicaTextureFormat::RGBA8 => (0x1908, 0x1401),
+            PicaTextureFormat::RGB8 => (0x1907, 0x1401),
+            PicaTextureFormat::RGBA5551 => (0x1908, 0x8034),
+            PicaTextureFormat::RGB565 => (0x1907, 0x8363),
+            PicaTextureFormat::RGBA4 => (0x1908, 0x8033),
+            PicaTextureFormat::LA8 => (0x190A, 0x1401),
+            PicaTextureFormat::L8 => (0x1909, 0x1401),
+            PicaTextureFormat::A8 => (0x1906, 0x1401),
+            PicaTextureFormat::LA4 => (0x190A, 0x6760),
+            PicaTextureFormat::L4 => (0x1909, 0x6761),
+            PicaTextureFormat::A4 => (0x1906, 0x6761),
+            PicaTextureFormat::ETC1 => (0x675A, 0),
+            PicaTextureFormat::ETC1A4 => (0x675B, 0),
+            PicaTextureFormat::HiLo8 => return None,
+        };
+        
+        Some(pair)
+    }
+    
+    /// Format with the given OpenGL format and type, the reverse of [`gl_format_and_type`]
+    ///
+    /// [`gl_format_and_type`]: PicaTextureFormat::gl_format_and_type
+    pub fn from_gl_format_and_type(gl_format: u32, gl_type: u32) -> Option<Self> {
+        Self::ALL.into_iter().find(|format| format.gl_format_and_type() == Some((gl_format, gl_type)))
+    }
+    
+    /// Amount of bytes a single image of this format takes up
+    pub fn image_size(self, width: u32, height: u32) -> usize {
+        self.buffer_size(width, height, 1)
+    }
+    
+    /// Amount of bytes an image with `mip_levels` levels (including the full size one)
+    /// takes up, which should match the `buffer_length` of its image data. Every level
+    /// is half the size of the previous one and padded to whole [`TILE_SIZE`] tiles,
+    /// and 4 bit formats round up to whole bytes
+    pub fn buffer_size(self, width: u32, height: u32, mip_levels: u32) -> usize {
+        (0..mip_levels)
+            .map(|level| {
+                let (width, height) = mipmap_dimensions(width, height, level);
+                let width = width.max(1).next_multiple_of(TILE_SIZE) as usize;
+                let height = height.max(1).next_multiple_of(TILE_SIZE) as usize;
+                
+                (width * height * self.get_bpp() as usize).div_ceil(8)
+            })
+            .sum()
+    }
+    
     pub fn get_bpp(&self) -> u32 {
         match self {
             PicaTextureFormat::RGBA8 => 32,
@@ -61,25 +140,265 @@ impl PicaTextureFormat {
     }
 }
 
+/// Where the image data gets placed when the file is loaded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, BinRead, BinWrite)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[brw(repr(u32), little)]
+pub enum MemoryArea {
+    #[default]
+    None = 0,
+    Fcram = 0x10000,
+    VramA = 0x20000,
+    VramB = 0x30000,
+}
+
 #[derive(Clone, PartialEq, Eq, BinRead, BinWrite)]
 #[brw(little)]
-#[br(assert(location_ptr == 0, "ImageData has location_ptr {}", location_ptr))]
 pub struct ImageData {
     pub height: u32,
     pub width: u32,
     
     #[brw(ignore)]
-    pub image_bytes: Vec<u8>,
+    pub image_bytes: SharedBytes,
+    
+    // render targets only get their buffer at runtime, buffer_pointer is null for those
+    #[brw(ignore)]
+    render_target: bool,
     
     buffer_length: u32,
     #[br(parse_with = brw_relative_pointer)]
     #[bw(map = |_| 0u32)]
     buffer_pointer: Option<Pointer>,
     
+    /// Allocator the image was loaded with. Only set at runtime, so it's always
+    /// written as zero, see [`ImageData::validate`]
+    #[bw(map = |_| 0u32)]
     pub dynamic_alloc: u32,
     pub bits_per_pixel: u32,
-    pub location_ptr: u32, // ?
-    pub memory_area: u32,
+    /// Physical address of the image data. Only set at runtime, so it's always
+    /// written as zero, see [`ImageData::validate`]
+    #[bw(map = |_| 0u32)]
+    pub location_ptr: u32,
+    pub memory_area: MemoryArea,
+}
+
+impl ImageData {
+    pub fn new(width: u32, height: u32, format: PicaTextureFormat, image_bytes: Vec<u8>) -> Result<Self> {
+        Ok(Self {
+            height,
+            width,
+            buffer_length: image_bytes.len().try_into()?,
+            image_bytes: image_bytes.into(),
+            render_target: false,
+            buffer_pointer: None,
+            dynamic_alloc: 0,
+            bits_per_pixel: format.get_bpp(),
+            location_ptr: 0,
+            memory_area: MemoryArea::None,
+        })
+    }
+    
+    /// Absolute offset of the image bytes in the file this was parsed from
+    pub fn buffer_pointer(&self) -> Option<Pointer> {
+        self.buffer_pointer
+    }
+    
+    /// Length of the image bytes as it gets written, see [`CgfxTexture::normalize`]
+    pub fn buffer_length(&self) -> u32 {
+        self.buffer_length
+    }
+    
+    /// Whether this image has no image bytes in the file because it's
+    /// rendered into at runtime
+    pub fn is_render_target(&self) -> bool {
+        self.render_target
+    }
+    
+    /// Fields that should only be set at runtime but have a value, like in files dumped
+    /// from memory. They get written as zero either way
+    pub fn validate(&self) -> Vec<ImageDataIssue> {
+        let mut issues = Vec::new();
+        
+        if self.dynamic_alloc != 0 {
+            issues.push(ImageDataIssue::DynamicAlloc(self.dynamic_alloc));
+        }
+        
+        if self.location_ptr != 0 {
+            issues.push(ImageDataIssue::LocationPtr(self.location_ptr));
+        }
+        
+        issues
+    }
+    
+    /// Replaces the image bytes, for example with a different amount of mipmaps
+    pub fn set_image_bytes(&mut self, image_bytes: Vec<u8>) -> Result<()> {
+        self.buffer_length = image_bytes.len().try_into()?;
+        self.image_bytes = image_bytes.into();
+        Ok(())
+    }
+}
+
+/// See [`ImageData::validate`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageDataIssue {
+    DynamicAlloc(u32),
+    LocationPtr(u32),
+}
+
+impl Display for ImageDataIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImageDataIssue::DynamicAlloc(value) => write!(f, "dynamic_alloc is 0x{value:x} instead of zero"),
+            ImageDataIssue::LocationPtr(value) => write!(f, "location_ptr is 0x{value:x} instead of zero"),
+        }
+    }
+}
+
+/// What happens to the mipmaps of a texture when it gets written,
+/// see [`WriteOptions::mipmap_policy`](crate::WriteOptions::mipmap_policy)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MipmapPolicy {
+    /// Write the mipmaps the texture already has
+    #[default]
+    Keep,
+    /// Only write the full size image, which saves a third of the memory
+    Strip,
+    /// Replace the mipmaps with newly generated ones, see [`CgfxTexture::generate_mipmaps`]
+    Regenerate,
+}
+
+/// Which format a texture gets decoded with when its `texture_format` disagrees with
+/// its `gl_format` and `gl_type`, as written by some tools. See
+/// [`ReadOptions::format_policy`](crate::ReadOptions::format_policy)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FormatPolicy {
+    #[default]
+    TrustTextureFormat,
+    TrustGlFormat,
+    /// Whichever of the two formats the size of the image data matches, or the
+    /// texture format if that doesn't tell them apart
+    MatchBufferSize,
+}
+
+/// How a single texture gets re-encoded, overriding the defaults of its [`RecompressConfig`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TextureRecompress {
+    pub format: Option<PicaTextureFormat>,
+    pub quality: Option<EncodeQuality>,
+    /// Leave the texture as it is
+    pub skip: bool,
+}
+
+/// Settings for re-encoding many textures at once, see [`TextureDict::recompress`]
+///
+/// ```
+/// use ctr_bcres::{image_codec::EncodeQuality, texture::{PicaTextureFormat, RecompressConfig, TextureRecompress}};
+///
+/// let mut config = RecompressConfig::new(PicaTextureFormat::ETC1);
+/// config.excluded_formats.push(PicaTextureFormat::L8);
+/// config.overrides.insert("ui_font".to_string(), TextureRecompress { skip: true, ..Default::default() });
+/// config.overrides.insert("hero".to_string(), TextureRecompress {
+///     quality: Some(EncodeQuality::Best),
+///     ..Default::default()
+/// });
+///
+/// assert_eq!(config.settings_for("hero", PicaTextureFormat::RGBA8),
+///     Some((PicaTextureFormat::ETC1, EncodeQuality::Best)));
+/// assert_eq!(config.settings_for("ui_font", PicaTextureFormat::RGBA8), None);
+/// assert_eq!(config.settings_for("shadow", PicaTextureFormat::L8), None);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecompressConfig {
+    pub format: PicaTextureFormat,
+    pub quality: EncodeQuality,
+    /// Textures currently in one of these formats are left as they are,
+    /// unless their override sets a format
+    pub excluded_formats: Vec<PicaTextureFormat>,
+    /// Settings for single textures, by name
+    pub overrides: HashMap<String, TextureRecompress>,
+}
+
+impl RecompressConfig {
+    pub fn new(format: PicaTextureFormat) -> Self {
+        Self {
+            format,
+            quality: EncodeQuality::default(),
+            excluded_formats: Vec::new(),
+            overrides: HashMap::new(),
+        }
+    }
+    
+    /// Format and quality a texture named `name` that is currently in `format` gets
+    /// re-encoded with, None if it's left alone. Textures that are already
+    /// in the target format are never re-encoded, since that only loses quality
+    pub fn settings_for(&self, name: &str, format: PicaTextureFormat) -> Option<(PicaTextureFormat, EncodeQuality)> {
+        let texture_override = self.overrides.get(name).copied().unwrap_or_default();
+        
+        if texture_override.skip || texture_override.format.is_none() && self.excluded_formats.contains(&format) {
+            return None;
+        }
+        
+        let target = texture_override.format.unwrap_or(self.format);
+        (target != format).then_some((target, texture_override.quality.unwrap_or(self.quality)))
+    }
+}
+
+/// Textures re-encoded by [`TextureDict::recompress`]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct TextureRecompression {
+    /// Name of every re-encoded texture with its old and new format
+    pub recompressed: Vec<(String, PicaTextureFormat, PicaTextureFormat)>,
+    /// Size of the image data of all re-encoded textures before and after
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+}
+
+/// The two formats of a texture that disagree, see [`CgfxTexture::format_mismatch`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FormatMismatch {
+    pub texture_format: PicaTextureFormat,
+    /// Format inferred from `gl_format` and `gl_type`
+    pub gl_format: PicaTextureFormat,
+}
+
+fn gl_format_and_type(format: PicaTextureFormat) -> Result<(u32, u32)> {
+    format.gl_format_and_type()
+        .ok_or_else(|| anyhow!("Format {format:?} has no known OpenGL format and type"))
+}
+
+// images can't get smaller than a single swizzling tile
+const MIN_MIPMAP_SIZE: u32 = 8;
+
+/// Width and height of the tiles images are swizzled in
+pub const TILE_SIZE: u32 = 8;
+
+fn mipmap_dimensions(width: u32, height: u32, level: u32) -> (u32, u32) {
+    (width >> level, height >> level)
+}
+
+// averages every 2x2 block of pixels
+fn downsample(pixels: &[RgbaColor], width: u32, height: u32) -> Vec<RgbaColor> {
+    let (width, height) = (width as usize, height as usize);
+    let mut out = Vec::with_capacity(width / 2 * height / 2);
+    
+    for y in (0..height - 1).step_by(2) {
+        for x in (0..width - 1).step_by(2) {
+            let block = [
+                pixels[y * width + x],
+                pixels[y * width + x + 1],
+                pixels[(y + 1) * width + x],
+                pixels[(y + 1) * width + x + 1],
+            ];
+            
+            let average = |channel: fn(&RgbaColor) -> u8| {
+                ((block.iter().map(|color| u32::from(channel(color))).sum::<u32>() + 2) / 4) as u8
+            };
+            
+            out.push(RgbaColor::new(average(|c| c.r), average(|c| c.g), average(|c| c.b), average(|c| c.a)));
+        }
+    }
+    
+    out
 }
 
 impl Debug for ImageData {
@@ -88,6 +407,7 @@ impl Debug for ImageData {
             .field("height", &self.height)
             .field("width", &self.width)
             .field("image_bytes", &format!("<buffer, {} bytes>", self.image_bytes.len()))
+            .field("render_target", &self.render_target)
             .field("buffer_length", &self.buffer_length)
             .field("buffer_pointer", &self.buffer_pointer)
             .field("dynamic_alloc", &self.dynamic_alloc)
@@ -100,6 +420,7 @@ impl Debug for ImageData {
 
 #[derive(Debug, Clone, BinRead, BinWrite, PartialEq)]
 #[brw(little)]
+#[bw(import(preserve_runtime_handles: bool))]
 pub struct CgfxTextureCommon {
     // cgfx object header
     pub cgfx_object_header: CgfxObjectHeader,
@@ -110,7 +431,8 @@ pub struct CgfxTextureCommon {
     pub gl_format: u32,
     pub gl_type: u32,
     pub mipmap_size: u32,
-    pub texture_obj: u32,
+    #[bw(args(preserve_runtime_handles))]
+    pub texture_obj: RuntimeHandle,
     pub location_flag: u32,
     pub texture_format: PicaTextureFormat,
 }
@@ -119,6 +441,91 @@ pub struct CgfxTextureCommon {
 pub enum CgfxTexture {
     Cube(CgfxTextureCommon, Box<[ImageData; 6]>),
     Image(CgfxTextureCommon, Option<ImageData>),
+    /// Texture of a type this library doesn't know, like array or 3D textures
+    Unknown(CgfxTextureCommon, UnknownTexture),
+}
+
+/// Contents of a texture of an unknown type, see [`CgfxTexture::Unknown`]. It is
+/// assumed to point to one image data per layer right after its common fields, the
+/// way image and cube textures do, and is written with the same layout again
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnknownTexture {
+    /// Raw type discriminant
+    pub texture_type: u32,
+    /// Empty if the texture doesn't point to any image data that matches its format and size
+    pub images: Vec<ImageData>,
+    /// Bytes between the image data pointers and the first image data
+    pub fields: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedTexture {
+    pub name: Option<String>,
+    pub width: u32,
+    pub height: u32,
+    
+    // one entry per image, so six for cube textures
+    pub images: Vec<Vec<RgbaColor>>,
+}
+
+impl DecodedTexture {
+    /// All images with their channels normalized to 0.0 - 1.0, for analyzing them
+    /// without having to care about the precision of the original format
+    pub fn images_f32(&self) -> Vec<Vec<[f32; 4]>> {
+        self.images.iter()
+            .map(|image| image.iter().map(|color| color.to_f32()).collect())
+            .collect()
+    }
+}
+
+/// Amount of image bytes of a texture, read without reading the image bytes themselves
+pub(crate) struct TextureSize(pub u32);
+
+impl CgfxCollectionValue for TextureSize {
+    fn read_dict_value<R: Read + Seek>(reader: &mut R) -> Result<Self> {
+        let start = reader.stream_position()?;
+        let discriminant = reader.read_u32::<LittleEndian>()?;
+        CgfxTextureCommon::read(reader)?;
+        
+        let image_count = match TextureType::try_from(discriminant) {
+            Ok(TextureType::Cube) => 6,
+            Ok(TextureType::Image) => 1,
+            Err(_) => {
+                // finding the images of unknown textures needs their image data anyway
+                reader.seek(SeekFrom::Start(start))?;
+                let texture = CgfxTexture::from_reader(reader)?;
+                
+                let size = texture.images().iter()
+                    .filter(|image| !image.is_render_target())
+                    .map(|image| image.buffer_length)
+                    .sum();
+                
+                return Ok(Self(size));
+            },
+        };
+        
+        let mut size = 0;
+        
+        for _ in 0..image_count {
+            let Some(pointer) = Pointer::read(reader)? else {
+                continue;
+            };
+            
+            scoped_reader_pos!(reader);
+            reader.seek(SeekFrom::Current(i64::from(pointer) - 4))?;
+            
+            let data = ImageData::read(reader)?;
+            if data.buffer_pointer.is_some() {
+                size += data.buffer_length;
+            }
+        }
+        
+        Ok(Self(size))
+    }
+    
+    fn write_dict_value<W: Write + Seek>(&self, _writer: &mut W, _ctx: &mut WriteContext) -> Result<()> {
+        bail!("Texture sizes can not be written")
+    }
 }
 
 fn image_data<R: Read + Seek>(reader: &mut R) -> Result<Option<ImageData>> {
@@ -130,11 +537,8 @@ fn image_data<R: Read + Seek>(reader: &mut R) -> Result<Option<ImageData>> {
             reader.seek(SeekFrom::Current(i64::from(pointer) - 4))?;
             
             let mut data = ImageData::read(reader)?;
-            reader.seek(SeekFrom::Start(data.buffer_pointer.unwrap().into()))?;
-            
-            let mut image_bytes: Vec<u8> = vec![0; data.buffer_length.try_into()?];
-            reader.read_exact(&mut image_bytes)?;
-            data.image_bytes = image_bytes;
+            limits::check_texture_dimensions(data.width, data.height)?;
+            read_image_bytes(reader, &mut data)?;
             
             Ok::<ImageData, Error>(data)
         })
@@ -143,56 +547,167 @@ fn image_data<R: Read + Seek>(reader: &mut R) -> Result<Option<ImageData>> {
     Ok(data)
 }
 
+fn read_image_bytes<R: Read + Seek>(reader: &mut R, data: &mut ImageData) -> Result<()> {
+    if let Some(buffer_pointer) = data.buffer_pointer {
+        reader.seek(SeekFrom::Start(buffer_pointer.into()))?;
+        
+        data.image_bytes = read_shared_bytes(reader, data.buffer_length.try_into()?)?;
+    } else {
+        data.render_target = true;
+    }
+    
+    Ok(())
+}
+
+// unknown fields of unknown texture types can't be longer than this
+const MAX_UNKNOWN_FIELDS_LENGTH: u64 = 0x100;
+
+// textures of unknown types are assumed to be laid out like image and cube textures, with
+// a pointer to the image data of every layer after the common fields. pointers are read
+// until one is null or doesn't point to image data that matches the texture, and the
+// bytes between them and the first image data are kept as the type's own fields
+fn read_unknown_texture<R: Read + Seek>(reader: &mut R, texture_type: u32, common: &CgfxTextureCommon) -> Result<UnknownTexture> {
+    let mut images = Vec::new();
+    let mut first_image_offset: Option<u64> = None;
+    
+    loop {
+        let position = reader.stream_position()?;
+        
+        let image = (|| {
+            let pointer = Pointer::read(reader).ok()??;
+            let offset = position.checked_add(u64::from(pointer))?;
+            
+            scoped_reader_pos!(reader);
+            reader.seek(SeekFrom::Start(offset)).ok()?;
+            
+            // check everything before reading the image bytes, so that random data can't
+            // make it allocate arbitrary amounts of memory
+            let image = ImageData::read(reader).ok()?;
+            let expected_length = common.texture_format.buffer_size(common.width, common.height, common.mipmap_size.max(1));
+            let matches = image.width == common.width && image.height == common.height
+                && image.bits_per_pixel == common.texture_format.get_bpp()
+                && (image.buffer_pointer.is_none() || image.buffer_length as usize == expected_length);
+            
+            matches.then_some((image, offset))
+        })();
+        
+        let Some((mut image, offset)) = image else {
+            reader.seek(SeekFrom::Start(position))?;
+            break;
+        };
+        
+        {
+            scoped_reader_pos!(reader);
+            read_image_bytes(reader, &mut image)?;
+        }
+        
+        images.push(image);
+        first_image_offset = Some(first_image_offset.map_or(offset, |first| first.min(offset)));
+    }
+    
+    let fields_start = reader.stream_position()?;
+    let fields_length = first_image_offset
+        .and_then(|offset| offset.checked_sub(fields_start))
+        .filter(|&length| length <= MAX_UNKNOWN_FIELDS_LENGTH)
+        .unwrap_or(0);
+    
+    let mut fields = vec![0; fields_length.try_into()?];
+    reader.read_exact(&mut fields)?;
+    
+    Ok(UnknownTexture { texture_type, images, fields })
+}
+
+// pointers to every image, followed by the fields and then the image data they point to
+fn write_image_list<'a, W: Write + Seek>(writer: &mut W, ctx: &mut WriteContext,
+    images: impl ExactSizeIterator<Item = &'a ImageData>, fields: &[u8]) -> Result<()>
+{
+    let pointers_offset = Pointer::current(writer)?;
+    
+    for _ in 0..images.len() {
+        writer.write_u32::<LittleEndian>(0)?;
+    }
+    
+    writer.write_all(fields)?;
+    
+    for (i, image) in images.enumerate() {
+        let pointer_offset = pointers_offset + u32::try_from(i * 4)?;
+        let current_offset = Pointer::current(writer)?;
+        write_at_pointer(writer, pointer_offset, (current_offset - pointer_offset).into())?;
+        
+        if !image.render_target {
+            ctx.add_image_reference_to_current_end(current_offset + 12)?;
+            ctx.append_to_image_section(&image.image_bytes)?;
+        }
+        
+        image.write(writer)?;
+    }
+    
+    Ok(())
+}
+
+impl RuntimeHandles for CgfxTexture {
+    fn clear_runtime_handles(&mut self) {
+        self.metadata_mut().texture_obj.clear_runtime_handles();
+    }
+}
+
 impl CgfxTexture {
     pub fn from_reader<R: Read + Seek>(reader: &mut R) -> Result<Self> {
-        let texture_type_discriminant = reader.read_u32::<LittleEndian>()?;
+        let discriminant = reader.read_u32::<LittleEndian>()?;
         
         let common = CgfxTextureCommon::read(reader)?;
+        limits::check_texture_dimensions(common.width, common.height)?;
         
-        let result = match texture_type_discriminant {
-            0x20000009 => CgfxTexture::Cube(common,
-                Box::new(try_array_init(|_| image_data(reader).transpose().unwrap())?)),
-            0x20000011 => CgfxTexture::Image(common, image_data(reader)?),
-            
-            _ => return Err(Error::msg(format!("Invalid Texture discriminant {:x}", texture_type_discriminant)))
+        let result = match TextureType::try_from(discriminant) {
+            Ok(TextureType::Cube) => CgfxTexture::Cube(common,
+                Box::new(try_array_init(|_| image_data(reader)?.ok_or_else(|| anyhow!("Cube texture is missing a face")))?)),
+            Ok(TextureType::Image) => CgfxTexture::Image(common, image_data(reader)?),
+            Err(_) => {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(texture = common.cgfx_object_header.name.as_deref(), discriminant,
+                    "Unknown texture type");
+                
+                let unknown = read_unknown_texture(reader, discriminant, &common)?;
+                CgfxTexture::Unknown(common, unknown)
+            },
         };
         
         Ok(result)
     }
     
     pub fn to_writer<W: Write + Seek>(&self, writer: &mut W, ctx: &mut WriteContext) -> Result<()> {
-        // write discriminant
-        let discriminant: u32 = match self {
-            CgfxTexture::Cube(_, _) => 0x20000009,
-            CgfxTexture::Image(_, _) => 0x20000011,
-        };
+        let name = self.metadata().cgfx_object_header.name.as_deref();
         
-        writer.write_u32::<LittleEndian>(discriminant)?;
+        match ctx.options().mipmap_policy(name) {
+            MipmapPolicy::Keep => self.write_texture(writer, ctx),
+            policy => {
+                let mut texture = self.clone();
+                texture.apply_mipmap_policy(policy)?;
+                texture.write_texture(writer, ctx)
+            },
+        }
+    }
+    
+    fn write_texture<W: Write + Seek>(&self, writer: &mut W, ctx: &mut WriteContext) -> Result<()> {
+        // write discriminant
+        writer.write_u32::<LittleEndian>(self.discriminant())?;
         
         // write common stuff
-        let common = match self {
-            CgfxTexture::Cube(common, _) => common,
-            CgfxTexture::Image(common, _) => common,
-        };
+        let common = self.metadata();
         
-        let common_offset = Pointer::current(writer)?;
-        let name_offset = common_offset + 8;
-        assert!(common.cgfx_object_header.metadata_pointer.is_none());
+        add_object_header_name(writer, &common.cgfx_object_header, ctx)?;
         
-        if let Some(name) = &common.cgfx_object_header.name {
-            ctx.add_string(name)?;
-            ctx.add_string_reference(name_offset, name.clone());
-        }
-        
-        common.write(writer)?;
+        common.write_args(writer, (ctx.options().preserve_runtime_handles,))?;
         
         // write texture specific stuff
         match self {
-            CgfxTexture::Cube(_, _images) => todo!(),
+            CgfxTexture::Cube(_, images) => write_image_list(writer, ctx, images.iter(), &[])?,
             CgfxTexture::Image(_, image) => {
-                writer.write_u32::<LittleEndian>(4)?;
+                // image data follows directly after the pointer, null if there is none
+                writer.write_u32::<LittleEndian>(if image.is_some() { 4 } else { 0 })?;
                 
-                if let Some(image) = image {
+                // render targets keep their null buffer pointer
+                if let Some(image) = image.as_ref().filter(|image| !image.render_target) {
                     // make sure image.buffer_pointer gets updated
                     let current_offset = Pointer::current(writer)?;
                     ctx.add_image_reference_to_current_end(current_offset + 12)?;
@@ -202,15 +717,48 @@ impl CgfxTexture {
                 // when are they serialized? here or after the textures in general?
                 image.write(writer)?;
             },
+            // same layout as it was read with, see read_unknown_texture
+            CgfxTexture::Unknown(_, unknown) => write_image_list(writer, ctx, unknown.images.iter(), &unknown.fields)?,
         }
         
         Ok(())
     }
     
+    /// None for textures of unknown types
+    pub fn texture_type(&self) -> Option<TextureType> {
+        match self {
+            CgfxTexture::Cube(_, _) => Some(TextureType::Cube),
+            CgfxTexture::Image(_, _) => Some(TextureType::Image),
+            CgfxTexture::Unknown(_, _) => None,
+        }
+    }
+    
+    /// Raw type discriminant the texture gets written with
+    pub fn discriminant(&self) -> u32 {
+        match self {
+            CgfxTexture::Unknown(_, unknown) => unknown.texture_type,
+            _ => self.texture_type().unwrap().into(),
+        }
+    }
+    
+    /// Issues of all images of the texture, see [`ImageData::validate`]
+    pub fn validate_images(&self) -> Vec<ImageDataIssue> {
+        self.images().into_iter().flat_map(ImageData::validate).collect()
+    }
+    
+    pub fn images(&self) -> Vec<&ImageData> {
+        match self {
+            CgfxTexture::Image(_, image) => image.iter().collect(),
+            CgfxTexture::Cube(_, images) => images.iter().collect(),
+            CgfxTexture::Unknown(_, unknown) => unknown.images.iter().collect(),
+        }
+    }
+    
     pub fn metadata(&self) -> &CgfxTextureCommon {
         match self {
             CgfxTexture::Image(common, _) => common,
             CgfxTexture::Cube(common, _) => common,
+            CgfxTexture::Unknown(common, _) => common,
         }
     }
     
@@ -218,9 +766,343 @@ impl CgfxTexture {
         match self {
             CgfxTexture::Image(common, _) => common,
             CgfxTexture::Cube(common, _) => common,
+            CgfxTexture::Unknown(common, _) => common,
         }
     }
     
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all,
+        fields(name = self.metadata().cgfx_object_header.name.as_deref())))]
+    pub fn decode(&self) -> Result<DecodedTexture> {
+        let common = self.metadata();
+        ensure!(!self.is_render_target(), "Texture {:?} is a render target and has no image data",
+            common.cgfx_object_header.name);
+        
+        let images = self.images().into_iter()
+            .map(|image| decode_swizzled_buffer(&image.image_bytes, common.texture_format, image.width, image.height))
+            .collect::<Result<Vec<Vec<RgbaColor>>>>()?;
+        
+        Ok(DecodedTexture {
+            name: common.cgfx_object_header.name.clone(),
+            width: common.width,
+            height: common.height,
+            images,
+        })
+    }
+    
+    /// Decodes a thumbnail of the texture that is at most `max_size` pixels wide and high,
+    /// unless that would make its shorter side less than a pixel.
+    /// Only the largest mipmap that fits gets decoded, so this is a lot faster than
+    /// [`Self::decode`] for textures with mipmaps. If no mipmap fits, the smallest one
+    /// gets decoded and downsampled
+    pub fn decode_preview(&self, max_size: u32) -> Result<DecodedTexture> {
+        let common = self.metadata();
+        let format = common.texture_format;
+        ensure!(!self.is_render_target(), "Texture {:?} is a render target and has no image data",
+            common.cgfx_object_header.name);
+        ensure!(max_size > 0, "Preview size can not be zero");
+        
+        let images = self.images();
+        let available_bytes = images.iter().map(|image| image.image_bytes.len()).min().unwrap_or(0);
+        
+        // every level that was stored and that the swizzling can handle
+        let levels = (0..common.mipmap_size.max(1))
+            .take_while(|&level| {
+                let (width, height) = mipmap_dimensions(common.width, common.height, level);
+                level == 0 || width >= MIN_MIPMAP_SIZE && height >= MIN_MIPMAP_SIZE
+                    && format.buffer_size(common.width, common.height, level + 1) <= available_bytes
+            })
+            .count() as u32;
+        
+        let level = (0..levels)
+            .find(|&level| {
+                let (width, height) = mipmap_dimensions(common.width, common.height, level);
+                width.max(height) <= max_size
+            })
+            .unwrap_or(levels - 1);
+        
+        let offset = format.buffer_size(common.width, common.height, level);
+        let (mut width, mut height) = mipmap_dimensions(common.width, common.height, level);
+        
+        let mut images = images.into_iter()
+            .map(|image| decode_swizzled_buffer(image.image_bytes.get(offset..).unwrap_or_default(), format, width, height))
+            .collect::<Result<Vec<Vec<RgbaColor>>>>()?;
+        
+        while width.max(height) > max_size && width >= 2 && height >= 2 {
+            images = images.iter().map(|pixels| downsample(pixels, width, height)).collect();
+            (width, height) = (width / 2, height / 2);
+        }
+        
+        Ok(DecodedTexture {
+            name: common.cgfx_object_header.name.clone(),
+            width,
+            height,
+            images,
+        })
+    }
+    
+    /// Encodes `pixels` into a new texture with a single image
+    pub fn from_rgba(name: &str, width: u32, height: u32, format: PicaTextureFormat, pixels: &[RgbaColor]) -> Result<Self> {
+        ensure!(format.support().encode, "Encoding format {:?} is not implemented yet", format);
+        
+        let image_bytes = encode_swizzled_buffer(pixels, format, width, height)?;
+        Self::from_swizzled(name, width, height, format, 1, image_bytes)
+    }
+    
+    /// New texture with a single image made of already swizzled `image_bytes`, which
+    /// contain `mip_levels` levels
+    pub fn from_swizzled(name: &str, width: u32, height: u32, format: PicaTextureFormat, mip_levels: u32,
+        image_bytes: Vec<u8>) -> Result<Self>
+    {
+        ensure!(width != 0 && height != 0 && width.is_multiple_of(TILE_SIZE) && height.is_multiple_of(TILE_SIZE),
+            "Texture size {width}x{height} has to be a multiple of {TILE_SIZE}");
+        ensure!(mip_levels != 0, "Texture needs at least one mip level");
+        
+        let expected_length = format.buffer_size(width, height, mip_levels);
+        ensure!(image_bytes.len() == expected_length,
+            "Expected {expected_length} bytes for a {width}x{height} {format:?} texture with {mip_levels} mip levels, got {}",
+            image_bytes.len());
+        
+        let (gl_format, gl_type) = gl_format_and_type(format)?;
+        
+        let common = CgfxTextureCommon {
+            cgfx_object_header: CgfxObjectHeader {
+                magic: "TXOB".to_string(),
+                revision: 0,
+                name: Some(name.to_string()),
+                metadata_count: 0,
+                metadata_pointer: None,
+                metadata: None,
+            },
+            height,
+            width,
+            gl_format,
+            gl_type,
+            mipmap_size: mip_levels,
+            texture_obj: RuntimeHandle::default(),
+            location_flag: 0,
+            texture_format: format,
+        };
+        
+        Ok(CgfxTexture::Image(common, Some(ImageData::new(width, height, format, image_bytes)?)))
+    }
+    
+    /// Replaces the image of a texture with newly encoded `pixels`, keeping
+    /// the rest of its metadata
+    pub fn set_rgba(&mut self, width: u32, height: u32, format: PicaTextureFormat, pixels: &[RgbaColor]) -> Result<()> {
+        let CgfxTexture::Image(common, image) = self else {
+            bail!("Replacing the images of cube textures is not supported");
+        };
+        
+        ensure!(format.support().encode, "Encoding format {:?} is not implemented yet", format);
+        let (gl_format, gl_type) = gl_format_and_type(format)?;
+        
+        let image_bytes = encode_swizzled_buffer(pixels, format, width, height)?;
+        
+        common.width = width;
+        common.height = height;
+        common.gl_format = gl_format;
+        common.gl_type = gl_type;
+        common.mipmap_size = 1;
+        common.texture_format = format;
+        
+        let memory_area = image.as_ref().map_or(MemoryArea::None, |image| image.memory_area);
+        let mut new_image = ImageData::new(width, height, format, image_bytes)?;
+        new_image.memory_area = memory_area;
+        
+        *image = Some(new_image);
+        Ok(())
+    }
+    
+    pub fn is_render_target(&self) -> bool {
+        self.images().iter().any(|image| image.is_render_target())
+    }
+    
+    /// The texture's format and the one its `gl_format` and `gl_type` stand for, if they
+    /// differ. Textures whose OpenGL format and type don't stand for any format, like
+    /// zero for both, never disagree
+    pub fn format_mismatch(&self) -> Option<FormatMismatch> {
+        let common = self.metadata();
+        let gl_format = PicaTextureFormat::from_gl_format_and_type(common.gl_format, common.gl_type)?;
+        
+        (gl_format != common.texture_format).then_some(FormatMismatch {
+            texture_format: common.texture_format,
+            gl_format,
+        })
+    }
+    
+    /// Switches the texture to the format `policy` trusts if its formats disagree, returning
+    /// the new format if it changed. `gl_format` and `gl_type` are left as they are
+    pub fn resolve_format_mismatch(&mut self, policy: FormatPolicy) -> Option<PicaTextureFormat> {
+        let mismatch = self.format_mismatch()?;
+        
+        let format = match policy {
+            FormatPolicy::TrustTextureFormat => return None,
+            FormatPolicy::TrustGlFormat => mismatch.gl_format,
+            FormatPolicy::MatchBufferSize => {
+                let common = self.metadata();
+                let image = self.images().into_iter().find(|image| !image.is_render_target())?;
+                
+                let buffer_size = |format: PicaTextureFormat|
+                    format.buffer_size(common.width, common.height, common.mipmap_size.max(1));
+                
+                if buffer_size(mismatch.texture_format) != image.image_bytes.len()
+                    && buffer_size(mismatch.gl_format) == image.image_bytes.len()
+                {
+                    mismatch.gl_format
+                } else {
+                    return None;
+                }
+            },
+        };
+        
+        self.metadata_mut().texture_format = format;
+        for image in self.images_mut() {
+            image.bits_per_pixel = format.get_bpp();
+        }
+        
+        Some(format)
+    }
+    
+    /// Recomputes the fields of all images that are derived from the format, the size
+    /// and the image bytes, so that the texture can be written after editing it directly
+    pub fn normalize(&mut self) -> Result<()> {
+        let common = self.metadata_mut();
+        let format = common.texture_format;
+        let (width, height) = (common.width, common.height);
+        
+        for image in self.images_mut() {
+            image.width = width;
+            image.height = height;
+            image.bits_per_pixel = format.get_bpp();
+            
+            // render targets don't have any image bytes to count
+            if !image.is_render_target() {
+                image.buffer_length = image.image_bytes.len().try_into()?;
+            }
+        }
+        
+        Ok(())
+    }
+    
+    pub fn images_mut(&mut self) -> Vec<&mut ImageData> {
+        match self {
+            CgfxTexture::Image(_, image) => image.iter_mut().collect(),
+            CgfxTexture::Cube(_, images) => images.iter_mut().collect(),
+            CgfxTexture::Unknown(_, unknown) => unknown.images.iter_mut().collect(),
+        }
+    }
+    
+    /// Re-encodes every image of the texture including its mipmaps into `format`,
+    /// keeping the rest of its metadata. Unlike [`Self::set_rgba`], this also works for
+    /// cube textures
+    pub fn recompress(&mut self, format: PicaTextureFormat, quality: EncodeQuality) -> Result<()> {
+        let common = self.metadata().clone();
+        let old_format = common.texture_format;
+        let levels = common.mipmap_size.max(1);
+        let options = EncodeOptions { quality, ..Default::default() };
+        
+        ensure!(old_format.support().decode, "Decoding format {old_format:?} is not implemented yet");
+        ensure!(format.support().encode, "Encoding format {format:?} is not implemented yet");
+        let (gl_format, gl_type) = gl_format_and_type(format)?;
+        
+        for image in self.images_mut() {
+            if image.is_render_target() {
+                continue;
+            }
+            
+            ensure!(image.image_bytes.len() >= old_format.buffer_size(common.width, common.height, levels),
+                "Texture {:?} is missing image data", common.cgfx_object_header.name);
+            
+            let mut image_bytes = Vec::with_capacity(format.buffer_size(common.width, common.height, levels));
+            
+            for level in 0..levels {
+                let offset = old_format.buffer_size(common.width, common.height, level);
+                let (width, height) = mipmap_dimensions(common.width, common.height, level);
+                
+                let pixels = decode_swizzled_buffer(&image.image_bytes[offset..], old_format, width, height)?;
+                image_bytes.extend(encode_swizzled_buffer_with_options(&pixels, format, width, height, &options)?);
+            }
+            
+            image.set_image_bytes(image_bytes)?;
+            image.bits_per_pixel = format.get_bpp();
+        }
+        
+        let common = self.metadata_mut();
+        common.texture_format = format;
+        common.gl_format = gl_format;
+        common.gl_type = gl_type;
+        
+        Ok(())
+    }
+    
+    pub fn apply_mipmap_policy(&mut self, policy: MipmapPolicy) -> Result<()> {
+        match policy {
+            MipmapPolicy::Keep => Ok(()),
+            MipmapPolicy::Strip => self.strip_mipmaps(),
+            MipmapPolicy::Regenerate => self.generate_mipmaps(),
+        }
+    }
+    
+    /// Removes all mipmaps, only keeping the full size image
+    pub fn strip_mipmaps(&mut self) -> Result<()> {
+        let common = self.metadata().clone();
+        let size = common.texture_format.image_size(common.width, common.height);
+        
+        for image in self.images_mut() {
+            if image.is_render_target() {
+                continue;
+            }
+            
+            ensure!(image.image_bytes.len() >= size, "Texture {:?} is missing image data", common.cgfx_object_header.name);
+            image.set_image_bytes(image.image_bytes[..size].to_vec())?;
+        }
+        
+        self.metadata_mut().mipmap_size = 1;
+        Ok(())
+    }
+    
+    /// Replaces all mipmaps with ones generated from the full size image, halving
+    /// the size until either side would get smaller than 8 pixels
+    pub fn generate_mipmaps(&mut self) -> Result<()> {
+        let common = self.metadata().clone();
+        let format = common.texture_format;
+        
+        ensure!(format.support().generate_mipmaps, "Generating mipmaps for format {format:?} is not supported");
+        
+        let mut level_count = 1;
+        while mipmap_dimensions(common.width, common.height, level_count).0 >= MIN_MIPMAP_SIZE
+            && mipmap_dimensions(common.width, common.height, level_count).1 >= MIN_MIPMAP_SIZE
+        {
+            level_count += 1;
+        }
+        
+        for image in self.images_mut() {
+            if image.is_render_target() {
+                continue;
+            }
+            
+            let size = format.image_size(common.width, common.height);
+            ensure!(image.image_bytes.len() >= size, "Texture {:?} is missing image data", common.cgfx_object_header.name);
+            
+            let mut pixels = decode_swizzled_buffer(&image.image_bytes[..size], format, common.width, common.height)?;
+            let mut image_bytes = Vec::with_capacity(format.buffer_size(common.width, common.height, level_count));
+            image_bytes.extend_from_slice(&image.image_bytes[..size]);
+            
+            for level in 1..level_count {
+                let (width, height) = mipmap_dimensions(common.width, common.height, level - 1);
+                pixels = downsample(&pixels, width, height);
+                
+                let (width, height) = mipmap_dimensions(common.width, common.height, level);
+                image_bytes.extend(encode_swizzled_buffer(&pixels, format, width, height)?);
+            }
+            
+            image.set_image_bytes(image_bytes)?;
+        }
+        
+        self.metadata_mut().mipmap_size = level_count;
+        Ok(())
+    }
+    
     pub fn size(&self) -> u32 {
         match self {
             CgfxTexture::Image(_, image_data) => {
@@ -232,7 +1114,110 @@ impl CgfxTexture {
             },
             CgfxTexture::Cube(_, vec) =>
                 vec.iter().map(|image| image.image_bytes.len() as u32).sum(),
+            CgfxTexture::Unknown(_, unknown) =>
+                unknown.images.iter().map(|image| image.image_bytes.len() as u32).sum(),
+        }
+    }
+}
+
+pub type TextureDict = CgfxDict<CgfxTexture>;
+
+impl TextureDict {
+    /// Amount of bytes all textures in this dict take up once loaded onto the GPU
+    pub fn total_vram(&self) -> u32 {
+        self.values().map(CgfxTexture::size).sum()
+    }
+    
+    pub fn with_format(&self, format: PicaTextureFormat) -> impl Iterator<Item = &CgfxTexture> {
+        self.values().filter(move |texture| texture.metadata().texture_format == format)
+    }
+    
+    /// Copy of the dict with the mipmap policies of `options` applied to every texture,
+    /// so that writing it with [`MipmapPolicy::Keep`] gives the same bytes as writing
+    /// this dict with `options`. With the `parallel` feature, the textures get
+    /// re-encoded on one thread per core
+    pub fn with_mipmap_policies(&self, options: &WriteOptions) -> Result<TextureDict> {
+        let apply = |node: &mut CgfxNode<CgfxTexture>| -> Result<()> {
+            if let Some(texture) = &mut node.value {
+                texture.apply_mipmap_policy(options.mipmap_policy(texture.metadata().cgfx_object_header.name.as_deref()))?;
+            }
+            
+            Ok(())
+        };
+        
+        let mut dict = self.clone();
+        
+        #[cfg(feature = "parallel")]
+        {
+            let workers = crate::batch::default_worker_count();
+            let chunk_size = dict.nodes.len().div_ceil(workers).max(1);
+            
+            std::thread::scope(|scope| {
+                let handles: Vec<_> = dict.nodes.chunks_mut(chunk_size)
+                    .map(|chunk| scope.spawn(|| chunk.iter_mut().try_for_each(apply)))
+                    .collect();
+                
+                handles.into_iter()
+                    .try_for_each(|handle| handle.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)))
+            })?;
+        }
+        
+        #[cfg(not(feature = "parallel"))]
+        dict.nodes.iter_mut().try_for_each(apply)?;
+        
+        Ok(dict)
+    }
+    
+    /// Re-encodes every texture `config` selects, see [`RecompressConfig::settings_for`].
+    /// Render targets are left alone. With the `parallel` feature, the textures get
+    /// re-encoded on one thread per core
+    pub fn recompress(&mut self, config: &RecompressConfig) -> Result<TextureRecompression> {
+        type Recompressed = Option<(String, PicaTextureFormat, PicaTextureFormat, u64, u64)>;
+        
+        let apply = |node: &mut CgfxNode<CgfxTexture>| -> Result<Recompressed> {
+            let (Some(name), Some(texture)) = (&node.name, &mut node.value) else {
+                return Ok(None);
+            };
+            
+            let old_format = texture.metadata().texture_format;
+            let Some((format, quality)) = config.settings_for(name, old_format).filter(|_| !texture.is_render_target()) else {
+                return Ok(None);
+            };
+            
+            let bytes_before = u64::from(texture.size());
+            texture.recompress(format, quality)?;
+            
+            Ok(Some((name.clone(), old_format, format, bytes_before, u64::from(texture.size()))))
+        };
+        
+        #[cfg(feature = "parallel")]
+        let results: Vec<Recompressed> = {
+            let workers = crate::batch::default_worker_count();
+            let chunk_size = self.nodes.len().div_ceil(workers).max(1);
+            
+            std::thread::scope(|scope| {
+                let handles: Vec<_> = self.nodes.chunks_mut(chunk_size)
+                    .map(|chunk| scope.spawn(|| chunk.iter_mut().map(apply).collect::<Result<Vec<_>>>()))
+                    .collect();
+                
+                handles.into_iter()
+                    .map(|handle| handle.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)))
+                    .collect::<Result<Vec<_>>>()
+            })?.into_iter().flatten().collect()
+        };
+        
+        #[cfg(not(feature = "parallel"))]
+        let results: Vec<Recompressed> = self.nodes.iter_mut().map(apply).collect::<Result<_>>()?;
+        
+        let mut recompression = TextureRecompression::default();
+        
+        for (name, old_format, format, bytes_before, bytes_after) in results.into_iter().flatten() {
+            recompression.recompressed.push((name, old_format, format));
+            recompression.bytes_before += bytes_before;
+            recompression.bytes_after += bytes_after;
         }
+        
+        Ok(recompression)
     }
 }
 
@@ -0,0 +1,355 @@
+// importing textures from the container formats texture artists usually deliver,
+// and from the bare swizzled payloads some tools exchange instead
+use std::io::{Cursor, Read};
+
+use anyhow::{anyhow, bail, ensure, Result};
+use byteorder::{LittleEndian, ReadBytesExt};
+
+use crate::{
+    image_codec::RgbaColor,
+    limits,
+    texture::{CgfxTexture, PicaTextureFormat},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RgbaImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<RgbaColor>,
+}
+
+const DDS_MAGIC: &[u8; 4] = b"DDS ";
+const KTX2_MAGIC: &[u8; 12] = b"\xABKTX 20\xBB\r\n\x1A\n";
+
+/// Decodes a DDS or KTX2 file (detected by its magic number) and encodes its
+/// first image into a new texture of the given format
+pub fn import_texture(bytes: &[u8], name: &str, format: PicaTextureFormat) -> Result<CgfxTexture> {
+    let image = decode_image(bytes)?;
+    CgfxTexture::from_rgba(name, image.width, image.height, format, &image.pixels)
+}
+
+/// Size and format of a bare swizzled texture payload, as exchanged by tools that
+/// don't use any container. None of it can be told from the payload itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawTextureLayout {
+    pub width: u32,
+    pub height: u32,
+    pub format: PicaTextureFormat,
+    /// Length of the tool's own header in front of the payload, which gets skipped
+    pub header_length: usize,
+}
+
+/// Wraps a raw swizzled payload into a new texture without decoding it, so that it
+/// also works for formats this library can't encode. The amount of mip levels is
+/// inferred from the length of the payload
+pub fn import_raw_texture(bytes: &[u8], name: &str, layout: RawTextureLayout) -> Result<CgfxTexture> {
+    let RawTextureLayout { width, height, format, header_length } = layout;
+    
+    let payload = bytes.get(header_length..)
+        .ok_or_else(|| anyhow!("Raw texture is {} bytes long, which is shorter than its {header_length} byte header", bytes.len()))?;
+    
+    // every level makes the payload longer, so at most one amount of levels fits
+    let mip_levels = (1..=16)
+        .find(|&levels| format.buffer_size(width, height, levels) == payload.len())
+        .ok_or_else(|| anyhow!("Raw texture payload of {} bytes doesn't match a {width}x{height} {format:?} texture with any amount of mip levels, \
+            expected {} bytes for a single level", payload.len(), format.image_size(width, height)))?;
+    
+    CgfxTexture::from_swizzled(name, width, height, format, mip_levels, payload.to_vec())
+}
+
+/// Swizzled image bytes of `texture` including all mip levels, the reverse of
+/// [`import_raw_texture`]. The layout has no header, prepend it where a tool needs one
+pub fn export_raw_texture(texture: &CgfxTexture) -> Result<(Vec<u8>, RawTextureLayout)> {
+    let common = texture.metadata();
+    
+    let CgfxTexture::Image(_, Some(image)) = texture else {
+        bail!("Only textures with a single image can be exported as a raw payload");
+    };
+    ensure!(!image.is_render_target(), "Texture {:?} is a render target and has no image data",
+        common.cgfx_object_header.name);
+    
+    let layout = RawTextureLayout {
+        width: common.width,
+        height: common.height,
+        format: common.texture_format,
+        header_length: 0,
+    };
+    
+    Ok((image.image_bytes.to_vec(), layout))
+}
+
+pub fn decode_image(bytes: &[u8]) -> Result<RgbaImage> {
+    if bytes.starts_with(DDS_MAGIC) {
+        decode_dds(bytes)
+    } else if bytes.starts_with(KTX2_MAGIC) {
+        decode_ktx2(bytes)
+    } else {
+        bail!("Unknown image container, only DDS and KTX2 are supported")
+    }
+}
+
+// layout of the pixels in a texture container, shared by DDS and KTX2
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SourceFormat {
+    // bits per pixel plus r, g, b, a masks
+    Masked(u32, [u32; 4]),
+    Bc1,
+    Bc2,
+    Bc3,
+}
+
+/// Decodes the top mip level of an uncompressed or BC1-BC3 (DXT1-DXT5) compressed DDS file
+pub fn decode_dds(bytes: &[u8]) -> Result<RgbaImage> {
+    ensure!(bytes.starts_with(DDS_MAGIC), "Invalid magic number for DDS file");
+    ensure!(bytes.len() >= 128, "DDS file is too short");
+    
+    let mut reader = Cursor::new(&bytes[4..]);
+    
+    let header_size = reader.read_u32::<LittleEndian>()?;
+    ensure!(header_size == 124, "Invalid DDS header size {header_size}");
+    
+    let _flags = reader.read_u32::<LittleEndian>()?;
+    let height = reader.read_u32::<LittleEndian>()?;
+    let width = reader.read_u32::<LittleEndian>()?;
+    
+    // pitch, depth, mip map count and 11 reserved words
+    reader.set_position(4 * 18);
+    
+    let _pixel_format_size = reader.read_u32::<LittleEndian>()?;
+    let pixel_format_flags = reader.read_u32::<LittleEndian>()?;
+    let mut four_cc = [0u8; 4];
+    reader.read_exact(&mut four_cc)?;
+    let bit_count = reader.read_u32::<LittleEndian>()?;
+    let mut masks = [0u32; 4];
+    reader.read_u32_into::<LittleEndian>(&mut masks)?;
+    
+    let mut data_offset = 128;
+    
+    let format = if pixel_format_flags & 0x4 != 0 {
+        match &four_cc {
+            b"DXT1" => SourceFormat::Bc1,
+            b"DXT2" | b"DXT3" => SourceFormat::Bc2,
+            b"DXT4" | b"DXT5" => SourceFormat::Bc3,
+            b"DX10" => {
+                ensure!(bytes.len() >= 148, "DDS file is too short");
+                data_offset = 148;
+                
+                let dxgi_format = u32::from_le_bytes(bytes[128..132].try_into()?);
+                
+                match dxgi_format {
+                    // R8G8B8A8_UNORM(_SRGB)
+                    28 | 29 => SourceFormat::Masked(32, [0xff, 0xff00, 0xff0000, 0xff000000]),
+                    // B8G8R8A8_UNORM(_SRGB)
+                    87 | 91 => SourceFormat::Masked(32, [0xff0000, 0xff00, 0xff, 0xff000000]),
+                    // BC1 to BC3, UNORM and SRGB
+                    71 | 72 => SourceFormat::Bc1,
+                    74 | 75 => SourceFormat::Bc2,
+                    77 | 78 => SourceFormat::Bc3,
+                    _ => bail!("Unsupported DXGI format {dxgi_format} in DDS file"),
+                }
+            },
+            _ => bail!("Unsupported DDS compression {:?}", String::from_utf8_lossy(&four_cc)),
+        }
+    } else {
+        // no alpha flag means the alpha mask is garbage
+        if pixel_format_flags & 0x3 == 0 {
+            masks[3] = 0;
+        }
+        
+        // luminance only stores its mask in the red mask
+        if pixel_format_flags & 0x20000 != 0 {
+            masks = [masks[0], masks[0], masks[0], masks[3]];
+        }
+        
+        SourceFormat::Masked(bit_count, masks)
+    };
+    
+    decode_source(&bytes[data_offset..], format, width, height)
+}
+
+/// Decodes the top mip level of the first layer of a KTX2 file. Supercompressed
+/// files are not supported.
+pub fn decode_ktx2(bytes: &[u8]) -> Result<RgbaImage> {
+    ensure!(bytes.starts_with(KTX2_MAGIC), "Invalid magic number for KTX2 file");
+    
+    let mut reader = Cursor::new(&bytes[12..]);
+    
+    let vk_format = reader.read_u32::<LittleEndian>()?;
+    let _type_size = reader.read_u32::<LittleEndian>()?;
+    let width = reader.read_u32::<LittleEndian>()?;
+    let height = reader.read_u32::<LittleEndian>()?.max(1);
+    let depth = reader.read_u32::<LittleEndian>()?;
+    let _layer_count = reader.read_u32::<LittleEndian>()?;
+    let face_count = reader.read_u32::<LittleEndian>()?;
+    let _level_count = reader.read_u32::<LittleEndian>()?;
+    let supercompression = reader.read_u32::<LittleEndian>()?;
+    
+    ensure!(depth <= 1, "3D textures are not supported");
+    ensure!(face_count == 1, "Cube map KTX2 files are not supported");
+    ensure!(supercompression == 0, "Supercompressed KTX2 files are not supported");
+    
+    // skip dfd, kvd and sgd offsets and lengths to get to the first level
+    reader.set_position(reader.position() + 4 * 4 + 8 * 2);
+    let level_offset: usize = reader.read_u64::<LittleEndian>()?.try_into()?;
+    let level_length: usize = reader.read_u64::<LittleEndian>()?.try_into()?;
+    
+    let level_end = level_offset.checked_add(level_length)
+        .ok_or_else(|| anyhow!("KTX2 level data is out of bounds"))?;
+    let level = bytes.get(level_offset..level_end)
+        .ok_or_else(|| anyhow!("KTX2 level data is out of bounds"))?;
+    
+    let format = match vk_format {
+        // R8G8B8A8_UNORM, R8G8B8A8_SRGB
+        37 | 43 => SourceFormat::Masked(32, [0xff, 0xff00, 0xff0000, 0xff000000]),
+        // B8G8R8A8_UNORM, B8G8R8A8_SRGB
+        44 | 50 => SourceFormat::Masked(32, [0xff0000, 0xff00, 0xff, 0xff000000]),
+        // R8G8B8_UNORM, R8G8B8_SRGB
+        23 | 29 => SourceFormat::Masked(24, [0xff, 0xff00, 0xff0000, 0]),
+        // BC1 RGB and RGBA, UNORM and SRGB
+        131..=134 => SourceFormat::Bc1,
+        135 | 136 => SourceFormat::Bc2,
+        137 | 138 => SourceFormat::Bc3,
+        _ => bail!("Unsupported Vulkan format {vk_format} in KTX2 file"),
+    };
+    
+    decode_source(level, format, width, height)
+}
+
+// the dimensions come straight from the file header, so they go through the parse
+// limits and get checked for overflow before anything gets allocated for them
+fn checked_size(width: u32, height: u32, bytes_per_unit: usize) -> Result<usize> {
+    (width as usize).checked_mul(height as usize)
+        .and_then(|count| count.checked_mul(bytes_per_unit))
+        .ok_or_else(|| anyhow!("Image of {width}x{height} pixels is too large"))
+}
+
+fn decode_source(data: &[u8], format: SourceFormat, width: u32, height: u32) -> Result<RgbaImage> {
+    limits::check_texture_dimensions(width, height)?;
+    
+    let pixels = match format {
+        SourceFormat::Masked(bit_count, masks) => decode_masked(data, bit_count, masks, width, height)?,
+        SourceFormat::Bc1 | SourceFormat::Bc2 | SourceFormat::Bc3 => decode_bc(data, format, width, height)?,
+    };
+    
+    Ok(RgbaImage {
+        width,
+        height,
+        pixels,
+    })
+}
+
+fn decode_masked(data: &[u8], bit_count: u32, masks: [u32; 4], width: u32, height: u32) -> Result<Vec<RgbaColor>> {
+    ensure!(matches!(bit_count, 8 | 16 | 24 | 32), "Unsupported bit count {bit_count}");
+    
+    let bytes_per_pixel = bit_count as usize / 8;
+    let pixel_count = checked_size(width, height, 1)?;
+    ensure!(data.len() >= checked_size(width, height, bytes_per_pixel)?, "Image data is too short");
+    
+    let channel = |raw: u32, mask: u32, default: u8| {
+        if mask == 0 {
+            return default;
+        }
+        
+        let value = ((raw & mask) >> mask.trailing_zeros()) as u64;
+        let max = (mask >> mask.trailing_zeros()) as u64;
+        
+        ((value * 255 + max / 2) / max) as u8
+    };
+    
+    let pixels = data.chunks_exact(bytes_per_pixel)
+        .take(pixel_count)
+        .map(|chunk| {
+            let mut raw_bytes = [0u8; 4];
+            raw_bytes[..bytes_per_pixel].copy_from_slice(chunk);
+            let raw = u32::from_le_bytes(raw_bytes);
+            
+            RgbaColor::new(
+                channel(raw, masks[0], 0),
+                channel(raw, masks[1], 0),
+                channel(raw, masks[2], 0),
+                channel(raw, masks[3], 0xFF),
+            )
+        })
+        .collect();
+    
+    Ok(pixels)
+}
+
+fn rgb565(raw: u16) -> [u32; 3] {
+    let r = (raw >> 11) as u32 & 0x1f;
+    let g = (raw >> 5) as u32 & 0x3f;
+    let b = raw as u32 & 0x1f;
+    
+    [(r << 3) | (r >> 2), (g << 2) | (g >> 4), (b << 3) | (b >> 2)]
+}
+
+fn decode_bc(data: &[u8], format: SourceFormat, width: u32, height: u32) -> Result<Vec<RgbaColor>> {
+    let block_size = if format == SourceFormat::Bc1 { 8 } else { 16 };
+    let blocks_x = width.div_ceil(4) as usize;
+    let blocks_y = height.div_ceil(4) as usize;
+    
+    ensure!(data.len() >= checked_size(width.div_ceil(4), height.div_ceil(4), block_size)?, "Image data is too short");
+    
+    let mut output = vec![RgbaColor::default(); checked_size(width, height, 1)?];
+    
+    for (block_index, block) in data.chunks_exact(block_size).take(blocks_x * blocks_y).enumerate() {
+        let (alpha_block, color_block) = block.split_at(block_size - 8);
+        
+        // color endpoints and the two interpolated colors
+        let color0 = u16::from_le_bytes([color_block[0], color_block[1]]);
+        let color1 = u16::from_le_bytes([color_block[2], color_block[3]]);
+        let [a, b] = [rgb565(color0), rgb565(color1)];
+        
+        let mut palette = [[a[0], a[1], a[2], 255], [b[0], b[1], b[2], 255], [0; 4], [0; 4]];
+        
+        if color0 > color1 || format != SourceFormat::Bc1 {
+            palette[2] = [(2 * a[0] + b[0]) / 3, (2 * a[1] + b[1]) / 3, (2 * a[2] + b[2]) / 3, 255];
+            palette[3] = [(a[0] + 2 * b[0]) / 3, (a[1] + 2 * b[1]) / 3, (a[2] + 2 * b[2]) / 3, 255];
+        } else {
+            palette[2] = [(a[0] + b[0]) / 2, (a[1] + b[1]) / 2, (a[2] + b[2]) / 2, 255];
+        }
+        
+        let color_indices = u32::from_le_bytes(color_block[4..8].try_into()?);
+        
+        // only used by bc3
+        let alpha0 = alpha_block.first().copied().unwrap_or(0) as u32;
+        let alpha1 = alpha_block.get(1).copied().unwrap_or(0) as u32;
+        let alpha_indices = alpha_block.get(2..8)
+            .map_or(0, |bytes| bytes.iter().rev().fold(0u64, |acc, byte| (acc << 8) | *byte as u64));
+        
+        for pixel in 0..16 {
+            let x = (block_index % blocks_x) * 4 + pixel % 4;
+            let y = (block_index / blocks_x) * 4 + pixel / 4;
+            
+            if x >= width as usize || y >= height as usize {
+                continue;
+            }
+            
+            let [r, g, b, mut alpha] = palette[(color_indices >> (pixel * 2)) as usize & 3];
+            
+            match format {
+                SourceFormat::Bc2 => {
+                    let nibble = (alpha_block[pixel / 2] >> ((pixel % 2) * 4)) & 0xF;
+                    alpha = nibble as u32 * 17;
+                },
+                SourceFormat::Bc3 => {
+                    let index = (alpha_indices >> (pixel * 3)) as u32 & 7;
+                    
+                    alpha = match index {
+                        0 => alpha0,
+                        1 => alpha1,
+                        _ if alpha0 > alpha1 => ((8 - index) * alpha0 + (index - 1) * alpha1) / 7,
+                        6 => 0,
+                        7 => 255,
+                        _ => ((6 - index) * alpha0 + (index - 1) * alpha1) / 5,
+                    };
+                },
+                _ => {},
+            }
+            
+            output[y * width as usize + x] = RgbaColor::new(r as u8, g as u8, b as u8, alpha as u8);
+        }
+    }
+    
+    Ok(output)
+}
@@ -13,7 +13,9 @@ const BLZ_SHIFT: u32 = 1;
 
 /// bits to check
 /// 
-///     ((((1 << BLZ_SHIFT) - 1) << (8 - BLZ_SHIFT)
+/// ```text
+/// ((((1 << BLZ_SHIFT) - 1) << (8 - BLZ_SHIFT)
+/// ```
 const BLZ_MASK: u32 = 0x80;
 
 /// max number of bytes to not encode
@@ -24,16 +26,20 @@ const BLZ_THRESHOLD_U32: u32 = 2;
 
 /// max lz offset (aka BLZ_N)
 /// 
-///     ((1 << 12) + 2)
+/// ```text
+/// ((1 << 12) + 2)
+/// ```
 const BLZ_MAX_OFFSET: usize = 0x1002;
 
 /// max coded (aka BLZ_F)
 /// 
-///     ((1 << 4) + BLZ_THRESHOLD)
+/// ```text
+/// ((1 << 4) + BLZ_THRESHOLD)
+/// ```
 const BLZ_MAX_CODED: usize = 0x12;
 
 pub fn blz_decode(input_buffer: &[u8]) -> Result<Vec<u8>> {
-    if input_buffer.len() % 4 != 0 {
+    if !input_buffer.len().is_multiple_of(4) {
         return Err(Error::msg("Input buffer has an invalid length (must be multiple of 4)"));
     }
     
@@ -44,7 +50,7 @@ pub fn blz_decode(input_buffer: &[u8]) -> Result<Vec<u8>> {
     // extracting basic information
     let input_buffer_length: u32 = input_buffer.len().try_into().unwrap();
     
-    let mut input_buffer_u32: Vec<u32> = iter::repeat(0).take(input_buffer.len() / 4).collect();
+    let mut input_buffer_u32: Vec<u32> = iter::repeat_n(0, input_buffer.len() / 4).collect();
     LittleEndian::read_u32_into(input_buffer, &mut input_buffer_u32);
     
     let result_size_increase = input_buffer_u32[input_buffer_u32.len() - 1];
@@ -66,9 +72,9 @@ pub fn blz_decode(input_buffer: &[u8]) -> Result<Vec<u8>> {
     let encoded_length_usize: usize = encoded_length.try_into().unwrap();
     let unencoded_length_usize: usize = unencoded_length.try_into().unwrap();
     
-    let result_size: usize = (input_buffer_length + result_size_increase)
-        .try_into()
-        .unwrap();
+    let result_size: usize = input_buffer_length.checked_add(result_size_increase)
+        .ok_or_else(|| Error::msg("Resulting file too large"))?
+        .try_into()?;
     ensure!(result_size <= RAW_MAXIM, "Resulting file too large");
     
     // start populating result with unencoded area
@@ -104,7 +110,7 @@ pub fn blz_decode(input_buffer: &[u8]) -> Result<Vec<u8>> {
                 break;
             }
             
-            result_buffer.push(encoded.read_u8().unwrap());
+            result_buffer.push(encoded.read_u8()?);
         } else {
             if encoded.position() + 1 == encoded_length.into() {
                 break;
@@ -113,12 +119,10 @@ pub fn blz_decode(input_buffer: &[u8]) -> Result<Vec<u8>> {
             let mut pos: usize = read_u8_as_usize(&mut encoded)? << 8 | read_u8_as_usize(&mut encoded)?;
             let len: usize = (pos >> 12) + BLZ_THRESHOLD + 1;
             
-            if result_buffer.len() + len > result_size {
-                panic!("Wrong decoded length");
-                // len = result_size;
-            }
+            ensure!(result_buffer.len() + len <= result_size, "Wrong decoded length");
             
             pos = (pos & 0xFFF) + 3;
+            ensure!(pos <= result_buffer.len(), "Back reference points before the start of the file");
             
             for _ in 0..len {
                 result_buffer.push(result_buffer[result_buffer.len() - pos]);
@@ -231,7 +235,7 @@ pub fn blz_encode(input_buffer: &mut [u8]) -> Result<Vec<u8>> {
         let size_increase = input_buffer_length - input_bytes_left - result_bytes_written;
         let mut header_length = 8;
         
-        while container_buffer.len() % 4 != 0 {
+        while !container_buffer.len().is_multiple_of(4) {
             container_buffer.push(0xFF);
             header_length += 1;
         }
@@ -249,7 +253,9 @@ pub fn blz_encode(input_buffer: &mut [u8]) -> Result<Vec<u8>> {
 ///
 /// Returns slice of search result in the form of
 /// 
-///     (found_length, found_position)
+/// ```text
+/// (found_length, found_position)
+/// ```
 fn search(input: &Cursor<&[u8]>, input_buffer: &[u8], prev_position_result: Option<u32>) -> (u32, Option<u32>) {
     let mut length_result: usize = BLZ_THRESHOLD;
     let mut position_result: Option<u32> = prev_position_result;
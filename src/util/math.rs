@@ -1,5 +1,9 @@
-use std::{io::{Read, Seek, Write}, mem::MaybeUninit};
+use std::{
+    io::{Read, Seek, Write},
+    ops::{Mul, Sub},
+};
 
+use anyhow::{ensure, Result};
 use binrw::{BinRead, BinResult, BinWrite, Endian};
 #[cfg(feature = "bytemuck")]
 use bytemuck::{Pod, Zeroable};
@@ -47,6 +51,82 @@ impl Vec3 {
     pub fn new(x: f32, y: f32, z: f32) -> Self {
         Vec3 { x, y, z }
     }
+    
+    pub fn length(self) -> f32 {
+        (self.x * self.x + self.y * self.y + self.z * self.z).sqrt()
+    }
+    
+    pub fn dot(self, other: Self) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+    
+    pub fn cross(self, other: Self) -> Self {
+        Vec3::new(
+            self.y * other.z - self.z * other.y,
+            self.z * other.x - self.x * other.z,
+            self.x * other.y - self.y * other.x,
+        )
+    }
+    
+    /// Same direction with a length of one, zero vectors stay zero
+    pub fn normalized(self) -> Self {
+        let length = self.length();
+        
+        if length > 0.0 {
+            self * (1.0 / length)
+        } else {
+            self
+        }
+    }
+}
+
+impl Mul<f32> for Vec3 {
+    type Output = Self;
+    
+    fn mul(self, rhs: f32) -> Self {
+        Vec3::new(self.x * rhs, self.y * rhs, self.z * rhs)
+    }
+}
+
+impl Sub for Vec3 {
+    type Output = Self;
+    
+    fn sub(self, rhs: Self) -> Self {
+        Vec3::new(self.x - rhs.x, self.y - rhs.y, self.z - rhs.z)
+    }
+}
+
+/// Size of one CGFX unit, for converting positions between a file and formats with
+/// a fixed unit like glTF, which uses meters. Games differ in what one unit means,
+/// many use centimeters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Units {
+    pub meters_per_unit: f32,
+}
+
+impl Units {
+    pub const METERS: Self = Self { meters_per_unit: 1.0 };
+    pub const CENTIMETERS: Self = Self { meters_per_unit: 0.01 };
+    
+    pub fn new(meters_per_unit: f32) -> Result<Self> {
+        ensure!(meters_per_unit > 0.0 && meters_per_unit.is_finite(), "Invalid unit size {meters_per_unit}");
+        Ok(Self { meters_per_unit })
+    }
+    
+    pub fn to_meters(self, value: Vec3) -> Vec3 {
+        value * self.meters_per_unit
+    }
+    
+    pub fn from_meters(self, value: Vec3) -> Vec3 {
+        value * (1.0 / self.meters_per_unit)
+    }
+}
+
+/// One CGFX unit is one meter, so nothing gets converted
+impl Default for Units {
+    fn default() -> Self {
+        Self::METERS
+    }
 }
 
 #[cfg(feature = "glam")]
@@ -95,7 +175,7 @@ impl From<Vec4> for glam::Vec4 {
 }
 
 // binrw matrix helper
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 #[repr(C)]
 pub struct SerializableMatrix<const R: usize, const C: usize> {
     data: [[f32; R]; C],
@@ -105,12 +185,11 @@ impl<const R: usize, const C: usize> BinRead for SerializableMatrix<R, C> {
     type Args<'a> = ();
 
     fn read_options<T: Read + Seek>(reader: &mut T, endian: Endian, _: Self::Args<'_>) -> BinResult<Self> {
-        // SAFETY: all zeroes is a valid bit pattern of floats
-        let mut data: [[f32; R]; C] = unsafe { MaybeUninit::zeroed().assume_init() };
+        let mut data: [[f32; R]; C] = [[0.0; R]; C];
         
-        for i in 0..C {
-            for j in 0..R {
-                data[i][j] = f32::read_options(reader, endian, ())?;
+        for column in &mut data {
+            for value in column {
+                *value = f32::read_options(reader, endian, ())?;
             }
         }
         
@@ -132,34 +211,146 @@ pub type Mat3 = SerializableMatrix<3, 3>;
 pub type Mat3x4 = SerializableMatrix<3, 4>;
 pub type Mat4 = SerializableMatrix<4, 4>;
 
+impl Mat3 {
+    pub const IDENTITY: Self = Self {
+        data: [
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, 0.0, 1.0],
+        ],
+    };
+}
+
+// affine transformation, the last column is the translation
+impl Mat3x4 {
+    pub const IDENTITY: Self = Self {
+        data: [
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, 0.0, 1.0],
+            [0.0, 0.0, 0.0],
+        ],
+    };
+    
+    /// Builds a transformation that scales, then rotates around X, Y and Z (in radians)
+    /// and finally translates, the way bones are transformed
+    pub fn from_srt(scale: Vec3, rotation: Vec3, translation: Vec3) -> Self {
+        let (sx, cx) = rotation.x.sin_cos();
+        let (sy, cy) = rotation.y.sin_cos();
+        let (sz, cz) = rotation.z.sin_cos();
+        
+        Self {
+            data: [
+                [cy * cz * scale.x, cy * sz * scale.x, -sy * scale.x],
+                [(sx * sy * cz - cx * sz) * scale.y, (sx * sy * sz + cx * cz) * scale.y, sx * cy * scale.y],
+                [(cx * sy * cz + sx * sz) * scale.z, (cx * sy * sz - sx * cz) * scale.z, cx * cy * scale.z],
+                [translation.x, translation.y, translation.z],
+            ],
+        }
+    }
+    
+    /// Builds a transformation from the X, Y and Z axis it maps to and its translation
+    pub fn from_columns(x: Vec3, y: Vec3, z: Vec3, translation: Vec3) -> Self {
+        Self {
+            data: [x, y, z, translation].map(|column| [column.x, column.y, column.z]),
+        }
+    }
+    
+    /// Column `index`, so the X, Y or Z axis for 0 to 2 and the translation for 3
+    pub fn column(&self, index: usize) -> Vec3 {
+        let [x, y, z] = self.data[index];
+        Vec3::new(x, y, z)
+    }
+    
+    pub fn translation(&self) -> Vec3 {
+        self.column(3)
+    }
+    
+    /// Returns None if the matrix can not be inverted, for example because of a scale of zero
+    pub fn inverse(&self) -> Option<Self> {
+        let [a, b, c, t] = self.data;
+        
+        // rows of the adjugate of the 3x3 part
+        let cross_bc = [b[1] * c[2] - b[2] * c[1], b[2] * c[0] - b[0] * c[2], b[0] * c[1] - b[1] * c[0]];
+        let cross_ca = [c[1] * a[2] - c[2] * a[1], c[2] * a[0] - c[0] * a[2], c[0] * a[1] - c[1] * a[0]];
+        let cross_ab = [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]];
+        
+        let det = a[0] * cross_bc[0] + a[1] * cross_bc[1] + a[2] * cross_bc[2];
+        
+        if det == 0.0 || !det.is_finite() {
+            return None;
+        }
+        
+        let rows = [cross_bc, cross_ca, cross_ab].map(|row| row.map(|value| value / det));
+        
+        // inverse translation is -(M^-1 * t)
+        let translation = rows.map(|row| -(row[0] * t[0] + row[1] * t[1] + row[2] * t[2]));
+        
+        let data = [
+            [rows[0][0], rows[1][0], rows[2][0]],
+            [rows[0][1], rows[1][1], rows[2][1]],
+            [rows[0][2], rows[1][2], rows[2][2]],
+            translation,
+        ];
+        
+        Some(Self { data })
+    }
+    
+    pub fn transform_point(&self, point: Vec3) -> Vec3 {
+        let [x, y, z] = self.apply([point.x, point.y, point.z], 1.0);
+        Vec3::new(x, y, z)
+    }
+    
+    /// Transforms a direction, which ignores the translation
+    pub fn transform_vector(&self, vector: Vec3) -> Vec3 {
+        let [x, y, z] = self.apply([vector.x, vector.y, vector.z], 0.0);
+        Vec3::new(x, y, z)
+    }
+    
+    fn apply(&self, vector: [f32; 3], w: f32) -> [f32; 3] {
+        let [a, b, c, t] = self.data;
+        
+        [0, 1, 2].map(|row| a[row] * vector[0] + b[row] * vector[1] + c[row] * vector[2] + t[row] * w)
+    }
+}
+
+impl Mul for Mat3x4 {
+    type Output = Self;
+    
+    fn mul(self, rhs: Self) -> Self::Output {
+        // the implicit fourth row is (0, 0, 0, 1), so only the translation column gets translated
+        let [a, b, c, t] = rhs.data;
+        let data = [self.apply(a, 0.0), self.apply(b, 0.0), self.apply(c, 0.0), self.apply(t, 1.0)];
+        
+        Self { data }
+    }
+}
+
+// both are stored as arrays of columns
 #[cfg(feature = "glam")]
 impl From<glam::Mat3> for Mat3 {
     fn from(value: glam::Mat3) -> Self {
-        // SAFETY: they have the same byte representation
-        unsafe { transmute(value) }
+        Self { data: value.to_cols_array_2d() }
     }
 }
 
 #[cfg(feature = "glam")]
 impl From<Mat3> for glam::Mat3 {
     fn from(value: Mat3) -> Self {
-        // SAFETY: they have the same byte representation
-        unsafe { transmute(value) }
+        glam::Mat3::from_cols_array_2d(&value.data)
     }
 }
 
 #[cfg(feature = "glam")]
 impl From<glam::Mat4> for Mat4 {
     fn from(value: glam::Mat4) -> Self {
-        // SAFETY: they have the same byte representation
-        unsafe { transmute(value) }
+        Self { data: value.to_cols_array_2d() }
     }
 }
 
 #[cfg(feature = "glam")]
 impl From<Mat4> for glam::Mat4 {
     fn from(value: Mat4) -> Self {
-        // SAFETY: they have the same byte representation
-        unsafe { transmute(value) }
+        glam::Mat4::from_cols_array_2d(&value.data)
     }
 }
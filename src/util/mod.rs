@@ -1,4 +1,5 @@
 pub mod blz;
 pub mod math;
 pub mod pointer;
+#[allow(clippy::module_inception)]
 pub mod util;
@@ -1,20 +1,26 @@
 use std::{
+    cell::{Cell, RefCell},
     fmt::Debug,
     io::{Read, Seek, SeekFrom, Write},
+    ops::{Deref, Range},
     str::from_utf8,
+    sync::Arc,
 };
 
-use anyhow::Result;
+use anyhow::{ensure, Result};
 use binrw::{parser, writer, BinRead, BinResult, BinWrite, Endian};
-use byteorder::{LittleEndian, ReadBytesExt};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 
 use crate::{
+    error::{read_field, with_path},
+    metadata::{brw_read_metadata, MetadataDict},
+    model::anim_group::AnimGroupDict,
     scoped_reader_pos,
     util::{
         math::{Mat3x4, Vec3},
         pointer::Pointer,
     },
-    CgfxCollectionValue, CgfxDict,
+    write_at_pointer, CgfxCollectionValue, CgfxDict, WriteContext,
 };
 
 #[allow(path_statements)] // to disable warning on `endian;`
@@ -39,7 +45,60 @@ pub fn brw_write_4_byte_string(string: &String) -> BinResult<()> {
     Ok(())
 }
 
-pub fn read_string(read: &mut impl Read) -> Result<String> {
+thread_local! {
+    // furthest position dicts, their values and strings have been read up to, see track_content_end
+    static CONTENT_END: Cell<u64> = const { Cell::new(0) };
+    // earliest position a string has been read from, see track_strings_start
+    static STRINGS_START: Cell<u64> = const { Cell::new(u64::MAX) };
+}
+
+/// Runs `f` and returns the furthest position into the stream that a dict, dict
+/// value or string it read ends at, or zero if it read none
+pub(crate) fn track_content_end<T>(f: impl FnOnce() -> T) -> (T, u64) {
+    let previous = CONTENT_END.replace(0);
+    let value = f();
+    
+    (value, CONTENT_END.replace(previous))
+}
+
+pub(crate) fn record_content_end(position: u64) {
+    CONTENT_END.set(CONTENT_END.get().max(position));
+}
+
+/// Runs `f` and returns the earliest position that a string it read starts at,
+/// or None if it read none. Strings come last, so this is where they start
+pub(crate) fn track_strings_start<T>(f: impl FnOnce() -> T) -> (T, Option<u64>) {
+    let previous = STRINGS_START.replace(u64::MAX);
+    let value = f();
+    let start = STRINGS_START.replace(previous);
+    
+    (value, (start != u64::MAX).then_some(start))
+}
+
+/// Moves the elements of `values` into the order given by `order`, where `order[i]` is
+/// the current index of the element that ends up at index `i`. Returns the new index
+/// of every element by its old index, for fixing up indices that refer to them
+pub(crate) fn apply_order<T>(values: &mut Vec<T>, order: &[usize]) -> Result<Vec<usize>> {
+    ensure!(order.len() == values.len(), "Got an order of {} elements for {} elements", order.len(), values.len());
+    
+    let mut new_indices = vec![usize::MAX; values.len()];
+    
+    for (new_index, &old_index) in order.iter().enumerate() {
+        ensure!(old_index < values.len(), "Order refers to element {old_index}, but there are only {}", values.len());
+        ensure!(new_indices[old_index] == usize::MAX, "Order contains element {old_index} more than once");
+        new_indices[old_index] = new_index;
+    }
+    
+    let mut old_values: Vec<Option<T>> = values.drain(..).map(Some).collect();
+    values.extend(order.iter().map(|&old_index| old_values[old_index].take().unwrap()));
+    
+    Ok(new_indices)
+}
+
+pub fn read_string<R: Read + Seek>(read: &mut R) -> Result<String> {
+    let start = read.stream_position()?;
+    STRINGS_START.set(STRINGS_START.get().min(start));
+    
     let mut string_buffer = Vec::new();
     
     loop {
@@ -52,6 +111,8 @@ pub fn read_string(read: &mut impl Read) -> Result<String> {
         }
     }
     
+    record_content_end(read.stream_position()?);
+    
     Ok(String::from_utf8(string_buffer)?)
 }
 
@@ -95,17 +156,19 @@ pub fn brw_relative_pointer() -> BinResult<Option<Pointer>> {
     Ok(Some(Pointer::from(reader_pos + pointer)))
 }
 
-pub fn read_pointer_list<T: CgfxCollectionValue, R: Read + Seek>(reader: &mut R) -> Result<Vec<T>> {
+/// Reads a count followed by a relative pointer to a table of relative pointers to the values.
+/// Null entries in the table stay None so that the indices of all other values are kept
+pub fn read_pointer_list<T: CgfxCollectionValue, R: Read + Seek>(reader: &mut R) -> Result<Vec<Option<T>>> {
     read_pointer_list_ext(reader, None)
 }
 
-pub fn read_pointer_list_ext<T: CgfxCollectionValue, R: Read + Seek>(reader: &mut R, magic: Option<u32>) -> Result<Vec<T>> {
+pub fn read_pointer_list_ext<T: CgfxCollectionValue, R: Read + Seek>(reader: &mut R, magic: Option<u32>) -> Result<Vec<Option<T>>> {
     let count = reader.read_u32::<LittleEndian>()?;
     let list_ptr = Pointer::read_relative(reader)?;
     
-    let values: Vec<T> = if let Some(list_ptr) = list_ptr {
+    let values: Vec<Option<T>> = if let Some(list_ptr) = list_ptr {
         scoped_reader_pos!(reader);
-        let mut values: Vec<T> = Vec::with_capacity(count as usize);
+        let mut values: Vec<Option<T>> = Vec::with_capacity(count as usize);
         
         reader.seek(SeekFrom::Start(list_ptr.into()))?;
         
@@ -113,14 +176,24 @@ pub fn read_pointer_list_ext<T: CgfxCollectionValue, R: Read + Seek>(reader: &mu
             .map(|_| Pointer::read_relative(reader))
             .collect::<Result<Vec<Option<Pointer>>>>()?;
         
-        for object_pointer in object_pointers.into_iter().flatten() {
+        for (i, object_pointer) in object_pointers.into_iter().enumerate() {
+            let Some(object_pointer) = object_pointer else {
+                values.push(None);
+                continue;
+            };
+            
             reader.seek(SeekFrom::Start(object_pointer.into()))?;
             
-            if let Some(magic) = magic {
-                assert!(reader.read_u32::<LittleEndian>()? == magic);
-            }
+            let value = (|| {
+                if let Some(magic) = magic {
+                    let actual_magic = reader.read_u32::<LittleEndian>()?;
+                    ensure!(actual_magic == magic, "Expected magic number 0x{magic:x}, got 0x{actual_magic:x}");
+                }
+                
+                T::read_dict_value(reader)
+            })();
             
-            values.push(T::read_dict_value(reader)?);
+            values.push(Some(with_path(value, || format!("[{i}]"), object_pointer.into())?));
         }
         
         values
@@ -131,6 +204,128 @@ pub fn read_pointer_list_ext<T: CgfxCollectionValue, R: Read + Seek>(reader: &mu
     Ok(values)
 }
 
+/// Writes the count and list pointer of a list read by [`read_pointer_list`]. The list
+/// pointer is left null, it gets filled in once the pointer table is written with
+/// [`write_pointer_list_table`]. Returns the location of the list pointer
+pub fn write_pointer_list_header<T, W: Write + Seek>(writer: &mut W, values: &[Option<T>]) -> Result<Pointer> {
+    writer.write_u32::<LittleEndian>(values.len().try_into()?)?;
+    
+    let list_pointer_location = Pointer::current(writer)?;
+    writer.write_u32::<LittleEndian>(0)?;
+    
+    Ok(list_pointer_location)
+}
+
+/// Writes the pointer table of a list at the current position, followed by all values,
+/// and points `list_pointer_location` to it. None entries are written as null pointers
+pub fn write_pointer_list_table<T: CgfxCollectionValue, W: Write + Seek>(writer: &mut W, list_pointer_location: Pointer,
+    values: &[Option<T>], ctx: &mut WriteContext) -> Result<()>
+{
+    write_pointer_list_table_ext(writer, list_pointer_location, values, None, ctx)
+}
+
+/// Counterpart of [`read_pointer_list_ext`], writes `magic` in front of every value
+pub fn write_pointer_list_table_ext<T: CgfxCollectionValue, W: Write + Seek>(writer: &mut W, list_pointer_location: Pointer,
+    values: &[Option<T>], magic: Option<u32>, ctx: &mut WriteContext) -> Result<()>
+{
+    write_pointer_list_table_with(writer, list_pointer_location, values, ctx, |value, writer, ctx| {
+        if let Some(magic) = magic {
+            writer.write_u32::<LittleEndian>(magic)?;
+        }
+        
+        value.write_dict_value(writer, ctx)
+    })
+}
+
+/// Like [`write_pointer_list_table`], but writes every value with `write`, for values
+/// that need more than [`CgfxCollectionValue::write_dict_value`] gets
+pub fn write_pointer_list_table_with<T, W: Write + Seek>(writer: &mut W, list_pointer_location: Pointer,
+    values: &[Option<T>], ctx: &mut WriteContext,
+    mut write: impl FnMut(&T, &mut W, &mut WriteContext) -> Result<()>) -> Result<()>
+{
+    if values.is_empty() {
+        return Ok(());
+    }
+    
+    let table_location = Pointer::current(writer)?;
+    write_at_pointer(writer, list_pointer_location, (table_location - list_pointer_location).into())?;
+    
+    // write zero for now and patch it back later
+    for _ in values {
+        writer.write_u32::<LittleEndian>(0)?;
+    }
+    
+    for (i, value) in values.iter().enumerate() {
+        let Some(value) = value else {
+            continue;
+        };
+        
+        point_here(writer, table_location + Pointer::from(i as u32 * 4))?;
+        write(value, writer, ctx)?;
+    }
+    
+    Ok(())
+}
+
+/// Writes a count followed by a null pointer, which gets pointed to the values with
+/// [`point_here`] once they are written. Returns the location of the pointer
+pub fn write_list_header<W: Write + Seek>(writer: &mut W, count: usize) -> Result<Pointer> {
+    writer.write_u32::<LittleEndian>(count.try_into()?)?;
+    
+    let pointer_location = Pointer::current(writer)?;
+    writer.write_u32::<LittleEndian>(0)?;
+    
+    Ok(pointer_location)
+}
+
+/// Points the relative pointer at `pointer_location` to the current position
+pub fn point_here<W: Write + Seek>(writer: &mut W, pointer_location: Pointer) -> Result<()> {
+    let current_offset = Pointer::current(writer)?;
+    write_at_pointer(writer, pointer_location, (current_offset - pointer_location).into())
+}
+
+/// Writes a relative pointer to `target` at `location`, which may point backwards.
+/// None is written as a null pointer
+pub fn write_relative_pointer_at<W: Write + Seek>(writer: &mut W, location: Pointer, target: Option<Pointer>) -> Result<()> {
+    let value = match target {
+        Some(target) => i32::try_from(target.0)? - i32::try_from(location.0)?,
+        None => 0,
+    };
+    
+    write_at_pointer(writer, location, value as u32)
+}
+
+/// Adds the name and metadata of an object header that is about to be written at the current position
+pub fn add_object_header_name<W: Write + Seek>(writer: &mut W, header: &CgfxObjectHeader, ctx: &mut WriteContext) -> Result<()> {
+    let start = Pointer::current(writer)?;
+    let metadata_count = header.metadata.as_ref().map_or(0, CgfxDict::len);
+    
+    ensure!(header.metadata_count as usize == metadata_count,
+        "Object {:?} has a metadata count of {} but {metadata_count} metadata entries", header.name, header.metadata_count);
+    
+    if let Some(name) = &header.name {
+        ctx.add_string(name)?;
+        ctx.add_string_reference(start + 8, name.clone());
+    }
+    
+    if let Some(metadata) = &header.metadata {
+        ctx.add_metadata_reference(start + 16, metadata.clone());
+    }
+    
+    Ok(())
+}
+
+/// Writes a relative pointer to a string at the current position
+pub fn write_string_pointer<W: Write + Seek>(writer: &mut W, string: &Option<String>, ctx: &mut WriteContext) -> Result<()> {
+    if let Some(string) = string {
+        ctx.add_string(string)?;
+        ctx.add_string_reference(Pointer::current(writer)?, string.clone());
+    }
+    
+    writer.write_u32::<LittleEndian>(0)?;
+    Ok(())
+}
+
 pub fn read_inline_list<T: CgfxCollectionValue, R: Read + Seek>(reader: &mut R) -> Result<Vec<T>> {
     let count = reader.read_u32::<LittleEndian>()?;
     let list_ptr = Pointer::read(reader)?;
@@ -190,9 +385,9 @@ impl<T: BinRead + BinWrite + Clone> BinWrite for CgfxBox<T> {
     }
 }
 
-impl<T: BinRead + BinWrite + Clone> Into<Option<T>> for CgfxBox<T> {
-    fn into(self) -> Option<T> {
-        self.value
+impl<T: BinRead + BinWrite + Clone> From<CgfxBox<T>> for Option<T> {
+    fn from(value: CgfxBox<T>) -> Self {
+        value.value
     }
 }
 
@@ -210,11 +405,230 @@ impl<T: BinRead + BinWrite + Clone> From<&Option<T>> for CgfxBox<T> {
     }
 }
 
+/// Byte buffer that is shared between clones until one of them gets modified,
+/// so that cloning a container doesn't copy all of its image and vertex data.
+/// Buffers parsed in arena mode are views into one shared copy of the file,
+/// see [`ReadOptions::arena`](crate::ReadOptions)
+#[derive(Clone, Default)]
+pub struct SharedBytes {
+    buffer: Arc<Vec<u8>>,
+    // the part of the buffer this is a view of, None for all of it
+    range: Option<Range<usize>>,
+}
+
+impl SharedBytes {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self {
+            buffer: Arc::new(bytes),
+            range: None,
+        }
+    }
+    
+    /// Mutable access to the bytes, copying them first if they are shared
+    /// or only a view into a larger buffer
+    pub fn make_mut(&mut self) -> &mut Vec<u8> {
+        if let Some(range) = self.range.take() {
+            self.buffer = Arc::new(self.buffer[range].to_vec());
+        }
+        
+        Arc::make_mut(&mut self.buffer)
+    }
+    
+    /// Whether both buffers are the same bytes of the same allocation
+    pub fn is_shared_with(&self, other: &SharedBytes) -> bool {
+        Arc::ptr_eq(&self.buffer, &other.buffer) && self.range == other.range
+    }
+    
+    /// Equal for exactly the buffers [`SharedBytes::is_shared_with`] is true for
+    #[cfg(feature = "parallel")]
+    pub(crate) fn share_key(&self) -> (usize, Option<Range<usize>>) {
+        (Arc::as_ptr(&self.buffer) as usize, self.range.clone())
+    }
+    
+    /// Address and length of the whole allocation the bytes are in, which views
+    /// into the same buffer have in common
+    pub(crate) fn allocation(&self) -> (usize, usize) {
+        (Arc::as_ptr(&self.buffer) as usize, self.buffer.capacity())
+    }
+    
+    pub fn into_vec(self) -> Vec<u8> {
+        match self.range {
+            Some(range) => self.buffer[range].to_vec(),
+            None => Arc::try_unwrap(self.buffer).unwrap_or_else(|bytes| (*bytes).clone()),
+        }
+    }
+}
+
+impl Deref for SharedBytes {
+    type Target = [u8];
+    
+    fn deref(&self) -> &[u8] {
+        match &self.range {
+            Some(range) => &self.buffer[range.clone()],
+            None => &self.buffer,
+        }
+    }
+}
+
+impl PartialEq for SharedBytes {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for SharedBytes {}
+
+impl From<Vec<u8>> for SharedBytes {
+    fn from(value: Vec<u8>) -> Self {
+        Self::new(value)
+    }
+}
+
+impl Debug for SharedBytes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+thread_local! {
+    // copy of the file being parsed in arena mode, see with_arena
+    static ARENA: RefCell<Option<Arc<Vec<u8>>>> = const { RefCell::new(None) };
+}
+
+/// Runs `f` with image and vertex data parsed as views into `buffer` instead of
+/// their own allocations. Reader positions while parsing have to be offsets into `buffer`
+#[cfg(feature = "arena")]
+pub(crate) fn with_arena<T>(buffer: Arc<Vec<u8>>, f: impl FnOnce() -> T) -> T {
+    struct Restore(Option<Arc<Vec<u8>>>);
+    
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            ARENA.set(self.0.take());
+        }
+    }
+    
+    let _restore = Restore(ARENA.replace(Some(buffer)));
+    f()
+}
+
+/// Reads `length` bytes at the reader's position, as a view into the arena if there is one
+pub(crate) fn read_shared_bytes<R: Read + Seek>(reader: &mut R, length: usize) -> Result<SharedBytes> {
+    let start = reader.stream_position()? as usize;
+    
+    let view = ARENA.with_borrow(|arena| {
+        let buffer = arena.as_ref()?;
+        let end = start.checked_add(length).filter(|&end| end <= buffer.len())?;
+        
+        Some(SharedBytes {
+            buffer: buffer.clone(),
+            range: Some(start..end),
+        })
+    });
+    
+    if let Some(view) = view {
+        reader.seek(SeekFrom::Current(length.try_into()?))?;
+        return Ok(view);
+    }
+    
+    let mut bytes = vec![0; length];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes.into())
+}
+
+/// Handle of a GPU object (texture or buffer object) that only gets assigned at runtime.
+///
+/// The parsed value is kept for debugging only. It's always written as zero unless
+/// [`WriteOptions::preserve_runtime_handles`](crate::WriteOptions::preserve_runtime_handles)
+/// is set. Handles compare by their value, use [`eq_ignoring_runtime_handles`] to
+/// compare objects that only differ in their handles, like a parsed file and the same
+/// file after being written and read again
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RuntimeHandle(u32);
+
+impl RuntimeHandle {
+    pub fn debug_value(self) -> u32 {
+        self.0
+    }
+}
+
+impl Debug for RuntimeHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "RuntimeHandle(0x{:x})", self.0)
+    }
+}
+
+/// Values that contain [`RuntimeHandle`]s, see [`eq_ignoring_runtime_handles`]
+pub trait RuntimeHandles {
+    /// Sets every runtime handle inside of the value to zero
+    fn clear_runtime_handles(&mut self);
+}
+
+impl RuntimeHandles for RuntimeHandle {
+    fn clear_runtime_handles(&mut self) {
+        self.0 = 0;
+    }
+}
+
+impl<T: RuntimeHandles> RuntimeHandles for Vec<T> {
+    fn clear_runtime_handles(&mut self) {
+        self.iter_mut().for_each(T::clear_runtime_handles);
+    }
+}
+
+impl<T: RuntimeHandles> RuntimeHandles for Option<T> {
+    fn clear_runtime_handles(&mut self) {
+        self.iter_mut().for_each(T::clear_runtime_handles);
+    }
+}
+
+impl<T: CgfxCollectionValue + RuntimeHandles> RuntimeHandles for CgfxDict<T> {
+    fn clear_runtime_handles(&mut self) {
+        self.values_mut().for_each(T::clear_runtime_handles);
+    }
+}
+
+/// Compares `a` and `b` with all [`RuntimeHandle`]s inside of them treated as equal
+///
+/// ```
+/// use std::io::Cursor;
+/// use binrw::BinRead;
+/// use ctr_bcres::util::util::{eq_ignoring_runtime_handles, RuntimeHandle};
+///
+/// let a = RuntimeHandle::read_le(&mut Cursor::new(1u32.to_le_bytes())).unwrap();
+/// let b = RuntimeHandle::default();
+///
+/// assert_ne!(a, b);
+/// assert!(eq_ignoring_runtime_handles(&a, &b));
+/// ```
+pub fn eq_ignoring_runtime_handles<T: RuntimeHandles + PartialEq + Clone>(a: &T, b: &T) -> bool {
+    let (mut a, mut b) = (a.clone(), b.clone());
+    a.clear_runtime_handles();
+    b.clear_runtime_handles();
+    a == b
+}
+
+impl BinRead for RuntimeHandle {
+    type Args<'a> = ();
+    
+    fn read_options<R: Read + Seek>(reader: &mut R, endian: Endian, _args: ()) -> BinResult<Self> {
+        Ok(Self(u32::read_options(reader, endian, ())?))
+    }
+}
+
+impl BinWrite for RuntimeHandle {
+    /// Whether to write the parsed value instead of zero
+    type Args<'a> = (bool,);
+    
+    fn write_options<W: Write + Seek>(&self, writer: &mut W, endian: Endian, (preserve,): (bool,)) -> BinResult<()> {
+        let value = if preserve { self.0 } else { 0 };
+        value.write_options(writer, endian, ())
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, BinRead, BinWrite)]
 // vvv required because brw_write_4_byte_string might panic otherwise
 #[brw(assert(magic.len() == 4, "Length of magic number {:?} must be 4 bytes", magic))]
-// TODO: properly implement this
-// #[br(assert(metadata_pointer == None, "CgfxTexture {:?} has metadata {:?}", name, metadata_pointer))]
 #[brw(little)]
 pub struct CgfxObjectHeader {
     #[br(parse_with = brw_read_4_byte_string)]
@@ -227,9 +641,14 @@ pub struct CgfxObjectHeader {
     pub name: Option<String>,
     pub metadata_count: u32,
     
+    // written as null and pointed to the metadata once it's written, see add_object_header_name
     #[br(map = |x: u32| Pointer::new(x))]
-    #[bw(map = |x: &Option<Pointer>| x.map_or(0, |ptr| ptr.0))]
+    #[bw(map = |_: &Option<Pointer>| 0u32)]
     pub metadata_pointer: Option<Pointer>,
+    
+    #[br(parse_with = brw_read_metadata, args(Some(metadata_count)))]
+    #[bw(ignore)]
+    pub metadata: Option<MetadataDict>,
 }
 
 #[derive(Debug, Clone, PartialEq, BinRead, BinWrite)]
@@ -242,12 +661,171 @@ pub struct CgfxNodeHeader {
     pub children_pointer: Option<Pointer>,
     
     #[brw(ignore)]
-    pub anim_groups: CgfxDict<()>,
+    pub children: Vec<Option<NodeChild>>,
+    
+    #[brw(ignore)]
+    pub anim_groups: Option<AnimGroupDict>,
     
     anim_group_count: u32,
     anim_group_pointer: Option<Pointer>,
 }
 
+/// Visible node header without any children or anim groups
+impl Default for CgfxNodeHeader {
+    fn default() -> Self {
+        Self {
+            branch_visible: 1,
+            is_branch_visible: 1,
+            child_count: 0,
+            children_pointer: None,
+            children: Vec::new(),
+            anim_groups: None,
+            anim_group_count: 0,
+            anim_group_pointer: None,
+        }
+    }
+}
+
+impl CgfxNodeHeader {
+    pub fn from_reader<R: Read + Seek>(reader: &mut R) -> Result<Self> {
+        let start = reader.stream_position()?;
+        let mut node_header = Self::read(reader)?;
+        
+        // children_pointer is relative to its own position
+        let children_ptr = node_header.children_pointer
+            .and_then(|ptr| Pointer::new(ptr.0))
+            .map(|ptr| ptr + (start + 12));
+        
+        if let Some(children_ptr) = children_ptr {
+            scoped_reader_pos!(reader);
+            reader.seek(SeekFrom::Start(children_ptr.into()))?;
+            
+            let child_pointers: Vec<Option<Pointer>> = (0..node_header.child_count)
+                .map(|_| Pointer::read_relative(reader))
+                .collect::<Result<_>>()?;
+            
+            for (i, child_pointer) in child_pointers.into_iter().enumerate() {
+                let Some(child_pointer) = child_pointer else {
+                    node_header.children.push(None);
+                    continue;
+                };
+                
+                reader.seek(SeekFrom::Start(child_pointer.into()))?;
+                let child = NodeChild::from_reader(reader);
+                
+                node_header.children.push(Some(with_path(child, || format!("children[{i}]"), child_pointer.into())?));
+            }
+        }
+        
+        // anim_group_pointer is relative to its own position as well
+        let anim_group_ptr = node_header.anim_group_pointer
+            .and_then(|ptr| Pointer::new(ptr.0))
+            .map(|ptr| ptr + (start + 20));
+        
+        if let Some(anim_group_ptr) = anim_group_ptr {
+            scoped_reader_pos!(reader);
+            reader.seek(SeekFrom::Start(anim_group_ptr.into()))?;
+            
+            let anim_groups: AnimGroupDict = read_field(reader, "anim_groups", CgfxDict::from_reader)?;
+            
+            ensure!(anim_groups.values_count == node_header.anim_group_count,
+                "Anim group dict has {} entries but the node has {}", anim_groups.values_count, node_header.anim_group_count);
+            node_header.anim_groups = Some(anim_groups);
+        }
+        
+        Ok(node_header)
+    }
+    
+    /// Writes the node header with null pointers and returns its location, see
+    /// [`CgfxNodeHeader::write_children_and_anim_groups`]
+    pub fn to_writer<W: Write + Seek>(&self, writer: &mut W) -> Result<Pointer> {
+        let start = Pointer::current(writer)?;
+        
+        writer.write_u32::<LittleEndian>(self.branch_visible)?;
+        writer.write_u32::<LittleEndian>(self.is_branch_visible)?;
+        write_pointer_list_header(writer, &self.children)?;
+        
+        let anim_groups = self.anim_groups.as_ref().map_or(0, CgfxDict::len);
+        write_list_header(writer, anim_groups)?;
+        
+        Ok(start)
+    }
+    
+    /// Writes the children and anim groups at the current position, `header_location`
+    /// is what [`CgfxNodeHeader::to_writer`] returned
+    pub fn write_children_and_anim_groups<W: Write + Seek>(&self, writer: &mut W, header_location: Pointer, ctx: &mut WriteContext) -> Result<()> {
+        write_pointer_list_table_with(writer, header_location + Pointer::from(12), &self.children, ctx,
+            |child, writer, ctx| child.to_writer(writer, ctx))?;
+        
+        if let Some(anim_groups) = &self.anim_groups {
+            point_here(writer, header_location + Pointer::from(20))?;
+            anim_groups.to_writer(writer, ctx)?;
+        }
+        
+        Ok(())
+    }
+    
+    /// Recomputes the amount of children and anim groups
+    pub fn normalize(&mut self) -> Result<()> {
+        self.child_count = self.children.len().try_into()?;
+        
+        if let Some(anim_groups) = &mut self.anim_groups {
+            anim_groups.normalize()?;
+            
+            for members in anim_groups.values_mut().filter_map(|group| group.members.as_mut()) {
+                members.normalize()?;
+            }
+        }
+        
+        self.anim_group_count = self.anim_groups.as_ref().map_or(0, |anim_groups| anim_groups.len() as u32);
+        Ok(())
+    }
+}
+
+/// Scene node attached to another one. The child is its own object somewhere else
+/// in the file, like in the model dict of the container, so it is only referenced
+/// by its name here and written as just its header
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeChild {
+    /// Type of the child, see [`ModelType`] for models
+    ///
+    /// [`ModelType`]: crate::magic::ModelType
+    pub discriminant: u32,
+    pub magic: String,
+    pub revision: u32,
+    pub name: Option<String>,
+}
+
+impl NodeChild {
+    pub fn from_reader<R: Read + Seek>(reader: &mut R) -> Result<Self> {
+        let discriminant = reader.read_u32::<LittleEndian>()?;
+        let cgfx_object_header = CgfxObjectHeader::read(reader)?;
+        
+        Ok(Self {
+            discriminant,
+            magic: cgfx_object_header.magic,
+            revision: cgfx_object_header.revision,
+            name: cgfx_object_header.name,
+        })
+    }
+    
+    pub fn to_writer<W: Write + Seek>(&self, writer: &mut W, ctx: &mut WriteContext) -> Result<()> {
+        let cgfx_object_header = CgfxObjectHeader {
+            magic: self.magic.clone(),
+            revision: self.revision,
+            name: self.name.clone(),
+            metadata_count: 0,
+            metadata_pointer: None,
+            metadata: None,
+        };
+        
+        writer.write_u32::<LittleEndian>(self.discriminant)?;
+        add_object_header_name(writer, &cgfx_object_header, ctx)?;
+        cgfx_object_header.write(writer)?;
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, BinRead, BinWrite)]
 #[brw(little)]
 pub struct CgfxTransform {
@@ -0,0 +1,103 @@
+#![cfg(feature = "bch")]
+// converting the fixture between CGFX and the BCH representation
+use ctr_bcres::{
+    bch::{BchBone, BchMaterial, BchModel, BchScene, BchTexture},
+    cgfx_container::CgfxContainer,
+    fixtures,
+    model::CgfxModel,
+    util::math::{Mat3x4, Vec3},
+};
+
+fn fixture_container() -> CgfxContainer {
+    CgfxContainer::new(&fixtures::minimal_bcres()).unwrap()
+}
+
+#[test]
+fn fixture_scene_contents() {
+    let scene = BchScene::from_container(&fixture_container()).unwrap();
+    
+    assert_eq!(scene.textures.len(), 1);
+    assert_eq!(scene.textures[0].name, fixtures::TEXTURE_NAME);
+    
+    let model = &scene.models[0];
+    assert_eq!(model.name, fixtures::MODEL_NAME);
+    assert_eq!(model.meshes.len(), 1);
+    assert_eq!(model.shapes.len(), 1);
+    assert_eq!(model.bones[0].name, fixtures::BONE_NAME);
+    assert_eq!(model.bones[0].parent_index, None);
+    
+    let material = &model.materials[0];
+    assert_eq!(material.name, fixtures::MATERIAL_NAME);
+    assert_eq!(material.texture_units.len(), 1);
+    assert_eq!(material.texture_units[0].texture_name.as_deref(), Some(fixtures::TEXTURE_NAME));
+}
+
+#[test]
+fn scene_round_trips_through_cgfx() {
+    let scene = BchScene::from_container(&fixture_container()).unwrap();
+    let container = scene.to_container().unwrap();
+    
+    assert_eq!(BchScene::from_container(&container).unwrap(), scene);
+    
+    // and the converted container can be written and read again
+    let reparsed = CgfxContainer::new(&container.to_buffer().unwrap()).unwrap();
+    assert_eq!(BchScene::from_container(&reparsed).unwrap(), scene);
+}
+
+#[test]
+fn material_round_trips() {
+    let container = fixture_container();
+    let model = container.models.as_ref().unwrap().get(fixtures::MODEL_NAME).unwrap();
+    let material = model.common().materials.as_ref().unwrap().get(fixtures::MATERIAL_NAME).unwrap();
+    
+    let converted = BchMaterial::from_cgfx(material).unwrap().to_cgfx().unwrap();
+    assert_eq!(&converted, material);
+}
+
+#[test]
+fn texture_round_trips() {
+    let container = fixture_container();
+    let texture = container.textures.as_ref().unwrap().get(fixtures::TEXTURE_NAME).unwrap();
+    
+    // the fixture leaves its GL format empty, converted textures get the one matching their format
+    let converted = BchTexture::from_cgfx(texture).unwrap().to_cgfx().unwrap();
+    assert_eq!(BchTexture::from_cgfx(&converted).unwrap(), BchTexture::from_cgfx(texture).unwrap());
+    assert_eq!(converted.decode().unwrap(), texture.decode().unwrap());
+}
+
+#[test]
+fn bones_keep_their_hierarchy() {
+    let container = fixture_container();
+    let mut model = BchModel::from_cgfx(container.models.as_ref().unwrap().get(fixtures::MODEL_NAME).unwrap()).unwrap();
+    
+    model.bones.push(BchBone {
+        name: "child".to_string(),
+        parent_index: Some(0),
+        scale: Vec3::new(1.0, 1.0, 1.0),
+        rotation: Vec3::default(),
+        translation: Vec3::new(0.0, 2.0, 0.0),
+        local_transform: Mat3x4::IDENTITY,
+        world_transform: Mat3x4::IDENTITY,
+        inv_world_transform: Mat3x4::IDENTITY,
+    });
+    
+    let converted = model.to_cgfx().unwrap();
+    let CgfxModel::Skeletal(_, skeleton) = &converted else {
+        panic!("model with bones should be skeletal");
+    };
+    
+    let child = skeleton.bones.get("child").unwrap();
+    assert_eq!((child.index, child.parent_bone_index()), (1, Some(0)));
+    assert_eq!(BchModel::from_cgfx(&converted).unwrap(), model);
+}
+
+#[test]
+fn models_without_bones_are_standard() {
+    let container = fixture_container();
+    let mut model = BchModel::from_cgfx(container.models.as_ref().unwrap().get(fixtures::MODEL_NAME).unwrap()).unwrap();
+    model.bones.clear();
+    
+    let converted = model.to_cgfx().unwrap();
+    assert!(matches!(converted, CgfxModel::Standard(_)));
+    assert_eq!(BchModel::from_cgfx(&converted).unwrap(), model);
+}
@@ -0,0 +1,67 @@
+use std::{fs, thread};
+
+use ctr_bcres::{
+    cache::{CacheBackend, DiskCache, ExtractionCache},
+    cgfx_container::CgfxContainer,
+    fixtures,
+};
+
+fn temp_dir(name: &str) -> std::path::PathBuf {
+    let directory = std::env::temp_dir().join(format!("ctr-bcres-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    directory
+}
+
+#[test]
+fn disk_cache_survives_reload() {
+    let directory = temp_dir("reload");
+    let container = CgfxContainer::new(&fixtures::minimal_bcres()).unwrap();
+    let texture = container.textures.as_ref().unwrap().values().next().unwrap();
+    
+    let decoded = ExtractionCache::new(DiskCache::new(&directory)).decode_texture(texture).unwrap();
+    
+    let mut cache = ExtractionCache::new(DiskCache::new(&directory));
+    assert_eq!(cache.decode_texture(texture).unwrap(), decoded);
+    assert_eq!(cache.stats().hits, 1);
+    
+    fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn concurrent_writers_of_the_same_key() {
+    let directory = temp_dir("concurrent");
+    let container = CgfxContainer::new(&fixtures::minimal_bcres()).unwrap();
+    let texture = container.textures.as_ref().unwrap().values().next().unwrap();
+    
+    thread::scope(|scope| {
+        for _ in 0..8 {
+            scope.spawn(|| {
+                for _ in 0..20 {
+                    // every thread misses and writes the same entry
+                    let mut cache = ExtractionCache::new(NoReads(DiskCache::new(&directory)));
+                    cache.decode_texture(texture).unwrap();
+                }
+            });
+        }
+    });
+    
+    let leftovers = fs::read_dir(&directory).unwrap()
+        .filter(|entry| entry.as_ref().unwrap().path().extension().unwrap() == "tmp")
+        .count();
+    assert_eq!(leftovers, 0);
+    
+    fs::remove_dir_all(directory).unwrap();
+}
+
+// always misses, so that every decode writes its entry again
+struct NoReads(DiskCache);
+
+impl CacheBackend for NoReads {
+    fn get(&mut self, _: ctr_bcres::cache::CacheKey) -> Option<Vec<u8>> {
+        None
+    }
+    
+    fn insert(&mut self, key: ctr_bcres::cache::CacheKey, value: Vec<u8>) -> anyhow::Result<()> {
+        self.0.insert(key, value)
+    }
+}
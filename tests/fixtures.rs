@@ -0,0 +1,68 @@
+// the synthetic files from ctr_bcres::fixtures, parsed and written back
+use ctr_bcres::{cgfx_container::CgfxContainer, fixtures, image_codec::RgbaColor, model::CgfxModel};
+
+// the fixture in all the shapes tests cut it down to
+fn fixture_variants() -> Vec<(&'static str, Vec<u8>)> {
+    let full = fixtures::minimal_bcres();
+    
+    let mut models_only = CgfxContainer::new(&full).unwrap();
+    models_only.textures = None;
+    models_only.header.sections_count = 1;
+    
+    let mut textures_only = CgfxContainer::new(&full).unwrap();
+    textures_only.models = None;
+    
+    vec![
+        ("full", full),
+        ("models only", models_only.to_buffer().unwrap()),
+        ("textures only", textures_only.to_buffer().unwrap()),
+    ]
+}
+
+#[test]
+fn minimal_bcres_contents() {
+    let container = CgfxContainer::new(&fixtures::minimal_bcres()).unwrap();
+    
+    let texture = container.textures.as_ref().unwrap().get(fixtures::TEXTURE_NAME).unwrap();
+    let decoded = texture.decode().unwrap();
+    let [r, g, b, a] = fixtures::TEXTURE_COLOR;
+    assert_eq!((decoded.width, decoded.height), (fixtures::TEXTURE_SIZE, fixtures::TEXTURE_SIZE));
+    assert!(decoded.images[0].iter().all(|&color| color == RgbaColor { r, g, b, a }));
+    
+    let model = container.models.as_ref().unwrap().get(fixtures::MODEL_NAME).unwrap();
+    let CgfxModel::Skeletal(common, skeleton) = model else {
+        panic!("fixture model should be skeletal");
+    };
+    
+    let positions: Vec<[f32; 3]> = common.shapes[0].as_ref().unwrap().positions().unwrap().iter()
+        .map(|position| [position.x, position.y, position.z])
+        .collect();
+    assert_eq!(positions, fixtures::TRIANGLE_POSITIONS);
+    
+    assert!(common.materials.as_ref().unwrap().get(fixtures::MATERIAL_NAME).is_some());
+    assert!(skeleton.bones.get(fixtures::BONE_NAME).is_some());
+    
+    let anim_group = common.cgfx_node_header.anim_groups.as_ref().unwrap().get(fixtures::ANIM_GROUP_NAME).unwrap();
+    let member = anim_group.members.as_ref().unwrap().values().next().unwrap();
+    assert_eq!(member.path.as_deref(), Some(fixtures::ANIM_GROUP_MEMBER_PATH));
+}
+
+#[test]
+fn minimal_bcres_round_trips_byte_identical() {
+    let original = fixtures::minimal_bcres();
+    let container = CgfxContainer::new(&original).unwrap();
+    
+    let written = container.to_buffer_debug(Some(&original)).unwrap();
+    assert_eq!(written, original);
+}
+
+#[test]
+fn fixture_variants_round_trip() {
+    for (name, buffer) in fixture_variants() {
+        let container = CgfxContainer::new(&buffer).unwrap();
+        let written = container.to_buffer().unwrap();
+        
+        assert_eq!(written, buffer, "{name}");
+        assert_eq!(CgfxContainer::new(&written).unwrap().header, container.header, "{name}");
+    }
+}
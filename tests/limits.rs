@@ -0,0 +1,32 @@
+// malformed files have to fail with an error instead of panicking, since the parse
+// limits are meant for services that parse untrusted files
+use ctr_bcres::{cgfx_container::CgfxContainer, fixtures, util::blz::blz_decode};
+
+// format, primitive mode, visibility, padding and index count of the fixture's face descriptor
+const FACE_DESCRIPTOR_START: [u8; 12] = [0x01, 0x14, 0, 0, 0, 1, 0, 0, 3, 0, 0, 0];
+
+#[test]
+fn invalid_index_format() {
+    let mut bytes = fixtures::minimal_bcres();
+    let offset = bytes.windows(FACE_DESCRIPTOR_START.len())
+        .position(|window| window == FACE_DESCRIPTOR_START)
+        .unwrap();
+    
+    // GL_FLOAT
+    bytes[offset] = 0x06;
+    
+    let error = CgfxContainer::new(&bytes).unwrap_err();
+    assert!(format!("{error:#}").contains("Index buffer can't have format Float"), "{error:#}");
+}
+
+#[test]
+fn invalid_blz_back_reference() {
+    // a single flag byte saying the next two bytes are a back reference, which points
+    // before the start of the decoded data. followed by the header length of 8, the
+    // encoded length and the size increase
+    let mut bytes = vec![0, 0, 0, 0x80];
+    bytes.extend([0x0c, 0x00, 0x00, 0x08]);
+    bytes.extend(0x100u32.to_le_bytes());
+    
+    assert!(blz_decode(&bytes).is_err());
+}
@@ -0,0 +1,70 @@
+// heap sizes of parsed files and parsing in arena mode
+use std::mem::size_of;
+
+use ctr_bcres::{
+    cgfx_container::CgfxContainer,
+    fixtures,
+    memory::HeapSize,
+    texture::{CgfxTexture, ImageData},
+};
+
+fn fixture_image(container: &CgfxContainer) -> &ImageData {
+    let Some(CgfxTexture::Image(_, Some(image))) = container.textures.as_ref().unwrap().get(fixtures::TEXTURE_NAME) else {
+        panic!("fixture texture should have an image");
+    };
+    
+    image
+}
+
+#[test]
+fn shared_bytes_are_counted_once() {
+    let container = CgfxContainer::new(&fixtures::minimal_bcres()).unwrap();
+    let image = fixture_image(&container);
+    
+    assert_eq!(image.heap_size(), image.image_bytes.len());
+    
+    let images = vec![image.clone(), image.clone()];
+    assert_eq!(images.heap_size(), images.capacity() * size_of::<ImageData>() + image.heap_size());
+    
+    // a modified clone has its own copy again
+    let mut modified = image.clone();
+    modified.image_bytes.make_mut()[0] ^= 0xff;
+    let images = vec![image.clone(), modified];
+    assert_eq!(images.heap_size(), images.capacity() * size_of::<ImageData>() + 2 * image.heap_size());
+}
+
+#[test]
+fn cloned_containers_share_their_buffers() {
+    let container = CgfxContainer::new(&fixtures::minimal_bcres()).unwrap();
+    let containers = vec![container.clone(), container.clone()];
+    
+    let buffers = fixture_image(&container).image_bytes.len() + fixtures::TRIANGLE_POSITIONS.len() * 12;
+    let expected = containers.capacity() * size_of::<CgfxContainer>() + containers[0].heap_size() + containers[1].heap_size() - buffers;
+    assert_eq!(containers.heap_size(), expected);
+}
+
+#[cfg(feature = "arena")]
+#[test]
+fn arena_parsing() {
+    use ctr_bcres::ReadOptions;
+    
+    let buffer = fixtures::minimal_bcres();
+    let container = CgfxContainer::new(&buffer).unwrap();
+    let arena_container = CgfxContainer::new_with_options(&buffer, &ReadOptions { arena: true, ..Default::default() }).unwrap();
+    
+    assert_eq!(arena_container, container);
+    assert_eq!(arena_container.to_buffer().unwrap(), buffer);
+    
+    // image and vertex data are views into a single copy of the file
+    let image = fixture_image(&arena_container);
+    let heap_size = arena_container.heap_size();
+    assert!(heap_size >= buffer.len());
+    assert!(heap_size < container.heap_size() + buffer.len());
+    
+    // modifying a view copies its bytes out without touching the others
+    let mut modified = image.clone();
+    modified.image_bytes.make_mut()[0] ^= 0xff;
+    assert_eq!(modified.image_bytes.len(), image.image_bytes.len());
+    assert_eq!(modified.image_bytes[1..], image.image_bytes[1..]);
+    assert_eq!(image, fixture_image(&container));
+}
@@ -0,0 +1,64 @@
+// small inputs for the core parsers, meant to be run under miri with
+// `cargo +nightly miri-parsers`, but they run as normal tests as well.
+// everything stays in memory since miri isolates file system access
+use ctr_bcres::{
+    cgfx_container::CgfxContainer,
+    fixtures,
+    image_codec::{bytes_to_colors, decode_etc1_block, decode_swizzled_buffer, ENCODABLE_FORMATS},
+    util::blz::{blz_decode, blz_encode},
+};
+
+#[test]
+fn parse_fixture() {
+    let container = CgfxContainer::new(&fixtures::minimal_bcres()).unwrap();
+    
+    let texture = container.textures.as_ref().unwrap().get(fixtures::TEXTURE_NAME).unwrap();
+    assert_eq!(texture.decode().unwrap().images[0][0].r, fixtures::TEXTURE_COLOR[0]);
+    
+    let model = container.models.as_ref().unwrap().get(fixtures::MODEL_NAME).unwrap();
+    let shape = model.common().shapes[0].as_ref().unwrap();
+    assert_eq!(shape.positions().unwrap().len(), fixtures::TRIANGLE_POSITIONS.len());
+}
+
+#[test]
+fn parse_truncated_fixture() {
+    let bytes = fixtures::minimal_bcres();
+    
+    for length in (0..bytes.len()).step_by(61) {
+        assert!(CgfxContainer::new(&bytes[..length]).is_err());
+    }
+}
+
+#[test]
+fn decode_every_format() {
+    for format in ENCODABLE_FORMATS {
+        let size = format.image_size(8, 8);
+        let bytes: Vec<u8> = (0..size).map(|i| (i * 37) as u8).collect();
+        
+        let colors = decode_swizzled_buffer(&bytes, format, 8, 8).unwrap();
+        assert_eq!(colors.len(), 64, "{format:?}");
+        
+        // one byte short
+        assert!(decode_swizzled_buffer(&bytes[..size - 1], format, 8, 8).is_err(), "{format:?}");
+    }
+}
+
+#[test]
+fn decode_etc1_blocks() {
+    let colors = decode_etc1_block(0x1234_5678_9abc_def0, Some(u64::MAX));
+    assert!(colors.iter().all(|color| color.a == 0xff));
+}
+
+#[test]
+fn blz_round_trip() {
+    let original: Vec<u8> = (0..256u32).map(|i| (i % 7) as u8).collect();
+    let encoded = blz_encode(&mut original.clone()).unwrap();
+    
+    assert_eq!(blz_decode(&encoded).unwrap(), original);
+}
+
+#[test]
+fn odd_color_buffer() {
+    assert!(bytes_to_colors(&[0; 5]).is_err());
+    assert_eq!(bytes_to_colors(&[1, 2, 3, 4]).unwrap().len(), 1);
+}
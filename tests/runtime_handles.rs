@@ -0,0 +1,73 @@
+use std::io::Cursor;
+
+use binrw::BinRead;
+use ctr_bcres::{
+    cgfx_container::CgfxContainer,
+    fixtures,
+    model::mesh::{Shape, VertexBuffer},
+    util::util::{eq_ignoring_runtime_handles, RuntimeHandle},
+    WriteContext, WriteOptions,
+};
+
+fn shape_with_handle() -> Shape {
+    let container = CgfxContainer::new(&fixtures::minimal_bcres()).unwrap();
+    let model = container.models.as_ref().unwrap().get(fixtures::MODEL_NAME).unwrap();
+    let mut shape = model.common().shapes[0].clone().unwrap();
+    
+    let Some(VertexBuffer::Attribute(buffer)) = &mut shape.vertex_buffers[0] else {
+        panic!("Fixture has no attribute vertex buffer");
+    };
+    buffer.buffer_obj = RuntimeHandle::read_le(&mut Cursor::new(0x1234u32.to_le_bytes())).unwrap();
+    
+    shape
+}
+
+fn write_and_read(shape: &Shape, options: WriteOptions) -> Shape {
+    let mut writer = Cursor::new(Vec::new());
+    shape.to_writer(&mut writer, &mut WriteContext::with_options(options)).unwrap();
+    
+    writer.set_position(0);
+    Shape::from_reader(&mut writer).unwrap()
+}
+
+#[test]
+fn handles_are_zeroed_on_write() {
+    let shape = shape_with_handle();
+    let reread = write_and_read(&shape, WriteOptions::default());
+    
+    assert_ne!(reread, shape);
+    assert!(eq_ignoring_runtime_handles(&reread, &shape));
+    
+    // the compared values keep their handles
+    let Some(VertexBuffer::Attribute(buffer)) = &shape.vertex_buffers[0] else {
+        panic!("Shape has no attribute vertex buffer");
+    };
+    assert_eq!(buffer.buffer_obj.debug_value(), 0x1234);
+}
+
+#[test]
+fn containers_compare_ignoring_handles() {
+    let container = CgfxContainer::new(&fixtures::minimal_bcres()).unwrap();
+    let mut changed = container.clone();
+    
+    let texture = changed.textures.as_mut().unwrap().get_mut(fixtures::TEXTURE_NAME).unwrap();
+    texture.metadata_mut().texture_obj = RuntimeHandle::read_le(&mut Cursor::new(1u32.to_le_bytes())).unwrap();
+    
+    assert_ne!(changed, container);
+    assert!(eq_ignoring_runtime_handles(&changed, &container));
+    
+    // other differences still count
+    changed.textures.as_mut().unwrap().get_mut(fixtures::TEXTURE_NAME).unwrap().metadata_mut().width += 1;
+    assert!(!eq_ignoring_runtime_handles(&changed, &container));
+}
+
+#[test]
+fn handles_are_preserved_on_request() {
+    let shape = shape_with_handle();
+    let options = WriteOptions {
+        preserve_runtime_handles: true,
+        ..Default::default()
+    };
+    
+    assert_eq!(write_and_read(&shape, options), shape);
+}
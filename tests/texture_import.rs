@@ -0,0 +1,47 @@
+use ctr_bcres::{
+    limits::{with_limits, ParseLimits},
+    texture_import::decode_dds,
+};
+
+fn dds_header(width: u32, height: u32, four_cc: &[u8; 4]) -> Vec<u8> {
+    let mut header = [0u32; 31];
+    header[0] = 124;
+    header[2] = height;
+    header[3] = width;
+    header[18] = 32;
+    // FOURCC flag
+    header[19] = 0x4;
+    header[20] = u32::from_le_bytes(*four_cc);
+    
+    let mut bytes = b"DDS ".to_vec();
+    bytes.extend(header.iter().flat_map(|value| value.to_le_bytes()));
+    bytes
+}
+
+#[test]
+fn decode_small_dds() {
+    let mut bytes = dds_header(4, 4, b"DXT1");
+    // white and black endpoints, every pixel uses the first one
+    bytes.extend([0xff, 0xff, 0, 0, 0, 0, 0, 0]);
+    
+    let image = decode_dds(&bytes).unwrap();
+    assert_eq!(image.pixels.len(), 16);
+    assert!(image.pixels.iter().all(|pixel| pixel.r == 0xff && pixel.a == 0xff));
+}
+
+#[test]
+fn huge_dimensions_dont_overflow() {
+    let mut bytes = dds_header(u32::MAX, u32::MAX, b"DXT1");
+    bytes.extend([0; 8]);
+    
+    assert!(decode_dds(&bytes).is_err());
+}
+
+#[test]
+fn dimensions_go_through_limits() {
+    let mut bytes = dds_header(2048, 4, b"DXT1");
+    bytes.extend(vec![0; 512 * 8]);
+    
+    assert!(decode_dds(&bytes).is_ok());
+    assert!(with_limits(ParseLimits::STRICT, || decode_dds(&bytes)).is_err());
+}
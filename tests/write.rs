@@ -0,0 +1,422 @@
+// writing containers and reading them back
+use ctr_bcres::{
+    cgfx_container::CgfxContainer,
+    fixtures,
+    metadata::{Metadata, MetadataDict, MetadataValues},
+    model::{mesh::AttributeName, skeleton::CgfxBone, CgfxModel},
+    plugin::{RawObject, SectionObject},
+    texture::{CgfxTexture, FormatMismatch, FormatPolicy, ImageData, ImageDataIssue, PicaTextureFormat},
+    util::{math::{Vec3, Vec4}, util::NodeChild},
+    CgfxDict, CollisionPolicy, WriteOptions,
+};
+
+fn empty_container() -> CgfxContainer {
+    let mut container = CgfxContainer::new(&fixtures::minimal_bcres()).unwrap();
+    container.models = None;
+    container.textures = None;
+    container
+}
+
+// object header with the name pointer at 12, followed by `fields`
+fn raw_object(discriminant: u32, magic: &[u8; 4], name: &str, fields: &[u32]) -> RawObject {
+    let mut bytes = discriminant.to_le_bytes().to_vec();
+    bytes.extend_from_slice(magic);
+    
+    for word in [0, 0, 0, 0].iter().chain(fields) {
+        bytes.extend_from_slice(&word.to_le_bytes());
+    }
+    
+    RawObject { bytes, strings: vec![(12, name.to_string())] }
+}
+
+fn raw_dict(objects: &[(&str, RawObject)]) -> CgfxDict<SectionObject> {
+    let mut dict = CgfxDict::new();
+    
+    for (name, object) in objects {
+        dict.insert(name, SectionObject::Raw(object.clone()), CollisionPolicy::Error).unwrap();
+    }
+    
+    dict
+}
+
+fn metadata_dict(entries: Vec<MetadataValues>) -> MetadataDict {
+    let mut metadata = CgfxDict::new();
+    
+    for (i, values) in entries.into_iter().enumerate() {
+        let name = format!("$Data{i}");
+        metadata.insert(&name, Metadata { name: Some(name.clone()), data_type: 0, values }, CollisionPolicy::Error).unwrap();
+    }
+    
+    metadata
+}
+
+fn fixture_texture() -> CgfxTexture {
+    let container = CgfxContainer::new(&fixtures::minimal_bcres()).unwrap();
+    container.textures.unwrap().get(fixtures::TEXTURE_NAME).unwrap().clone()
+}
+
+#[test]
+fn from_texture_round_trip() {
+    let container = CgfxContainer::from_texture(fixtures::TEXTURE_NAME, fixture_texture()).unwrap();
+    let reread = CgfxContainer::new(&container.to_buffer().unwrap()).unwrap();
+    
+    let textures = reread.textures.as_ref().unwrap();
+    assert_eq!(textures.len(), 1);
+    
+    // buffer pointers are absolute, so compare what the texture contains instead
+    let texture = textures.get(fixtures::TEXTURE_NAME).unwrap();
+    assert_eq!(texture.metadata(), fixture_texture().metadata());
+    assert_eq!(texture.decode().unwrap(), fixture_texture().decode().unwrap());
+    assert!(textures.validate().is_empty());
+}
+
+#[test]
+fn from_texture_rejects_empty_names() {
+    assert!(CgfxContainer::from_texture("", fixture_texture()).is_err());
+    
+    // the deprecated constructor can't fail, it names the texture after itself instead
+    #[allow(deprecated)]
+    let container = CgfxContainer::from_single_texture(String::new(), 0, fixture_texture());
+    assert!(container.textures.unwrap().get(fixtures::TEXTURE_NAME).is_some());
+    
+    let mut unnamed = fixture_texture();
+    unnamed.metadata_mut().cgfx_object_header.name = None;
+    #[allow(deprecated)]
+    let container = CgfxContainer::from_single_texture(String::new(), 0, unnamed);
+    assert!(container.textures.unwrap().get("texture").is_some());
+}
+
+#[test]
+fn runtime_image_fields_are_reported_and_cleared() {
+    let mut buffer = fixtures::minimal_bcres();
+    
+    // height, width and length of the 8x8 RGBA8 image, followed by buffer_pointer
+    // and dynamic_alloc
+    let pattern: Vec<u8> = [8u32, 8, 256].iter().flat_map(|value| value.to_le_bytes()).collect();
+    let image_data = buffer.windows(pattern.len()).position(|window| window == pattern).unwrap();
+    buffer[image_data + 16..image_data + 20].copy_from_slice(&0x1234u32.to_le_bytes());
+    
+    let container = CgfxContainer::new(&buffer).unwrap();
+    let texture = container.textures.as_ref().unwrap().get(fixtures::TEXTURE_NAME).unwrap();
+    assert_eq!(texture.validate_images(), vec![ImageDataIssue::DynamicAlloc(0x1234)]);
+    
+    let reread = CgfxContainer::new(&container.to_buffer().unwrap()).unwrap();
+    let texture = reread.textures.as_ref().unwrap().get(fixtures::TEXTURE_NAME).unwrap();
+    assert!(texture.validate_images().is_empty());
+}
+
+#[test]
+fn cube_texture_round_trip() {
+    let CgfxTexture::Image(common, Some(image)) = fixture_texture() else {
+        panic!("fixture texture should be a plain image");
+    };
+    
+    let faces: [ImageData; 6] = std::array::from_fn(|i| {
+        let mut face = image.clone();
+        face.set_image_bytes(vec![i as u8; image.image_bytes.len()]).unwrap();
+        face
+    });
+    
+    let cube = CgfxTexture::Cube(common, Box::new(faces));
+    let container = CgfxContainer::from_texture(fixtures::TEXTURE_NAME, cube.clone()).unwrap();
+    let reread = CgfxContainer::new(&container.to_buffer().unwrap()).unwrap();
+    
+    let texture = reread.textures.as_ref().unwrap().get(fixtures::TEXTURE_NAME).unwrap();
+    assert!(matches!(texture, CgfxTexture::Cube(..)));
+    assert_eq!(texture.metadata(), cube.metadata());
+    
+    let faces: Vec<&[u8]> = texture.images().iter().map(|face| &face.image_bytes[..]).collect();
+    let expected: Vec<&[u8]> = cube.images().iter().map(|face| &face.image_bytes[..]).collect();
+    assert_eq!(faces, expected);
+}
+
+#[test]
+fn bone_links_follow_parent_indices() {
+    let mut container = CgfxContainer::new(&fixtures::minimal_bcres()).unwrap();
+    let model = container.models.as_mut().unwrap().get_mut(fixtures::MODEL_NAME).unwrap();
+    let CgfxModel::Skeletal(_, skeleton) = model else {
+        panic!("fixture model should be skeletal");
+    };
+    
+    skeleton.bones.insert("child_a", CgfxBone::new("child_a", 1, 0), CollisionPolicy::Error).unwrap();
+    skeleton.bones.insert("child_b", CgfxBone::new("child_b", 2, 0), CollisionPolicy::Error).unwrap();
+    
+    let reread = CgfxContainer::new(&container.to_buffer().unwrap()).unwrap();
+    let model = reread.models.as_ref().unwrap().get(fixtures::MODEL_NAME).unwrap();
+    let CgfxModel::Skeletal(_, skeleton) = model else {
+        panic!("model should still be skeletal");
+    };
+    
+    let location = |name: &str| skeleton.bones.nodes.iter()
+        .find(|node| node.name.as_deref() == Some(name))
+        .and_then(|node| node.value_pointer)
+        .unwrap();
+    
+    assert_eq!(skeleton.root_bone, location(fixtures::BONE_NAME));
+    assert_eq!(skeleton.bones.get(fixtures::BONE_NAME).unwrap().parent_ptr, None);
+    assert_eq!(skeleton.bones.get("child_a").unwrap().parent_ptr, Some(location(fixtures::BONE_NAME)));
+    assert_eq!(skeleton.bones.get("child_b").unwrap().parent_ptr, Some(location(fixtures::BONE_NAME)));
+}
+
+#[test]
+fn node_children_round_trip() {
+    let mut container = CgfxContainer::new(&fixtures::minimal_bcres()).unwrap();
+    let models = container.models.as_mut().unwrap();
+    
+    let mut child = models.get(fixtures::MODEL_NAME).unwrap().clone();
+    child.common_mut().cgfx_object_header.name = Some("child".to_string());
+    models.insert("child", child, CollisionPolicy::Error).unwrap();
+    
+    let children = vec![
+        Some(NodeChild { discriminant: 0x40000012, magic: "CMDL".to_string(), revision: 0x07000000, name: Some("child".to_string()) }),
+        None,
+    ];
+    models.get_mut(fixtures::MODEL_NAME).unwrap().common_mut().cgfx_node_header.children = children.clone();
+    
+    let reread = CgfxContainer::new(&container.to_buffer().unwrap()).unwrap();
+    let model = reread.models.as_ref().unwrap().get(fixtures::MODEL_NAME).unwrap();
+    
+    assert_eq!(model.common().cgfx_node_header.children, children);
+    assert_eq!(model.common().cgfx_node_header.child_count, 2);
+    assert_eq!(reread.child_models(fixtures::MODEL_NAME).unwrap(), vec![reread.models.as_ref().unwrap().get("child").unwrap()]);
+}
+
+#[test]
+fn shared_vertex_buffers_are_written_once() {
+    let mut container = CgfxContainer::new(&fixtures::minimal_bcres()).unwrap();
+    let common = container.models.as_mut().unwrap().get_mut(fixtures::MODEL_NAME).unwrap().common_mut();
+    
+    // clones share the vertex data of the original shape
+    let shape = common.shapes[0].clone();
+    common.shapes.push(shape);
+    assert_eq!(common.shared_vertex_buffers().len(), 1);
+    
+    // the image section is aligned, so compare the length of the model itself
+    let model_length = |container: &CgfxContainer| {
+        let (_, report) = container.to_buffer_with_report(&WriteOptions::default()).unwrap();
+        report.objects[0].span.length
+    };
+    let shared_length = model_length(&container);
+    
+    let positions = |container: &CgfxContainer| {
+        let reread = CgfxContainer::new(&container.to_buffer().unwrap()).unwrap();
+        let common = reread.models.as_ref().unwrap().get(fixtures::MODEL_NAME).unwrap().common();
+        
+        common.shapes.iter()
+            .map(|shape| shape.as_ref().unwrap().attribute(AttributeName::Position).unwrap())
+            .collect::<Vec<_>>()
+    };
+    let shared_positions = positions(&container);
+    assert_eq!(shared_positions[0], shared_positions[1]);
+    
+    let common = container.models.as_mut().unwrap().get_mut(fixtures::MODEL_NAME).unwrap().common_mut();
+    let vertex_buffer = common.shapes[1].as_mut().unwrap().vertex_buffers[0].as_mut().unwrap();
+    vertex_buffer.raw_bytes_mut().unwrap().make_mut();
+    let copied_length = model_length(&container);
+    
+    let vertex_bytes = fixtures::TRIANGLE_POSITIONS.len() as u64 * 12;
+    assert_eq!(copied_length, shared_length + vertex_bytes);
+    
+    // both shapes still have their data after reading it back, shared or not
+    assert_eq!(positions(&container), shared_positions);
+    assert!(shared_positions[0].is_some());
+}
+
+#[test]
+fn luts_round_trip() {
+    // empty samplers dict
+    let lut = raw_object(0x04000000, b"LUTS", "Lut", &[0, 0]);
+    
+    let mut container = empty_container();
+    container.luts = Some(raw_dict(&[("Lut", lut.clone())]));
+    
+    let reread = CgfxContainer::new(&container.to_buffer().unwrap()).unwrap();
+    assert_eq!(reread.luts.as_ref().unwrap().get("Lut").unwrap().raw(), Some(&lut));
+}
+
+#[test]
+fn animations_round_trip() {
+    // the pointer at 28 points to the float after it
+    let walk = raw_object(0, b"CANM", "Walk", &[1, 4, 0x3f800000]);
+    let run = raw_object(0, b"CANM", "Run", &[2, 4, 0x40000000]);
+    
+    let mut container = empty_container();
+    container.skeletal_animations = Some(raw_dict(&[("Walk", walk.clone()), ("Run", run.clone())]));
+    
+    let reread = CgfxContainer::new(&container.to_buffer().unwrap()).unwrap();
+    let animations: Vec<_> = reread.skeletal_animations.as_ref().unwrap().entries().collect();
+    let expected: Vec<_> = container.skeletal_animations.as_ref().unwrap().entries().collect();
+    assert_eq!(animations, expected);
+    
+    // moving an object to somewhere else in the file keeps its pointers working
+    let mut container = empty_container();
+    container.skeletal_animations = Some(raw_dict(&[("Run", run.clone())]));
+    
+    let reread = CgfxContainer::new(&container.to_buffer().unwrap()).unwrap();
+    assert_eq!(reread.skeletal_animations.as_ref().unwrap().get("Run").unwrap().raw(), Some(&run));
+}
+
+#[test]
+fn empty_dicts_round_trip() {
+    let mut container = empty_container();
+    container.models = Some(CgfxDict::new());
+    container.textures = Some(CgfxDict::new());
+    container.luts = Some(CgfxDict::new());
+    
+    let buffer = container.to_buffer().unwrap();
+    let reread = CgfxContainer::new(&buffer).unwrap();
+    
+    assert!(reread.models.as_ref().is_some_and(CgfxDict::is_empty));
+    assert!(reread.textures.as_ref().is_some_and(CgfxDict::is_empty));
+    assert!(reread.luts.as_ref().is_some_and(CgfxDict::is_empty));
+    // the fixture's header has an IMAG section, which stays even though it's empty now
+    assert_eq!(reread.header.sections_count, 2);
+    assert_eq!(reread.to_buffer().unwrap(), buffer);
+}
+
+#[test]
+fn missing_image_section_round_trip() {
+    let mut container = CgfxContainer::new(&fixtures::minimal_bcres()).unwrap();
+    container.textures = None;
+    container.header.sections_count = 1;
+    
+    let buffer = container.to_buffer().unwrap();
+    let reread = CgfxContainer::new(&buffer).unwrap();
+    
+    assert_eq!(reread.header.sections_count, 1);
+    assert_eq!(reread.header.file_length as usize, buffer.len());
+    assert!(reread.textures.is_none());
+    assert_eq!(reread.models_iter().count(), 1);
+    assert_eq!(reread.to_buffer().unwrap(), buffer);
+}
+
+#[test]
+fn render_target_round_trip() {
+    let CgfxTexture::Image(common, Some(_)) = fixture_texture() else {
+        panic!("fixture texture should be a plain image");
+    };
+    let render_target = CgfxTexture::Image(common, None);
+    
+    // without any image data, there is no IMAG section to write
+    let container = CgfxContainer::from_texture("RenderTarget", render_target.clone()).unwrap();
+    let buffer = container.to_buffer().unwrap();
+    let reread = CgfxContainer::new(&buffer).unwrap();
+    
+    let texture = reread.textures.as_ref().unwrap().get("RenderTarget").unwrap();
+    assert!(matches!(texture, CgfxTexture::Image(_, None)));
+    assert_eq!(reread.header.sections_count, 1);
+    assert_eq!(texture.metadata(), render_target.metadata());
+    assert_eq!(reread.to_buffer().unwrap(), buffer);
+    
+    // next to a texture with image data
+    let mut container = CgfxContainer::new(&fixtures::minimal_bcres()).unwrap();
+    container.textures.as_mut().unwrap().insert("RenderTarget", render_target.clone(), CollisionPolicy::Error).unwrap();
+    
+    let buffer = container.to_buffer().unwrap();
+    let reread = CgfxContainer::new(&buffer).unwrap();
+    
+    // buffer pointers depend on where the textures end up, so compare their contents
+    let contents = |container: &CgfxContainer| -> Vec<_> {
+        container.textures_iter()
+            .map(|(name, texture)| {
+                let images: Vec<Vec<u8>> = texture.images().iter().map(|image| image.image_bytes.to_vec()).collect();
+                (name.to_string(), texture.metadata().clone(), images)
+            })
+            .collect()
+    };
+    assert_eq!(contents(&reread), contents(&container));
+    assert_eq!(reread.to_buffer().unwrap(), buffer);
+}
+
+#[test]
+fn metadata_round_trip() {
+    let texture_metadata = metadata_dict(vec![
+        MetadataValues::Floats(vec![0.5, 2.0]),
+        MetadataValues::Integers(vec![-1, 7]),
+        MetadataValues::Strings { format: 0, values: vec![Some("first".to_string()), None, Some("third".to_string())] },
+        MetadataValues::Vectors(vec![Vec3::new(1.0, 2.0, 3.0)]),
+        MetadataValues::Colors(vec![Vec4::new(1.0, 0.5, 0.25, 1.0)]),
+    ]);
+    let bone_metadata = metadata_dict(vec![MetadataValues::Integers(Vec::new())]);
+    
+    let mut container = CgfxContainer::new(&fixtures::minimal_bcres()).unwrap();
+    
+    let header = &mut container.textures.as_mut().unwrap().get_mut(fixtures::TEXTURE_NAME).unwrap()
+        .metadata_mut().cgfx_object_header;
+    header.metadata_count = texture_metadata.len() as u32;
+    header.metadata = Some(texture_metadata.clone());
+    
+    let model = container.models.as_mut().unwrap().get_mut(fixtures::MODEL_NAME).unwrap();
+    let CgfxModel::Skeletal(_, skeleton) = model else {
+        panic!("fixture model should be skeletal");
+    };
+    skeleton.bones.get_mut(fixtures::BONE_NAME).unwrap().metadata = Some(bone_metadata.clone());
+    
+    let buffer = container.to_buffer().unwrap();
+    let reread = CgfxContainer::new(&buffer).unwrap();
+    
+    let texture = reread.textures.as_ref().unwrap().get(fixtures::TEXTURE_NAME).unwrap();
+    assert_eq!(texture.metadata().cgfx_object_header.metadata.as_ref().unwrap().values().collect::<Vec<_>>(),
+        texture_metadata.values().collect::<Vec<_>>());
+    
+    let CgfxModel::Skeletal(_, skeleton) = reread.models.as_ref().unwrap().get(fixtures::MODEL_NAME).unwrap() else {
+        panic!("fixture model should be skeletal");
+    };
+    let bone = skeleton.bones.get(fixtures::BONE_NAME).unwrap();
+    assert_eq!(bone.metadata.as_ref().unwrap().values().collect::<Vec<_>>(), bone_metadata.values().collect::<Vec<_>>());
+    
+    assert_eq!(reread.to_buffer().unwrap(), buffer);
+}
+
+#[test]
+fn unknown_metadata_is_not_written() {
+    let mut container = CgfxContainer::new(&fixtures::minimal_bcres()).unwrap();
+    
+    let header = &mut container.textures.as_mut().unwrap().get_mut(fixtures::TEXTURE_NAME).unwrap()
+        .metadata_mut().cgfx_object_header;
+    header.metadata_count = 1;
+    header.metadata = Some(metadata_dict(vec![MetadataValues::Unknown { discriminant: 0x20000000 }]));
+    
+    assert!(container.to_buffer().is_err());
+    
+    // the count has to match the entries
+    let header = &mut container.textures.as_mut().unwrap().get_mut(fixtures::TEXTURE_NAME).unwrap()
+        .metadata_mut().cgfx_object_header;
+    header.metadata = None;
+    assert!(container.to_buffer().is_err());
+}
+
+#[test]
+fn etc1_textures_get_their_gl_format() {
+    let pixels = fixture_texture().decode().unwrap().images.swap_remove(0);
+    let size = fixtures::TEXTURE_SIZE;
+    
+    for (format, gl_format) in [(PicaTextureFormat::ETC1, 0x675A), (PicaTextureFormat::ETC1A4, 0x675B)] {
+        let texture = CgfxTexture::from_rgba("etc1", size, size, format, &pixels).unwrap();
+        assert_eq!((texture.metadata().gl_format, texture.metadata().gl_type), (gl_format, 0));
+        
+        let mut replaced = fixture_texture();
+        replaced.set_rgba(size, size, format, &pixels).unwrap();
+        assert_eq!((replaced.metadata().gl_format, replaced.metadata().gl_type), (gl_format, 0));
+        assert_eq!(replaced.format_mismatch(), None);
+    }
+}
+
+#[test]
+fn etc1_gl_format_mismatch() {
+    // the fixture is RGBA8 and says nothing about its OpenGL format
+    let mut texture = fixture_texture();
+    assert_eq!(texture.format_mismatch(), None);
+    
+    texture.metadata_mut().gl_format = 0x675A;
+    texture.metadata_mut().gl_type = 0;
+    assert_eq!(texture.format_mismatch(), Some(FormatMismatch {
+        texture_format: PicaTextureFormat::RGBA8,
+        gl_format: PicaTextureFormat::ETC1,
+    }));
+    
+    // 256 bytes of image data are what an RGBA8 texture of that size needs, not an ETC1 one
+    assert_eq!(texture.clone().resolve_format_mismatch(FormatPolicy::MatchBufferSize), None);
+    assert_eq!(texture.resolve_format_mismatch(FormatPolicy::TrustGlFormat), Some(PicaTextureFormat::ETC1));
+    assert_eq!(texture.format_mismatch(), None);
+}